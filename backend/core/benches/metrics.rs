@@ -0,0 +1,141 @@
+//! Benchmarks for the metrics engine over synthetic repositories.
+//!
+//! Run with `cargo bench -p repoflow-core --bench metrics`; criterion compares against the previous run, so
+//! regressions show up as a change in the reported times.
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use repoflow_core::domain::{GitHubPR, PRState};
+use repoflow_core::metrics::{calculate_metrics, Event, Timeline};
+
+const SIZES: [usize; 3] = [10_000, 100_000, 1_000_000];
+const FETCH_DAYS: i64 = 90;
+
+fn now() -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap()
+}
+
+/// `count` pull requests opened over the fetch horizon, most merged and some closed within a few
+/// days, seeded so every run sees the same data.
+fn synthetic_prs(count: usize) -> Vec<GitHubPR> {
+    let mut rng = StdRng::seed_from_u64(count as u64);
+    let horizon = Duration::days(FETCH_DAYS).num_seconds();
+    (0..count as u64)
+        .map(|id| {
+            let created_at = now() - Duration::seconds(rng.gen_range(0..horizon));
+            let ended_at = created_at + Duration::seconds(rng.gen_range(0..7 * 24 * 3600));
+            let (state, merged_at, closed_at) = match rng.gen_range(0..10) {
+                0..=6 if ended_at <= now() => (PRState::Merged, Some(ended_at), Some(ended_at)),
+                7 if ended_at <= now() => (PRState::Closed, None, Some(ended_at)),
+                _ => (PRState::Open, None, None),
+            };
+            GitHubPR {
+                id,
+                created_at,
+                merged_at,
+                closed_at,
+                state,
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+fn bench_calculate_metrics(c: &mut Criterion) {
+    let mut group = c.benchmark_group("calculate_metrics");
+    group.sample_size(10);
+    for size in SIZES {
+        let prs = synthetic_prs(size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &prs, |b, prs| {
+            b.iter(|| {
+                calculate_metrics(
+                    black_box(prs),
+                    Duration::days(30),
+                    Duration::days(30),
+                    now(),
+                )
+            })
+        });
+    }
+    group.finish();
+}
+
+fn bench_timeline(c: &mut Criterion) {
+    let mut group = c.benchmark_group("timeline");
+    group.sample_size(10);
+    for size in SIZES {
+        let prs = synthetic_prs(size);
+        group.bench_with_input(BenchmarkId::new("new", size), &prs, |b, prs| {
+            b.iter(|| Timeline::new(black_box(prs)))
+        });
+
+        let timeline = Timeline::new(&prs);
+        let end = now() - Duration::days(10);
+        let start = end - Duration::days(30);
+        group.bench_with_input(
+            BenchmarkId::new("count_between", size),
+            &timeline,
+            |b, timeline| {
+                b.iter(|| timeline.count_between(Event::Merged, black_box(start), black_box(end)))
+            },
+        );
+        group.bench_with_input(
+            BenchmarkId::new("fast_merged_between", size),
+            &timeline,
+            |b, timeline| b.iter(|| timeline.fast_merged_between(black_box(start), black_box(end))),
+        );
+        group.bench_with_input(
+            BenchmarkId::new("open_at", size),
+            &timeline,
+            |b, timeline| b.iter(|| timeline.open_at(black_box(end))),
+        );
+    }
+    group.finish();
+}
+
+/// What serving cached metrics as JSON costs: serializing them again for every request, against
+/// splicing their serialization, made once when they were cached, into the response envelope.
+fn bench_response_body(c: &mut Criterion) {
+    let mut group = c.benchmark_group("response_body");
+    let metrics = calculate_metrics(
+        &synthetic_prs(10_000),
+        Duration::days(30),
+        Duration::days(30),
+        now(),
+    );
+    let meta = serde_json::json!({"fetched_at": now(), "complete": true});
+    group.bench_function("serialize", |b| {
+        b.iter(|| {
+            serde_json::to_vec(&serde_json::json!({
+                "data": black_box(&metrics).clone(),
+                "meta": &meta,
+            }))
+            .unwrap()
+        })
+    });
+
+    let json = bytes::Bytes::from(serde_json::to_vec(&metrics).unwrap());
+    group.bench_function("pre_serialized", |b| {
+        b.iter(|| {
+            let data = black_box(&json).clone();
+            let meta = serde_json::to_vec(&meta).unwrap();
+            let mut body = Vec::with_capacity(data.len() + meta.len() + 20);
+            body.extend_from_slice(b"{\"data\":");
+            body.extend_from_slice(&data);
+            body.extend_from_slice(b",\"meta\":");
+            body.extend_from_slice(&meta);
+            body.push(b'}');
+            body
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_calculate_metrics,
+    bench_timeline,
+    bench_response_body
+);
+criterion_main!(benches);
@@ -0,0 +1,1171 @@
+//! Alert rules on a repository's flow, checked whenever the background refresh recalculates a
+//! popular repository.
+//!
+//! Rules are managed through the API. Like the popular list, they're saved to
+//! `ALERT_RULES_FILE` when one is configured and otherwise last until the process exits. Every
+//! check is kept in a history, so thresholds can be tuned against the values rules actually saw.
+
+use crate::config::{AppConfig, Secret};
+use crate::domain::RepoId;
+use crate::http_client;
+use crate::jsonl::JsonLines;
+use crate::metrics::{FlowMetricsResponse, SummaryMetrics};
+use anyhow::Context;
+use chrono::{DateTime, Duration, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::Duration as StdDuration;
+use tokio::sync::RwLock;
+
+/// Rules kept at most.
+pub const MAX_ALERT_RULES: usize = 100;
+
+/// Recent checks of each rule kept in memory for history queries.
+const HISTORY_PER_RULE: usize = 1_000;
+
+/// Longest cooldown a rule may ask for: a week.
+const MAX_COOLDOWN_MINUTES: u64 = 7 * 24 * 60;
+
+pub fn default_cooldown_minutes() -> u64 {
+    60
+}
+
+/// The value of the default rolling window a rule is checked against.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertMetric {
+    Opened,
+    Merged,
+    Spread,
+    /// Percentage of opened pull requests that were merged.
+    MergeRate,
+    /// Percentage of merged pull requests that close an issue.
+    IssueLinkRate,
+    /// Pull requests open at the end of the latest day.
+    OpenCount,
+}
+
+impl AlertMetric {
+    fn value(self, summary: &SummaryMetrics, latest: Option<&FlowMetricsResponse>) -> f64 {
+        match self {
+            Self::Opened => summary.current_opened as f64,
+            Self::Merged => summary.current_merged as f64,
+            Self::Spread => summary.current_spread as f64,
+            Self::MergeRate => summary.merge_rate as f64,
+            Self::IssueLinkRate => summary.issue_link_rate as f64,
+            Self::OpenCount => latest.map_or(0.0, |day| day.open_count as f64),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            Self::Opened => "opened",
+            Self::Merged => "merged",
+            Self::Spread => "spread",
+            Self::MergeRate => "merge rate",
+            Self::IssueLinkRate => "issue link rate",
+            Self::OpenCount => "open count",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Comparator {
+    Above,
+    AtLeast,
+    Below,
+    AtMost,
+}
+
+impl Comparator {
+    fn holds(self, value: f64, threshold: f64) -> bool {
+        match self {
+            Self::Above => value > threshold,
+            Self::AtLeast => value >= threshold,
+            Self::Below => value < threshold,
+            Self::AtMost => value <= threshold,
+        }
+    }
+
+    fn phrase(self) -> &'static str {
+        match self {
+            Self::Above => "above",
+            Self::AtLeast => "at least",
+            Self::Below => "below",
+            Self::AtMost => "at most",
+        }
+    }
+}
+
+/// Where a firing rule is delivered.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertChannel {
+    /// A JSON POST whose `text` field also suits Slack and Mattermost incoming webhooks.
+    Webhook { url: String },
+    /// A Discord channel's incoming webhook.
+    Discord { url: String },
+    /// A Microsoft Teams incoming webhook or Workflows trigger, posted an Adaptive Card.
+    Teams { url: String },
+    /// An event triggered through the PagerDuty Events API v2.
+    #[serde(rename = "pagerduty")]
+    PagerDuty {
+        /// The integration key of the PagerDuty service.
+        routing_key: Secret,
+        #[serde(default)]
+        severity: Severity,
+    },
+    /// An alert created through the Opsgenie Alert API.
+    Opsgenie {
+        api_key: Secret,
+        /// "P1" (critical) to "P5" (informational).
+        #[serde(default = "default_opsgenie_priority")]
+        priority: String,
+    },
+}
+
+impl AlertChannel {
+    /// Problems with the channel's settings, empty when they're valid.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        match self {
+            AlertChannel::Webhook { url } => {
+                if !url.starts_with("http://") && !url.starts_with("https://") {
+                    problems.push("webhook url must be an http(s) URL".to_string());
+                }
+            }
+            AlertChannel::Discord { url } | AlertChannel::Teams { url } => {
+                if !url.starts_with("https://") {
+                    problems.push("webhook url must be an https URL".to_string());
+                }
+            }
+            AlertChannel::PagerDuty { routing_key, .. } => {
+                if routing_key.expose().trim().is_empty() {
+                    problems.push("pagerduty routing_key must not be empty".to_string());
+                }
+            }
+            AlertChannel::Opsgenie { api_key, priority } => {
+                if api_key.expose().trim().is_empty() {
+                    problems.push("opsgenie api_key must not be empty".to_string());
+                }
+                if !["P1", "P2", "P3", "P4", "P5"].contains(&priority.as_str()) {
+                    problems.push("opsgenie priority must be one of P1 to P5".to_string());
+                }
+            }
+        }
+        problems
+    }
+
+    /// Whether the channel posts messages people read, rather than opening incidents.
+    pub fn is_chat(&self) -> bool {
+        matches!(
+            self,
+            AlertChannel::Webhook { .. }
+                | AlertChannel::Discord { .. }
+                | AlertChannel::Teams { .. }
+        )
+    }
+}
+
+/// The severity of PagerDuty events.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Severity {
+    Critical,
+    Error,
+    #[default]
+    Warning,
+    Info,
+}
+
+fn default_opsgenie_priority() -> String {
+    "P3".to_string()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: String,
+    /// The repository the rule watches, or every popular repository when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo: Option<RepoId>,
+    pub metric: AlertMetric,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    pub channel: AlertChannel,
+    /// Minutes after firing during which the rule stays quiet for the same repository, even if
+    /// its condition still holds.
+    #[serde(default = "default_cooldown_minutes")]
+    pub cooldown_minutes: u64,
+    /// When the rule holds its alerts, e.g. overnight. Alerts held aren't sent later, but a
+    /// condition that still holds fires on the first check after the window.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quiet_hours: Option<QuietHours>,
+}
+
+/// A daily window, in a time zone, during which a rule sends nothing.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuietHours {
+    /// When the window starts, e.g. "22:00".
+    pub start: NaiveTime,
+    /// When it ends, e.g. "07:00"; the window spans midnight when this is before `start`.
+    pub end: NaiveTime,
+    /// An IANA time zone such as "Europe/Berlin". Defaults to UTC.
+    #[serde(default = "default_quiet_hours_timezone")]
+    pub timezone: Tz,
+}
+
+fn default_quiet_hours_timezone() -> Tz {
+    Tz::UTC
+}
+
+impl QuietHours {
+    fn contains(&self, at: DateTime<Utc>) -> bool {
+        let time = at.with_timezone(&self.timezone).time();
+        if self.start <= self.end {
+            self.start <= time && time < self.end
+        } else {
+            time >= self.start || time < self.end
+        }
+    }
+}
+
+impl AlertRule {
+    /// Problems with the rule, empty when it's valid.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        if let Some(repo) = &self.repo {
+            for problem in repo.naming_problems() {
+                problems.push(format!("'{}' is not a valid owner/repo: {}", repo, problem));
+            }
+        }
+        if !self.threshold.is_finite() {
+            problems.push("threshold must be a finite number".to_string());
+        }
+        problems.extend(self.channel.problems());
+        if self
+            .quiet_hours
+            .as_ref()
+            .is_some_and(|quiet| quiet.start == quiet.end)
+        {
+            problems.push("quiet_hours start and end must differ".to_string());
+        }
+        if self.cooldown_minutes > MAX_COOLDOWN_MINUTES {
+            problems.push(format!(
+                "cooldown_minutes must be at most {}",
+                MAX_COOLDOWN_MINUTES
+            ));
+        }
+        problems
+    }
+
+    fn applies_to(&self, repo_id: &RepoId) -> bool {
+        self.repo.as_ref().is_none_or(|repo| repo == repo_id)
+    }
+}
+
+/// A rule whose condition held, as delivered to its channel.
+#[derive(Debug, Serialize)]
+pub struct Firing<'a> {
+    pub rule_id: &'a str,
+    pub repo: String,
+    /// The same for every firing of a rule for a repository, so incident tools group them.
+    pub dedup_key: String,
+    pub metric: AlertMetric,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    pub value: f64,
+    pub text: String,
+}
+
+/// How a check of a rule turned out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    /// The condition didn't hold.
+    NotMet,
+    /// The condition held, but the rule fired within its cooldown.
+    CoolingDown,
+    /// The condition held during the rule's quiet hours.
+    QuietHours,
+    /// The condition held, but `ALERT_MAX_PER_HOUR` alerts had already been sent.
+    Throttled,
+    /// The condition held and the alert was sent.
+    Fired,
+}
+
+/// One check of one rule against one repository.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Evaluation {
+    pub rule_id: String,
+    pub repo: String,
+    pub evaluated_at: DateTime<Utc>,
+    pub metric: AlertMetric,
+    pub comparator: Comparator,
+    pub threshold: f64,
+    pub value: f64,
+    pub outcome: Outcome,
+    /// Why the alert couldn't be delivered, when it fired but delivery failed.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub delivery_error: Option<String>,
+}
+
+/// How one repository's refresh went, as listed in a refresh digest.
+#[derive(Clone, Debug, Serialize)]
+pub struct DigestEntry {
+    pub repo: String,
+    /// The default window's summary, when the refresh succeeded.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<SummaryMetrics>,
+    /// Why the refresh failed, when it did.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl DigestEntry {
+    fn line(&self) -> String {
+        match (&self.summary, &self.error) {
+            (_, Some(error)) => format!("{}: refresh failed ({})", self.repo, error),
+            (Some(summary), None) => format!(
+                "{}: {} opened, {} merged, spread {}, {}% merged",
+                self.repo,
+                summary.current_opened,
+                summary.current_merged,
+                summary.current_spread,
+                summary.merge_rate
+            ),
+            (None, None) => format!("{}: not refreshed", self.repo),
+        }
+    }
+}
+
+/// Discord rejects messages longer than this many characters.
+const DISCORD_MAX_CONTENT: usize = 2000;
+
+/// A Discord webhook message that never pings anyone.
+fn discord_message(text: &str) -> serde_json::Value {
+    let content: String = if text.chars().count() > DISCORD_MAX_CONTENT {
+        text.chars()
+            .take(DISCORD_MAX_CONTENT - 1)
+            .chain(std::iter::once('…'))
+            .collect()
+    } else {
+        text.to_string()
+    };
+    serde_json::json!({
+        "username": "RepoFlow",
+        "content": content,
+        // Repository names are user-controlled text; never ping anyone.
+        "allowed_mentions": {"parse": []},
+    })
+}
+
+/// A Teams message holding an Adaptive Card with a bold `title` above `text`.
+fn teams_card(title: &str, text: &str) -> serde_json::Value {
+    serde_json::json!({
+        "type": "message",
+        "attachments": [{
+            "contentType": "application/vnd.microsoft.card.adaptive",
+            "content": {
+                "$schema": "http://adaptivecards.io/schemas/adaptive-card.json",
+                "type": "AdaptiveCard",
+                "version": "1.4",
+                "body": [
+                    {"type": "TextBlock", "text": title, "weight": "Bolder"},
+                    {"type": "TextBlock", "text": text, "wrap": true},
+                ],
+            },
+        }],
+    })
+}
+
+/// Every check of every rule, appended as JSON lines to `ALERT_HISTORY_PATH` when configured.
+/// Queries are served from the latest checks of each rule, kept in memory and loaded from the
+/// file at startup, so they never read the file.
+struct History {
+    file: Option<JsonLines>,
+    recent: tokio::sync::Mutex<HashMap<String, VecDeque<Evaluation>>>,
+}
+
+impl History {
+    fn new(config: &AppConfig) -> Self {
+        let file = config
+            .alert_history_path
+            .clone()
+            .map(|path| JsonLines::new(path, config.alert_history_max_bytes));
+        let mut recent: HashMap<String, VecDeque<Evaluation>> = HashMap::new();
+        if let Some(file) = &file {
+            match file.read_tail::<Evaluation>(MAX_ALERT_RULES * HISTORY_PER_RULE) {
+                Ok(evaluations) => {
+                    for evaluation in evaluations {
+                        remember(&mut recent, evaluation);
+                    }
+                }
+                Err(e) => tracing::error!(
+                    "Failed to read alert history from {}: {}",
+                    file.path().display(),
+                    e
+                ),
+            }
+        }
+        Self {
+            file,
+            recent: tokio::sync::Mutex::new(recent),
+        }
+    }
+
+    async fn record(&self, evaluation: Evaluation) {
+        if let Some(file) = &self.file {
+            if let Err(e) = file.append(&evaluation).await {
+                tracing::error!(
+                    "Failed to write alert history to {}: {}",
+                    file.path().display(),
+                    e
+                );
+            }
+        }
+        remember(&mut *self.recent.lock().await, evaluation);
+    }
+
+    async fn query(&self, rule_id: &str, repo: Option<&str>, limit: usize) -> Vec<Evaluation> {
+        let recent = self.recent.lock().await;
+        let Some(checks) = recent.get(rule_id) else {
+            return Vec::new();
+        };
+        checks
+            .iter()
+            .rev()
+            .filter(|e| repo.is_none_or(|repo| e.repo == repo))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    async fn forget(&self, rule_id: &str) {
+        self.recent.lock().await.remove(rule_id);
+    }
+}
+
+/// `rule` as saved to the rules file, with the channel credentials that serializing redacts.
+fn stored_rule(rule: &AlertRule) -> serde_json::Result<serde_json::Value> {
+    let mut stored = serde_json::to_value(rule)?;
+    match &rule.channel {
+        AlertChannel::PagerDuty { routing_key, .. } => {
+            stored["channel"]["routing_key"] = routing_key.expose().into();
+        }
+        AlertChannel::Opsgenie { api_key, .. } => {
+            stored["channel"]["api_key"] = api_key.expose().into();
+        }
+        _ => {}
+    }
+    Ok(stored)
+}
+
+fn remember(recent: &mut HashMap<String, VecDeque<Evaluation>>, evaluation: Evaluation) {
+    let checks = recent.entry(evaluation.rule_id.clone()).or_default();
+    if checks.len() == HISTORY_PER_RULE {
+        checks.pop_front();
+    }
+    checks.push_back(evaluation);
+}
+
+/// Returned when adding a rule would exceed `MAX_ALERT_RULES`.
+#[derive(Debug)]
+pub struct RulesFull;
+
+impl std::fmt::Display for RulesFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "at most {} alert rules are allowed", MAX_ALERT_RULES)
+    }
+}
+
+impl std::error::Error for RulesFull {}
+
+pub struct AlertRules {
+    rules: RwLock<Vec<AlertRule>>,
+    file: Option<PathBuf>,
+    /// When each rule last fired for each repository, for cooldowns.
+    fired: Mutex<HashMap<(String, RepoId), DateTime<Utc>>>,
+    /// When alerts were sent in the last hour, across every rule.
+    sent: Mutex<VecDeque<DateTime<Utc>>>,
+    max_per_hour: usize,
+    history: History,
+    pagerduty_url: String,
+    opsgenie_url: String,
+    http: Option<reqwest::Client>,
+    timeout: StdDuration,
+}
+
+impl AlertRules {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            rules: RwLock::new(config.alert_rules.clone()),
+            file: config.alert_rules_file.clone(),
+            fired: Mutex::new(HashMap::new()),
+            sent: Mutex::new(VecDeque::new()),
+            max_per_hour: config.alert_max_per_hour as usize,
+            history: History::new(config),
+            pagerduty_url: config.pagerduty_events_url.clone(),
+            opsgenie_url: config.opsgenie_api_url.trim_end_matches('/').to_string(),
+            http: match http_client::reqwest_client(config) {
+                Ok(http) => Some(http),
+                Err(e) => {
+                    tracing::error!("Alert delivery disabled: {:#}", e);
+                    None
+                }
+            },
+            timeout: StdDuration::from_secs(config.request_timeout_seconds),
+        }
+    }
+
+    pub async fn list(&self) -> Vec<AlertRule> {
+        self.rules.read().await.clone()
+    }
+
+    pub async fn get(&self, id: &str) -> Option<AlertRule> {
+        self.rules
+            .read()
+            .await
+            .iter()
+            .find(|rule| rule.id == id)
+            .cloned()
+    }
+
+    /// Adds `rule`, or replaces the rule with its id. Returns whether it was new.
+    pub async fn put(&self, rule: AlertRule) -> anyhow::Result<bool> {
+        let mut rules = self.rules.write().await;
+        let mut updated = rules.clone();
+        let new = match updated.iter().position(|r| r.id == rule.id) {
+            Some(index) => {
+                updated[index] = rule;
+                false
+            }
+            None if updated.len() >= MAX_ALERT_RULES => return Err(RulesFull.into()),
+            None => {
+                updated.push(rule);
+                true
+            }
+        };
+        self.persist(&updated).await?;
+        *rules = updated;
+        Ok(new)
+    }
+
+    /// Deletes a rule, returning whether it existed.
+    pub async fn delete(&self, id: &str) -> anyhow::Result<bool> {
+        let mut rules = self.rules.write().await;
+        if !rules.iter().any(|rule| rule.id == id) {
+            return Ok(false);
+        }
+        let updated: Vec<AlertRule> = rules.iter().filter(|r| r.id != id).cloned().collect();
+        self.persist(&updated).await?;
+        *rules = updated;
+        self.fired
+            .lock()
+            .expect("alert cooldown lock poisoned")
+            .retain(|(rule_id, _), _| rule_id != id);
+        self.history.forget(id).await;
+        Ok(true)
+    }
+
+    /// Writes the rules to the configured file, replacing it atomically.
+    async fn persist(&self, rules: &[AlertRule]) -> anyhow::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let stored = rules
+            .iter()
+            .map(stored_rule)
+            .collect::<serde_json::Result<Vec<_>>>()?;
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(&stored)?)
+            .await
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, path)
+            .await
+            .with_context(|| format!("failed to replace {}", path.display()))
+    }
+
+    /// Checks each rule that applies to `repo_id` at `now`, starting the cooldowns of those
+    /// that fire.
+    async fn check(
+        &self,
+        repo_id: &RepoId,
+        summary: &SummaryMetrics,
+        latest: Option<&FlowMetricsResponse>,
+        now: DateTime<Utc>,
+    ) -> Vec<(AlertRule, Evaluation)> {
+        let rules = self.rules.read().await;
+        let mut fired = self.fired.lock().expect("alert cooldown lock poisoned");
+        let mut checked = Vec::new();
+        for rule in rules.iter().filter(|rule| rule.applies_to(repo_id)) {
+            let value = rule.metric.value(summary, latest);
+            let key = (rule.id.clone(), repo_id.clone());
+            let cooldown = Duration::minutes(rule.cooldown_minutes as i64);
+            let outcome = if !rule.comparator.holds(value, rule.threshold) {
+                Outcome::NotMet
+            } else if fired.get(&key).is_some_and(|last| now - *last < cooldown) {
+                Outcome::CoolingDown
+            } else if rule.quiet_hours.as_ref().is_some_and(|q| q.contains(now)) {
+                Outcome::QuietHours
+            } else if !self.take_send_slot(now) {
+                Outcome::Throttled
+            } else {
+                fired.insert(key, now);
+                Outcome::Fired
+            };
+            checked.push((
+                rule.clone(),
+                Evaluation {
+                    rule_id: rule.id.clone(),
+                    repo: repo_id.to_string(),
+                    evaluated_at: now,
+                    metric: rule.metric,
+                    comparator: rule.comparator,
+                    threshold: rule.threshold,
+                    value,
+                    outcome,
+                    delivery_error: None,
+                },
+            ));
+        }
+        checked
+    }
+
+    /// Counts an alert sent at `now` against `ALERT_MAX_PER_HOUR`, unless the limit is reached.
+    fn take_send_slot(&self, now: DateTime<Utc>) -> bool {
+        if self.max_per_hour == 0 {
+            return true;
+        }
+        let mut sent = self.sent.lock().expect("alert rate lock poisoned");
+        while sent
+            .front()
+            .is_some_and(|at| now - *at >= Duration::hours(1))
+        {
+            sent.pop_front();
+        }
+        if sent.len() >= self.max_per_hour {
+            return false;
+        }
+        sent.push_back(now);
+        true
+    }
+
+    /// Checks every rule that applies to `repo_id` against its latest metrics, delivering those
+    /// that fire and recording each check in the history.
+    pub async fn evaluate(
+        &self,
+        repo_id: &RepoId,
+        summary: &SummaryMetrics,
+        latest: Option<&FlowMetricsResponse>,
+    ) {
+        for (rule, mut evaluation) in self.check(repo_id, summary, latest, Utc::now()).await {
+            if evaluation.outcome == Outcome::Fired {
+                let firing = Firing {
+                    rule_id: &rule.id,
+                    repo: evaluation.repo.clone(),
+                    dedup_key: format!("repoflow-{}-{}", rule.id, repo_id),
+                    metric: rule.metric,
+                    comparator: rule.comparator,
+                    threshold: rule.threshold,
+                    value: evaluation.value,
+                    text: format!(
+                        "{} {} is {}, {} {}",
+                        repo_id,
+                        rule.metric.name(),
+                        evaluation.value,
+                        rule.comparator.phrase(),
+                        rule.threshold
+                    ),
+                };
+                if let Err(e) = self.deliver(&rule.channel, &firing).await {
+                    tracing::warn!(
+                        "Failed to deliver alert {} for {}: {:#}",
+                        rule.id,
+                        repo_id,
+                        e
+                    );
+                    evaluation.delivery_error = Some(format!("{:#}", e));
+                }
+            }
+            self.history.record(evaluation).await;
+        }
+    }
+
+    /// The most recent checks of rule `id`, newest first, from its latest 1,000.
+    pub async fn history(&self, id: &str, repo: Option<&str>, limit: usize) -> Vec<Evaluation> {
+        self.history.query(id, repo, limit).await
+    }
+
+    async fn deliver(&self, channel: &AlertChannel, firing: &Firing<'_>) -> anyhow::Result<()> {
+        let Some(http) = &self.http else {
+            anyhow::bail!("no HTTP client");
+        };
+        let (request, service) = match channel {
+            AlertChannel::Webhook { url } => (http.post(url).json(firing), "the webhook"),
+            AlertChannel::Discord { url } => (
+                http.post(url).json(&discord_message(&firing.text)),
+                "Discord",
+            ),
+            AlertChannel::Teams { url } => {
+                let title = format!("RepoFlow alert: {}", firing.repo);
+                (
+                    http.post(url).json(&teams_card(&title, &firing.text)),
+                    "Teams",
+                )
+            }
+            AlertChannel::PagerDuty {
+                routing_key,
+                severity,
+            } => {
+                let event = serde_json::json!({
+                    "routing_key": routing_key.expose(),
+                    "event_action": "trigger",
+                    "dedup_key": firing.dedup_key,
+                    "payload": {
+                        "summary": firing.text,
+                        "source": firing.repo,
+                        "severity": severity,
+                        "component": "repoflow",
+                        "custom_details": firing,
+                    },
+                });
+                (http.post(&self.pagerduty_url).json(&event), "PagerDuty")
+            }
+            AlertChannel::Opsgenie { api_key, priority } => {
+                let alert = serde_json::json!({
+                    // Opsgenie truncates messages beyond 130 characters.
+                    "message": firing.text.chars().take(130).collect::<String>(),
+                    "alias": firing.dedup_key,
+                    "description": firing.text,
+                    "source": "RepoFlow",
+                    "priority": priority,
+                    "details": {
+                        "repo": firing.repo,
+                        "metric": firing.metric.name(),
+                        "value": firing.value.to_string(),
+                        "threshold": firing.threshold.to_string(),
+                    },
+                });
+                let request = http
+                    .post(format!("{}/v2/alerts", self.opsgenie_url))
+                    .header("Authorization", format!("GenieKey {}", api_key.expose()))
+                    .json(&alert);
+                (request, "Opsgenie")
+            }
+        };
+        self.send(request, service).await
+    }
+
+    /// Posts one summary of a refresh cycle to a chat `channel`, a line per repository.
+    pub async fn send_digest(
+        &self,
+        channel: &AlertChannel,
+        entries: &[DigestEntry],
+    ) -> anyhow::Result<()> {
+        let Some(http) = &self.http else {
+            anyhow::bail!("no HTTP client");
+        };
+        let failed = entries.iter().filter(|entry| entry.error.is_some()).count();
+        let title = format!(
+            "RepoFlow refresh: {} repositories, {} failed",
+            entries.len(),
+            failed
+        );
+        let lines: Vec<String> = entries.iter().map(DigestEntry::line).collect();
+        let text = lines.join("\n");
+        let (request, service) = match channel {
+            AlertChannel::Webhook { url } => {
+                let digest = serde_json::json!({
+                    "text": format!("{}\n{}", title, text),
+                    "repos": entries,
+                });
+                (http.post(url).json(&digest), "the webhook")
+            }
+            AlertChannel::Discord { url } => {
+                let message = discord_message(&format!("**{}**\n{}", title, text));
+                (http.post(url).json(&message), "Discord")
+            }
+            AlertChannel::Teams { url } => {
+                // Adaptive Card text blocks need a blank line between paragraphs.
+                (
+                    http.post(url)
+                        .json(&teams_card(&title, &lines.join("\n\n"))),
+                    "Teams",
+                )
+            }
+            AlertChannel::PagerDuty { .. } | AlertChannel::Opsgenie { .. } => {
+                anyhow::bail!("refresh digests can only be sent to chat channels")
+            }
+        };
+        self.send(request, service).await
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder, service: &str) -> anyhow::Result<()> {
+        request
+            .timeout(self.timeout)
+            .send()
+            .await
+            .with_context(|| format!("the request to {} failed", service))?
+            .error_for_status()
+            .with_context(|| format!("{} rejected the message", service))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_config;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn rule(id: &str, url: &str) -> AlertRule {
+        AlertRule {
+            id: id.to_string(),
+            repo: None,
+            metric: AlertMetric::Spread,
+            comparator: Comparator::Above,
+            threshold: 5.0,
+            channel: AlertChannel::Webhook {
+                url: url.to_string(),
+            },
+            cooldown_minutes: 60,
+            quiet_hours: None,
+        }
+    }
+
+    fn summary(spread: i64) -> SummaryMetrics {
+        SummaryMetrics {
+            current_spread: spread,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_rules_are_validated() {
+        assert!(rule("r1", "https://hooks.example.com/x")
+            .problems()
+            .is_empty());
+        let mut invalid = rule("r1", "ftp://hooks.example.com/x");
+        invalid.threshold = f64::NAN;
+        invalid.cooldown_minutes = MAX_COOLDOWN_MINUTES + 1;
+        assert_eq!(invalid.problems().len(), 3);
+        // Discord and Teams only issue https URLs.
+        let mut discord = rule("r1", "");
+        discord.channel = AlertChannel::Discord {
+            url: "http://discord.com/api/webhooks/1/x".to_string(),
+        };
+        assert_eq!(discord.problems().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_rules_fire_once_per_cooldown() {
+        let rules = AlertRules::new(&test_config(&[]));
+        let mut other = rule("r2", "https://hooks.example.com/x");
+        other.repo = Some("acme/other".parse().unwrap());
+        rules
+            .put(rule("r1", "https://hooks.example.com/x"))
+            .await
+            .unwrap();
+        rules.put(other).await.unwrap();
+
+        let repo_id: RepoId = "acme/widgets".parse().unwrap();
+        let now = Utc::now();
+        let fire = |spread, at| {
+            let rules = &rules;
+            let repo_id = &repo_id;
+            async move {
+                rules
+                    .check(repo_id, &summary(spread), None, at)
+                    .await
+                    .into_iter()
+                    .filter(|(_, evaluation)| evaluation.outcome == Outcome::Fired)
+                    .map(|(rule, _)| rule.id)
+                    .collect::<Vec<_>>()
+            }
+        };
+        assert!(fire(5, now).await.is_empty());
+        assert_eq!(fire(6, now).await, ["r1"]);
+        assert!(fire(9, now + Duration::minutes(59)).await.is_empty());
+        assert_eq!(fire(9, now + Duration::minutes(60)).await, ["r1"]);
+    }
+
+    #[tokio::test]
+    async fn test_firing_rules_are_posted() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/hook"))
+            .and(body_partial_json(serde_json::json!({
+                "rule_id": "r1",
+                "repo": "acme/widgets",
+                "metric": "spread",
+                "value": 7.0,
+                "text": "acme/widgets spread is 7, above 5",
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let history =
+            std::env::temp_dir().join(format!("repoflow-alerts-{}.jsonl", rand::random::<u64>()));
+        let rules = AlertRules::new(&test_config(&[(
+            "ALERT_HISTORY_PATH",
+            history.to_str().unwrap(),
+        )]));
+        rules
+            .put(rule("r1", &format!("{}/hook", server.uri())))
+            .await
+            .unwrap();
+        let repo_id = "acme/widgets".parse().unwrap();
+        rules.evaluate(&repo_id, &summary(7), None).await;
+        rules.evaluate(&repo_id, &summary(8), None).await;
+        rules.evaluate(&repo_id, &summary(3), None).await;
+
+        let checks = rules.history("r1", None, 10).await;
+        let outcomes: Vec<(f64, Outcome)> = checks.iter().map(|e| (e.value, e.outcome)).collect();
+        assert_eq!(
+            outcomes,
+            [
+                (3.0, Outcome::NotMet),
+                (8.0, Outcome::CoolingDown),
+                (7.0, Outcome::Fired)
+            ]
+        );
+        assert!(rules.history("r1", Some("acme/other"), 10).await.is_empty());
+
+        // A restart loads the latest checks back from the file.
+        let restarted = AlertRules::new(&test_config(&[(
+            "ALERT_HISTORY_PATH",
+            history.to_str().unwrap(),
+        )]));
+        assert_eq!(restarted.history("r1", None, 2).await.len(), 2);
+        assert_eq!(restarted.history("r1", None, 10).await[0].value, 3.0);
+        let _ = std::fs::remove_file(history);
+    }
+
+    #[tokio::test]
+    async fn test_incident_channels() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v2/enqueue"))
+            .and(body_partial_json(serde_json::json!({
+                "routing_key": "pd-key",
+                "event_action": "trigger",
+                "dedup_key": "repoflow-pd-acme/widgets",
+                "payload": {"source": "acme/widgets", "severity": "critical"},
+            })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/v2/alerts"))
+            .and(header("authorization", "GenieKey og-key"))
+            .and(body_partial_json(serde_json::json!({
+                "alias": "repoflow-og-acme/widgets",
+                "priority": "P3",
+                "details": {"value": "7"},
+            })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let events_url = format!("{}/v2/enqueue", server.uri());
+        let rules = AlertRules::new(&test_config(&[
+            ("PAGERDUTY_EVENTS_URL", events_url.as_str()),
+            ("OPSGENIE_API_URL", server.uri().as_str()),
+        ]));
+        let channel = |json| serde_json::from_value(json).unwrap();
+        let mut pagerduty = rule("pd", "");
+        pagerduty.channel = channel(serde_json::json!({
+            "type": "pagerduty", "routing_key": "pd-key", "severity": "critical"
+        }));
+        let mut opsgenie = rule("og", "");
+        opsgenie.channel = channel(serde_json::json!({"type": "opsgenie", "api_key": "og-key"}));
+        assert!(pagerduty.problems().is_empty());
+        assert!(opsgenie.problems().is_empty());
+        rules.put(pagerduty).await.unwrap();
+        rules.put(opsgenie).await.unwrap();
+
+        rules
+            .evaluate(&"acme/widgets".parse().unwrap(), &summary(7), None)
+            .await;
+        let checks = rules.history("og", None, 10).await;
+        assert_eq!(checks[0].outcome, Outcome::Fired);
+        assert_eq!(checks[0].delivery_error, None);
+    }
+
+    #[tokio::test]
+    async fn test_channel_keys_are_redacted_but_saved() {
+        let file =
+            std::env::temp_dir().join(format!("repoflow-rules-{}.json", rand::random::<u64>()));
+        let mut config = test_config(&[]);
+        config.alert_rules_file = Some(file.clone());
+        let rules = AlertRules::new(&config);
+        let mut pagerduty = rule("pd", "");
+        pagerduty.channel = AlertChannel::PagerDuty {
+            routing_key: Secret::new("pd-key"),
+            severity: Severity::default(),
+        };
+        assert!(!format!("{:?}", pagerduty).contains("pd-key"));
+        assert!(!serde_json::to_string(&pagerduty)
+            .unwrap()
+            .contains("pd-key"));
+        rules.put(pagerduty.clone()).await.unwrap();
+
+        let saved: Vec<AlertRule> = serde_json::from_slice(&std::fs::read(&file).unwrap()).unwrap();
+        assert_eq!(saved, vec![pagerduty]);
+        let _ = std::fs::remove_file(file);
+    }
+
+    #[tokio::test]
+    async fn test_chat_channels() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/discord"))
+            .and(body_partial_json(serde_json::json!({
+                "content": "acme/widgets spread is 7, above 5",
+                "allowed_mentions": {"parse": []},
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/teams"))
+            .and(body_partial_json(serde_json::json!({
+                "type": "message",
+                "attachments": [{"contentType": "application/vnd.microsoft.card.adaptive"}],
+            })))
+            .respond_with(ResponseTemplate::new(202))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let rules = AlertRules::new(&test_config(&[]));
+        let mut discord = rule("discord", "");
+        discord.channel = AlertChannel::Discord {
+            url: format!("{}/discord", server.uri()),
+        };
+        let mut teams = rule("teams", "");
+        teams.channel = AlertChannel::Teams {
+            url: format!("{}/teams", server.uri()),
+        };
+        rules.put(discord).await.unwrap();
+        rules.put(teams).await.unwrap();
+
+        rules
+            .evaluate(&"acme/widgets".parse().unwrap(), &summary(7), None)
+            .await;
+    }
+
+    #[tokio::test]
+    async fn test_refresh_digest() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/discord"))
+            .and(body_partial_json(serde_json::json!({
+                "content": "**RepoFlow refresh: 2 repositories, 1 failed**\n\
+                            acme/widgets: 0 opened, 0 merged, spread 7, 0% merged\n\
+                            acme/gadgets: refresh failed (GitHub is down)",
+                "allowed_mentions": {"parse": []},
+            })))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/webhook"))
+            .and(body_partial_json(serde_json::json!({
+                "repos": [
+                    {"repo": "acme/widgets", "summary": {"current_spread": 7}},
+                    {"repo": "acme/gadgets", "error": "GitHub is down"},
+                ],
+            })))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let rules = AlertRules::new(&test_config(&[]));
+        let entries = [
+            DigestEntry {
+                repo: "acme/widgets".to_string(),
+                summary: Some(summary(7)),
+                error: None,
+            },
+            DigestEntry {
+                repo: "acme/gadgets".to_string(),
+                summary: None,
+                error: Some("GitHub is down".to_string()),
+            },
+        ];
+
+        for channel in [
+            AlertChannel::Discord {
+                url: format!("{}/discord", server.uri()),
+            },
+            AlertChannel::Webhook {
+                url: format!("{}/webhook", server.uri()),
+            },
+        ] {
+            rules.send_digest(&channel, &entries).await.unwrap();
+        }
+        let pagerduty = AlertChannel::PagerDuty {
+            routing_key: Secret::new("key"),
+            severity: Severity::default(),
+        };
+        assert!(rules.send_digest(&pagerduty, &entries).await.is_err());
+        assert_eq!(
+            discord_message(&"x".repeat(3000))["content"]
+                .as_str()
+                .unwrap()
+                .chars()
+                .count(),
+            DISCORD_MAX_CONTENT
+        );
+    }
+
+    #[tokio::test]
+    async fn test_quiet_hours_and_throttling() {
+        let rules = AlertRules::new(&test_config(&[("ALERT_MAX_PER_HOUR", "2")]));
+        let mut overnight = rule("overnight", "https://hooks.example.com/x");
+        overnight.quiet_hours = serde_json::from_value(serde_json::json!({
+            "start": "22:00",
+            "end": "07:00",
+            "timezone": "Europe/Berlin",
+        }))
+        .unwrap();
+        rules.put(overnight).await.unwrap();
+        let outcome = |repo: &str, at: &str| {
+            let rules = &rules;
+            let (repo, at) = (repo.parse().unwrap(), at.parse().unwrap());
+            async move { rules.check(&repo, &summary(9), None, at).await[0].1.outcome }
+        };
+
+        // 02:30 in Berlin, then 07:30.
+        assert_eq!(
+            outcome("acme/widgets", "2026-03-02T01:30:00Z").await,
+            Outcome::QuietHours
+        );
+        assert_eq!(
+            outcome("acme/widgets", "2026-03-02T06:30:00Z").await,
+            Outcome::Fired
+        );
+        assert_eq!(
+            outcome("acme/gadgets", "2026-03-02T06:40:00Z").await,
+            Outcome::Fired
+        );
+        assert_eq!(
+            outcome("acme/gizmos", "2026-03-02T06:50:00Z").await,
+            Outcome::Throttled
+        );
+        // The first alert's slot frees up an hour after it was sent.
+        assert_eq!(
+            outcome("acme/gizmos", "2026-03-02T07:30:00Z").await,
+            Outcome::Fired
+        );
+    }
+}
@@ -0,0 +1,235 @@
+//! Rolling windows counted in business days, so weekends and holidays don't make a window look
+//! slower than the working days it covers.
+
+use crate::config::AppConfig;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+use std::collections::BTreeSet;
+
+/// The days work happens on: every day but the weekend days and holidays.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct BusinessDays {
+    weekend: Vec<Weekday>,
+    holidays: BTreeSet<NaiveDate>,
+}
+
+impl BusinessDays {
+    pub fn new(weekend: &[Weekday], holidays: &[NaiveDate]) -> Self {
+        Self {
+            weekend: weekend.to_vec(),
+            holidays: holidays.iter().copied().collect(),
+        }
+    }
+
+    /// The business days of `config`, with the holidays of its calendar, whether or not windows
+    /// count them.
+    pub fn from_config(config: &AppConfig) -> Self {
+        Self::new(
+            &config.weekend_days,
+            &[config.holidays.as_slice(), &config.holiday_calendar].concat(),
+        )
+    }
+
+    pub fn is_business_day(&self, date: NaiveDate) -> bool {
+        !self.weekend.contains(&date.weekday()) && !self.is_holiday(date)
+    }
+
+    pub fn is_holiday(&self, date: NaiveDate) -> bool {
+        self.holidays.contains(&date)
+    }
+
+    /// Every holiday, in order.
+    pub fn holidays(&self) -> impl Iterator<Item = NaiveDate> + '_ {
+        self.holidays.iter().copied()
+    }
+
+    /// Business days in each week.
+    pub fn per_week(&self) -> i64 {
+        7 - self.weekend.len() as i64
+    }
+
+    /// Where a window of `days` business days ending at `end` starts: at the same time of day as
+    /// `end`, on the day before the earliest of them. Weekends and holidays in between are
+    /// covered too, so nothing that happened on them is lost.
+    pub fn window_start(&self, end: DateTime<Utc>, days: i64) -> DateTime<Utc> {
+        let last = end.date_naive();
+        let mut first = last;
+        let mut counted = 0;
+        // Validation keeps at least one business day a week, so this ends.
+        while days > 0 && self.per_week() > 0 {
+            if self.is_business_day(first) {
+                counted += 1;
+                if counted == days {
+                    break;
+                }
+            }
+            let Some(previous) = first.pred_opt() else {
+                break;
+            };
+            first = previous;
+        }
+        end - Duration::days((last - first).num_days() + 1)
+    }
+
+    /// The most calendar days a window of `days` business days spans, not counting holidays.
+    pub fn span(&self, days: i64) -> i64 {
+        let per_week = self.per_week().max(1);
+        (days.saturating_add(per_week - 1) / per_week).saturating_mul(7)
+    }
+}
+
+/// The dates of the events in an iCalendar file, such as an exported holiday calendar. Events
+/// span from their start date up to their end, which all-day events exclude. Recurrence rules
+/// aren't expanded, so a recurring event counts on its first date alone.
+pub fn parse_calendar(ics: &str) -> Result<Vec<NaiveDate>, InvalidCalendar> {
+    parse_events(ics).map_err(InvalidCalendar)
+}
+
+/// Returned for a holiday calendar that can't be read, saying why.
+#[derive(Debug)]
+pub struct InvalidCalendar(String);
+
+impl std::fmt::Display for InvalidCalendar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for InvalidCalendar {}
+
+fn parse_events(ics: &str) -> Result<Vec<NaiveDate>, String> {
+    // Long lines continue on lines starting with whitespace.
+    let mut lines: Vec<String> = Vec::new();
+    for line in ics.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continued), Some(last)) => last.push_str(continued),
+            _ => lines.push(line.trim_end().to_string()),
+        }
+    }
+    if lines
+        .iter()
+        .find(|line| !line.is_empty())
+        .map(String::as_str)
+        != Some("BEGIN:VCALENDAR")
+    {
+        return Err("expected an iCalendar file starting with BEGIN:VCALENDAR".to_string());
+    }
+
+    let mut dates = Vec::new();
+    let mut event: Option<Event> = None;
+    for line in &lines {
+        let Some((name, value)) = line.split_once(':') else {
+            continue;
+        };
+        let property = name.split(';').next().unwrap_or_default();
+        match (property.to_ascii_uppercase().as_str(), &mut event) {
+            ("BEGIN", None) if value.eq_ignore_ascii_case("VEVENT") => {
+                event = Some(Event::default())
+            }
+            ("DTSTART", Some(event)) => event.start = Some(parse_date(value)?),
+            ("DTEND", Some(event)) => event.end = Some((parse_date(value)?, value.len() == 8)),
+            ("END", Some(Event { start, end })) if value.eq_ignore_ascii_case("VEVENT") => {
+                let start = start.ok_or("an event has no DTSTART")?;
+                let last = match *end {
+                    Some((end, true)) => end.pred_opt().unwrap_or(end).max(start),
+                    Some((end, false)) => end.max(start),
+                    None => start,
+                };
+                if (last - start).num_days() >= MAX_EVENT_DAYS {
+                    return Err(format!(
+                        "the event starting {} lasts over {} days",
+                        start, MAX_EVENT_DAYS
+                    ));
+                }
+                dates.extend(start.iter_days().take_while(|date| *date <= last));
+                event = None;
+            }
+            _ => {}
+        }
+    }
+    dates.sort_unstable();
+    dates.dedup();
+    Ok(dates)
+}
+
+/// The dates of an event read so far.
+#[derive(Default)]
+struct Event {
+    start: Option<NaiveDate>,
+    /// With whether it's a date alone, which the event ends before.
+    end: Option<(NaiveDate, bool)>,
+}
+
+/// Longest event a holiday calendar may hold, so a typo can't mark centuries as holidays.
+const MAX_EVENT_DAYS: i64 = 366;
+
+/// The date of an iCalendar DATE or DATE-TIME value, such as "20261225" or "20261225T090000Z".
+fn parse_date(value: &str) -> Result<NaiveDate, String> {
+    value
+        .get(..8)
+        .and_then(|date| NaiveDate::parse_from_str(date, "%Y%m%d").ok())
+        .ok_or_else(|| format!("invalid date '{}'", value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(date: &str) -> DateTime<Utc> {
+        format!("{}T23:59:59Z", date).parse().unwrap()
+    }
+
+    #[test]
+    fn test_window_start_skips_weekends_and_holidays() {
+        let holiday = NaiveDate::from_ymd_opt(2026, 3, 4).unwrap();
+        let days = BusinessDays::new(&[Weekday::Sat, Weekday::Sun], &[holiday]);
+
+        // Friday 2026-03-06, back over the Wednesday holiday to Monday 2026-03-02.
+        assert_eq!(days.window_start(at("2026-03-06"), 4), at("2026-03-01"));
+        // From a Sunday, the weekend leading up to it stays in the window.
+        assert_eq!(days.window_start(at("2026-03-08"), 1), at("2026-03-05"));
+        // Monday 2026-03-09 back over the weekend to Friday.
+        assert_eq!(days.window_start(at("2026-03-09"), 2), at("2026-03-05"));
+        assert!(!days.is_business_day(holiday));
+        assert!(days.is_business_day(holiday.succ_opt().unwrap()));
+    }
+
+    #[test]
+    fn test_parse_calendar() {
+        let ics = "BEGIN:VCALENDAR\r\nVERSION:2.0\r\n\
+                   BEGIN:VEVENT\r\nSUMMARY:Winter\r\n  break\r\n\
+                   DTSTART;VALUE=DATE:20261224\r\nDTEND;VALUE=DATE:20261227\r\nEND:VEVENT\r\n\
+                   BEGIN:VEVENT\r\nDTSTART:20270101T000000Z\r\nEND:VEVENT\r\n\
+                   BEGIN:VEVENT\r\nDTSTART;TZID=Europe/Paris:20261225T090000\r\n\
+                   DTEND;TZID=Europe/Paris:20261225T170000\r\nEND:VEVENT\r\n\
+                   END:VCALENDAR\r\n";
+        let dates: Vec<String> = parse_calendar(ics)
+            .unwrap()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            dates,
+            ["2026-12-24", "2026-12-25", "2026-12-26", "2027-01-01"]
+        );
+
+        assert!(parse_calendar("2026-12-25").is_err());
+        let undated = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nSUMMARY:Someday\nEND:VEVENT\nEND:VCALENDAR";
+        assert!(parse_calendar(undated).is_err());
+        let endless = "BEGIN:VCALENDAR\nBEGIN:VEVENT\nDTSTART:20260101\nDTEND:30260101\n\
+                       END:VEVENT\nEND:VCALENDAR";
+        assert!(parse_calendar(endless).is_err());
+    }
+
+    #[test]
+    fn test_span() {
+        let days = BusinessDays::new(&[Weekday::Sat, Weekday::Sun], &[]);
+        assert_eq!(days.per_week(), 5);
+        assert_eq!(days.span(5), 7);
+        assert_eq!(days.span(6), 14);
+        assert_eq!(days.span(30), 42);
+        for end in ["2026-03-06", "2026-03-08", "2026-03-09"] {
+            let start = days.window_start(at(end), 30);
+            assert!((at(end) - start).num_days() <= days.span(30));
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Synthetic pull requests for `DEMO_MODE`, so the frontend can be developed and demoed without
+//! a token or network.
+//!
+//! Every repository gets its own pace, merge speed and slow drift, seeded from its name, with
+//! quieter weekends. Each day's pull requests are seeded from the repository and the date, so
+//! refetches agree on the past and only add what has "happened" since.
+
+use crate::config::AppConfig;
+use crate::domain::{GitHubPR, MergeQueueEntry, PRState, RepoId};
+use crate::source::{FetchedPullRequests, PullRequestSource};
+use async_trait::async_trait;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, Utc, Weekday};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::sync::Arc;
+
+/// Pull requests opened on one day at most, so each day can number its own.
+const MAX_PER_DAY: u64 = 64;
+
+const AREAS: [&str; 10] = [
+    "parser",
+    "cache",
+    "login",
+    "dashboard",
+    "CI",
+    "docs",
+    "API",
+    "scheduler",
+    "search",
+    "build",
+];
+const CHANGES: [&str; 6] = [
+    "Fix flaky {} test",
+    "Add {} support",
+    "Refactor {}",
+    "Speed up {}",
+    "Bump {} dependencies",
+    "Handle errors in {}",
+];
+
+/// Serves made-up pull requests for any repository.
+#[derive(Clone)]
+pub struct DemoSource {
+    labels: Vec<String>,
+    approvals: bool,
+    merge_queue: bool,
+    review_comments: bool,
+}
+
+/// How a repository's synthetic activity behaves.
+struct Profile {
+    seed: u64,
+    /// Pull requests opened on an average weekday.
+    per_day: f64,
+    merged_share: f64,
+    closed_share: f64,
+    /// Mean hours until a pull request is merged, or twice that until it's closed.
+    mean_hours: f64,
+    authors: u32,
+    /// Period in days and relative amplitude of the drift in pace, so charts have a shape.
+    drift: (f64, f64),
+}
+
+impl Profile {
+    fn new(repo_id: &RepoId) -> Self {
+        let seed = fnv1a(repo_id.to_string().to_lowercase().as_bytes());
+        let mut rng = StdRng::seed_from_u64(seed);
+        Self {
+            seed,
+            per_day: 2f64.powf(rng.gen_range(-1.0..4.0)),
+            merged_share: rng.gen_range(0.55..0.85),
+            closed_share: rng.gen_range(0.05..0.15),
+            mean_hours: rng.gen_range(4.0..96.0),
+            authors: rng.gen_range(3..40),
+            drift: (rng.gen_range(20.0..60.0), rng.gen_range(0.2..0.5)),
+        }
+    }
+
+    /// How many pull requests are opened on `date`.
+    fn opened_on(&self, date: NaiveDate, rng: &mut StdRng) -> u64 {
+        let weekday = match date.weekday() {
+            Weekday::Sat | Weekday::Sun => 0.25,
+            _ => 1.0,
+        };
+        let (period, amplitude) = self.drift;
+        let phase = date.num_days_from_ce() as f64 / period * std::f64::consts::TAU;
+        let expected = self.per_day * weekday * (1.0 + amplitude * phase.sin());
+        // Rounding at random keeps slow repositories from rounding down to nothing.
+        let count = (expected * rng.gen_range(0.6..1.4) + rng.gen::<f64>()).floor();
+        (count as u64).min(MAX_PER_DAY - 1)
+    }
+}
+
+/// FNV-1a, a hash that is stable across builds, unlike the standard library's.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+/// Hours until an event with the given mean, exponentially distributed like waiting times.
+fn hours_until(rng: &mut StdRng, mean: f64) -> Duration {
+    let hours = -(1.0 - rng.gen::<f64>()).ln() * mean;
+    Duration::seconds((hours * 3600.0) as i64)
+}
+
+impl DemoSource {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            labels: config.flow_labels.clone(),
+            approvals: config.github_fetch_approvals,
+            merge_queue: config.github_fetch_merge_queue,
+            review_comments: config.github_fetch_review_comments,
+        }
+    }
+
+    /// The pull requests of `repo_id` opened between `since` and `now`, newest first, with when
+    /// their review comments were posted.
+    fn generate(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> (Vec<GitHubPR>, Vec<DateTime<Utc>>) {
+        let profile = Profile::new(repo_id);
+        let mut pull_requests = Vec::new();
+        let mut comments = Vec::new();
+        for date in since.date_naive().iter_days() {
+            if date > now.date_naive() {
+                break;
+            }
+            let day = date.num_days_from_ce() as u64;
+            let mut rng =
+                StdRng::seed_from_u64(profile.seed ^ day.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let start = date
+                .and_hms_opt(0, 0, 0)
+                .expect("midnight is valid")
+                .and_utc();
+            for index in 0..profile.opened_on(date, &mut rng) {
+                let number = day * MAX_PER_DAY + index;
+                let pr = self.pull_request(&profile, &mut rng, number, start);
+                if self.review_comments {
+                    let open_until = pr.closed_at.unwrap_or(now).min(now);
+                    for _ in 0..rng.gen_range(0..6) {
+                        let open_seconds = (open_until - pr.created_at).num_seconds().max(1);
+                        comments.push(
+                            pr.created_at + Duration::seconds(rng.gen_range(0..open_seconds)),
+                        );
+                    }
+                }
+                if pr.created_at >= since && pr.created_at <= now {
+                    pull_requests.push(pr);
+                }
+            }
+        }
+        // Nothing after `now` has happened yet.
+        for pr in &mut pull_requests {
+            if pr.closed_at.is_some_and(|closed| closed > now) {
+                pr.state = PRState::Open;
+                pr.merged_at = None;
+                pr.closed_at = None;
+                pr.approvals = None;
+                pr.merge_queue = None;
+            }
+        }
+        pull_requests.sort_unstable_by_key(|pr| std::cmp::Reverse(pr.created_at));
+        comments.retain(|at| *at >= since && *at <= now);
+        comments.sort_unstable_by_key(|at| std::cmp::Reverse(*at));
+        (pull_requests, comments)
+    }
+
+    /// One pull request opened on the day starting at `start`, as it will end up.
+    fn pull_request(
+        &self,
+        profile: &Profile,
+        rng: &mut StdRng,
+        number: u64,
+        start: DateTime<Utc>,
+    ) -> GitHubPR {
+        let created_at = start + Duration::seconds(rng.gen_range(0..24 * 60 * 60));
+        let area = AREAS[rng.gen_range(0..AREAS.len())];
+        let change = CHANGES[rng.gen_range(0..CHANGES.len())];
+        let outcome: f64 = rng.gen();
+        let (state, merged_at, closed_at) = if outcome < profile.merged_share {
+            let merged_at = created_at + hours_until(rng, profile.mean_hours);
+            (PRState::Merged, Some(merged_at), Some(merged_at))
+        } else if outcome < profile.merged_share + profile.closed_share {
+            let closed_at = created_at + hours_until(rng, 2.0 * profile.mean_hours);
+            (PRState::Closed, None, Some(closed_at))
+        } else {
+            (PRState::Open, None, None)
+        };
+        GitHubPR {
+            id: number,
+            number,
+            title: change.replace("{}", area),
+            author: Some(format!("demo-dev-{}", rng.gen_range(0..profile.authors))),
+            labels: self
+                .labels
+                .iter()
+                .filter(|_| rng.gen_bool(0.25))
+                .cloned()
+                .collect(),
+            created_at,
+            merged_at,
+            closed_at,
+            updated_at: None,
+            state,
+            closes_issues: if rng.gen_bool(0.4) {
+                vec![rng.gen_range(1..number.max(2))]
+            } else {
+                Vec::new()
+            },
+            approvals: merged_at
+                .filter(|_| self.approvals)
+                .map(|_| match rng.gen_range(0..10) {
+                    0 => 0,
+                    1..=6 => 1,
+                    _ => 2,
+                }),
+            merge_queue: merged_at.filter(|_| self.merge_queue).map(|merged_at| {
+                let queued = Duration::minutes(rng.gen_range(5..90));
+                vec![MergeQueueEntry {
+                    enqueued_at: (merged_at - queued).max(created_at),
+                    dequeued_at: None,
+                }]
+            }),
+        }
+    }
+}
+
+#[async_trait]
+impl PullRequestSource for DemoSource {
+    async fn pull_requests(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        _max_pages: u32,
+    ) -> anyhow::Result<FetchedPullRequests> {
+        let (pull_requests, comments) = self.generate(repo_id, since, Utc::now());
+        Ok(FetchedPullRequests {
+            pull_requests,
+            truncated: false,
+            unfetched: None,
+            review_comments: self.review_comments.then_some(comments),
+        })
+    }
+
+    async fn is_public(&self, _repo_id: &RepoId) -> anyhow::Result<bool> {
+        Ok(true)
+    }
+
+    fn for_user(&self, _token: &str) -> anyhow::Result<Arc<dyn PullRequestSource>> {
+        Ok(Arc::new(self.clone()))
+    }
+
+    async fn check_access(&self) -> anyhow::Result<()> {
+        tracing::info!("Serving synthetic pull requests in demo mode");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::service::MetricsService;
+    use crate::test_support::test_config;
+
+    #[test]
+    fn test_generated_history_is_stable() {
+        let source = DemoSource::new(&test_config(&[("GITHUB_FETCH_APPROVALS", "true")]));
+        let repo_id: RepoId = "acme/widgets".parse().unwrap();
+        let now: DateTime<Utc> = "2026-03-01T12:00:00Z".parse().unwrap();
+        let since = now - Duration::days(90);
+
+        let (prs, _) = source.generate(&repo_id, since, now);
+        assert!(!prs.is_empty());
+        assert!(prs
+            .windows(2)
+            .all(|pair| pair[0].created_at >= pair[1].created_at));
+        assert!(prs
+            .iter()
+            .all(|pr| pr.created_at >= since && pr.created_at <= now));
+        assert!(prs
+            .iter()
+            .all(|pr| pr.closed_at.is_none_or(|closed| closed <= now)));
+        assert!(prs
+            .iter()
+            .any(|pr| pr.merged_at.is_some() && pr.approvals.is_some()));
+
+        // A day later, the past is unchanged, apart from pull requests that have since ended.
+        let (later, _) = source.generate(&repo_id, since, now + Duration::days(1));
+        let numbers = |prs: &[GitHubPR]| prs.iter().map(|pr| pr.number).collect::<Vec<_>>();
+        assert!(numbers(&later).ends_with(&numbers(&prs)));
+        let (other, _) = source.generate(&"acme/gadgets".parse().unwrap(), since, now);
+        assert_ne!(numbers(&other), numbers(&prs));
+    }
+
+    #[tokio::test]
+    async fn test_demo_mode_serves_any_repo() {
+        let service = MetricsService::new(&test_config(&[("DEMO_MODE", "true")])).unwrap();
+        let cached = service
+            .get("anyone/anything".parse().unwrap())
+            .await
+            .unwrap();
+        let summary = &cached.default_window().metrics.summary;
+        assert!(summary.current_opened > 0);
+        assert!(cached.complete);
+    }
+}
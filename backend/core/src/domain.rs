@@ -0,0 +1,280 @@
+//! The types the rest of the crate is built around: repositories and their pull requests.
+
+use chrono::{DateTime, Duration, Utc};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// A unique identifier for a GitHub repository.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RepoId {
+    /// The owner of the repository (e.g., "facebook").
+    pub owner: String,
+    /// The name of the repository (e.g., "react").
+    pub repo: String,
+}
+
+impl RepoId {
+    /// Whether both parts follow GitHub's naming rules: owners are alphanumeric with single
+    /// hyphens, repositories may also contain `.` and `_`.
+    pub fn is_well_formed(&self) -> bool {
+        self.naming_problems().is_empty()
+    }
+
+    /// Describes each way the owner and repository names break GitHub's naming rules.
+    pub fn naming_problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+        let owner = &self.owner;
+        if !(1..=39).contains(&owner.len()) {
+            problems.push("owner must be 1 to 39 characters long".to_string());
+        }
+        if !owner.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            problems.push("owner may only contain letters, digits and hyphens".to_string());
+        }
+        if owner.starts_with('-') || owner.ends_with('-') || owner.contains("--") {
+            problems.push(
+                "owner must not start or end with a hyphen or contain consecutive hyphens"
+                    .to_string(),
+            );
+        }
+
+        let repo = &self.repo;
+        if !(1..=100).contains(&repo.len()) {
+            problems.push("repository name must be 1 to 100 characters long".to_string());
+        }
+        if !repo
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+        {
+            problems.push(
+                "repository name may only contain letters, digits, '-', '_' and '.'".to_string(),
+            );
+        }
+        if repo == "." || repo == ".." {
+            problems.push("repository name must not be '.' or '..'".to_string());
+        }
+        problems
+    }
+}
+
+impl fmt::Display for RepoId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.owner, self.repo)
+    }
+}
+
+/// Why a repository name was rejected: every rule it breaks.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidRepoId(pub Vec<String>);
+
+impl fmt::Display for InvalidRepoId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0.join("; "))
+    }
+}
+
+impl std::error::Error for InvalidRepoId {}
+
+impl TryFrom<(&str, &str)> for RepoId {
+    type Error = InvalidRepoId;
+
+    /// Builds a repository from its owner and name, rejecting names GitHub wouldn't accept.
+    fn try_from((owner, repo): (&str, &str)) -> Result<Self, Self::Error> {
+        let repo_id = RepoId {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        };
+        let problems = repo_id.naming_problems();
+        if problems.is_empty() {
+            Ok(repo_id)
+        } else {
+            Err(InvalidRepoId(problems))
+        }
+    }
+}
+
+impl FromStr for RepoId {
+    type Err = InvalidRepoId;
+
+    /// Parses "owner/repo".
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (owner, repo) = s
+            .split_once('/')
+            .ok_or_else(|| InvalidRepoId(vec!["expected owner/repo".to_string()]))?;
+        RepoId::try_from((owner, repo))
+    }
+}
+
+/// Represents the possible states of a GitHub Pull Request in our system.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PRState {
+    /// The PR is currently open and active.
+    Open,
+    /// The PR has been closed without being merged.
+    Closed,
+    /// The PR has been successfully merged into the target branch.
+    Merged,
+    /// The state of the PR could not be determined.
+    #[default]
+    Unknown,
+}
+
+/// A simplified representation of a GitHub Pull Request used for calculating flow metrics.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct GitHubPR {
+    /// GitHub's database ID for this pull request, or its number where the source can't tell
+    /// the ID, as with the search API.
+    pub id: u64,
+    /// The pull request's number within its repository.
+    #[serde(default)]
+    pub number: u64,
+    /// The pull request's title.
+    #[serde(default)]
+    pub title: String,
+    /// Login of the account that opened it, unless the account has been deleted.
+    #[serde(default)]
+    pub author: Option<String>,
+    /// Names of its labels.
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// The exact timestamp when the pull request was first opened.
+    pub created_at: DateTime<Utc>,
+    /// The timestamp when the pull request was merged (None if not merged).
+    pub merged_at: Option<DateTime<Utc>>,
+    /// The timestamp when the pull request was closed, merged or not (None if still open).
+    pub closed_at: Option<DateTime<Utc>>,
+    /// The timestamp of its latest change, when the source reports one.
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    /// The current operational state of the pull request.
+    pub state: PRState,
+    /// Numbers of the issues in the same repository that the description says it closes.
+    #[serde(default)]
+    pub closes_issues: Vec<u64>,
+    /// Number of reviewers whose latest review approves it, when approvals were fetched.
+    #[serde(default)]
+    pub approvals: Option<u32>,
+    /// Its stays in the merge queue, when merge queue events were fetched.
+    #[serde(default)]
+    pub merge_queue: Option<Vec<MergeQueueEntry>>,
+}
+
+impl GitHubPR {
+    /// Time spent in the merge queue before merging, if it was merged and its merge queue events
+    /// were fetched.
+    pub fn time_in_merge_queue(&self) -> Option<Duration> {
+        let merged_at = self.merged_at?;
+        let stays = self.merge_queue.as_ref()?;
+        Some(
+            stays
+                .iter()
+                .map(|stay| {
+                    let left = stay.dequeued_at.unwrap_or(merged_at).min(merged_at);
+                    (left - stay.enqueued_at).max(Duration::zero())
+                })
+                .sum(),
+        )
+    }
+}
+
+/// One stay of a pull request in the merge queue.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct MergeQueueEntry {
+    pub enqueued_at: DateTime<Utc>,
+    /// When it was removed from the queue; `None` if it was merged from the queue or is still
+    /// queued.
+    pub dequeued_at: Option<DateTime<Utc>>,
+}
+
+/// The words GitHub recognises before `#N` as closing an issue when the pull request merges.
+const CLOSING_KEYWORDS: [&str; 9] = [
+    "close", "closes", "closed", "fix", "fixes", "fixed", "resolve", "resolves", "resolved",
+];
+
+/// Finds the issues a pull request description closes, such as `Fixes #12` or `closes: #3`,
+/// sorted and without duplicates.
+///
+/// Only same-repository references count; issues in other repositories are never ours to report.
+/// As on GitHub, each keyword applies to the single reference after it.
+pub fn closing_issue_references(text: &str) -> Vec<u64> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    let mut issues: Vec<u64> = words
+        .windows(2)
+        .filter(|pair| {
+            let keyword = pair[0]
+                .trim_start_matches(|c: char| !c.is_alphanumeric())
+                .trim_end_matches(':')
+                .to_ascii_lowercase();
+            CLOSING_KEYWORDS.contains(&keyword.as_str())
+        })
+        .filter_map(|pair| {
+            let reference = pair[1].strip_prefix('#')?;
+            let digits = reference.len()
+                - reference
+                    .trim_start_matches(|c: char| c.is_ascii_digit())
+                    .len();
+            let (number, rest) = reference.split_at(digits);
+            if !rest.chars().all(|c| c.is_ascii_punctuation()) {
+                return None;
+            }
+            number.parse().ok()
+        })
+        .collect();
+    issues.sort_unstable();
+    issues.dedup();
+    issues
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_repo_id_well_formed() {
+        let id = |owner: &str, repo: &str| RepoId {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        };
+        assert!(id("rust-lang", "rust").is_well_formed());
+        assert!(id("a", "my_repo.js").is_well_formed());
+        assert!(!id("-bad", "repo").is_well_formed());
+        assert!(!id("owner", "..").is_well_formed());
+        assert!(!id("owner", "").is_well_formed());
+        assert!(!id("own er", "repo").is_well_formed());
+        assert_eq!(
+            id("a--b", "..").naming_problems(),
+            vec![
+                "owner must not start or end with a hyphen or contain consecutive hyphens",
+                "repository name must not be '.' or '..'",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_repo_id() {
+        let repo_id: RepoId = "rust-lang/rust".parse().unwrap();
+        assert_eq!(repo_id, RepoId::try_from(("rust-lang", "rust")).unwrap());
+        assert_eq!(repo_id.to_string(), "rust-lang/rust");
+
+        assert_eq!(
+            "rust-lang".parse::<RepoId>().unwrap_err().to_string(),
+            "expected owner/repo"
+        );
+        assert!("-bad/repo".parse::<RepoId>().is_err());
+        assert!("owner/re/po".parse::<RepoId>().is_err());
+        assert_eq!(RepoId::try_from(("", "..")).unwrap_err().0.len(), 2);
+    }
+
+    #[test]
+    fn test_closing_issue_references() {
+        assert_eq!(
+            closing_issue_references("Fixes #12, and (closes: #3).\nResolved #12"),
+            vec![3, 12]
+        );
+        // A keyword covers only the reference right after it.
+        assert_eq!(closing_issue_references("fixes #1, #2"), vec![1]);
+        assert!(closing_issue_references("Refs #4, fixes other/repo#5").is_empty());
+        assert!(closing_issue_references("fix #4a or prefix #6 or fixes #").is_empty());
+    }
+}
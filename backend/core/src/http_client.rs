@@ -0,0 +1,173 @@
+//! Construction of the HTTP clients used to talk to GitHub.
+//!
+//! Octocrab's built-in HTTP client connects directly and only trusts the system roots, which many
+//! corporate networks don't allow. When `HTTPS_PROXY` or `EXTRA_CA_CERT_PATH` is set, we instead
+//! give Octocrab our own hyper client that tunnels through the proxy with HTTP `CONNECT` (skipping
+//! hosts listed in `NO_PROXY`) and trusts the extra CA certificates.
+
+use crate::config::AppConfig;
+use futures::future::BoxFuture;
+use http::header::{AUTHORIZATION, USER_AGENT};
+use http::{HeaderValue, Uri};
+use hyper_util::client::legacy::connect::proxy::Tunnel;
+use hyper_util::client::legacy::connect::HttpConnector;
+use hyper_util::client::legacy::Client;
+use hyper_util::client::proxy::matcher::Matcher;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use octocrab::service::middleware::base_uri::BaseUriLayer;
+use octocrab::service::middleware::extra_headers::ExtraHeadersLayer;
+use octocrab::{AuthState, Octocrab, OctocrabBuilder};
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::CertificateDer;
+use std::path::Path;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tokio::net::TcpStream;
+use tokio_rustls::rustls::{self, ClientConfig, RootCertStore};
+use tower::{BoxError, Service, ServiceExt};
+
+/// Builds an Octocrab client authenticating with `token`, or anonymously when there is none.
+pub fn octocrab(config: &AppConfig, token: Option<&str>) -> anyhow::Result<Octocrab> {
+    if config.https_proxy.is_none() && config.extra_ca_cert_path.is_none() {
+        let mut builder = Octocrab::builder().base_uri(config.github_api_url.as_str())?;
+        if let Some(token) = token {
+            builder = builder.personal_token(token.to_string());
+        }
+        return Ok(builder.build()?);
+    }
+
+    let matcher = config.https_proxy.as_ref().map(|proxy| {
+        Matcher::builder()
+            .all(proxy.clone())
+            .no(config.no_proxy.clone().unwrap_or_default())
+            .build()
+    });
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_tls_config(tls_config(config.extra_ca_cert_path.as_deref())?)
+        .https_or_http()
+        .enable_http1()
+        .wrap_connector(ProxyConnector::new(matcher));
+    let client = Client::builder(TokioExecutor::new()).build(connector);
+
+    let mut headers = vec![(USER_AGENT, HeaderValue::from_static("repoflow"))];
+    if let Some(token) = token {
+        let mut value = HeaderValue::from_str(&format!("Bearer {}", token))?;
+        value.set_sensitive(true);
+        headers.push((AUTHORIZATION, value));
+    }
+
+    Ok(OctocrabBuilder::new_empty()
+        .with_service(client)
+        .with_layer(&BaseUriLayer::new(config.github_api_url.parse::<Uri>()?))
+        .with_layer(&ExtraHeadersLayer::new(Arc::new(headers)))
+        .with_auth(AuthState::None)
+        .build()?)
+}
+
+/// Builds the HTTP client for GitHub's OAuth endpoints. reqwest honors `HTTPS_PROXY` and
+/// `NO_PROXY` itself, so only the extra CA certificates need configuring.
+pub fn reqwest_client(config: &AppConfig) -> anyhow::Result<reqwest::Client> {
+    let mut builder = reqwest::Client::builder();
+    if let Some(path) = &config.extra_ca_cert_path {
+        for cert in reqwest::Certificate::from_pem_bundle(&std::fs::read(path)?)? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+    Ok(builder.build()?)
+}
+
+/// Reads every certificate in a PEM bundle, failing if there are none.
+fn load_ca_certs(path: &Path) -> anyhow::Result<Vec<CertificateDer<'static>>> {
+    let certs = CertificateDer::pem_file_iter(path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow::anyhow!("failed to read CA bundle {}: {}", path.display(), e))?;
+    if certs.is_empty() {
+        anyhow::bail!("CA bundle {} contains no certificates", path.display());
+    }
+    Ok(certs)
+}
+
+/// TLS settings for connections to GitHub, trusting the platform's root certificates plus any
+/// from `extra_ca`.
+fn tls_config(extra_ca: Option<&Path>) -> anyhow::Result<ClientConfig> {
+    let mut roots = RootCertStore::empty();
+    let native = rustls_native_certs::load_native_certs();
+    for error in &native.errors {
+        tracing::warn!("Failed to load a system root certificate: {}", error);
+    }
+    roots.add_parsable_certificates(native.certs);
+    if let Some(path) = extra_ca {
+        for cert in load_ca_certs(path)? {
+            roots.add(cert)?;
+        }
+    }
+    if roots.is_empty() {
+        anyhow::bail!("no usable root certificates found");
+    }
+
+    Ok(
+        ClientConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_root_certificates(roots)
+            .with_no_client_auth(),
+    )
+}
+
+/// Opens TCP connections directly or through a `CONNECT` tunnel, as the proxy settings dictate.
+#[derive(Clone)]
+struct ProxyConnector {
+    matcher: Option<Arc<Matcher>>,
+    http: HttpConnector,
+}
+
+impl ProxyConnector {
+    fn new(matcher: Option<Matcher>) -> Self {
+        let mut http = HttpConnector::new();
+        // The TLS layer above us handles `https` URIs; we only provide the TCP stream.
+        http.enforce_http(false);
+        Self {
+            matcher: matcher.map(Arc::new),
+            http,
+        }
+    }
+}
+
+impl Service<Uri> for ProxyConnector {
+    type Response = TokioIo<TcpStream>;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, dst: Uri) -> Self::Future {
+        let http = self.http.clone();
+        match self.matcher.as_ref().and_then(|m| m.intercept(&dst)) {
+            Some(intercept) => {
+                let mut tunnel = Tunnel::new(intercept.uri().clone(), http);
+                if let Some(auth) = intercept.basic_auth() {
+                    tunnel = tunnel.with_auth(auth.clone());
+                }
+                Box::pin(async move { Ok(tunnel.oneshot(dst).await?) })
+            }
+            None => Box::pin(async move { Ok(http.oneshot(dst).await?) }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_load_ca_certs_rejects_empty_bundle() {
+        let path = std::env::temp_dir().join(format!("repoflow-ca-{}.pem", std::process::id()));
+        std::fs::write(&path, "not a certificate\n").unwrap();
+        let err = load_ca_certs(&path).unwrap_err();
+        assert!(err.to_string().contains("contains no certificates"));
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(load_ca_certs(Path::new("/nonexistent/ca.pem")).is_err());
+    }
+}
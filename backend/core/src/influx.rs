@@ -0,0 +1,174 @@
+//! Writes the flow of refreshed repositories to InfluxDB as line protocol, for teams whose
+//! time series live in Influx or Telegraf.
+//!
+//! Each displayed day becomes one point timestamped at its midnight (UTC), so rewriting a day on
+//! the next refresh replaces the point rather than adding another.
+
+use crate::config::{AppConfig, Secret};
+use crate::domain::RepoId;
+use crate::http_client;
+use crate::metrics::RepoMetricsResponse;
+use anyhow::Context;
+use chrono::NaiveDate;
+use std::fmt::Write;
+use std::time::Duration as StdDuration;
+
+pub struct InfluxSink {
+    http: reqwest::Client,
+    url: String,
+    token: Option<Secret>,
+    measurement: String,
+    timeout: StdDuration,
+}
+
+impl InfluxSink {
+    /// Creates a sink, or returns `None` when `INFLUX_WRITE_URL` is unset.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Option<Self>> {
+        let Some(url) = &config.influx_write_url else {
+            return Ok(None);
+        };
+        Ok(Some(Self {
+            http: http_client::reqwest_client(config)?,
+            url: url.clone(),
+            token: config.influx_token.clone(),
+            measurement: config.influx_measurement.clone(),
+            timeout: StdDuration::from_secs(config.request_timeout_seconds),
+        }))
+    }
+
+    /// Writes every day of `metrics`, computed over `window_days`, for `repo_id`.
+    pub async fn write(
+        &self,
+        repo_id: &RepoId,
+        metrics: &RepoMetricsResponse,
+        window_days: i64,
+    ) -> anyhow::Result<()> {
+        let body = self.lines(repo_id, metrics, window_days);
+        let mut request = self.http.post(&self.url).timeout(self.timeout).body(body);
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Token {}", token.expose()));
+        }
+        request
+            .send()
+            .await
+            .context("the InfluxDB write failed")?
+            .error_for_status()
+            .context("InfluxDB rejected the write")?;
+        Ok(())
+    }
+
+    fn lines(&self, repo_id: &RepoId, metrics: &RepoMetricsResponse, window_days: i64) -> String {
+        let mut lines = String::new();
+        for point in &metrics.time_series {
+            let Ok(date) = NaiveDate::parse_from_str(&point.date, "%Y-%m-%d") else {
+                continue;
+            };
+            let timestamp = date.and_hms_opt(0, 0, 0).expect("midnight is valid");
+            let _ = write!(
+                lines,
+                "{},repo={},window_days={} opened={}i,merged={}i,closed={}i,spread={}i,\
+                 open_count={}i,fast_merged={}i,backlog_merged={}i,issues_closed={}i",
+                escape(&self.measurement),
+                escape(&repo_id.to_string()),
+                window_days,
+                point.opened,
+                point.merged,
+                point.closed,
+                point.spread,
+                point.open_count,
+                point.fast_merged,
+                point.backlog_merged,
+                point.issues_closed,
+            );
+            if let Some(comments) = point.review_comments {
+                let _ = write!(lines, ",review_comments={}i", comments);
+            }
+            // Nanoseconds, the default precision of both the v1 and v2 write APIs.
+            let _ = writeln!(
+                lines,
+                " {}",
+                timestamp.and_utc().timestamp() * 1_000_000_000
+            );
+        }
+        lines
+    }
+}
+
+/// Escapes the characters line protocol gives meaning to in measurements and tag values.
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::FlowMetricsResponse;
+    use crate::test_support::test_config;
+    use wiremock::matchers::{header, method, path, query_param};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn metrics() -> RepoMetricsResponse {
+        let point = |date: &str, opened| FlowMetricsResponse {
+            date: date.to_string(),
+            opened,
+            merged: 2,
+            spread: opened as i64 - 2,
+            ..Default::default()
+        };
+        let mut metrics = crate::metrics::calculate_metrics(
+            &[],
+            chrono::Duration::days(1),
+            chrono::Duration::days(30),
+            chrono::Utc::now(),
+        );
+        metrics.time_series = vec![point("2026-03-01", 5), point("2026-03-02", 6)];
+        metrics
+    }
+
+    #[test]
+    fn test_days_become_points() {
+        let config = test_config(&[
+            ("INFLUX_WRITE_URL", "http://localhost:8086/write?db=flow"),
+            ("INFLUX_MEASUREMENT", "pr flow"),
+        ]);
+        let sink = InfluxSink::new(&config).unwrap().unwrap();
+        let body = sink.lines(&"acme/widgets".parse().unwrap(), &metrics(), 30);
+        let lines: Vec<&str> = body.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].starts_with(
+            "pr\\ flow,repo=acme/widgets,window_days=30 opened=5i,merged=2i,closed=0i,spread=3i,"
+        ));
+        assert!(lines[1].ends_with(" 1772409600000000000"));
+        assert!(!lines[0].contains("review_comments"));
+    }
+
+    #[tokio::test]
+    async fn test_writes_with_the_token() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v2/write"))
+            .and(query_param("bucket", "flow"))
+            .and(header("authorization", "Token influx-secret"))
+            .respond_with(ResponseTemplate::new(204))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let url = format!("{}/api/v2/write?org=acme&bucket=flow", server.uri());
+        let config = test_config(&[
+            ("INFLUX_WRITE_URL", url.as_str()),
+            ("INFLUX_TOKEN", "influx-secret"),
+        ]);
+        let sink = InfluxSink::new(&config).unwrap().unwrap();
+        sink.write(&"acme/widgets".parse().unwrap(), &metrics(), 30)
+            .await
+            .unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let body = String::from_utf8(requests[0].body.clone()).unwrap();
+        assert!(body.starts_with("repoflow_flow,repo=acme/widgets,window_days=30 opened=5i"));
+    }
+}
@@ -0,0 +1,116 @@
+//! Plain-language commentary on how a repository's flow changed, for the dashboard and digests.
+//!
+//! Each rule compares the latest rolling window with the one before it and speaks up only when the
+//! change is large enough to matter, so small repositories don't get a sentence per pull request.
+
+use crate::metrics::FlowMetricsResponse;
+
+/// Relative changes smaller than this, in percent, aren't worth a sentence.
+const MIN_CHANGE_PERCENT: f64 = 10.0;
+
+/// Windows with fewer opened pull requests than this are too small to compare rates on.
+const MIN_OPENED: usize = 5;
+
+/// Changes in the number of open pull requests, or in the spread, smaller than this aren't worth
+/// a sentence.
+const MIN_COUNT_CHANGE: i64 = 5;
+
+/// Sentences describing how `current` differs from `prior`, the `window_days` before it.
+pub fn generate(
+    current: &FlowMetricsResponse,
+    prior: &FlowMetricsResponse,
+    window_days: i64,
+) -> Vec<String> {
+    let period = format!("vs the prior {} days", window_days);
+    let mut insights = Vec::new();
+
+    if let Some(change) = percent_change(current.opened, prior.opened) {
+        insights.push(format!(
+            "{} PRs opened {} {}",
+            if change > 0.0 { "More" } else { "Fewer" },
+            describe(change),
+            period
+        ));
+    }
+
+    let rate = |m: &FlowMetricsResponse| m.merged as f64 / m.opened as f64;
+    if current.opened >= MIN_OPENED && prior.opened >= MIN_OPENED && prior.merged > 0 {
+        let change = (rate(current) - rate(prior)) / rate(prior) * 100.0;
+        if change.abs() >= MIN_CHANGE_PERCENT {
+            insights.push(format!(
+                "Merge rate {} {:.0}% {}",
+                if change > 0.0 { "rose" } else { "dropped" },
+                change.abs(),
+                period
+            ));
+        }
+    }
+
+    let backlog_change = current.open_count as i64 - prior.open_count as i64;
+    if backlog_change.abs() >= MIN_COUNT_CHANGE {
+        insights.push(format!(
+            "Backlog {} by {} PRs",
+            if backlog_change > 0 { "grew" } else { "shrank" },
+            backlog_change.abs()
+        ));
+    }
+
+    if current.spread > 0 && current.spread - prior.spread >= MIN_COUNT_CHANGE {
+        insights.push(format!(
+            "{} more PRs opened than merged, up from {}",
+            current.spread, prior.spread
+        ));
+    }
+
+    insights
+}
+
+/// The relative change from `prior` to `current` in percent, when both are large enough to
+/// compare and the change is worth mentioning.
+fn percent_change(current: usize, prior: usize) -> Option<f64> {
+    if current < MIN_OPENED || prior < MIN_OPENED {
+        return None;
+    }
+    let change = (current as f64 - prior as f64) / prior as f64 * 100.0;
+    (change.abs() >= MIN_CHANGE_PERCENT).then_some(change)
+}
+
+fn describe(change: f64) -> String {
+    format!("({}{:.0}%)", if change > 0.0 { "+" } else { "" }, change)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(opened: usize, merged: usize, open_count: usize) -> FlowMetricsResponse {
+        FlowMetricsResponse {
+            opened,
+            merged,
+            spread: opened as i64 - merged as i64,
+            open_count,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_large_changes_are_described() {
+        let insights = generate(&window(50, 20, 80), &window(40, 20, 38), 30);
+        assert_eq!(
+            insights,
+            [
+                "More PRs opened (+25%) vs the prior 30 days",
+                "Merge rate dropped 20% vs the prior 30 days",
+                "Backlog grew by 42 PRs",
+                "30 more PRs opened than merged, up from 20",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_small_changes_are_quiet() {
+        assert!(generate(&window(41, 20, 40), &window(40, 20, 38), 30).is_empty());
+        // Too few pull requests for rates to mean much.
+        assert!(generate(&window(4, 0, 0), &window(2, 2, 0), 7).is_empty());
+    }
+}
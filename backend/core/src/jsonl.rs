@@ -0,0 +1,157 @@
+//! Append-only files of JSON lines, such as the alert history and the audit log.
+//!
+//! A file that would grow past its size limit is renamed to `<path>.1`, replacing the one before,
+//! and a new file is started, so at most twice the limit is kept on disk.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::io::AsyncWriteExt;
+use tokio::sync::Mutex;
+
+/// How much of a file is read at a time when looking for its last lines.
+const TAIL_CHUNK: u64 = 64 * 1024;
+
+pub struct JsonLines {
+    path: PathBuf,
+    /// Size past which the file is rotated; 0 means never.
+    max_bytes: u64,
+    /// The file's size once known. Held while appending, so concurrent lines never interleave.
+    size: Mutex<Option<u64>>,
+}
+
+impl JsonLines {
+    pub fn new(path: PathBuf, max_bytes: u64) -> Self {
+        Self {
+            path,
+            max_bytes,
+            size: Mutex::new(None),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Where the file is moved when it's rotated.
+    pub fn rotated_path(&self) -> PathBuf {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        rotated.into()
+    }
+
+    /// Appends `value` as one line, rotating the file first if the line would take it past the
+    /// size limit.
+    pub async fn append<T: Serialize>(&self, value: &T) -> anyhow::Result<()> {
+        let mut line = serde_json::to_vec(value)?;
+        line.push(b'\n');
+
+        let mut size = self.size.lock().await;
+        let mut current = match *size {
+            Some(current) => current,
+            None => match tokio::fs::metadata(&self.path).await {
+                Ok(metadata) => metadata.len(),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => 0,
+                Err(e) => return Err(e.into()),
+            },
+        };
+        // Forget the size until the write succeeds, so a failed one is measured again.
+        *size = None;
+        if self.max_bytes > 0 && current > 0 && current + line.len() as u64 > self.max_bytes {
+            tokio::fs::rename(&self.path, self.rotated_path()).await?;
+            current = 0;
+        }
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(&line).await?;
+        // Tokio finishes writes in the background; wait for this one before counting it.
+        file.flush().await?;
+        *size = Some(current + line.len() as u64);
+        Ok(())
+    }
+
+    /// The last `count` values in the file, oldest first, reading only as much of its end as they
+    /// take. Lines that don't parse are skipped. Meant for startup, so it blocks.
+    pub fn read_tail<T: DeserializeOwned>(&self, count: usize) -> std::io::Result<Vec<T>> {
+        let mut file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e),
+        };
+        let mut start = file.metadata()?.len();
+        let mut tail = Vec::new();
+        let mut newlines = 0;
+        // One more newline than lines wanted means the first line wanted is whole.
+        while start > 0 && newlines <= count {
+            let chunk_start = start.saturating_sub(TAIL_CHUNK);
+            let mut chunk = vec![0; (start - chunk_start) as usize];
+            file.seek(SeekFrom::Start(chunk_start))?;
+            file.read_exact(&mut chunk)?;
+            newlines += chunk.iter().filter(|&&b| b == b'\n').count();
+            chunk.extend_from_slice(&tail);
+            tail = chunk;
+            start = chunk_start;
+        }
+
+        let mut lines: Vec<&[u8]> = tail.split(|&b| b == b'\n').collect();
+        if start > 0 {
+            // Cut off by where reading began.
+            lines.remove(0);
+        }
+        let mut values: Vec<T> = lines
+            .into_iter()
+            .filter(|line| !line.is_empty())
+            .filter_map(|line| serde_json::from_slice(line).ok())
+            .collect();
+        let excess = values.len().saturating_sub(count);
+        values.drain(..excess);
+        Ok(values)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_rotates_and_reads_the_tail() {
+        let path =
+            std::env::temp_dir().join(format!("repoflow-jsonl-{}.jsonl", rand::random::<u64>()));
+        // Each line is "NNNN\n", so two fit under the limit.
+        let lines = JsonLines::new(path.clone(), 12);
+        for n in 1000..1005 {
+            lines.append(&n).await.unwrap();
+        }
+
+        assert_eq!(lines.read_tail::<u32>(10).unwrap(), [1004]);
+        let rotated = JsonLines::new(lines.rotated_path(), 0);
+        assert_eq!(rotated.read_tail::<u32>(10).unwrap(), [1002, 1003]);
+        assert_eq!(rotated.read_tail::<u32>(1).unwrap(), [1003]);
+
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(lines.rotated_path());
+    }
+
+    #[test]
+    fn test_tail_of_a_long_file() {
+        let path =
+            std::env::temp_dir().join(format!("repoflow-jsonl-{}.jsonl", rand::random::<u64>()));
+        let contents: String = (0..50_000).map(|n| format!("{}\n", n)).collect();
+        std::fs::write(&path, format!("not json\n{}", contents)).unwrap();
+        let lines = JsonLines::new(path.clone(), 0);
+
+        let tail: Vec<u32> = lines.read_tail(3).unwrap();
+        assert_eq!(tail, [49_997, 49_998, 49_999]);
+        assert_eq!(lines.read_tail::<u32>(100_000).unwrap().len(), 50_000);
+        assert!(JsonLines::new(path.with_extension("missing"), 0)
+            .read_tail::<u32>(3)
+            .unwrap()
+            .is_empty());
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,45 @@
+//! The RepoFlow flow-metrics engine: fetching pull requests from GitHub, caching them and
+//! calculating rolling-window metrics, independent of any HTTP server.
+//!
+//! [`service::MetricsService`] is the entry point. It reads through a cache to a
+//! [`source::PullRequestSource`] (GitHub, or recorded fixtures in replay mode) and keeps popular
+//! repositories warm in the background, so it must be created inside a Tokio runtime.
+//!
+//! ```no_run
+//! use repoflow_core::config::AppConfig;
+//! use repoflow_core::domain::RepoId;
+//! use repoflow_core::service::MetricsService;
+//!
+//! # async fn run() -> anyhow::Result<()> {
+//! let config = AppConfig::from_env()?;
+//! let service = MetricsService::new(&config)?;
+//! let repo_id: RepoId = "rust-lang/rust".parse()?;
+//! let metrics = service.get(repo_id).await?;
+//! println!("{:?}", metrics.default_window().metrics.summary);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The calculations alone are in [`metrics`] and need neither a runtime nor GitHub.
+
+pub mod alerts;
+pub mod business_days;
+pub mod config;
+pub mod demo;
+pub mod domain;
+pub mod http_client;
+pub mod influx;
+pub mod insights;
+pub mod jsonl;
+pub mod metrics;
+pub mod popular;
+pub mod privacy;
+pub mod replay;
+pub mod service;
+pub mod snapshot;
+pub mod source;
+pub mod statsd;
+pub mod upstream;
+
+#[cfg(any(test, feature = "test-support"))]
+pub mod test_support;
@@ -0,0 +1,1617 @@
+use crate::business_days::BusinessDays;
+use crate::domain::GitHubPR;
+use chrono::{DateTime, Datelike, Duration, NaiveTime, TimeZone, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+const END_OF_DAY_HOUR: u32 = 23;
+const END_OF_DAY_MIN: u32 = 59;
+const END_OF_DAY_SEC: u32 = 59;
+
+/// How far GitHub's clock may run from ours before a pull request's timestamps count as bad data.
+const MAX_CLOCK_SKEW_MINUTES: i64 = 10;
+
+/// Merges per block of `Timeline::merged_opened_blocks`.
+const MERGE_BLOCK: usize = 512;
+
+/// The root response structure for repository metrics.
+#[derive(Debug, Serialize, Clone)]
+pub struct RepoMetricsResponse {
+    /// The calculated summary statistics for the latest period.
+    pub summary: SummaryMetrics,
+    /// The day-by-day time series data.
+    pub time_series: Vec<FlowMetricsResponse>,
+    /// Notable changes from the previous rolling window, in plain language.
+    pub insights: Vec<String>,
+    /// Opened and merged series of the pull requests carrying each configured label.
+    pub label_series: Vec<LabelSeries>,
+    /// The pull requests that have been open longest, oldest first.
+    pub longest_open: Vec<OpenPullRequest>,
+    /// The time series rescaled onto a comparable scale, present only when requested.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub normalized: Option<Vec<NormalizedFlowMetrics>>,
+}
+
+/// Calculated summary statistics for the latest data point.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct SummaryMetrics {
+    /// Number of PRs opened in the current rolling window.
+    pub current_opened: usize,
+    /// Number of PRs merged in the current rolling window.
+    pub current_merged: usize,
+    /// Number of PRs both opened and merged in the current rolling window.
+    pub current_fast_merged: usize,
+    /// The current difference between opened and merged PRs.
+    pub current_spread: i64,
+    /// The percentage of opened PRs that were merged.
+    pub merge_rate: u32,
+    /// The percentage of PRs merged in the current rolling window that close an issue.
+    pub issue_link_rate: u32,
+    /// Number of issues closed by PRs merged in the current rolling window.
+    pub current_issues_closed: usize,
+    /// How the PRs merged in the current rolling window were approved, when approvals are
+    /// recorded.
+    pub approval_shares: Option<ApprovalShares>,
+    /// Whether the spread is widening compared to the previous period.
+    pub is_widening: bool,
+}
+
+/// The percentage of merged PRs with each number of approvals.
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct ApprovalShares {
+    pub none: u32,
+    pub one: u32,
+    pub two_or_more: u32,
+}
+
+/// The flow of the pull requests carrying one label.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct LabelSeries {
+    pub label: String,
+    pub time_series: Vec<LabelFlowMetrics>,
+}
+
+/// A single data point of a label's series, counted like `FlowMetricsResponse`.
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct LabelFlowMetrics {
+    pub date: String,
+    pub opened: usize,
+    pub merged: usize,
+    pub spread: i64,
+}
+
+/// Pull request activity by weekday and hour of day (UTC), over the whole fetch window.
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct Heatmap {
+    /// One cell per weekday and hour, Monday 00:00 first.
+    pub cells: Vec<HeatmapCell>,
+}
+
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct HeatmapCell {
+    /// Days since Monday, so 0 is Monday and 6 is Sunday.
+    pub weekday: u32,
+    pub hour: u32,
+    pub opened: usize,
+    pub merged: usize,
+}
+
+/// A pull request that is still open.
+#[derive(Debug, Serialize, Clone, PartialEq, Eq)]
+pub struct OpenPullRequest {
+    pub number: u64,
+    pub title: String,
+    /// Login of the account that opened it, as `AUTHOR_PRIVACY` shows it, unless the account has
+    /// been deleted or its author opted out.
+    pub author: Option<String>,
+    pub opened_at: DateTime<Utc>,
+    /// Whole days it has been open.
+    pub age_days: i64,
+}
+
+/// A single data point in the flow metrics time series.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct FlowMetricsResponse {
+    /// The date for which the metrics were calculated (YYYY-MM-DD).
+    pub date: String,
+    /// Number of PRs opened within the rolling window.
+    pub opened: usize,
+    /// Number of PRs merged within the rolling window.
+    pub merged: usize,
+    /// Number of PRs closed without being merged within the rolling window.
+    pub closed: usize,
+    /// The difference between opened and merged PRs.
+    pub spread: i64,
+    /// Number of PRs open at the end of the day (work in progress).
+    pub open_count: usize,
+    /// Number of the merged PRs that were also opened within the rolling window.
+    pub fast_merged: usize,
+    /// Number of the merged PRs that were opened before the rolling window, i.e. backlog cleanup.
+    pub backlog_merged: usize,
+    /// Number of the merged PRs whose description closes at least one issue.
+    pub merged_closing_issues: usize,
+    /// Number of issues closed by merges within the rolling window.
+    pub issues_closed: usize,
+    /// Number of the merged PRs merged without an approval. Merges whose approvals weren't
+    /// recorded are left out of this and the next two counts.
+    pub merged_unapproved: usize,
+    /// Number of the merged PRs merged with exactly one approval.
+    pub merged_one_approval: usize,
+    /// Number of the merged PRs merged with two or more approvals.
+    pub merged_two_plus_approvals: usize,
+    /// Number of review comments posted on this day alone, when review comments were fetched.
+    pub review_comments: Option<usize>,
+    /// Median hours the merged PRs spent in the merge queue, when merge queue events were
+    /// fetched.
+    pub median_queue_hours: Option<f64>,
+    /// Median hours from opening to merge of the same PRs, less their time in the merge queue.
+    pub median_review_hours: Option<f64>,
+    /// Whether the date is a configured holiday, which can explain a dip.
+    pub is_holiday: bool,
+}
+
+/// Strategies for rescaling a time series so that repositories of different sizes can be compared.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Normalization {
+    /// Each value as a percentage change relative to the first data point.
+    PercentChange,
+    /// Each value as the number of standard deviations from the series mean.
+    Zscore,
+}
+
+/// A single data point of a normalized time series.
+#[derive(Debug, Serialize, Clone, Default, PartialEq)]
+pub struct NormalizedFlowMetrics {
+    /// The date of the underlying data point (YYYY-MM-DD).
+    pub date: String,
+    /// The normalized opened count.
+    pub opened: f64,
+    /// The normalized merged count.
+    pub merged: f64,
+    /// The normalized spread.
+    pub spread: f64,
+}
+
+/// How many of a repository's branches have gone without commits.
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct BranchStats {
+    /// Number of branches in the repository.
+    pub total: u64,
+    /// Number of branches whose latest commit is at least `stale_after_days` old.
+    pub stale: u64,
+    /// Days without a commit after which a branch counts as stale.
+    pub stale_after_days: i64,
+    /// Whether every branch was inspected. When false, `stale` covers only the branches fetched
+    /// before the page limit was reached.
+    pub complete: bool,
+}
+
+/// Optional pull request data that was configured to be fetched but is missing.
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum MissingField {
+    Approvals,
+    MergeQueue,
+    ReviewComments,
+}
+
+/// How much of a repository's data was fetched, for judging how far to trust its metrics.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Completeness {
+    /// From 0 to 100: mostly the share of the pull requests in the fetch window that were
+    /// fetched, less a little for each missing field.
+    pub score: u8,
+    /// Number of pull requests fetched.
+    pub fetched_pull_requests: u64,
+    /// Estimated pull requests in the fetch window, when the fetch was truncated and the
+    /// provider reported enough to tell. It's an upper bound, so the score may be too low.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimated_pull_requests: Option<u64>,
+    /// Whether the page limit cut the fetch short.
+    pub truncated: bool,
+    pub missing_fields: Vec<MissingField>,
+    /// Number of fetched pull requests left out of the metrics because their timestamps can't be
+    /// right, such as ones opened in the future.
+    pub dropped_out_of_range: usize,
+}
+
+/// The events in a set of pull requests as sorted timestamps, so the number of events in any time
+/// range is two binary searches rather than a scan over every pull request.
+#[derive(Debug, Clone, Default)]
+pub struct Timeline {
+    opened: Vec<DateTime<Utc>>,
+    merged: Vec<DateTime<Utc>>,
+    /// When each merged pull request was opened, in the same order as `merged`.
+    merged_opened: Vec<DateTime<Utc>>,
+    /// `merged_opened` in blocks of `MERGE_BLOCK`, each sorted, so the fast merges among whole
+    /// blocks are counted by a binary search per block.
+    merged_opened_blocks: Vec<Vec<DateTime<Utc>>>,
+    /// Closures without a merge.
+    closed: Vec<DateTime<Utc>>,
+    /// When each pull request stopped being open, by merge or closure.
+    ended: Vec<DateTime<Utc>>,
+    /// Merges of pull requests that close at least one issue.
+    merged_closing_issues: Vec<DateTime<Utc>>,
+    /// When each referenced issue was closed: by the first merge that closes it.
+    issues_closed: Vec<DateTime<Utc>>,
+    /// Merges with recorded approvals, by zero, one, and two or more approvals.
+    merged_by_approvals: [Vec<DateTime<Utc>>; 3],
+    /// When each review comment was posted, if review comments were fetched.
+    review_comments: Option<Vec<DateTime<Utc>>>,
+    /// Merges with fetched merge queue events, sorted by merge time, with the seconds spent in
+    /// the queue and outside it.
+    queued_merges: Vec<(DateTime<Utc>, i64, i64)>,
+    /// The timelines of the pull requests carrying each label series' label.
+    labels: Vec<(String, Timeline)>,
+    /// The weekends and holidays the series is annotated with.
+    calendar: Option<Arc<BusinessDays>>,
+    /// Whether windows count the calendar's business days rather than every day.
+    business_days: bool,
+}
+
+/// A kind of event recorded in a `Timeline`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Event {
+    Opened,
+    Merged,
+    /// Closed without being merged.
+    Closed,
+    /// Merged, closing at least one issue.
+    MergedClosingIssue,
+    /// An issue closed by a merge.
+    IssueClosed,
+    /// Merged with this many recorded approvals, where 2 stands for two or more.
+    MergedWithApprovals(u32),
+    /// A review comment posted. Nothing is recorded unless review comments were fetched.
+    ReviewComment,
+}
+
+impl Timeline {
+    pub fn new(prs: &[GitHubPR]) -> Self {
+        let mut merges: Vec<_> = prs
+            .iter()
+            .filter_map(|pr| Some((pr.merged_at?, pr.created_at)))
+            .collect();
+        merges.sort_unstable();
+        let mut issues_closed = HashMap::new();
+        for pr in prs {
+            let Some(merged_at) = pr.merged_at else {
+                continue;
+            };
+            for issue in &pr.closes_issues {
+                issues_closed
+                    .entry(*issue)
+                    .and_modify(|at: &mut DateTime<Utc>| *at = (*at).min(merged_at))
+                    .or_insert(merged_at);
+            }
+        }
+        let mut timeline = Timeline {
+            opened: prs.iter().map(|pr| pr.created_at).collect(),
+            merged: merges.iter().map(|(merged, _)| *merged).collect(),
+            merged_opened: merges.into_iter().map(|(_, opened)| opened).collect(),
+            merged_opened_blocks: Vec::new(),
+            closed: prs
+                .iter()
+                .filter(|pr| pr.merged_at.is_none())
+                .filter_map(|pr| pr.closed_at)
+                .collect(),
+            // An end recorded before the start still means the pull request was never open.
+            ended: prs
+                .iter()
+                .filter_map(|pr| Some(pr.merged_at.or(pr.closed_at)?.max(pr.created_at)))
+                .collect(),
+            merged_closing_issues: prs
+                .iter()
+                .filter(|pr| !pr.closes_issues.is_empty())
+                .filter_map(|pr| pr.merged_at)
+                .collect(),
+            issues_closed: issues_closed.into_values().collect(),
+            merged_by_approvals: Default::default(),
+            review_comments: None,
+            labels: Vec::new(),
+            calendar: None,
+            business_days: false,
+            queued_merges: prs
+                .iter()
+                .filter_map(|pr| {
+                    let merged_at = pr.merged_at?;
+                    let queued = pr.time_in_merge_queue()?.num_seconds();
+                    let open = (merged_at - pr.created_at).num_seconds().max(0);
+                    Some((merged_at, queued, (open - queued).max(0)))
+                })
+                .collect(),
+        };
+        timeline.queued_merges.sort_unstable();
+        timeline.merged_opened_blocks = timeline
+            .merged_opened
+            .chunks(MERGE_BLOCK)
+            .map(|block| {
+                let mut block = block.to_vec();
+                block.sort_unstable();
+                block
+            })
+            .collect();
+        for pr in prs {
+            if let (Some(merged_at), Some(approvals)) = (pr.merged_at, pr.approvals) {
+                timeline.merged_by_approvals[approvals.min(2) as usize].push(merged_at);
+            }
+        }
+        for times in [
+            &mut timeline.opened,
+            &mut timeline.closed,
+            &mut timeline.ended,
+            &mut timeline.merged_closing_issues,
+            &mut timeline.issues_closed,
+        ]
+        .into_iter()
+        .chain(&mut timeline.merged_by_approvals)
+        {
+            times.sort_unstable();
+        }
+        timeline
+    }
+
+    fn times(&self, event: Event) -> &[DateTime<Utc>] {
+        match event {
+            Event::Opened => &self.opened,
+            Event::Merged => &self.merged,
+            Event::Closed => &self.closed,
+            Event::MergedClosingIssue => &self.merged_closing_issues,
+            Event::IssueClosed => &self.issues_closed,
+            Event::MergedWithApprovals(approvals) => {
+                &self.merged_by_approvals[approvals.min(2) as usize]
+            }
+            Event::ReviewComment => self.review_comments.as_deref().unwrap_or_default(),
+        }
+    }
+
+    /// Median hours in and out of the merge queue of pull requests merged between `start` and
+    /// `end`, inclusive, or `None` if no merge in the range has merge queue events.
+    pub fn median_queue_and_review_hours(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Option<(f64, f64)> {
+        let first = self.queued_merges.partition_point(|(at, _, _)| *at < start);
+        let last = self.queued_merges.partition_point(|(at, _, _)| *at <= end);
+        let merges = &self.queued_merges[first..last];
+        if merges.is_empty() {
+            return None;
+        }
+        let median_hours = |mut seconds: Vec<i64>| {
+            seconds.sort_unstable();
+            let mid = seconds.len() / 2;
+            let median = if seconds.len().is_multiple_of(2) {
+                (seconds[mid - 1] + seconds[mid]) as f64 / 2.0
+            } else {
+                seconds[mid] as f64
+            };
+            (median / 360.0).round() / 10.0
+        };
+        Some((
+            median_hours(merges.iter().map(|(_, queued, _)| *queued).collect()),
+            median_hours(merges.iter().map(|(_, _, review)| *review).collect()),
+        ))
+    }
+
+    /// Adds a timeline for each of `labels`, of the pull requests among `prs` carrying it.
+    pub fn with_labels(mut self, prs: &[GitHubPR], labels: &[String]) -> Self {
+        self.labels = labels
+            .iter()
+            .map(|label| {
+                let labeled: Vec<GitHubPR> = prs
+                    .iter()
+                    .filter(|pr| pr.labels.iter().any(|l| l.eq_ignore_ascii_case(label)))
+                    .cloned()
+                    .collect();
+                (label.clone(), Timeline::new(&labeled))
+            })
+            .collect();
+        self
+    }
+
+    /// Adds the times review comments were posted, if they were fetched.
+    pub fn with_review_comments(mut self, times: Option<Vec<DateTime<Utc>>>) -> Self {
+        self.review_comments = times.map(|mut times| {
+            times.sort_unstable();
+            times
+        });
+        self
+    }
+
+    /// Flags the holidays of `calendar`, and counts windows in its business days instead of
+    /// every day if `business_days` is set.
+    pub fn with_calendar(mut self, calendar: Arc<BusinessDays>, business_days: bool) -> Self {
+        self.calendar = Some(calendar);
+        self.business_days = business_days;
+        self
+    }
+
+    /// Where the window of `window_size` ending at `end` starts.
+    pub fn window_start(&self, end: DateTime<Utc>, window_size: Duration) -> DateTime<Utc> {
+        match &self.calendar {
+            Some(calendar) if self.business_days => {
+                calendar.window_start(end, window_size.num_days())
+            }
+            _ => end - window_size,
+        }
+    }
+
+    fn is_holiday(&self, at: DateTime<Utc>) -> bool {
+        self.calendar
+            .as_ref()
+            .is_some_and(|calendar| calendar.is_holiday(at.date_naive()))
+    }
+
+    /// Number of events at or before `at`.
+    pub fn count_until(&self, event: Event, at: DateTime<Utc>) -> usize {
+        self.times(event).partition_point(|t| *t <= at)
+    }
+
+    /// Number of events between `start` and `end`, inclusive.
+    pub fn count_between(&self, event: Event, start: DateTime<Utc>, end: DateTime<Utc>) -> usize {
+        let times = self.times(event);
+        let before_start = times.partition_point(|t| *t < start);
+        times
+            .partition_point(|t| *t <= end)
+            .saturating_sub(before_start)
+    }
+
+    /// Number of pull requests merged between `start` and `end`, inclusive, that were opened no
+    /// earlier than `start`. The rest of the range's merges are backlog merges.
+    ///
+    /// Whole blocks of merges in the range take a binary search each, so only the merges in the
+    /// partial blocks at either end are scanned.
+    pub fn fast_merged_between(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> usize {
+        let first = self.merged.partition_point(|t| *t < start);
+        let last = self.merged.partition_point(|t| *t <= end);
+        let mut fast = 0;
+        let mut i = first;
+        while i < last {
+            let block_end = (i / MERGE_BLOCK + 1) * MERGE_BLOCK;
+            if i % MERGE_BLOCK == 0 && block_end <= last {
+                let block = &self.merged_opened_blocks[i / MERGE_BLOCK];
+                fast += MERGE_BLOCK - block.partition_point(|opened| *opened < start);
+                i = block_end;
+            } else {
+                let until = block_end.min(last);
+                fast += self.merged_opened[i..until]
+                    .iter()
+                    .filter(|opened| **opened >= start)
+                    .count();
+                i = until;
+            }
+        }
+        fast
+    }
+
+    /// Number of pull requests opened but not yet merged or closed at `at`.
+    ///
+    /// Only PRs within the fetch horizon are known, so long-lived PRs opened before it are not
+    /// counted.
+    pub fn open_at(&self, at: DateTime<Utc>) -> usize {
+        self.count_until(Event::Opened, at) - self.ended.partition_point(|t| *t <= at)
+    }
+}
+
+/// Calculates rolling window metrics from a list of Pull Requests.
+///
+/// # Arguments
+/// * `prs` - The list of PRs to analyze.
+/// * `days_to_display` - How many days of history to include in the response.
+/// * `window_size` - The size of the rolling window (e.g., 30 days).
+/// * `now` - The current time used as the reference point for calculations.
+pub fn calculate_metrics(
+    prs: &[GitHubPR],
+    days_to_display: Duration,
+    window_size: Duration,
+    now: DateTime<Utc>,
+) -> RepoMetricsResponse {
+    calculate_timeline_metrics(&Timeline::new(prs), days_to_display, window_size, now)
+}
+
+/// Calculates rolling window metrics like `calculate_metrics`, from an already built timeline so
+/// several window sizes can share it.
+pub fn calculate_timeline_metrics(
+    timeline: &Timeline,
+    days_to_display: Duration,
+    window_size: Duration,
+    now: DateTime<Utc>,
+) -> RepoMetricsResponse {
+    let target_dates: Vec<DateTime<Utc>> = (0..=days_to_display.num_days())
+        .rev()
+        .map(|i| {
+            let date = now - Duration::days(i);
+            // We set the time to the end of the day to ensure we capture all activity for that date.
+            Utc.with_ymd_and_hms(
+                date.year(),
+                date.month(),
+                date.day(),
+                END_OF_DAY_HOUR,
+                END_OF_DAY_MIN,
+                END_OF_DAY_SEC,
+            )
+            .unwrap()
+        })
+        .collect();
+    let time_series: Vec<FlowMetricsResponse> = target_dates
+        .iter()
+        .map(|target_date| calculate_day_metrics(timeline, *target_date, window_size))
+        .collect();
+    let label_series = timeline
+        .labels
+        .iter()
+        .map(|(label, labeled)| LabelSeries {
+            label: label.clone(),
+            time_series: target_dates
+                .iter()
+                .map(|target_date| {
+                    let window_start = timeline.window_start(*target_date, window_size);
+                    let opened = labeled.count_between(Event::Opened, window_start, *target_date);
+                    let merged = labeled.count_between(Event::Merged, window_start, *target_date);
+                    LabelFlowMetrics {
+                        date: target_date.format("%Y-%m-%d").to_string(),
+                        opened,
+                        merged,
+                        spread: opened as i64 - merged as i64,
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    let summary = calculate_summary(&time_series);
+
+    RepoMetricsResponse {
+        summary,
+        time_series,
+        insights: Vec::new(),
+        label_series,
+        longest_open: Vec::new(),
+        normalized: None,
+    }
+}
+
+/// Combines metrics of several repositories, calculated with the same window, as though their
+/// pull requests were in one repository. Medians, insights and the longest open pull requests
+/// don't add up across repositories, so they're left out.
+pub fn aggregate(members: &[&RepoMetricsResponse]) -> RepoMetricsResponse {
+    let mut days: BTreeMap<&str, FlowMetricsResponse> = BTreeMap::new();
+    let mut labels: Vec<(&str, BTreeMap<&str, LabelFlowMetrics>)> = Vec::new();
+    for metrics in members {
+        for point in &metrics.time_series {
+            let day = days
+                .entry(&point.date)
+                .or_insert_with(|| FlowMetricsResponse {
+                    date: point.date.clone(),
+                    ..Default::default()
+                });
+            day.opened += point.opened;
+            day.merged += point.merged;
+            day.closed += point.closed;
+            day.spread += point.spread;
+            day.open_count += point.open_count;
+            day.fast_merged += point.fast_merged;
+            day.backlog_merged += point.backlog_merged;
+            day.merged_closing_issues += point.merged_closing_issues;
+            day.issues_closed += point.issues_closed;
+            day.merged_unapproved += point.merged_unapproved;
+            day.merged_one_approval += point.merged_one_approval;
+            day.merged_two_plus_approvals += point.merged_two_plus_approvals;
+            if let Some(comments) = point.review_comments {
+                *day.review_comments.get_or_insert(0) += comments;
+            }
+        }
+        for series in &metrics.label_series {
+            let index = match labels.iter().position(|(label, _)| *label == series.label) {
+                Some(index) => index,
+                None => {
+                    labels.push((&series.label, BTreeMap::new()));
+                    labels.len() - 1
+                }
+            };
+            for point in &series.time_series {
+                let day = labels[index]
+                    .1
+                    .entry(&point.date)
+                    .or_insert_with(|| LabelFlowMetrics {
+                        date: point.date.clone(),
+                        ..Default::default()
+                    });
+                day.opened += point.opened;
+                day.merged += point.merged;
+                day.spread += point.spread;
+            }
+        }
+    }
+
+    let time_series: Vec<FlowMetricsResponse> = days.into_values().collect();
+    RepoMetricsResponse {
+        summary: calculate_summary(&time_series),
+        time_series,
+        insights: Vec::new(),
+        label_series: labels
+            .into_iter()
+            .map(|(label, days)| LabelSeries {
+                label: label.to_string(),
+                time_series: days.into_values().collect(),
+            })
+            .collect(),
+        longest_open: Vec::new(),
+        normalized: None,
+    }
+}
+
+/// Buckets when `prs` were opened and merged by weekday and hour.
+pub fn heatmap(prs: &[GitHubPR]) -> Heatmap {
+    let mut cells: Vec<HeatmapCell> = (0..7 * 24)
+        .map(|i| HeatmapCell {
+            weekday: i / 24,
+            hour: i % 24,
+            ..Default::default()
+        })
+        .collect();
+    let cell = |at: DateTime<Utc>| (at.weekday().num_days_from_monday() * 24 + at.hour()) as usize;
+    for pr in prs {
+        cells[cell(pr.created_at)].opened += 1;
+        if let Some(merged_at) = pr.merged_at {
+            cells[cell(merged_at)].merged += 1;
+        }
+    }
+    Heatmap { cells }
+}
+
+/// The `count` pull requests among `prs` that have been open longest at `now`, oldest first. Only
+/// pull requests opened within the fetch window can be seen, so older ones are missed.
+pub fn longest_open(prs: &[GitHubPR], count: usize, now: DateTime<Utc>) -> Vec<OpenPullRequest> {
+    let mut open: Vec<&GitHubPR> = prs
+        .iter()
+        .filter(|pr| pr.merged_at.is_none() && pr.closed_at.is_none())
+        .collect();
+    open.sort_by_key(|pr| (pr.created_at, pr.number));
+    open.into_iter()
+        .take(count)
+        .map(|pr| OpenPullRequest {
+            number: pr.number,
+            title: pr.title.clone(),
+            author: pr.author.clone(),
+            opened_at: pr.created_at,
+            age_days: (now - pr.created_at).num_days(),
+        })
+        .collect()
+}
+
+/// Rescales each series (opened, merged, spread) independently using the given strategy.
+pub fn normalize_series(
+    time_series: &[FlowMetricsResponse],
+    mode: Normalization,
+) -> Vec<NormalizedFlowMetrics> {
+    let opened = normalize_values(time_series.iter().map(|p| p.opened as f64), mode);
+    let merged = normalize_values(time_series.iter().map(|p| p.merged as f64), mode);
+    let spread = normalize_values(time_series.iter().map(|p| p.spread as f64), mode);
+
+    time_series
+        .iter()
+        .enumerate()
+        .map(|(i, point)| NormalizedFlowMetrics {
+            date: point.date.clone(),
+            opened: opened[i],
+            merged: merged[i],
+            spread: spread[i],
+        })
+        .collect()
+}
+
+fn normalize_values(values: impl Iterator<Item = f64>, mode: Normalization) -> Vec<f64> {
+    let values: Vec<f64> = values.collect();
+    match mode {
+        Normalization::PercentChange => {
+            let base = values.first().copied().unwrap_or_default();
+            // The spread can be negative, so the change is relative to the baseline's magnitude.
+            if base == 0.0 {
+                return vec![0.0; values.len()];
+            }
+            values
+                .iter()
+                .map(|v| (v - base) / base.abs() * 100.0)
+                .collect()
+        }
+        Normalization::Zscore => {
+            if values.is_empty() {
+                return values;
+            }
+            let n = values.len() as f64;
+            let mean = values.iter().sum::<f64>() / n;
+            let std_dev = (values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n).sqrt();
+            if std_dev == 0.0 {
+                return vec![0.0; values.len()];
+            }
+            values.iter().map(|v| (v - mean) / std_dev).collect()
+        }
+    }
+}
+
+/// Counts the branches among `last_commits` with no commit in the `stale_after_days` before `now`.
+pub fn branch_stats(
+    total: u64,
+    last_commits: &[DateTime<Utc>],
+    stale_after_days: i64,
+    now: DateTime<Utc>,
+) -> BranchStats {
+    let cutoff = now - Duration::days(stale_after_days);
+    BranchStats {
+        total,
+        stale: last_commits.iter().filter(|at| **at <= cutoff).count() as u64,
+        stale_after_days,
+        complete: last_commits.len() as u64 >= total,
+    }
+}
+
+/// Scores how completely a repository was fetched: `fetched` pull requests, with `unfetched`
+/// more left behind when `truncated`, and `missing_fields` of the optional ones configured.
+pub fn completeness(
+    fetched: u64,
+    truncated: bool,
+    unfetched: Option<u64>,
+    missing_fields: Vec<MissingField>,
+) -> Completeness {
+    const OPTIONAL_FIELDS: f64 = 3.0;
+    let estimated = unfetched
+        .filter(|_| truncated)
+        .map(|unfetched| fetched + unfetched);
+    let coverage = match (truncated, estimated) {
+        (false, _) => 1.0,
+        (true, Some(estimated)) if estimated > 0 => fetched as f64 / estimated as f64,
+        // Nothing says how much is missing, only that something is.
+        (true, _) => 0.5,
+    };
+    let fields = 1.0 - missing_fields.len() as f64 / OPTIONAL_FIELDS;
+    Completeness {
+        score: (100.0 * (0.8 * coverage + 0.2 * fields)).round() as u8,
+        fetched_pull_requests: fetched,
+        estimated_pull_requests: estimated,
+        truncated,
+        missing_fields,
+        dropped_out_of_range: 0,
+    }
+}
+
+/// Removes the pull requests whose timestamps can't be right as of `now`: opened before
+/// `earliest`, the cutoff the fetch asked for, or opened, merged or closed after `now`, allowing
+/// for clock skew either way. Returns how many were removed.
+///
+/// Left in, a pull request opened in the future would be counted as open in no day. One opened
+/// before the cutoff can only come from a source that ignored it, and would be counted in windows
+/// whose other pull requests weren't fetched.
+pub fn drop_out_of_range(
+    prs: &mut Vec<GitHubPR>,
+    earliest: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> usize {
+    let skew = Duration::minutes(MAX_CLOCK_SKEW_MINUTES);
+    let (earliest, latest) = (earliest - skew, now + skew);
+    let before = prs.len();
+    prs.retain(|pr| {
+        pr.created_at >= earliest
+            && [Some(pr.created_at), pr.merged_at, pr.closed_at]
+                .into_iter()
+                .flatten()
+                .all(|at| at <= latest)
+    });
+    before - prs.len()
+}
+
+/// Calculates the summary metrics based on the generated time series.
+fn calculate_summary(time_series: &[FlowMetricsResponse]) -> SummaryMetrics {
+    let Some(latest) = time_series.last() else {
+        return SummaryMetrics::default();
+    };
+
+    let previous = time_series.iter().rev().nth(1);
+
+    let merge_rate = if latest.opened > 0 {
+        ((latest.merged as f64 / latest.opened as f64) * 100.0).round() as u32
+    } else {
+        0
+    };
+
+    let issue_link_rate = if latest.merged > 0 {
+        ((latest.merged_closing_issues as f64 / latest.merged as f64) * 100.0).round() as u32
+    } else {
+        0
+    };
+
+    let approved = [
+        latest.merged_unapproved,
+        latest.merged_one_approval,
+        latest.merged_two_plus_approvals,
+    ];
+    let recorded: usize = approved.iter().sum();
+    let share = |count: usize| ((count as f64 / recorded as f64) * 100.0).round() as u32;
+    let approval_shares = (recorded > 0).then(|| ApprovalShares {
+        none: share(approved[0]),
+        one: share(approved[1]),
+        two_or_more: share(approved[2]),
+    });
+
+    let is_widening = previous.is_some_and(|p| latest.spread > p.spread);
+
+    SummaryMetrics {
+        current_opened: latest.opened,
+        current_merged: latest.merged,
+        current_fast_merged: latest.fast_merged,
+        current_spread: latest.spread,
+        merge_rate,
+        issue_link_rate,
+        current_issues_closed: latest.issues_closed,
+        approval_shares,
+        is_widening,
+    }
+}
+
+/// Calculates opened and merged metrics for a single point in time using a rolling window.
+pub fn calculate_day_metrics(
+    timeline: &Timeline,
+    target_date: DateTime<Utc>,
+    window_size: Duration,
+) -> FlowMetricsResponse {
+    let window_start = timeline.window_start(target_date, window_size);
+    let opened = timeline.count_between(Event::Opened, window_start, target_date);
+    let merged = timeline.count_between(Event::Merged, window_start, target_date);
+    let fast_merged = timeline.fast_merged_between(window_start, target_date);
+    let merge_hours = timeline.median_queue_and_review_hours(window_start, target_date);
+    let merged_with_approvals = |approvals| {
+        timeline.count_between(
+            Event::MergedWithApprovals(approvals),
+            window_start,
+            target_date,
+        )
+    };
+
+    FlowMetricsResponse {
+        date: target_date.format("%Y-%m-%d").to_string(),
+        opened,
+        merged,
+        closed: timeline.count_between(Event::Closed, window_start, target_date),
+        spread: opened as i64 - merged as i64,
+        open_count: timeline.open_at(target_date),
+        fast_merged,
+        backlog_merged: merged - fast_merged,
+        merged_closing_issues: timeline.count_between(
+            Event::MergedClosingIssue,
+            window_start,
+            target_date,
+        ),
+        issues_closed: timeline.count_between(Event::IssueClosed, window_start, target_date),
+        merged_unapproved: merged_with_approvals(0),
+        merged_one_approval: merged_with_approvals(1),
+        merged_two_plus_approvals: merged_with_approvals(2),
+        review_comments: timeline.review_comments.as_ref().map(|_| {
+            let day_start = target_date.date_naive().and_time(NaiveTime::MIN).and_utc();
+            timeline.count_between(Event::ReviewComment, day_start, target_date)
+        }),
+        median_queue_hours: merge_hours.map(|(queue, _)| queue),
+        median_review_hours: merge_hours.map(|(_, review)| review),
+        is_holiday: timeline.is_holiday(target_date),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::domain::{MergeQueueEntry, PRState};
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_calculate_metrics_empty() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        let days_to_display = Duration::days(1);
+        let window_size = Duration::days(30);
+        let response = calculate_metrics(&[], days_to_display, window_size, now);
+
+        assert_eq!(response.time_series.len(), 2);
+        assert_eq!(response.summary.current_opened, 0);
+        assert_eq!(response.summary.merge_rate, 0);
+    }
+
+    #[test]
+    fn test_calculate_metrics_with_data() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+
+        let prs = vec![
+            GitHubPR {
+                id: 1,
+                created_at: Utc.with_ymd_and_hms(2024, 1, 5, 10, 0, 0).unwrap(),
+                merged_at: Some(Utc.with_ymd_and_hms(2024, 1, 6, 10, 0, 0).unwrap()),
+                closed_at: Some(Utc.with_ymd_and_hms(2024, 1, 6, 10, 0, 0).unwrap()),
+                state: PRState::Merged,
+                ..Default::default()
+            },
+            GitHubPR {
+                id: 2,
+                created_at: Utc.with_ymd_and_hms(2024, 1, 9, 10, 0, 0).unwrap(),
+                merged_at: None,
+                closed_at: None,
+                state: PRState::Open,
+                ..Default::default()
+            },
+        ];
+
+        let days_to_display = Duration::days(0); // Only today
+        let window_size = Duration::days(30); // 30 day window
+        let response = calculate_metrics(&prs, days_to_display, window_size, now);
+
+        assert_eq!(response.time_series.len(), 1);
+        assert_eq!(response.summary.current_opened, 2);
+        assert_eq!(response.summary.current_merged, 1);
+        assert_eq!(response.summary.merge_rate, 50);
+        assert_eq!(response.time_series[0].open_count, 1);
+        // Merged PRs also carry closed_at but must not be counted as closed.
+        assert_eq!(response.time_series[0].closed, 0);
+    }
+
+    #[test]
+    fn test_open_count_tracks_lifecycle() {
+        let pr = |id, created: u32, closed: Option<u32>| GitHubPR {
+            id,
+            created_at: Utc.with_ymd_and_hms(2024, 1, created, 10, 0, 0).unwrap(),
+            merged_at: None,
+            closed_at: closed.map(|d| Utc.with_ymd_and_hms(2024, 1, d, 10, 0, 0).unwrap()),
+            state: PRState::Closed,
+            ..Default::default()
+        };
+        let prs = vec![pr(1, 2, Some(4)), pr(2, 3, None), pr(3, 5, Some(5))];
+
+        let now = Utc.with_ymd_and_hms(2024, 1, 5, 12, 0, 0).unwrap();
+        let response = calculate_metrics(&prs, Duration::days(4), Duration::days(30), now);
+        let open: Vec<usize> = response.time_series.iter().map(|p| p.open_count).collect();
+        let closed: Vec<usize> = response.time_series.iter().map(|p| p.closed).collect();
+
+        assert_eq!(open, vec![0, 1, 2, 1, 1]);
+        assert_eq!(closed, vec![0, 0, 0, 1, 2]);
+    }
+
+    #[test]
+    fn test_timeline_counts() {
+        let day = |d: u32| Utc.with_ymd_and_hms(2024, 1, d, 10, 0, 0).unwrap();
+        let pr = |id, created: u32, merged: Option<u32>, closed: Option<u32>| GitHubPR {
+            id,
+            created_at: day(created),
+            merged_at: merged.map(day),
+            closed_at: closed.map(day),
+            state: PRState::Unknown,
+            ..Default::default()
+        };
+        // The third PR was closed before it was opened, as can happen with imported history.
+        let prs = vec![
+            pr(1, 2, Some(5), Some(5)),
+            pr(2, 4, None, None),
+            pr(3, 4, None, Some(3)),
+        ];
+        let timeline = Timeline::new(&prs);
+
+        assert_eq!(timeline.count_until(Event::Opened, day(3)), 1);
+        assert_eq!(timeline.count_between(Event::Opened, day(2), day(4)), 3);
+        assert_eq!(timeline.count_between(Event::Merged, day(6), day(9)), 0);
+        assert_eq!(timeline.count_until(Event::Closed, day(9)), 1);
+        assert_eq!(timeline.open_at(day(4)), 2);
+        assert_eq!(timeline.open_at(day(5)), 1);
+    }
+
+    #[test]
+    fn test_fast_and_backlog_merges() {
+        let day = |d: u32| Utc.with_ymd_and_hms(2024, 1, d, 10, 0, 0).unwrap();
+        let merged = |id, created, merged| GitHubPR {
+            id,
+            created_at: day(created),
+            merged_at: Some(day(merged)),
+            closed_at: Some(day(merged)),
+            state: PRState::Merged,
+            ..Default::default()
+        };
+        // Only the last PR was opened within the week before the 20th.
+        let prs = vec![merged(1, 2, 15), merged(2, 10, 18), merged(3, 16, 19)];
+
+        let now = Utc.with_ymd_and_hms(2024, 1, 20, 12, 0, 0).unwrap();
+        let response = calculate_metrics(&prs, Duration::days(0), Duration::days(7), now);
+        let point = &response.time_series[0];
+
+        assert_eq!(point.merged, 3);
+        assert_eq!(point.fast_merged, 1);
+        assert_eq!(point.backlog_merged, 2);
+        assert_eq!(response.summary.current_fast_merged, 1);
+    }
+
+    #[test]
+    fn test_fast_merges_across_blocks() {
+        use rand::{rngs::StdRng, Rng, SeedableRng};
+
+        let mut rng = StdRng::seed_from_u64(447);
+        let base = Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap();
+        let prs: Vec<GitHubPR> = (0..5 * MERGE_BLOCK as u64)
+            .map(|id| {
+                let created_at = base + Duration::hours(rng.gen_range(0..24 * 90));
+                let merged_at = created_at + Duration::hours(rng.gen_range(0..24 * 20));
+                GitHubPR {
+                    id,
+                    created_at,
+                    merged_at: Some(merged_at),
+                    closed_at: Some(merged_at),
+                    state: PRState::Merged,
+                    ..Default::default()
+                }
+            })
+            .collect();
+        let timeline = Timeline::new(&prs);
+
+        for _ in 0..200 {
+            let start = base + Duration::hours(rng.gen_range(0..24 * 110));
+            let end = start + Duration::hours(rng.gen_range(0..24 * 60));
+            let expected = prs
+                .iter()
+                .filter(|pr| pr.merged_at.is_some_and(|at| at >= start && at <= end))
+                .filter(|pr| pr.created_at >= start)
+                .count();
+            assert_eq!(timeline.fast_merged_between(start, end), expected);
+        }
+    }
+
+    #[test]
+    fn test_issue_links() {
+        let day = |d: u32| Utc.with_ymd_and_hms(2024, 1, d, 10, 0, 0).unwrap();
+        let pr = |id, merged: Option<u32>, closes_issues: Vec<u64>| GitHubPR {
+            id,
+            created_at: day(1),
+            merged_at: merged.map(day),
+            closed_at: merged.map(day),
+            state: PRState::Unknown,
+            closes_issues,
+            ..Default::default()
+        };
+        // Issue 7 was closed by the first of two merges; unmerged PRs close nothing.
+        let prs = vec![
+            pr(1, Some(3), vec![7]),
+            pr(2, Some(12), vec![7, 8]),
+            pr(3, Some(13), vec![]),
+            pr(4, Some(14), vec![]),
+            pr(5, None, vec![9]),
+        ];
+
+        let now = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+        let response = calculate_metrics(&prs, Duration::days(0), Duration::days(7), now);
+        let point = &response.time_series[0];
+
+        assert_eq!(point.merged, 3);
+        assert_eq!(point.merged_closing_issues, 1);
+        assert_eq!(point.issues_closed, 1);
+        assert_eq!(response.summary.issue_link_rate, 33);
+        assert_eq!(response.summary.current_issues_closed, 1);
+    }
+
+    #[test]
+    fn test_approval_coverage() {
+        let day = |d: u32| Utc.with_ymd_and_hms(2024, 1, d, 10, 0, 0).unwrap();
+        let merged = |id, approvals: Option<u32>| GitHubPR {
+            id,
+            created_at: day(1),
+            merged_at: Some(day(10)),
+            closed_at: Some(day(10)),
+            state: PRState::Merged,
+            approvals,
+            ..Default::default()
+        };
+        let prs = vec![
+            merged(1, Some(0)),
+            merged(2, Some(1)),
+            merged(3, Some(2)),
+            merged(4, Some(5)),
+            merged(5, None),
+        ];
+
+        let now = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+        let response = calculate_metrics(&prs, Duration::days(0), Duration::days(7), now);
+        let point = &response.time_series[0];
+
+        assert_eq!(
+            (
+                point.merged_unapproved,
+                point.merged_one_approval,
+                point.merged_two_plus_approvals
+            ),
+            (1, 1, 2)
+        );
+        assert_eq!(
+            response.summary.approval_shares,
+            Some(ApprovalShares {
+                none: 25,
+                one: 25,
+                two_or_more: 50
+            })
+        );
+
+        // Without recorded approvals there are no shares to report.
+        let response = calculate_metrics(&prs[4..], Duration::days(0), Duration::days(7), now);
+        assert_eq!(response.summary.approval_shares, None);
+    }
+
+    #[test]
+    fn test_review_comments_are_daily() {
+        let at = |d: u32, h: u32| Utc.with_ymd_and_hms(2024, 1, d, h, 0, 0).unwrap();
+        let now = at(15, 12);
+        let timeline = Timeline::new(&[]);
+        let response =
+            calculate_timeline_metrics(&timeline, Duration::days(2), Duration::days(7), now);
+        assert!(response
+            .time_series
+            .iter()
+            .all(|p| p.review_comments.is_none()));
+
+        let timeline =
+            timeline.with_review_comments(Some(vec![at(15, 9), at(13, 0), at(15, 0), at(10, 5)]));
+        let response =
+            calculate_timeline_metrics(&timeline, Duration::days(2), Duration::days(7), now);
+        let comments: Vec<_> = response
+            .time_series
+            .iter()
+            .map(|p| p.review_comments)
+            .collect();
+        assert_eq!(comments, vec![Some(1), Some(0), Some(2)]);
+    }
+
+    #[test]
+    fn test_queue_time_is_apart_from_review_time() {
+        let at = |d: u32, h: u32| Utc.with_ymd_and_hms(2024, 1, d, h, 0, 0).unwrap();
+        let merged = |id, queue: Option<Vec<MergeQueueEntry>>| GitHubPR {
+            id,
+            created_at: at(10, 0),
+            merged_at: Some(at(11, 0)),
+            closed_at: Some(at(11, 0)),
+            state: PRState::Merged,
+            merge_queue: queue,
+            ..Default::default()
+        };
+        let stay = |from, to: Option<u32>| MergeQueueEntry {
+            enqueued_at: at(10, from),
+            dequeued_at: to.map(|h| at(10, h)),
+        };
+        // Queued for 2, 4 and 20 hours; the last stay ended with the merge.
+        let prs = vec![
+            merged(1, Some(vec![stay(10, Some(12))])),
+            merged(2, Some(vec![stay(2, Some(4)), stay(6, Some(8))])),
+            merged(3, Some(vec![stay(4, None)])),
+            merged(4, None),
+        ];
+
+        let now = at(15, 12);
+        let response = calculate_metrics(&prs, Duration::days(0), Duration::days(7), now);
+        let point = &response.time_series[0];
+        assert_eq!(point.median_queue_hours, Some(4.0));
+        assert_eq!(point.median_review_hours, Some(20.0));
+
+        let response = calculate_metrics(&prs[3..], Duration::days(0), Duration::days(7), now);
+        assert_eq!(response.time_series[0].median_queue_hours, None);
+    }
+
+    #[test]
+    fn test_label_series() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let pr = |id, labels: &[&str], merged: bool| GitHubPR {
+            id,
+            labels: labels.iter().map(|l| l.to_string()).collect(),
+            created_at: now - Duration::days(2),
+            merged_at: merged.then_some(now - Duration::days(1)),
+            ..Default::default()
+        };
+        let prs = [
+            pr(1, &["Bug"], true),
+            pr(2, &["bug", "feature"], false),
+            pr(3, &[], true),
+        ];
+        let labels = ["bug".to_string(), "tech-debt".to_string()];
+        let timeline = Timeline::new(&prs).with_labels(&prs, &labels);
+        let response =
+            calculate_timeline_metrics(&timeline, Duration::days(1), Duration::days(7), now);
+
+        assert_eq!(response.label_series.len(), 2);
+        let bug = &response.label_series[0];
+        assert_eq!(bug.label, "bug");
+        assert_eq!(bug.time_series.len(), 2);
+        assert_eq!(
+            bug.time_series[1],
+            LabelFlowMetrics {
+                date: "2024-01-10".to_string(),
+                opened: 2,
+                merged: 1,
+                spread: 1,
+            }
+        );
+        assert_eq!(response.label_series[1].time_series[1].opened, 0);
+        assert!(
+            calculate_metrics(&prs, Duration::days(1), Duration::days(7), now)
+                .label_series
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_heatmap() {
+        // A Wednesday.
+        let at = Utc.with_ymd_and_hms(2024, 1, 10, 14, 30, 0).unwrap();
+        let prs = [
+            GitHubPR {
+                created_at: at,
+                merged_at: Some(at + Duration::days(5)),
+                ..Default::default()
+            },
+            GitHubPR {
+                created_at: at + Duration::minutes(20),
+                ..Default::default()
+            },
+        ];
+
+        let heatmap = heatmap(&prs);
+        assert_eq!(heatmap.cells.len(), 168);
+        let wednesday = &heatmap.cells[2 * 24 + 14];
+        assert_eq!((wednesday.weekday, wednesday.hour), (2, 14));
+        assert_eq!((wednesday.opened, wednesday.merged), (2, 0));
+        // Merged the following Monday.
+        assert_eq!(heatmap.cells[14].merged, 1);
+        assert_eq!(heatmap.cells.iter().map(|c| c.opened).sum::<usize>(), 2);
+    }
+
+    #[test]
+    fn test_longest_open() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 12, 0, 0).unwrap();
+        let pr = |number, days: i64, closed: bool| GitHubPR {
+            number,
+            title: format!("PR {}", number),
+            created_at: now - Duration::days(days),
+            closed_at: closed.then_some(now),
+            ..Default::default()
+        };
+        let prs = [
+            pr(1, 3, false),
+            pr(2, 40, true),
+            pr(3, 20, false),
+            pr(4, 9, false),
+        ];
+
+        let longest = longest_open(&prs, 2, now);
+        let numbers: Vec<u64> = longest.iter().map(|pr| pr.number).collect();
+        assert_eq!(numbers, [3, 4]);
+        assert_eq!(longest[0].age_days, 20);
+        assert_eq!(longest[0].title, "PR 3");
+        assert!(longest_open(&prs, 0, now).is_empty());
+    }
+
+    #[test]
+    fn test_branch_stats() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let commits = [
+            now - Duration::days(1),
+            now - Duration::days(90),
+            now - Duration::days(400),
+        ];
+        let stats = branch_stats(3, &commits, 90, now);
+        assert_eq!(stats.stale, 2);
+        assert!(stats.complete);
+
+        let stats = branch_stats(5, &commits[..1], 90, now);
+        assert_eq!((stats.total, stats.stale), (5, 0));
+        assert!(!stats.complete);
+    }
+
+    #[test]
+    fn test_aggregate() {
+        let now = Utc.with_ymd_and_hms(2024, 1, 10, 12, 0, 0).unwrap();
+        let pr = |id, opened_days_ago, merged_days_ago: Option<i64>| GitHubPR {
+            id,
+            created_at: now - Duration::days(opened_days_ago),
+            merged_at: merged_days_ago.map(|days| now - Duration::days(days)),
+            closed_at: merged_days_ago.map(|days| now - Duration::days(days)),
+            ..Default::default()
+        };
+        let metrics =
+            |prs: &[GitHubPR]| calculate_metrics(prs, Duration::days(1), Duration::days(7), now);
+        let first = metrics(&[pr(1, 2, Some(1))]);
+        let second = metrics(&[pr(2, 3, None), pr(3, 1, None)]);
+
+        let combined = aggregate(&[&first, &second]);
+        assert_eq!(combined.time_series.len(), 2);
+        let today = combined.time_series.last().unwrap();
+        assert_eq!((today.opened, today.merged, today.open_count), (3, 1, 2));
+        assert_eq!(combined.summary.current_opened, 3);
+        assert_eq!(combined.summary.merge_rate, 33);
+        assert_eq!(aggregate(&[]).time_series.len(), 0);
+    }
+
+    #[test]
+    fn test_completeness() {
+        let full = completeness(40, false, None, Vec::new());
+        assert_eq!(full.score, 100);
+        assert_eq!(full.estimated_pull_requests, None);
+
+        let partial = completeness(100, true, Some(300), vec![MissingField::ReviewComments]);
+        assert_eq!(partial.estimated_pull_requests, Some(400));
+        // 80% of a quarter fetched, plus 20% of two thirds of the fields.
+        assert_eq!(partial.score, 33);
+
+        assert_eq!(completeness(100, true, None, Vec::new()).score, 60);
+    }
+
+    #[test]
+    fn test_drop_out_of_range() {
+        let now = Utc.with_ymd_and_hms(2026, 3, 1, 12, 0, 0).unwrap();
+        let at = |minutes: i64| now + Duration::minutes(minutes);
+        let pr = |number, created_at, merged_at| GitHubPR {
+            number,
+            created_at,
+            merged_at,
+            ..Default::default()
+        };
+        let mut prs = vec![
+            pr(1, at(-60), Some(at(5))),
+            pr(2, at(60), None),
+            pr(3, at(-60), Some(at(60))),
+            pr(4, Utc.with_ymd_and_hms(1970, 1, 1, 0, 0, 0).unwrap(), None),
+            pr(5, at(-60 * 24), None),
+        ];
+        let dropped = drop_out_of_range(&mut prs, now - Duration::days(1), now);
+        assert_eq!(dropped, 3);
+        let kept: Vec<u64> = prs.iter().map(|pr| pr.number).collect();
+        // The first is merged in the future, but within the allowed skew.
+        assert_eq!(kept, [1, 5]);
+    }
+
+    #[test]
+    fn test_calculate_summary_empty() {
+        let metrics = calculate_summary(&[]);
+        assert_eq!(metrics.current_opened, 0);
+        assert_eq!(metrics.current_merged, 0);
+        assert_eq!(metrics.current_spread, 0);
+        assert_eq!(metrics.merge_rate, 0);
+        assert!(!metrics.is_widening);
+    }
+
+    fn point(opened: usize, merged: usize) -> FlowMetricsResponse {
+        FlowMetricsResponse {
+            date: "2024-01-01".to_string(),
+            opened,
+            merged,
+            closed: 0,
+            spread: opened as i64 - merged as i64,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_normalize_percent_change() {
+        let series = vec![point(10, 5), point(15, 5), point(5, 10)];
+        let normalized = normalize_series(&series, Normalization::PercentChange);
+
+        assert_eq!(normalized[0].opened, 0.0);
+        assert_eq!(normalized[1].opened, 50.0);
+        assert_eq!(normalized[2].opened, -50.0);
+        assert_eq!(normalized[2].merged, 100.0);
+        // Spread goes 5 -> 10 -> -5.
+        assert_eq!(normalized[1].spread, 100.0);
+        assert_eq!(normalized[2].spread, -200.0);
+    }
+
+    #[test]
+    fn test_normalize_percent_change_zero_baseline() {
+        let series = vec![point(0, 0), point(4, 2)];
+        let normalized = normalize_series(&series, Normalization::PercentChange);
+
+        assert!(normalized
+            .iter()
+            .all(|p| p.opened == 0.0 && p.merged == 0.0));
+    }
+
+    #[test]
+    fn test_normalize_zscore() {
+        let series = vec![point(2, 3), point(4, 3), point(6, 3)];
+        let normalized = normalize_series(&series, Normalization::Zscore);
+
+        let std_dev = (8.0_f64 / 3.0).sqrt();
+        assert!((normalized[0].opened + 2.0 / std_dev).abs() < 1e-9);
+        assert_eq!(normalized[1].opened, 0.0);
+        assert!((normalized[2].opened - 2.0 / std_dev).abs() < 1e-9);
+        // A constant series has no variance and normalizes to zero.
+        assert!(normalized.iter().all(|p| p.merged == 0.0));
+    }
+
+    mod properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        /// The straightforward scan over every PR that `Timeline` replaces, kept as a reference.
+        fn brute_force_day(
+            prs: &[GitHubPR],
+            target_date: DateTime<Utc>,
+            window_size: Duration,
+        ) -> (usize, usize, usize, usize, usize, usize, usize) {
+            let window_start = target_date - window_size;
+            let within = |t: DateTime<Utc>| t >= window_start && t <= target_date;
+            let opened = prs.iter().filter(|pr| within(pr.created_at)).count();
+            let merged = prs
+                .iter()
+                .filter(|pr| pr.merged_at.is_some_and(within))
+                .count();
+            let fast_merged = prs
+                .iter()
+                .filter(|pr| pr.merged_at.is_some_and(within) && pr.created_at >= window_start)
+                .count();
+            let closed = prs
+                .iter()
+                .filter(|pr| pr.merged_at.is_none() && pr.closed_at.is_some_and(within))
+                .count();
+            let open = prs
+                .iter()
+                .filter(|pr| pr.created_at <= target_date)
+                .filter(|pr| {
+                    pr.merged_at
+                        .or(pr.closed_at)
+                        .is_none_or(|ended| ended > target_date)
+                })
+                .count();
+            let merged_closing_issues = prs
+                .iter()
+                .filter(|pr| pr.merged_at.is_some_and(within) && !pr.closes_issues.is_empty())
+                .count();
+            let mut first_closes = HashMap::new();
+            for pr in prs {
+                for issue in &pr.closes_issues {
+                    if let Some(merged_at) = pr.merged_at {
+                        let at = first_closes.entry(*issue).or_insert(merged_at);
+                        *at = merged_at.min(*at);
+                    }
+                }
+            }
+            let issues_closed = first_closes.values().filter(|at| within(**at)).count();
+            (
+                opened,
+                merged,
+                closed,
+                open,
+                fast_merged,
+                merged_closing_issues,
+                issues_closed,
+            )
+        }
+
+        /// Reference times around the 2024 US and EU daylight saving transitions, which must make
+        /// no difference to UTC day boundaries.
+        fn now() -> impl Strategy<Value = DateTime<Utc>> {
+            prop_oneof![
+                Just(Utc.with_ymd_and_hms(2024, 3, 10, 7, 30, 0).unwrap()),
+                Just(Utc.with_ymd_and_hms(2024, 3, 31, 1, 0, 0).unwrap()),
+                Just(Utc.with_ymd_and_hms(2024, 11, 3, 6, 0, 0).unwrap()),
+                Just(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+            ]
+        }
+
+        /// PRs opened up to 120 days before `now` and up to 5 days after it (clock skew or bad
+        /// data), some ending before they started, closing a few of ten issues and with or without
+        /// recorded approvals. Times fall on a half-hour grid, less a second
+        /// for about half of them, so they regularly land exactly on window boundaries.
+        fn prs(now: DateTime<Utc>) -> impl Strategy<Value = Vec<GitHubPR>> {
+            let instant = |steps| {
+                (steps, any::<bool>()).prop_map(|(n, early): (i64, bool)| n * 1800 - early as i64)
+            };
+            let steps_per_day: i64 = 48;
+            let pr = (
+                instant(-120 * steps_per_day..5 * steps_per_day),
+                proptest::option::of(instant(-steps_per_day..20 * steps_per_day)),
+                0..3u8,
+                proptest::collection::btree_set(0..10u64, 0..3),
+                proptest::option::of(0..4u32),
+            );
+            proptest::collection::vec(pr, 0..60).prop_map(move |specs| {
+                specs
+                    .into_iter()
+                    .enumerate()
+                    .map(|(id, (created, duration, kind, issues, approvals))| {
+                        let created_at = now + Duration::seconds(created);
+                        let ended_at = duration.map(|d| created_at + Duration::seconds(d));
+                        let (state, merged_at) = match (ended_at, kind) {
+                            (None, _) => (PRState::Open, None),
+                            (Some(_), 0) => (PRState::Closed, None),
+                            (Some(at), _) => (PRState::Merged, Some(at)),
+                        };
+                        GitHubPR {
+                            id: id as u64,
+                            created_at,
+                            merged_at,
+                            closed_at: ended_at,
+                            state,
+                            closes_issues: issues.into_iter().collect(),
+                            approvals,
+                            ..Default::default()
+                        }
+                    })
+                    .collect()
+            })
+        }
+
+        fn case() -> impl Strategy<Value = (DateTime<Utc>, Vec<GitHubPR>, i64, i64)> {
+            now().prop_flat_map(|now| (Just(now), prs(now), 0..45i64, 0..45i64))
+        }
+
+        proptest! {
+            #[test]
+            fn spread_is_opened_minus_merged((now, prs, days, window) in case()) {
+                let response = calculate_metrics(&prs, Duration::days(days), Duration::days(window), now);
+                for point in &response.time_series {
+                    prop_assert_eq!(point.spread, point.opened as i64 - point.merged as i64);
+                    prop_assert_eq!(point.fast_merged + point.backlog_merged, point.merged);
+                    let approved = point.merged_unapproved + point.merged_one_approval + point.merged_two_plus_approvals;
+                    prop_assert!(approved <= point.merged);
+                }
+            }
+
+            #[test]
+            fn series_covers_consecutive_days((now, prs, days, window) in case()) {
+                let response = calculate_metrics(&prs, Duration::days(days), Duration::days(window), now);
+                prop_assert_eq!(response.time_series.len() as i64, days + 1);
+                let dates: Vec<_> = response
+                    .time_series
+                    .iter()
+                    .map(|p| chrono::NaiveDate::parse_from_str(&p.date, "%Y-%m-%d").unwrap())
+                    .collect();
+                prop_assert_eq!(*dates.last().unwrap(), now.date_naive());
+                for pair in dates.windows(2) {
+                    prop_assert_eq!(pair[1] - pair[0], Duration::days(1));
+                }
+            }
+
+            #[test]
+            fn prefix_counts_are_monotonic((now, prs, _, _) in case()) {
+                let timeline = Timeline::new(&prs);
+                let instants: Vec<_> = (-130..10).map(|d| now + Duration::days(d)).collect();
+                for event in [Event::Opened, Event::Merged, Event::Closed] {
+                    let counts: Vec<_> = instants.iter().map(|at| timeline.count_until(event, *at)).collect();
+                    prop_assert!(counts.windows(2).all(|pair| pair[0] <= pair[1]));
+                }
+                prop_assert_eq!(timeline.count_until(Event::Opened, now + Duration::days(10)), prs.len());
+            }
+
+            #[test]
+            fn windows_match_brute_force((now, prs, days, window) in case()) {
+                let response = calculate_metrics(&prs, Duration::days(days), Duration::days(window), now);
+                for point in &response.time_series {
+                    let date = chrono::NaiveDate::parse_from_str(&point.date, "%Y-%m-%d").unwrap();
+                    let target = date.and_hms_opt(END_OF_DAY_HOUR, END_OF_DAY_MIN, END_OF_DAY_SEC).unwrap().and_utc();
+                    prop_assert_eq!(
+                        (
+                            point.opened,
+                            point.merged,
+                            point.closed,
+                            point.open_count,
+                            point.fast_merged,
+                            point.merged_closing_issues,
+                            point.issues_closed,
+                        ),
+                        brute_force_day(&prs, target, Duration::days(window))
+                    );
+                }
+            }
+
+            #[test]
+            fn empty_windows_count_nothing((now, prs, days, window) in case()) {
+                // Every event happens before the earliest window opens.
+                let cutoff = now - Duration::days(days + window + 1);
+                let before: Vec<_> = prs
+                    .into_iter()
+                    .filter(|pr| pr.created_at < cutoff)
+                    .filter(|pr| pr.merged_at.or(pr.closed_at).is_none_or(|t| t < cutoff))
+                    .collect();
+                let response = calculate_metrics(&before, Duration::days(days), Duration::days(window), now);
+                prop_assert!(response.time_series.iter().all(|p| p.opened == 0 && p.merged == 0 && p.closed == 0));
+                prop_assert_eq!(response.summary.merge_rate, 0);
+            }
+        }
+    }
+}
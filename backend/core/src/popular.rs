@@ -0,0 +1,159 @@
+//! The list of popular repositories, which can be changed at runtime by operators.
+//!
+//! The list starts from `POPULAR_REPOS` or `POPULAR_REPOS_FILE`. When a file is configured,
+//! changes made through the admin API are written back to it so they survive restarts; otherwise
+//! they last only until the process exits.
+
+use crate::config::{PopularRepo, MAX_POPULAR_REPOS};
+use crate::domain::RepoId;
+use anyhow::Context;
+use std::path::PathBuf;
+use tokio::sync::RwLock;
+
+/// Result of adding a repository to the list.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Added {
+    /// The repository was not in the list before.
+    New,
+    /// An existing entry was replaced with new details.
+    Updated,
+}
+
+/// Returned when adding a repository would exceed `MAX_POPULAR_REPOS`.
+#[derive(Debug)]
+pub struct ListFull;
+
+impl std::fmt::Display for ListFull {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "at most {} popular repositories are allowed",
+            MAX_POPULAR_REPOS
+        )
+    }
+}
+
+impl std::error::Error for ListFull {}
+
+pub struct PopularRepoStore {
+    repos: RwLock<Vec<PopularRepo>>,
+    file: Option<PathBuf>,
+}
+
+impl PopularRepoStore {
+    pub fn new(repos: Vec<PopularRepo>, file: Option<PathBuf>) -> Self {
+        Self {
+            repos: RwLock::new(repos),
+            file,
+        }
+    }
+
+    /// The current list, in the order it was configured and added.
+    pub async fn list(&self) -> Vec<PopularRepo> {
+        self.repos.read().await.clone()
+    }
+
+    pub async fn get(&self, repo_id: &RepoId) -> Option<PopularRepo> {
+        self.repos
+            .read()
+            .await
+            .iter()
+            .find(|popular| popular.id == *repo_id)
+            .cloned()
+    }
+
+    /// Adds a repository, or replaces the details of one already in the list.
+    pub async fn add(&self, popular: PopularRepo) -> anyhow::Result<Added> {
+        let mut repos = self.repos.write().await;
+        let mut updated = repos.clone();
+        let added = match updated.iter().position(|p| p.id == popular.id) {
+            Some(index) => {
+                updated[index] = popular;
+                Added::Updated
+            }
+            None if updated.len() >= MAX_POPULAR_REPOS => return Err(ListFull.into()),
+            None => {
+                updated.push(popular);
+                Added::New
+            }
+        };
+        self.persist(&updated).await?;
+        *repos = updated;
+        Ok(added)
+    }
+
+    /// Removes a repository, returning its entry if it was in the list.
+    pub async fn remove(&self, repo_id: &RepoId) -> anyhow::Result<Option<PopularRepo>> {
+        let mut repos = self.repos.write().await;
+        let Some(index) = repos.iter().position(|p| p.id == *repo_id) else {
+            return Ok(None);
+        };
+        let mut updated = repos.clone();
+        let removed = updated.remove(index);
+        self.persist(&updated).await?;
+        *repos = updated;
+        Ok(Some(removed))
+    }
+
+    /// Writes the list to the configured file, replacing it atomically so a crash mid-write
+    /// can't leave a truncated file that fails to load on the next start.
+    async fn persist(&self, repos: &[PopularRepo]) -> anyhow::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(repos)?)
+            .await
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, path)
+            .await
+            .with_context(|| format!("failed to replace {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn popular(owner: &str, repo: &str) -> PopularRepo {
+        PopularRepo::from(RepoId {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        })
+    }
+
+    #[tokio::test]
+    async fn test_changes_are_persisted() {
+        let path =
+            std::env::temp_dir().join(format!("repoflow-popular-{}.json", rand::random::<u64>()));
+        let store = PopularRepoStore::new(vec![popular("facebook", "react")], Some(path.clone()));
+
+        assert_eq!(
+            store.add(popular("rust-lang", "rust")).await.unwrap(),
+            Added::New
+        );
+        let mut renamed = popular("rust-lang", "rust");
+        renamed.display_name = Some("Rust".to_string());
+        assert_eq!(store.add(renamed).await.unwrap(), Added::Updated);
+        assert_eq!(
+            store
+                .remove(&popular("facebook", "react").id)
+                .await
+                .unwrap(),
+            Some(popular("facebook", "react"))
+        );
+        assert!(store
+            .remove(&popular("facebook", "react").id)
+            .await
+            .unwrap()
+            .is_none());
+
+        let saved: Vec<PopularRepo> =
+            serde_json::from_slice(&std::fs::read(&path).unwrap()).unwrap();
+        assert_eq!(saved, store.list().await);
+        assert_eq!(saved.len(), 1);
+        assert_eq!(saved[0].display_name.as_deref(), Some("Rust"));
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
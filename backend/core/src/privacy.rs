@@ -0,0 +1,100 @@
+//! How pull request authors are shown in contributor-level outputs, per `AUTHOR_PRIVACY`.
+//!
+//! Pseudonyms are keyed HMACs of the lowercased login, so they can't be reversed by hashing a list
+//! of known logins without the key.
+
+use crate::config::{AppConfig, AuthorPrivacy};
+use ring::hmac;
+use std::collections::HashSet;
+
+/// Maps logins to what may be shown for them.
+#[derive(Clone)]
+pub struct Anonymizer {
+    mode: AuthorPrivacy,
+    key: hmac::Key,
+    buckets: u32,
+    opted_out: HashSet<String>,
+}
+
+impl Anonymizer {
+    pub fn new(config: &AppConfig) -> Self {
+        let key = match &config.author_hash_key {
+            Some(key) => key.expose().as_bytes().to_vec(),
+            None => rand::random::<[u8; 32]>().to_vec(),
+        };
+        Self {
+            mode: config.author_privacy,
+            key: hmac::Key::new(hmac::HMAC_SHA256, &key),
+            buckets: config.author_buckets,
+            opted_out: config
+                .author_opt_out
+                .iter()
+                .map(|login| login.to_ascii_lowercase())
+                .collect(),
+        }
+    }
+
+    /// What to show for `login`, or `None` if its author opted out.
+    pub fn display(&self, login: &str) -> Option<String> {
+        let lowercase = login.to_ascii_lowercase();
+        if self.opted_out.contains(&lowercase) {
+            return None;
+        }
+        match self.mode {
+            AuthorPrivacy::None => Some(login.to_string()),
+            AuthorPrivacy::Hash => Some(format!("author-{:08x}", self.digest(&lowercase) >> 32)),
+            AuthorPrivacy::Bucket => Some(format!(
+                "group-{}",
+                self.digest(&lowercase) % u64::from(self.buckets) + 1
+            )),
+        }
+    }
+
+    fn digest(&self, login: &str) -> u64 {
+        let tag = hmac::sign(&self.key, login.as_bytes());
+        let bytes: [u8; 8] = tag.as_ref()[..8]
+            .try_into()
+            .expect("HMAC-SHA256 is 32 bytes");
+        u64::from_be_bytes(bytes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_config;
+
+    fn anonymizer(vars: &[(&str, &str)]) -> Anonymizer {
+        Anonymizer::new(&test_config(vars))
+    }
+
+    #[test]
+    fn test_hashed_authors_are_stable_per_key() {
+        let vars = [("AUTHOR_PRIVACY", "hash"), ("AUTHOR_HASH_KEY", "k1")];
+        let alice = anonymizer(&vars).display("alice").unwrap();
+        assert!(alice.starts_with("author-") && alice.len() == "author-".len() + 8);
+        assert_eq!(anonymizer(&vars).display("Alice").unwrap(), alice);
+        assert_ne!(anonymizer(&vars).display("bob").unwrap(), alice);
+        let other_key = anonymizer(&[("AUTHOR_PRIVACY", "hash"), ("AUTHOR_HASH_KEY", "k2")]);
+        assert_ne!(other_key.display("alice").unwrap(), alice);
+    }
+
+    #[test]
+    fn test_bucketed_authors_share_groups() {
+        let authors = anonymizer(&[("AUTHOR_PRIVACY", "bucket"), ("AUTHOR_BUCKETS", "2")]);
+        let groups: HashSet<String> = (0..50)
+            .map(|i| authors.display(&format!("user{}", i)).unwrap())
+            .collect();
+        assert_eq!(
+            groups,
+            HashSet::from(["group-1".to_string(), "group-2".to_string()])
+        );
+    }
+
+    #[test]
+    fn test_opted_out_authors_are_withheld() {
+        let authors = anonymizer(&[("AUTHOR_OPT_OUT", "Alice, carol")]);
+        assert_eq!(authors.display("ALICE"), None);
+        assert_eq!(authors.display("Bob").as_deref(), Some("Bob"));
+    }
+}
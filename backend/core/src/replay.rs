@@ -0,0 +1,287 @@
+//! Recording and replaying pull request data for offline development.
+//!
+//! With `GITHUB_MODE=record`, every response from the live source is also written to
+//! `GITHUB_FIXTURES_DIR/<owner>/<repo>/`. With `GITHUB_MODE=replay`, those files are served instead
+//! and GitHub is never contacted, so demos and local development work without a token.
+//!
+//! Replayed timestamps are shifted forward by the time elapsed since recording, so the charts keep
+//! the shape they had when recorded instead of drifting out of the display window.
+
+use crate::domain::{GitHubPR, RepoId};
+use crate::source::{
+    FetchedBranches, FetchedPullRequests, PullRequestSource, RateLimit, RepoCalendar,
+    SecurityAlerts,
+};
+use crate::upstream::{ErrorClass, UpstreamError};
+use anyhow::Context;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+const PULLS_FILE: &str = "pulls.json";
+const REPO_FILE: &str = "repo.json";
+
+#[derive(Serialize, Deserialize)]
+struct RecordedPulls {
+    recorded_at: DateTime<Utc>,
+    pull_requests: Vec<GitHubPR>,
+    #[serde(default)]
+    truncated: bool,
+    #[serde(default)]
+    unfetched: Option<u64>,
+    #[serde(default)]
+    review_comments: Option<Vec<DateTime<Utc>>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecordedRepo {
+    public: bool,
+}
+
+/// Returns the fixture directory for a repository, refusing names that would escape `root`.
+fn fixture_dir(root: &Path, repo_id: &RepoId) -> anyhow::Result<PathBuf> {
+    for part in [&repo_id.owner, &repo_id.repo] {
+        if part.is_empty() || part == "." || part == ".." || part.contains(['/', '\\', '\0']) {
+            anyhow::bail!("invalid repository name {}", repo_id);
+        }
+    }
+    Ok(root.join(&repo_id.owner).join(&repo_id.repo))
+}
+
+async fn write_json<T: Serialize>(path: PathBuf, value: &T) -> anyhow::Result<()> {
+    if let Some(parent) = path.parent() {
+        tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(&path, serde_json::to_vec_pretty(value)?)
+        .await
+        .with_context(|| format!("failed to write {}", path.display()))
+}
+
+async fn read_json<T: DeserializeOwned>(path: PathBuf) -> anyhow::Result<T> {
+    let bytes = tokio::fs::read(&path).await.map_err(|_| {
+        UpstreamError::new(
+            ErrorClass::NotFound,
+            format!("no recording at {}", path.display()),
+        )
+    })?;
+    Ok(serde_json::from_slice(&bytes)?)
+}
+
+/// Wraps a source and saves everything it returns as fixtures.
+pub struct RecordingSource {
+    inner: Arc<dyn PullRequestSource>,
+    dir: PathBuf,
+}
+
+impl RecordingSource {
+    pub fn new(inner: Arc<dyn PullRequestSource>, dir: PathBuf) -> Self {
+        Self { inner, dir }
+    }
+}
+
+#[async_trait]
+impl PullRequestSource for RecordingSource {
+    async fn pull_requests(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> anyhow::Result<FetchedPullRequests> {
+        let fetched = self.inner.pull_requests(repo_id, since, max_pages).await?;
+        let recording = RecordedPulls {
+            recorded_at: Utc::now(),
+            pull_requests: fetched.pull_requests,
+            truncated: fetched.truncated,
+            unfetched: fetched.unfetched,
+            review_comments: fetched.review_comments,
+        };
+        let path = fixture_dir(&self.dir, repo_id)?.join(PULLS_FILE);
+        // A failed write shouldn't fail the request that produced the data.
+        if let Err(e) = write_json(path, &recording).await {
+            tracing::warn!("Failed to record pull requests for {}: {:#}", repo_id, e);
+        }
+        Ok(FetchedPullRequests {
+            pull_requests: recording.pull_requests,
+            truncated: recording.truncated,
+            unfetched: recording.unfetched,
+            review_comments: recording.review_comments,
+        })
+    }
+
+    async fn branches(
+        &self,
+        repo_id: &RepoId,
+        max_pages: u32,
+    ) -> anyhow::Result<Option<FetchedBranches>> {
+        self.inner.branches(repo_id, max_pages).await
+    }
+
+    async fn security_alerts(
+        &self,
+        repo_id: &RepoId,
+        max_pages: u32,
+    ) -> anyhow::Result<Option<SecurityAlerts>> {
+        self.inner.security_alerts(repo_id, max_pages).await
+    }
+
+    async fn calendar(
+        &self,
+        repo_id: &RepoId,
+        max_pages: u32,
+    ) -> anyhow::Result<Option<RepoCalendar>> {
+        self.inner.calendar(repo_id, max_pages).await
+    }
+
+    async fn is_public(&self, repo_id: &RepoId) -> anyhow::Result<bool> {
+        let public = self.inner.is_public(repo_id).await?;
+        let path = fixture_dir(&self.dir, repo_id)?.join(REPO_FILE);
+        if let Err(e) = write_json(path, &RecordedRepo { public }).await {
+            tracing::warn!("Failed to record visibility of {}: {:#}", repo_id, e);
+        }
+        Ok(public)
+    }
+
+    fn for_user(&self, token: &str) -> anyhow::Result<Arc<dyn PullRequestSource>> {
+        Ok(Arc::new(Self::new(
+            self.inner.for_user(token)?,
+            self.dir.clone(),
+        )))
+    }
+
+    async fn check_access(&self) -> anyhow::Result<()> {
+        self.inner.check_access().await
+    }
+
+    async fn rate_limit(&self) -> anyhow::Result<Option<RateLimit>> {
+        self.inner.rate_limit().await
+    }
+}
+
+/// Serves fixtures saved by `RecordingSource`.
+#[derive(Clone)]
+pub struct ReplaySource {
+    dir: PathBuf,
+}
+
+impl ReplaySource {
+    pub fn new(dir: PathBuf) -> Self {
+        Self { dir }
+    }
+}
+
+#[async_trait]
+impl PullRequestSource for ReplaySource {
+    async fn pull_requests(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        _max_pages: u32,
+    ) -> anyhow::Result<FetchedPullRequests> {
+        let recording: RecordedPulls =
+            read_json(fixture_dir(&self.dir, repo_id)?.join(PULLS_FILE)).await?;
+        let shift = Utc::now() - recording.recorded_at;
+
+        let pull_requests = recording
+            .pull_requests
+            .into_iter()
+            .map(|mut pr| {
+                pr.created_at += shift;
+                pr.merged_at = pr.merged_at.map(|t| t + shift);
+                pr.closed_at = pr.closed_at.map(|t| t + shift);
+                pr
+            })
+            .filter(|pr| pr.created_at >= since)
+            .collect();
+        let review_comments = recording.review_comments.map(|comments| {
+            comments
+                .into_iter()
+                .map(|at| at + shift)
+                .filter(|at| *at >= since)
+                .collect()
+        });
+        Ok(FetchedPullRequests {
+            pull_requests,
+            truncated: recording.truncated,
+            unfetched: recording.unfetched,
+            review_comments,
+        })
+    }
+
+    async fn is_public(&self, repo_id: &RepoId) -> anyhow::Result<bool> {
+        let recording: anyhow::Result<RecordedRepo> =
+            read_json(fixture_dir(&self.dir, repo_id)?.join(REPO_FILE)).await;
+        // Repositories recorded only anonymously are public by definition.
+        Ok(recording.map_or(true, |r| r.public))
+    }
+
+    fn for_user(&self, _token: &str) -> anyhow::Result<Arc<dyn PullRequestSource>> {
+        Ok(Arc::new(self.clone()))
+    }
+
+    async fn check_access(&self) -> anyhow::Result<()> {
+        tracing::info!(
+            "Replaying recorded GitHub responses from {}",
+            self.dir.display()
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pr, MockPullRequestSource};
+    use chrono::Duration;
+
+    fn repo_id(owner: &str, repo: &str) -> RepoId {
+        RepoId {
+            owner: owner.to_string(),
+            repo: repo.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_record_then_replay() {
+        let dir = std::env::temp_dir().join(format!("repoflow-replay-{}", std::process::id()));
+        let live = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 3, Some(1)), pr(2, 40, None)]);
+        let recorder = RecordingSource::new(Arc::new(live), dir.clone());
+        let since = Utc::now() - Duration::days(30);
+
+        let recorded = recorder
+            .pull_requests(&repo_id("acme", "widgets"), since, 1)
+            .await
+            .unwrap()
+            .pull_requests;
+        assert_eq!(recorded.len(), 1);
+
+        let replay = ReplaySource::new(dir.clone());
+        let replayed = replay
+            .pull_requests(&repo_id("acme", "widgets"), since, 1)
+            .await
+            .unwrap()
+            .pull_requests;
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(replayed[0].id, 1);
+        assert!(replayed[0].created_at >= recorded[0].created_at);
+        assert!(replay
+            .pull_requests(&repo_id("acme", "other"), since, 1)
+            .await
+            .is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_fixture_dir_rejects_traversal() {
+        let root = Path::new("fixtures");
+        assert_eq!(
+            fixture_dir(root, &repo_id("acme", "widgets")).unwrap(),
+            root.join("acme").join("widgets")
+        );
+        assert!(fixture_dir(root, &repo_id("..", "etc")).is_err());
+        assert!(fixture_dir(root, &repo_id("acme", "a\\b")).is_err());
+    }
+}
@@ -0,0 +1,1757 @@
+//! Service layer for querying and caching repository metrics.
+//!
+//! This module implements `MetricsService`, which acts as the main entry point for retrieving
+//! repository metrics. It handles:
+//! 1. Checking the in-memory cache for existing data.
+//! 2. Fetching raw data from the `PullRequestSource` if the cache is empty.
+//! 3. Calculating domain-specific metrics from the raw data.
+//! 4. Proactively refreshing popular repositories in the background, on a schedule, as their
+//!    cache entries expire, or both (`REFRESH_STRATEGY`).
+//!
+//! Metrics fetched with a signed-in user's token are cached under that user when the repository
+//! is private, so private data is never served to anyone else.
+
+use crate::alerts::{AlertChannel, AlertRules, DigestEntry};
+use crate::business_days::{self, BusinessDays};
+use crate::config::{AppConfig, GitHubMode, PopularRepo};
+use crate::demo::DemoSource;
+use crate::domain::{GitHubPR, RepoId};
+use crate::influx::InfluxSink;
+use crate::insights;
+use crate::metrics::{self, RepoMetricsResponse};
+use crate::popular::{Added, PopularRepoStore};
+use crate::privacy::Anonymizer;
+use crate::replay::{RecordingSource, ReplaySource};
+use crate::snapshot::{self, RepoSnapshot, Snapshot};
+use crate::source::{
+    FetchedPullRequests, GitHubSource, PullRequestSource, RateLimit, RepoCalendar, SecurityAlerts,
+};
+use crate::statsd::StatsdExporter;
+use crate::upstream;
+use anyhow::Context;
+use bytes::Bytes;
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::{self, StreamExt};
+use moka::future::Cache;
+use moka::notification::RemovalCause;
+use rand::Rng;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration as StdDuration;
+use tokio::sync::mpsc;
+
+/// How often expired entries are looked for when refreshing on expiry. The cache only notices
+/// expirations while doing maintenance, which otherwise waits for the next read or write.
+const EXPIRY_CHECK_INTERVAL: StdDuration = StdDuration::from_secs(1);
+
+/// How long a repository's estimated page depth is reused before its pull requests are counted
+/// again. Counting uses the search API, whose quota is far smaller than the core one.
+const DEPTH_ESTIMATE_TTL: StdDuration = StdDuration::from_secs(24 * 60 * 60);
+
+/// The identity and OAuth token of a signed-in user on whose behalf a fetch is made.
+#[derive(Clone, Debug)]
+pub struct UserCredentials {
+    /// The GitHub login of the user.
+    pub login: String,
+    /// The user's OAuth access token.
+    pub token: String,
+}
+
+/// Partitions the cache so that private repository data stays with the user who fetched it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+enum CacheScope {
+    Public,
+    User(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct CacheKey {
+    repo_id: RepoId,
+    scope: CacheScope,
+}
+
+impl CacheKey {
+    fn public(repo_id: RepoId) -> Self {
+        Self {
+            repo_id,
+            scope: CacheScope::Public,
+        }
+    }
+
+    /// The key of what `user` sees of `repo_id`, or the server's token when `None`.
+    fn seen_by(repo_id: RepoId, user: Option<&UserCredentials>) -> Self {
+        Self {
+            repo_id,
+            scope: user.map_or(CacheScope::Public, |user| {
+                CacheScope::User(user.login.clone())
+            }),
+        }
+    }
+}
+
+/// Rolling window sizes, in days, computed alongside the configured one whenever the fetch window
+/// is long enough, so requests for any of them are served from the same cached fetch.
+pub const WINDOW_VARIANTS: [i64; 3] = [7, 30, 90];
+
+/// Metrics computed over one rolling window size.
+#[derive(Clone, Debug)]
+pub struct WindowedMetrics {
+    pub metrics: RepoMetricsResponse,
+    /// `metrics` serialized as JSON, so the common request needn't serialize them again.
+    pub json: Bytes,
+}
+
+/// Metrics as cached, with when and how completely their data was fetched.
+#[derive(Clone, Debug)]
+pub struct CachedMetrics {
+    /// Metrics for each available window size in days, all from the same pull requests.
+    windows: BTreeMap<i64, WindowedMetrics>,
+    /// The configured window size, used when a request doesn't ask for one.
+    default_window: i64,
+    /// Activity by weekday and hour across every fetched pull request.
+    pub heatmap: metrics::Heatmap,
+    pub fetched_at: DateTime<Utc>,
+    /// False when the page limit cut the fetch short, so older pull requests are missing.
+    pub complete: bool,
+    /// When incomplete, an upper bound on the pull requests that weren't fetched, if known.
+    pub unfetched: Option<u64>,
+    /// How far the fetched data can be trusted.
+    pub completeness: metrics::Completeness,
+    /// Whether the pull requests came from the startup snapshot rather than a fetch.
+    pub from_snapshot: bool,
+    /// The pull requests the metrics were calculated from, for views of a subset of them.
+    pull_requests: Vec<GitHubPR>,
+}
+
+impl CachedMetrics {
+    pub fn window(&self, days: i64) -> Option<&WindowedMetrics> {
+        self.windows.get(&days)
+    }
+
+    /// Metrics for the configured window size.
+    pub fn default_window(&self) -> &WindowedMetrics {
+        &self.windows[&self.default_window]
+    }
+
+    /// The pull requests of `repo`, as cached here, for a startup snapshot.
+    pub fn to_snapshot(&self, repo: RepoId) -> RepoSnapshot {
+        RepoSnapshot {
+            repo,
+            fetched_at: self.fetched_at,
+            truncated: !self.complete,
+            unfetched: self.unfetched,
+            pull_requests: self.pull_requests.clone(),
+        }
+    }
+}
+
+/// Outcome of the most recent background refreshes of a popular repository.
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct RefreshStatus {
+    pub last_attempt: Option<DateTime<Utc>>,
+    pub last_success: Option<DateTime<Utc>>,
+    pub last_error: Option<String>,
+}
+
+/// Progress of the background refresher, shared with the status endpoint.
+#[derive(Default)]
+struct RefreshTracker {
+    /// Repositories still waiting to be refreshed in the current cycle.
+    queue_depth: AtomicUsize,
+    /// Whether the first cycle, which warms the cache at startup, has finished.
+    preloaded: AtomicBool,
+    /// Whether the startup snapshot held every popular repository.
+    seeded: AtomicBool,
+    repos: RwLock<HashMap<RepoId, RefreshStatus>>,
+    /// Set while operators have paused refreshes.
+    maintenance: RwLock<Option<Maintenance>>,
+}
+
+/// A pause of background refreshes, and the alerts they send, during GitHub outages or planned
+/// work. Cached data keeps being served, but read-through fetches of uncached repositories still
+/// go to GitHub.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct Maintenance {
+    pub since: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+}
+
+/// A popular repository removed from the list, remembered for `REMOVED_REPO_RETENTION_DAYS` so
+/// it can be restored with the data it had.
+#[derive(Debug)]
+pub struct RemovedRepo {
+    pub popular: PopularRepo,
+    pub removed_at: DateTime<Utc>,
+    metrics: Option<Arc<CachedMetrics>>,
+    status: Option<RefreshStatus>,
+}
+
+#[derive(Clone)]
+pub struct MetricsService {
+    cache: Cache<CacheKey, Arc<CachedMetrics>>,
+    source: Arc<dyn PullRequestSource>,
+    config: AppConfig,
+    refresh: Arc<RefreshTracker>,
+    popular: Arc<PopularRepoStore>,
+    /// Page depths estimated from pull request counts, with `MAX_GITHUB_API_PAGES_CEILING`.
+    depths: Cache<RepoId, u32>,
+    /// Kept per user, since each sees the repositories their token can read.
+    branches: Cache<CacheKey, metrics::BranchStats>,
+    /// Kept per user, since GitHub only shows alerts to a repository's admins.
+    security: Cache<CacheKey, SecurityAlerts>,
+    calendars: Cache<RepoId, Arc<RepoCalendar>>,
+    /// Recently removed popular repositories, dropped once their retention ends.
+    removed: Cache<RepoId, Arc<RemovedRepo>>,
+    authors: Anonymizer,
+    statsd: Option<Arc<StatsdExporter>>,
+    influx: Option<Arc<InfluxSink>>,
+    alerts: Arc<AlertRules>,
+    /// Read-through fetches of uncached repositories in progress.
+    cold_fetches: Arc<AtomicUsize>,
+    /// Weekends and holidays, replaced when a holiday calendar is uploaded.
+    business_days: Arc<RwLock<Arc<BusinessDays>>>,
+    /// Held while an uploaded holiday calendar is saved, so the file matches the last one applied.
+    holidays_upload: Arc<tokio::sync::Mutex<()>>,
+    /// Pauses requests to the source, for every user, while it keeps failing.
+    breaker: Arc<upstream::CircuitBreaker>,
+}
+
+/// A claim on one of the `max_queued_cold_fetches` slots, released when dropped.
+struct ColdFetch(Arc<AtomicUsize>);
+
+impl Drop for ColdFetch {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+impl MetricsService {
+    /// Initializes a new MetricsService backed by GitHub, or by its recordings in replay mode.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        Ok(Self::with_source(config, Self::configured_source(config)?))
+    }
+
+    /// Initializes a MetricsService like `new`, but without the background refresh of popular
+    /// repositories, for one-shot use that would otherwise fetch them twice.
+    pub fn without_refresh(config: &AppConfig) -> anyhow::Result<Self> {
+        Ok(Self::build(config, Self::configured_source(config)?, None))
+    }
+
+    fn configured_source(config: &AppConfig) -> anyhow::Result<Arc<dyn PullRequestSource>> {
+        if config.demo_mode {
+            return Ok(Arc::new(DemoSource::new(config)));
+        }
+        let dir = config.github_fixtures_dir.clone();
+        Ok(match config.github_mode {
+            GitHubMode::Replay => Arc::new(ReplaySource::new(dir)),
+            mode => {
+                let github = Arc::new(GitHubSource::new(config)?);
+                if mode == GitHubMode::Record {
+                    Arc::new(RecordingSource::new(github, dir))
+                } else {
+                    github
+                }
+            }
+        })
+    }
+
+    /// Initializes a MetricsService that reads from the given source.
+    ///
+    /// This sets up the in-memory cache and starts the background refresh tasks for popular
+    /// repositories.
+    pub fn with_source(config: &AppConfig, source: Arc<dyn PullRequestSource>) -> Self {
+        if config.refresh_strategy.on_expiry() {
+            let (expired_tx, expired_rx) = mpsc::unbounded_channel();
+            let service = Self::build(config, source, Some(expired_tx));
+            service.start_seeding();
+            service.start_background_refresh();
+            service.start_expiry_refresh(expired_rx);
+            service
+        } else {
+            let service = Self::build(config, source, None);
+            service.start_seeding();
+            service.start_background_refresh();
+            service
+        }
+    }
+
+    /// Builds the service. Repositories whose public entries expire are sent to `expired`.
+    fn build(
+        config: &AppConfig,
+        source: Arc<dyn PullRequestSource>,
+        expired: Option<mpsc::UnboundedSender<RepoId>>,
+    ) -> Self {
+        let mut cache = Cache::builder()
+            .max_capacity(config.cache_max_capacity)
+            .time_to_live(config.cache_ttl());
+        if let Some(expired) = expired {
+            cache = cache.eviction_listener(move |key: Arc<CacheKey>, _, cause| {
+                if cause == RemovalCause::Expired && key.scope == CacheScope::Public {
+                    let _ = expired.send(key.repo_id.clone());
+                }
+            });
+        }
+        let cache = cache.build();
+
+        Self {
+            cache,
+            source,
+            config: config.clone(),
+            refresh: Arc::new(RefreshTracker::default()),
+            depths: Cache::builder()
+                .max_capacity(config.cache_max_capacity)
+                .time_to_live(DEPTH_ESTIMATE_TTL)
+                .build(),
+            branches: Cache::builder()
+                .max_capacity(config.cache_max_capacity)
+                .time_to_live(config.cache_ttl())
+                .build(),
+            security: Cache::builder()
+                .max_capacity(config.cache_max_capacity)
+                .time_to_live(config.cache_ttl())
+                .build(),
+            calendars: Cache::builder()
+                .max_capacity(config.cache_max_capacity)
+                .time_to_live(config.cache_ttl())
+                .build(),
+            authors: Anonymizer::new(config),
+            statsd: match StatsdExporter::new(config) {
+                Ok(exporter) => exporter.map(Arc::new),
+                Err(e) => {
+                    tracing::error!("StatsD export disabled: {:#}", e);
+                    None
+                }
+            },
+            influx: match InfluxSink::new(config) {
+                Ok(sink) => sink.map(Arc::new),
+                Err(e) => {
+                    tracing::error!("InfluxDB writes disabled: {:#}", e);
+                    None
+                }
+            },
+            alerts: Arc::new(AlertRules::new(config)),
+            cold_fetches: Arc::new(AtomicUsize::new(0)),
+            business_days: Arc::new(RwLock::new(Arc::new(BusinessDays::from_config(config)))),
+            holidays_upload: Arc::new(tokio::sync::Mutex::new(())),
+            breaker: Arc::new(upstream::CircuitBreaker::new(
+                config.github_circuit_threshold,
+                StdDuration::from_secs(config.github_circuit_cooldown_seconds),
+            )),
+            removed: Cache::builder()
+                .time_to_live(config.removed_repo_retention())
+                .build(),
+            popular: Arc::new(PopularRepoStore::new(
+                config.popular_repos.clone(),
+                config.popular_repos_file.clone(),
+            )),
+        }
+    }
+
+    /// Retrieves metrics for a repository, fetching them if not cached (read-through).
+    pub async fn get(&self, repo_id: RepoId) -> anyhow::Result<Arc<CachedMetrics>> {
+        let key = CacheKey::public(repo_id);
+        if let Some(metrics) = self.cache.get(&key).await {
+            return Ok(metrics);
+        }
+
+        let _slot = self.start_cold_fetch(&key.repo_id)?;
+        let metrics = self
+            .fetch_and_calculate_metrics(self.source.as_ref(), &key.repo_id)
+            .await?;
+
+        self.cache.insert(key, metrics.clone()).await;
+
+        Ok(metrics)
+    }
+
+    /// Returns the publicly cached metrics for a repository without fetching anything.
+    pub async fn cached(&self, repo_id: &RepoId) -> Option<Arc<CachedMetrics>> {
+        self.cache.get(&CacheKey::public(repo_id.clone())).await
+    }
+
+    /// Returns the publicly cached summary for a repository without fetching anything.
+    pub async fn cached_summary(&self, repo_id: &RepoId) -> Option<metrics::SummaryMetrics> {
+        self.cached(repo_id)
+            .await
+            .map(|cached| cached.default_window().metrics.summary.clone())
+    }
+
+    /// Counts a repository's branches and how many are stale, or returns `None` when the source
+    /// can't list branches.
+    pub async fn branch_stats(
+        &self,
+        repo_id: &RepoId,
+        user: Option<&UserCredentials>,
+    ) -> anyhow::Result<Option<metrics::BranchStats>> {
+        let key = CacheKey::seen_by(repo_id.clone(), user);
+        if let Some(stats) = self.branches.get(&key).await {
+            return Ok(Some(stats));
+        }
+        let source = self.source_for(user)?;
+        let fetched = self
+            .call_source(|| source.branches(repo_id, self.config.max_github_api_pages))
+            .await?;
+        let Some(fetched) = fetched else {
+            return Ok(None);
+        };
+        let stats = metrics::branch_stats(
+            fetched.total,
+            &fetched.last_commits,
+            self.config.stale_branch_days,
+            Utc::now(),
+        );
+        self.branches.insert(key, stats.clone()).await;
+        Ok(Some(stats))
+    }
+
+    /// Counts a repository's open security alerts with `user`'s token, or the server's when
+    /// `None`, or returns `None` when the source can't report them.
+    pub async fn security_alerts(
+        &self,
+        repo_id: &RepoId,
+        user: Option<&UserCredentials>,
+    ) -> anyhow::Result<Option<SecurityAlerts>> {
+        let key = CacheKey::seen_by(repo_id.clone(), user);
+        if let Some(alerts) = self.security.get(&key).await {
+            return Ok(Some(alerts));
+        }
+        let source = self.source_for(user)?;
+        let alerts = self
+            .call_source(|| source.security_alerts(repo_id, self.config.max_github_api_pages))
+            .await?;
+        if let Some(alerts) = &alerts {
+            self.security.insert(key, alerts.clone()).await;
+        }
+        Ok(alerts)
+    }
+
+    /// The source fetching with `user`'s token, or the server's when `None`.
+    fn source_for(
+        &self,
+        user: Option<&UserCredentials>,
+    ) -> anyhow::Result<Arc<dyn PullRequestSource>> {
+        match user {
+            Some(user) => self.source.for_user(&user.token),
+            None => Ok(self.source.clone()),
+        }
+    }
+
+    /// Lists a repository's releases and milestone due dates with the server's token, or returns
+    /// `None` when the source can't.
+    pub async fn calendar(&self, repo_id: &RepoId) -> anyhow::Result<Option<Arc<RepoCalendar>>> {
+        if let Some(calendar) = self.calendars.get(repo_id).await {
+            return Ok(Some(calendar));
+        }
+        let calendar = self
+            .call_source(|| {
+                self.source
+                    .calendar(repo_id, self.config.max_github_api_pages)
+            })
+            .await?;
+        let Some(calendar) = calendar else {
+            return Ok(None);
+        };
+        let calendar = Arc::new(calendar);
+        self.calendars
+            .insert(repo_id.clone(), calendar.clone())
+            .await;
+        Ok(Some(calendar))
+    }
+
+    /// Retrieves metrics using a signed-in user's token, which may grant access to private repos.
+    ///
+    /// Public repositories share the regular cache; private ones are cached per user.
+    pub async fn get_for_user(
+        &self,
+        repo_id: RepoId,
+        user: &UserCredentials,
+    ) -> anyhow::Result<Arc<CachedMetrics>> {
+        let public_key = CacheKey::public(repo_id.clone());
+        if let Some(metrics) = self.cache.get(&public_key).await {
+            return Ok(metrics);
+        }
+        let user_key = CacheKey {
+            repo_id,
+            scope: CacheScope::User(user.login.clone()),
+        };
+        if let Some(metrics) = self.cache.get(&user_key).await {
+            return Ok(metrics);
+        }
+
+        let _slot = self.start_cold_fetch(&user_key.repo_id)?;
+        let source = self.source.for_user(&user.token)?;
+        let is_public = self
+            .call_source(|| source.is_public(&user_key.repo_id))
+            .await?;
+        let metrics = self
+            .fetch_and_calculate_metrics(source.as_ref(), &user_key.repo_id)
+            .await?;
+
+        let key = if is_public { public_key } else { user_key };
+        self.cache.insert(key, metrics.clone()).await;
+
+        Ok(metrics)
+    }
+
+    /// Claims a cold fetch slot for `repo_id`, failing as overloaded when all are taken.
+    fn start_cold_fetch(&self, repo_id: &RepoId) -> anyhow::Result<ColdFetch> {
+        let limit = self.config.max_queued_cold_fetches;
+        let claimed =
+            self.cold_fetches
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |queued| {
+                    (limit == 0 || queued < limit).then_some(queued + 1)
+                });
+        if claimed.is_err() {
+            return Err(upstream::UpstreamError::new(
+                upstream::ErrorClass::Overloaded,
+                format!("{} cold fetches already queued before {}", limit, repo_id),
+            )
+            .into());
+        }
+        Ok(ColdFetch(self.cold_fetches.clone()))
+    }
+
+    /// Starts a background task serving the configured snapshot, if any, until popular
+    /// repositories are fetched.
+    fn start_seeding(&self) {
+        if self.config.snapshot_file.is_none() && self.config.snapshot_url.is_none() {
+            return;
+        }
+        let service = self.clone();
+        tokio::spawn(async move {
+            match snapshot::load(&service.config).await {
+                Ok(Some(snapshot)) => {
+                    let seeded = service.seed(snapshot).await;
+                    tracing::info!("Serving {} popular repositories from the snapshot", seeded);
+                }
+                Ok(None) => {}
+                Err(e) => tracing::error!("Failed to load the snapshot: {:#}", e),
+            }
+        });
+    }
+
+    /// Caches the metrics of the popular repositories in `snapshot` that haven't been fetched
+    /// yet, returning how many. The metrics are as of when each was snapshotted.
+    pub async fn seed(&self, snapshot: Snapshot) -> usize {
+        let popular: HashSet<RepoId> = self
+            .popular
+            .list()
+            .await
+            .into_iter()
+            .map(|popular| popular.id)
+            .collect();
+        let mut seeded = HashSet::new();
+        for repo in snapshot.repos {
+            if !popular.contains(&repo.repo) || seeded.contains(&repo.repo) {
+                continue;
+            }
+            let fetched = FetchedPullRequests {
+                pull_requests: repo.pull_requests,
+                truncated: repo.truncated,
+                unfetched: repo.unfetched,
+                review_comments: None,
+            };
+            let since = repo.fetched_at - Duration::days(self.config.pr_fetch_days);
+            let mut metrics = self.calculate(fetched, since, repo.fetched_at);
+            Arc::make_mut(&mut metrics).from_snapshot = true;
+            // A fetch that finished first is newer.
+            self.cache
+                .entry(CacheKey::public(repo.repo.clone()))
+                .or_insert(metrics)
+                .await;
+            seeded.insert(repo.repo);
+        }
+        if seeded.len() == popular.len() {
+            self.refresh.seeded.store(true, Ordering::Relaxed);
+        }
+        seeded.len()
+    }
+
+    /// Starts a background task that periodically refreshes metrics for popular repositories.
+    fn start_background_refresh(&self) {
+        let service = self.clone();
+        let config = self.config.clone();
+
+        tokio::spawn(async move {
+            tracing::info!("Starting background refresh task for popular repositories");
+            // Refresh popular repos at half their TTL to ensure they are always fresh/warm.
+            let period = config.cache_ttl() / 2;
+            let mut interval = tokio::time::interval(period);
+            let mut first_cycle = true;
+
+            loop {
+                interval.tick().await;
+                if service.maintenance().is_some() {
+                    tracing::info!("Skipping the refresh of popular repositories for maintenance");
+                    continue;
+                }
+                let cycle_start = tokio::time::Instant::now();
+                tracing::info!("Refreshing popular repositories...");
+                let popular_repos = service.popular.list().await;
+                service
+                    .refresh
+                    .queue_depth
+                    .store(popular_repos.len(), Ordering::Relaxed);
+
+                // Repositories share a request only when they share a page limit.
+                let mut by_max_pages: BTreeMap<u32, Vec<RepoId>> = BTreeMap::new();
+                for popular in popular_repos {
+                    let max_pages = service
+                        .max_pages(service.source.as_ref(), &popular.id, popular.max_pages)
+                        .await;
+                    by_max_pages.entry(max_pages).or_default().push(popular.id);
+                }
+                let batch_size = config.github_graphql_batch_size.max(1);
+                let batches: Vec<(&[RepoId], u32)> = by_max_pages
+                    .iter()
+                    .flat_map(|(max_pages, repo_ids)| {
+                        repo_ids.chunks(batch_size).map(|batch| (batch, *max_pages))
+                    })
+                    .collect();
+
+                // The first cycle warms an empty cache, so it runs at once. Later cycles spread
+                // batches over half the period to avoid a spike every period; the last refresh
+                // still lands well within the TTL of the one before.
+                let spread = if first_cycle {
+                    StdDuration::ZERO
+                } else {
+                    period / 2
+                };
+                first_cycle = false;
+                let offsets = refresh_offsets(batches.len(), spread);
+                stream::iter(offsets.into_iter().zip(batches))
+                    .for_each_concurrent(
+                        Some(config.popular_repos_concurrency_limit),
+                        |(offset, (batch, max_pages))| {
+                            let service = &service;
+                            async move {
+                                tokio::time::sleep_until(cycle_start + offset).await;
+                                service.refresh_batch(batch, max_pages).await
+                            }
+                        },
+                    )
+                    .await;
+
+                service.refresh.preloaded.store(true, Ordering::Relaxed);
+                tracing::info!("Finished refreshing popular repositories");
+                if let Some(channel) = &config.refresh_digest_channel {
+                    let repo_ids: Vec<&RepoId> = by_max_pages.values().flatten().collect();
+                    service.send_refresh_digest(channel, &repo_ids).await;
+                }
+                // Without a schedule, the first cycle only warms the cache for expiry refreshes.
+                if !config.refresh_strategy.is_scheduled() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Starts a background task that refetches popular repositories as their entries expire.
+    /// Other repositories are left to expire, so only what's being requested stays cached.
+    fn start_expiry_refresh(&self, mut expired: mpsc::UnboundedReceiver<RepoId>) {
+        let service = self.clone();
+
+        tokio::spawn(async move {
+            let mut maintenance = tokio::time::interval(EXPIRY_CHECK_INTERVAL);
+            loop {
+                tokio::select! {
+                    Some(repo_id) = expired.recv() => {
+                        let Some(popular) = service.popular.get(&repo_id).await else {
+                            continue;
+                        };
+                        tracing::info!("Refreshing expired popular repository {}", repo_id);
+                        let service = service.clone();
+                        tokio::spawn(async move {
+                            let max_pages = service
+                                .max_pages(service.source.as_ref(), &repo_id, popular.max_pages)
+                                .await;
+                            service.refresh_batch(&[repo_id], max_pages).await
+                        });
+                    }
+                    _ = maintenance.tick() => service.cache.run_pending_tasks().await,
+                }
+            }
+        });
+    }
+
+    /// Refreshes metrics for a batch of repositories and updates the cache.
+    ///
+    /// This is used by the background task to keep popular repositories' metrics warm.
+    async fn refresh_batch(&self, repo_ids: &[RepoId], max_pages: u32) {
+        if self.maintenance().is_some() {
+            return;
+        }
+        let attempted_at = Utc::now();
+        let since = self.fetch_cutoff();
+        let results = self
+            .source
+            .batch_pull_requests(repo_ids, since, max_pages)
+            .await;
+
+        for (repo_id, result) in repo_ids.iter().zip(results) {
+            // A batch shares one request, so retry only the repositories that failed transiently.
+            let result = match result {
+                Err(e) if upstream::classify(&e).is_retryable() => {
+                    self.fetch_pull_requests(self.source.as_ref(), repo_id, since)
+                        .await
+                }
+                result => result,
+            };
+            let error = match result {
+                Ok(fetched) => {
+                    let metrics = self.calculate(fetched, since, Utc::now());
+                    if let Some(statsd) = &self.statsd {
+                        statsd.export(
+                            repo_id,
+                            &metrics.default_window().metrics.summary,
+                            &metrics.completeness,
+                        );
+                    }
+                    if let Some(influx) = &self.influx {
+                        // Written in the background, so a slow InfluxDB can't hold up the cycle.
+                        let (influx, repo_id, metrics) =
+                            (influx.clone(), repo_id.clone(), metrics.clone());
+                        tokio::spawn(async move {
+                            let window = metrics.default_window();
+                            if let Err(e) = influx
+                                .write(&repo_id, &window.metrics, metrics.default_window)
+                                .await
+                            {
+                                tracing::warn!("Failed to write {} to InfluxDB: {:#}", repo_id, e);
+                            }
+                        });
+                    }
+                    {
+                        // Delivered in the background too, so slow channels can't hold it up.
+                        let (alerts, repo_id, metrics) =
+                            (self.alerts.clone(), repo_id.clone(), metrics.clone());
+                        tokio::spawn(async move {
+                            let window = &metrics.default_window().metrics;
+                            alerts
+                                .evaluate(&repo_id, &window.summary, window.time_series.last())
+                                .await;
+                        });
+                    }
+                    self.cache
+                        .insert(CacheKey::public(repo_id.clone()), metrics)
+                        .await;
+                    tracing::info!("Refreshed metrics for {}", repo_id);
+                    None
+                }
+                Err(e) => {
+                    tracing::error!("Failed to refresh popular repo {}: {}", repo_id, e);
+                    Some(e.to_string())
+                }
+            };
+            self.record_refresh(repo_id, attempted_at, error);
+        }
+    }
+
+    fn record_refresh(&self, repo_id: &RepoId, attempted_at: DateTime<Utc>, error: Option<String>) {
+        let _ = self
+            .refresh
+            .queue_depth
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |n| {
+                Some(n.saturating_sub(1))
+            });
+        let mut repos = self
+            .refresh
+            .repos
+            .write()
+            .expect("refresh status lock poisoned");
+        let status = repos.entry(repo_id.clone()).or_default();
+        status.last_attempt = Some(attempted_at);
+        if error.is_none() {
+            status.last_success = Some(attempted_at);
+        }
+        status.last_error = error;
+    }
+
+    /// Posts how each of `repo_ids` fared in the cycle that just finished to `channel`.
+    async fn send_refresh_digest(&self, channel: &AlertChannel, repo_ids: &[&RepoId]) {
+        let statuses = self.refresh_statuses();
+        let mut entries = Vec::with_capacity(repo_ids.len());
+        for &repo_id in repo_ids {
+            let error = statuses
+                .get(repo_id)
+                .and_then(|status| status.last_error.clone());
+            let summary = match error {
+                Some(_) => None,
+                None => self.cached_summary(repo_id).await,
+            };
+            entries.push(DigestEntry {
+                repo: repo_id.to_string(),
+                summary,
+                error,
+            });
+        }
+        if let Err(e) = self.alerts.send_digest(channel, &entries).await {
+            tracing::warn!("Failed to send the refresh digest: {:#}", e);
+        }
+    }
+
+    /// The alert rules checked after each refresh.
+    pub fn alert_rules(&self) -> &AlertRules {
+        &self.alerts
+    }
+
+    /// The popular repositories currently being kept warm.
+    pub async fn popular_repos(&self) -> Vec<PopularRepo> {
+        self.popular.list().await
+    }
+
+    /// Adds or updates a popular repository and refreshes it right away rather than waiting for
+    /// the next cycle.
+    pub async fn add_popular_repo(&self, popular: PopularRepo) -> anyhow::Result<Added> {
+        let repo_id = popular.id.clone();
+        let max_pages_override = popular.max_pages;
+        let added = self.popular.add(popular).await?;
+        self.removed.invalidate(&repo_id).await;
+
+        let service = self.clone();
+        tokio::spawn(async move {
+            let max_pages = service
+                .max_pages(service.source.as_ref(), &repo_id, max_pages_override)
+                .await;
+            service.refresh_batch(&[repo_id], max_pages).await
+        });
+        Ok(added)
+    }
+
+    /// Stops refreshing a popular repository, returning whether it was in the list. It can be
+    /// restored until `REMOVED_REPO_RETENTION_DAYS` have passed.
+    pub async fn remove_popular_repo(&self, repo_id: &RepoId) -> anyhow::Result<bool> {
+        let Some(popular) = self.popular.remove(repo_id).await? else {
+            return Ok(false);
+        };
+        let status = self
+            .refresh
+            .repos
+            .write()
+            .expect("refresh status lock poisoned")
+            .remove(repo_id);
+        if self.config.removed_repo_retention_days > 0 {
+            let removed = RemovedRepo {
+                popular,
+                removed_at: Utc::now(),
+                metrics: self.cache.get(&CacheKey::public(repo_id.clone())).await,
+                status,
+            };
+            self.removed
+                .insert(repo_id.clone(), Arc::new(removed))
+                .await;
+        }
+        Ok(true)
+    }
+
+    /// Popular repositories removed within `REMOVED_REPO_RETENTION_DAYS`, most recent first.
+    pub fn removed_repos(&self) -> Vec<Arc<RemovedRepo>> {
+        let mut removed: Vec<Arc<RemovedRepo>> =
+            self.removed.iter().map(|(_, removed)| removed).collect();
+        removed.sort_by_key(|removed| std::cmp::Reverse(removed.removed_at));
+        removed
+    }
+
+    /// Returns a recently removed repository to the popular list with its details, refresh
+    /// history and, while still fresh, its last metrics. Returns `None` if it wasn't removed
+    /// within `REMOVED_REPO_RETENTION_DAYS`.
+    pub async fn restore_popular_repo(
+        &self,
+        repo_id: &RepoId,
+    ) -> anyhow::Result<Option<PopularRepo>> {
+        let Some(removed) = self.removed.get(repo_id).await else {
+            return Ok(None);
+        };
+        // Restored before the refresh `add_popular_repo` starts, so they can't replace its results.
+        let key = CacheKey::public(repo_id.clone());
+        if let Some(metrics) = &removed.metrics {
+            let fresh = Utc::now() - metrics.fetched_at
+                < Duration::seconds(self.config.cache_ttl_seconds as i64);
+            if fresh && !self.cache.contains_key(&key) {
+                self.cache.insert(key, metrics.clone()).await;
+            }
+        }
+        if let Some(status) = &removed.status {
+            self.refresh
+                .repos
+                .write()
+                .expect("refresh status lock poisoned")
+                .insert(repo_id.clone(), status.clone());
+        }
+        if let Err(e) = self.add_popular_repo(removed.popular.clone()).await {
+            self.refresh
+                .repos
+                .write()
+                .expect("refresh status lock poisoned")
+                .remove(repo_id);
+            return Err(e);
+        }
+        Ok(Some(removed.popular.clone()))
+    }
+
+    /// Whether every popular repository has been fetched at least once since startup, whether
+    /// or not the fetches succeeded.
+    pub fn preload_complete(&self) -> bool {
+        self.refresh.preloaded.load(Ordering::Relaxed)
+    }
+
+    /// Whether the startup snapshot had metrics for every popular repository.
+    pub fn seeded(&self) -> bool {
+        self.refresh.seeded.load(Ordering::Relaxed)
+    }
+
+    /// The current maintenance pause, if any.
+    pub fn maintenance(&self) -> Option<Maintenance> {
+        self.refresh
+            .maintenance
+            .read()
+            .expect("maintenance lock poisoned")
+            .clone()
+    }
+
+    /// Pauses background refreshes and alerts with `Some`, or resumes them with `None`.
+    pub fn set_maintenance(&self, maintenance: Option<Maintenance>) {
+        match &maintenance {
+            Some(m) => tracing::warn!(
+                "Entering maintenance{}",
+                m.reason
+                    .as_deref()
+                    .map(|r| format!(": {}", r))
+                    .unwrap_or_default()
+            ),
+            None => tracing::info!("Leaving maintenance"),
+        }
+        *self
+            .refresh
+            .maintenance
+            .write()
+            .expect("maintenance lock poisoned") = maintenance;
+    }
+
+    /// Number of popular repositories still pending in the current refresh cycle.
+    pub fn refresh_queue_depth(&self) -> usize {
+        self.refresh.queue_depth.load(Ordering::Relaxed)
+    }
+
+    /// The latest refresh outcome for each popular repository that has been refreshed.
+    pub fn refresh_statuses(&self) -> HashMap<RepoId, RefreshStatus> {
+        self.refresh
+            .repos
+            .read()
+            .expect("refresh status lock poisoned")
+            .clone()
+    }
+
+    /// Approximate number of entries in the metrics cache.
+    pub fn cache_entry_count(&self) -> u64 {
+        self.cache.entry_count()
+    }
+
+    /// Checks at startup that the source is reachable and the configured token is accepted.
+    pub async fn check_github_access(&self) -> anyhow::Result<()> {
+        self.source.check_access().await
+    }
+
+    /// Fetches the source's current rate limits, if it has any.
+    pub async fn rate_limit(&self) -> anyhow::Result<Option<RateLimit>> {
+        self.source.rate_limit().await
+    }
+
+    /// Seconds until the rate limit that is failing requests resets, using the signed-in user's
+    /// limits when there is one.
+    pub async fn retry_after(&self, user: Option<&UserCredentials>) -> u64 {
+        let limits = match self.source_for(user) {
+            Ok(source) => source.rate_limit().await,
+            Err(e) => Err(e),
+        };
+        let limits = limits.unwrap_or_else(|e| {
+            tracing::debug!("Could not read rate limit for Retry-After: {}", e);
+            None
+        });
+        upstream::retry_after_seconds(limits.as_ref(), Utc::now())
+    }
+
+    /// Fetches PRs from the given source and calculates flow metrics.
+    async fn fetch_and_calculate_metrics(
+        &self,
+        source: &dyn PullRequestSource,
+        repo_id: &RepoId,
+    ) -> anyhow::Result<Arc<CachedMetrics>> {
+        let since = self.fetch_cutoff();
+        let fetched = self.fetch_pull_requests(source, repo_id, since).await?;
+        Ok(self.calculate(fetched, since, Utc::now()))
+    }
+
+    /// Fetches the PRs created since `since`, retrying transient failures.
+    async fn fetch_pull_requests(
+        &self,
+        source: &dyn PullRequestSource,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<FetchedPullRequests> {
+        let max_pages = self.pages_to_fetch(source, repo_id).await;
+        self.call_source(|| source.pull_requests(repo_id, since, max_pages))
+            .await
+    }
+
+    /// Runs `attempt` through the circuit breaker, retrying transient failures as configured.
+    async fn call_source<T, F, Fut>(&self, mut attempt: F) -> anyhow::Result<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = anyhow::Result<T>>,
+    {
+        upstream::retry(self.config.github_max_retries, || {
+            self.breaker.call(attempt())
+        })
+        .await
+    }
+
+    /// Pages a read-through fetch of `repo_id` would read, the best estimate of how long it takes.
+    pub async fn estimated_pages(&self, repo_id: &RepoId) -> u32 {
+        self.pages_to_fetch(self.source.as_ref(), repo_id).await
+    }
+
+    async fn pages_to_fetch(&self, source: &dyn PullRequestSource, repo_id: &RepoId) -> u32 {
+        let max_pages_override = self
+            .popular
+            .get(repo_id)
+            .await
+            .and_then(|popular| popular.max_pages);
+        self.max_pages(source, repo_id, max_pages_override).await
+    }
+
+    /// Pages to read for `repo_id`: its `max_pages` override, else enough for its pull requests in
+    /// the fetch window when `MAX_GITHUB_API_PAGES_CEILING` is set, else `MAX_GITHUB_API_PAGES`.
+    async fn max_pages(
+        &self,
+        source: &dyn PullRequestSource,
+        repo_id: &RepoId,
+        max_pages_override: Option<u32>,
+    ) -> u32 {
+        let default = self.config.max_github_api_pages;
+        if let Some(max_pages) = max_pages_override {
+            return max_pages;
+        }
+        let Some(ceiling) = self.config.max_github_api_pages_ceiling else {
+            return default;
+        };
+        if let Some(max_pages) = self.depths.get(repo_id).await {
+            return max_pages;
+        }
+
+        match source
+            .pull_request_count(repo_id, self.fetch_cutoff())
+            .await
+        {
+            Ok(Some(count)) => {
+                let max_pages = pages_for(count, self.config.github_per_page, ceiling);
+                tracing::debug!(
+                    "{} has {} pull requests in the fetch window; fetching up to {} pages",
+                    repo_id,
+                    count,
+                    max_pages
+                );
+                self.depths.insert(repo_id.clone(), max_pages).await;
+                max_pages
+            }
+            Ok(None) => default,
+            Err(e) => {
+                tracing::debug!(
+                    "Could not count pull requests for {}, fetching up to {} pages: {}",
+                    repo_id,
+                    default,
+                    e
+                );
+                default
+            }
+        }
+    }
+
+    /// The oldest creation date of pull requests that are fetched.
+    fn fetch_cutoff(&self) -> DateTime<Utc> {
+        Utc::now() - Duration::days(self.config.pr_fetch_days)
+    }
+
+    /// Window sizes requests may ask for: the configured one, plus each variant, and a week,
+    /// whose oldest displayed window still lies within the fetched pull requests.
+    pub fn window_sizes(&self) -> Vec<i64> {
+        let max_window = self.config.pr_fetch_days - self.config.metrics_days_to_display;
+        let mut sizes: Vec<i64> = WINDOW_VARIANTS
+            .into_iter()
+            .chain([self.week_window()])
+            .filter(|&days| self.span(days) <= max_window)
+            .chain([self.config.metrics_window_size])
+            .collect();
+        sizes.sort_unstable();
+        sizes.dedup();
+        sizes
+    }
+
+    /// The window size covering a week: 7 days, or a week's business days.
+    pub fn week_window(&self) -> i64 {
+        if self.config.business_days {
+            self.business_days().per_week()
+        } else {
+            7
+        }
+    }
+
+    /// The most calendar days a window of `days` spans.
+    fn span(&self, days: i64) -> i64 {
+        if self.config.business_days {
+            self.business_days().span(days)
+        } else {
+            days
+        }
+    }
+
+    /// The weekends and holidays windows skip in business-day mode, and that the time series
+    /// flags.
+    pub fn business_days(&self) -> Arc<BusinessDays> {
+        self.business_days
+            .read()
+            .expect("business days lock poisoned")
+            .clone()
+    }
+
+    /// Replaces the holidays of the previous calendar with those of the iCalendar file `ics`,
+    /// saving it to `holidays_file` if set. Metrics already cached keep the holidays they were
+    /// calculated with until they're refreshed.
+    pub async fn set_holiday_calendar(&self, ics: &str) -> anyhow::Result<Arc<BusinessDays>> {
+        let holiday_calendar = business_days::parse_calendar(ics)?;
+        let _upload = self.holidays_upload.lock().await;
+        if let Some(path) = &self.config.holidays_file {
+            let tmp = path.with_extension("tmp");
+            tokio::fs::write(&tmp, ics)
+                .await
+                .with_context(|| format!("failed to write {}", tmp.display()))?;
+            tokio::fs::rename(&tmp, path)
+                .await
+                .with_context(|| format!("failed to replace {}", path.display()))?;
+        }
+        let business_days = Arc::new(BusinessDays::new(
+            &self.config.weekend_days,
+            &[self.config.holidays.as_slice(), &holiday_calendar].concat(),
+        ));
+        *self
+            .business_days
+            .write()
+            .expect("business days lock poisoned") = business_days.clone();
+        tracing::info!(
+            "Loaded {} holidays from an uploaded calendar",
+            holiday_calendar.len()
+        );
+        Ok(business_days)
+    }
+
+    /// Calculates the metrics of `fetched`, the pull requests created since `since`, as of `now`,
+    /// when the fetch finished.
+    fn calculate(
+        &self,
+        mut fetched: FetchedPullRequests,
+        since: DateTime<Utc>,
+        now: DateTime<Utc>,
+    ) -> Arc<CachedMetrics> {
+        let mut completeness = metrics::completeness(
+            fetched.pull_requests.len() as u64,
+            fetched.truncated,
+            fetched.unfetched,
+            self.missing_fields(&fetched),
+        );
+        completeness.dropped_out_of_range =
+            metrics::drop_out_of_range(&mut fetched.pull_requests, since, now);
+        if completeness.dropped_out_of_range > 0 {
+            tracing::warn!(
+                "Left {} pull requests with impossible timestamps out of the metrics",
+                completeness.dropped_out_of_range
+            );
+        }
+        let timeline = metrics::Timeline::new(&fetched.pull_requests)
+            .with_labels(&fetched.pull_requests, &self.config.flow_labels)
+            .with_review_comments(fetched.review_comments)
+            .with_calendar(self.business_days(), self.config.business_days);
+        let mut longest_open =
+            metrics::longest_open(&fetched.pull_requests, self.config.longest_open_count, now);
+        for open in &mut longest_open {
+            open.author = open
+                .author
+                .as_deref()
+                .and_then(|login| self.authors.display(login));
+        }
+        let windows = self
+            .window_sizes()
+            .into_iter()
+            .map(|days| {
+                let mut metrics = self.window_metrics(&timeline, days, now);
+                metrics.longest_open = longest_open.clone();
+                // Plain data with string keys always serializes.
+                let json = serde_json::to_vec(&metrics)
+                    .expect("metrics serialize to JSON")
+                    .into();
+                (days, WindowedMetrics { metrics, json })
+            })
+            .collect();
+        Arc::new(CachedMetrics {
+            windows,
+            default_window: self.config.metrics_window_size,
+            heatmap: metrics::heatmap(&fetched.pull_requests),
+            fetched_at: now,
+            complete: !fetched.truncated,
+            unfetched: fetched.unfetched,
+            completeness,
+            from_snapshot: false,
+            pull_requests: fetched.pull_requests,
+        })
+    }
+
+    /// Metrics for the `days` window of `timeline`, with insights from the window before it.
+    fn window_metrics(
+        &self,
+        timeline: &metrics::Timeline,
+        days: i64,
+        now: DateTime<Utc>,
+    ) -> RepoMetricsResponse {
+        let window = Duration::days(days);
+        let mut metrics = metrics::calculate_timeline_metrics(
+            timeline,
+            Duration::days(self.config.metrics_days_to_display),
+            window,
+            now,
+        );
+        // The previous window is only compared when it was fetched in full.
+        if self.span(2 * days) <= self.config.pr_fetch_days {
+            // Where the latest point of every series ends.
+            let today_end = now
+                .date_naive()
+                .and_hms_opt(23, 59, 59)
+                .expect("a valid time")
+                .and_utc();
+            let prior_end = timeline.window_start(today_end, window);
+            let prior = metrics::calculate_day_metrics(timeline, prior_end, window);
+            if let Some(current) = metrics.time_series.last() {
+                metrics.insights = insights::generate(current, &prior, days);
+            }
+        }
+        metrics
+    }
+
+    /// Metrics for the `days` window of just the pull requests in `cached` that `keep` selects,
+    /// as of when they were fetched. Review comments can't be told apart by pull request, and
+    /// the longest open pull requests name their authors, so both are left out.
+    pub fn metrics_where(
+        &self,
+        cached: &CachedMetrics,
+        days: i64,
+        keep: impl Fn(&GitHubPR) -> bool,
+    ) -> RepoMetricsResponse {
+        let selected: Vec<GitHubPR> = cached
+            .pull_requests
+            .iter()
+            .filter(|pr| keep(pr))
+            .cloned()
+            .collect();
+        let timeline = metrics::Timeline::new(&selected)
+            .with_labels(&selected, &self.config.flow_labels)
+            .with_calendar(self.business_days(), self.config.business_days);
+        self.window_metrics(&timeline, days, cached.fetched_at)
+    }
+
+    /// The optional data `config` asks for that `fetched` lacks, as when a source can't provide it.
+    fn missing_fields(&self, fetched: &FetchedPullRequests) -> Vec<metrics::MissingField> {
+        let mut merged = fetched
+            .pull_requests
+            .iter()
+            .filter(|pr| pr.merged_at.is_some());
+        let mut missing = Vec::new();
+        if self.config.github_fetch_approvals && merged.clone().any(|pr| pr.approvals.is_none()) {
+            missing.push(metrics::MissingField::Approvals);
+        }
+        if self.config.github_fetch_merge_queue && merged.any(|pr| pr.merge_queue.is_none()) {
+            missing.push(metrics::MissingField::MergeQueue);
+        }
+        if self.config.github_fetch_review_comments && fetched.review_comments.is_none() {
+            missing.push(metrics::MissingField::ReviewComments);
+        }
+        missing
+    }
+}
+
+/// Enough pages for `count` pull requests, plus one for those opened since they were counted,
+/// but at most `ceiling`.
+fn pages_for(count: u64, per_page: u8, ceiling: u32) -> u32 {
+    let needed = count.div_ceil(u64::from(per_page.max(1))) + 1;
+    needed.min(u64::from(ceiling)) as u32
+}
+
+/// Random start offsets within `spread` for `count` refresh batches, in ascending order so that
+/// batches waiting for a concurrency slot are always the next ones due.
+fn refresh_offsets(count: usize, spread: StdDuration) -> Vec<StdDuration> {
+    let mut rng = rand::thread_rng();
+    let mut offsets: Vec<StdDuration> = (0..count)
+        .map(|_| spread.mul_f64(rng.gen::<f64>()))
+        .collect();
+    offsets.sort_unstable();
+    offsets
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pr, test_config, MockPullRequestSource};
+
+    fn user(login: &str) -> UserCredentials {
+        UserCredentials {
+            login: login.to_string(),
+            token: format!("gho_{}", login),
+        }
+    }
+
+    fn repo_id() -> RepoId {
+        RepoId {
+            owner: "acme".to_string(),
+            repo: "widgets".to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_private_repo_cached_per_user() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 2, None)])
+            .private();
+        let service = MetricsService::with_source(&test_config(&[]), Arc::new(source.clone()));
+
+        service
+            .get_for_user(repo_id(), &user("alice"))
+            .await
+            .unwrap();
+        service
+            .get_for_user(repo_id(), &user("alice"))
+            .await
+            .unwrap();
+        assert_eq!(source.calls(), 1);
+
+        service.get_for_user(repo_id(), &user("bob")).await.unwrap();
+        assert_eq!(source.calls(), 2);
+
+        // Anonymous requests must not see the private data cached for users.
+        service.get(repo_id()).await.unwrap();
+        assert_eq!(source.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_refresh_on_expiry() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .with_repo("acme/other", vec![]);
+        let config = test_config(&[
+            ("POPULAR_REPOS", "acme/widgets"),
+            ("CACHE_TTL_SECONDS", "1"),
+            ("REFRESH_STRATEGY", "on_expiry"),
+        ]);
+        let service = MetricsService::with_source(&config, Arc::new(source.clone()));
+        while !service.preload_complete() {
+            tokio::task::yield_now().await;
+        }
+        service.get("acme/other".parse().unwrap()).await.unwrap();
+        assert_eq!(source.calls(), 2);
+
+        // Real time, since the cache's expiry clock isn't Tokio's.
+        tokio::time::sleep(StdDuration::from_millis(2500)).await;
+        assert!(
+            source.calls() >= 3,
+            "expired popular repository was not refetched"
+        );
+        assert!(service.cached_summary(&repo_id()).await.is_some());
+        assert!(service
+            .cached_summary(&"acme/other".parse().unwrap())
+            .await
+            .is_none());
+    }
+
+    #[test]
+    fn test_pages_for() {
+        assert_eq!(pages_for(0, 100, 50), 1);
+        assert_eq!(pages_for(100, 100, 50), 2);
+        assert_eq!(pages_for(101, 100, 50), 3);
+        assert_eq!(pages_for(1_000_000, 100, 50), 50);
+    }
+
+    #[tokio::test]
+    async fn test_max_pages_follows_pull_request_count() {
+        let prs = (0..25).map(|id| pr(id, 1, None)).collect();
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", prs)
+            .with_repo("acme/quiet", vec![])
+            .counting();
+        let config = test_config(&[
+            ("GITHUB_PER_PAGE", "10"),
+            ("MAX_GITHUB_API_PAGES", "2"),
+            ("MAX_GITHUB_API_PAGES_CEILING", "20"),
+        ]);
+        let service = MetricsService::with_source(&config, Arc::new(source.clone()));
+
+        service.get(repo_id()).await.unwrap();
+        assert_eq!(source.last_max_pages(), 4);
+        service.get("acme/quiet".parse().unwrap()).await.unwrap();
+        assert_eq!(source.last_max_pages(), 1);
+
+        // Sources that can't count fall back to the global limit.
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", vec![]);
+        let service = MetricsService::with_source(&config, Arc::new(source.clone()));
+        service.get(repo_id()).await.unwrap();
+        assert_eq!(source.last_max_pages(), 2);
+    }
+
+    #[test]
+    fn test_refresh_offsets() {
+        let spread = StdDuration::from_secs(600);
+        let offsets = refresh_offsets(50, spread);
+        assert_eq!(offsets.len(), 50);
+        assert!(offsets.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(offsets.iter().all(|offset| *offset < spread));
+        assert!(refresh_offsets(3, StdDuration::ZERO)
+            .iter()
+            .all(|offset| offset.is_zero()));
+    }
+
+    #[tokio::test]
+    async fn test_review_comments_reach_metrics() {
+        let now = Utc::now();
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .with_review_comments(vec![now, now, now - Duration::days(400)]);
+        let service = MetricsService::with_source(&test_config(&[]), Arc::new(source));
+
+        let cached = service.get(repo_id()).await.unwrap();
+        let today = cached.default_window().metrics.time_series.last().unwrap();
+        assert_eq!(today.review_comments, Some(2));
+    }
+
+    #[tokio::test]
+    async fn test_missing_fields_lower_completeness() {
+        let config = test_config(&[
+            ("GITHUB_FETCH_APPROVALS", "true"),
+            ("GITHUB_FETCH_REVIEW_COMMENTS", "true"),
+        ]);
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 5, Some(1)), pr(2, 3, None)])
+            .with_review_comments(vec![]);
+        let service = MetricsService::with_source(&config, Arc::new(source));
+
+        let completeness = service.get(repo_id()).await.unwrap().completeness.clone();
+        assert_eq!(
+            completeness.missing_fields,
+            [metrics::MissingField::Approvals]
+        );
+        assert_eq!(completeness.score, 93);
+    }
+
+    #[tokio::test]
+    async fn test_pull_requests_near_the_cutoff_survive_a_long_fetch() {
+        let config = test_config(&[]);
+        let service =
+            MetricsService::with_source(&config, Arc::new(MockPullRequestSource::default()));
+        let since = Utc::now() - Duration::days(config.pr_fetch_days);
+        let fetched = FetchedPullRequests {
+            pull_requests: vec![GitHubPR {
+                created_at: since + Duration::minutes(1),
+                ..Default::default()
+            }],
+            ..Default::default()
+        };
+
+        let cached = service.calculate(fetched, since, Utc::now() + Duration::hours(2));
+        assert_eq!(cached.completeness.dropped_out_of_range, 0);
+    }
+
+    #[tokio::test]
+    async fn test_future_pull_requests_are_dropped() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 5, Some(1)), pr(2, -3, None)]);
+        let service = MetricsService::with_source(&test_config(&[]), Arc::new(source));
+
+        let cached = service.get(repo_id()).await.unwrap();
+        assert_eq!(cached.completeness.dropped_out_of_range, 1);
+        assert_eq!(cached.completeness.fetched_pull_requests, 2);
+        let summary = &cached.default_window().metrics.summary;
+        assert_eq!(summary.current_opened, 1);
+        assert_eq!(
+            cached
+                .default_window()
+                .metrics
+                .time_series
+                .last()
+                .unwrap()
+                .open_count,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn test_removed_repos_can_be_restored() {
+        let config = test_config(&[("POPULAR_REPOS", "acme/widgets")]);
+        let source =
+            MockPullRequestSource::default().with_repo("acme/widgets", vec![pr(1, 3, None)]);
+        let service = MetricsService::build(&config, Arc::new(source), None);
+        let fetched = service.get(repo_id()).await.unwrap();
+
+        assert!(service.remove_popular_repo(&repo_id()).await.unwrap());
+        assert!(service.popular_repos().await.is_empty());
+        assert_eq!(service.removed_repos().len(), 1);
+
+        service.cache.invalidate_all();
+        let restored = service.restore_popular_repo(&repo_id()).await.unwrap();
+        assert_eq!(restored.map(|popular| popular.id), Some(repo_id()));
+        assert_eq!(service.popular_repos().await.len(), 1);
+        assert!(service.removed_repos().is_empty());
+        let cached = service
+            .cache
+            .get(&CacheKey::public(repo_id()))
+            .await
+            .unwrap();
+        assert_eq!(cached.fetched_at, fetched.fetched_at);
+
+        assert!(service
+            .restore_popular_repo(&repo_id())
+            .await
+            .unwrap()
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_popular_repos_are_seeded_from_the_snapshot() {
+        let path =
+            std::env::temp_dir().join(format!("repoflow-snapshot-{}.json", rand::random::<u64>()));
+        let config = test_config(&[
+            ("POPULAR_REPOS", "acme/widgets"),
+            ("SNAPSHOT_FILE", path.to_str().unwrap()),
+        ]);
+        let source =
+            MockPullRequestSource::default().with_repo("acme/widgets", vec![pr(1, 1, None)]);
+        let service = MetricsService::build(&config, Arc::new(source), None);
+        let snapshotted_at = Utc::now() - Duration::days(2);
+        let repo = |repo: &str, pull_requests| RepoSnapshot {
+            repo: repo.parse().unwrap(),
+            fetched_at: snapshotted_at,
+            truncated: false,
+            unfetched: None,
+            pull_requests,
+        };
+        let snapshot = Snapshot {
+            created_at: snapshotted_at,
+            repos: vec![
+                repo("acme/widgets", vec![pr(1, 5, None), pr(2, 4, Some(3))]),
+                repo("acme/gadgets", vec![]),
+            ],
+        };
+        snapshot::write(&snapshot, &path).await.unwrap();
+
+        let loaded = snapshot::load(&config).await.unwrap().unwrap();
+        assert_eq!(service.seed(loaded).await, 1);
+        assert!(service.seeded());
+        let cached = service.cached(&repo_id()).await.unwrap();
+        assert!(cached.from_snapshot);
+        assert_eq!(cached.fetched_at, snapshotted_at);
+        assert_eq!(cached.default_window().metrics.summary.current_opened, 2);
+        assert!(service
+            .cached(&"acme/gadgets".parse().unwrap())
+            .await
+            .is_none());
+
+        // The first fetch replaces it, and a snapshot loaded later doesn't undo that.
+        service.refresh_batch(&[repo_id()], 1).await;
+        assert!(!service.cached(&repo_id()).await.unwrap().from_snapshot);
+        service.seed(snapshot).await;
+        assert!(!service.cached(&repo_id()).await.unwrap().from_snapshot);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_removed_repos_are_forgotten_without_retention() {
+        let config = test_config(&[
+            ("POPULAR_REPOS", "acme/widgets"),
+            ("REMOVED_REPO_RETENTION_DAYS", "0"),
+        ]);
+        let service =
+            MetricsService::build(&config, Arc::new(MockPullRequestSource::default()), None);
+        assert!(service.remove_popular_repo(&repo_id()).await.unwrap());
+        assert!(service.removed_repos().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_metrics_of_a_subset() {
+        let mut prs = vec![pr(1, 5, Some(1)), pr(2, 3, None), pr(3, 2, None)];
+        prs[0].author = Some("alice".to_string());
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", prs);
+        let service = MetricsService::with_source(&test_config(&[]), Arc::new(source));
+
+        let cached = service.get(repo_id()).await.unwrap();
+        let metrics =
+            service.metrics_where(&cached, 30, |pr| pr.author.as_deref() == Some("alice"));
+        assert_eq!(metrics.summary.current_opened, 1);
+        assert_eq!(metrics.summary.current_merged, 1);
+        assert!(metrics.longest_open.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_longest_open_in_every_window() {
+        let source = MockPullRequestSource::default().with_repo(
+            "acme/widgets",
+            vec![pr(1, 5, None), pr(2, 8, Some(1)), pr(3, 9, None)],
+        );
+        let config = test_config(&[("LONGEST_OPEN_COUNT", "1")]);
+        let service = MetricsService::with_source(&config, Arc::new(source));
+
+        let cached = service.get(repo_id()).await.unwrap();
+        for days in service.window_sizes() {
+            let longest = &cached.window(days).unwrap().metrics.longest_open;
+            assert_eq!(longest.len(), 1);
+            assert_eq!(longest[0].number, 3);
+            assert_eq!(longest[0].age_days, 9);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_longest_open_authors_are_anonymized() {
+        let mut prs = vec![pr(1, 5, None), pr(2, 9, None)];
+        prs[0].author = Some("alice".to_string());
+        prs[1].author = Some("bob".to_string());
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", prs);
+        let config = test_config(&[("AUTHOR_PRIVACY", "hash"), ("AUTHOR_OPT_OUT", "bob")]);
+        let service = MetricsService::with_source(&config, Arc::new(source));
+
+        let cached = service.get(repo_id()).await.unwrap();
+        let longest = &cached.default_window().metrics.longest_open;
+        assert_eq!(longest[0].author, None);
+        assert!(longest[1].author.as_ref().unwrap().starts_with("author-"));
+        // Team filters still see real logins.
+        let metrics =
+            service.metrics_where(&cached, 30, |pr| pr.author.as_deref() == Some("alice"));
+        assert_eq!(metrics.summary.current_opened, 1);
+    }
+
+    #[tokio::test]
+    async fn test_insights_need_the_prior_window() {
+        // Ten opened in the last week against five the week before.
+        let prs: Vec<_> = (0..15)
+            .map(|id| pr(id, if id < 10 { 1 } else { 10 }, None))
+            .collect();
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", prs);
+        let config = test_config(&[
+            ("PR_FETCH_DAYS", "37"),
+            ("METRICS_DAYS_TO_DISPLAY", "7"),
+            ("METRICS_WINDOW_SIZE", "7"),
+        ]);
+        let service = MetricsService::with_source(&config, Arc::new(source));
+
+        let cached = service.get(repo_id()).await.unwrap();
+        let insights = &cached.window(7).unwrap().metrics.insights;
+        assert!(
+            insights.contains(&"More PRs opened (+100%) vs the prior 7 days".to_string()),
+            "{:?}",
+            insights
+        );
+        // Twice 30 days reaches past the fetch window.
+        assert!(cached.window(30).unwrap().metrics.insights.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_business_day_windows_skip_holidays() {
+        let today = Utc::now().date_naive();
+        let holidays: Vec<String> = (0..10)
+            .map(|days| (today - Duration::days(days)).to_string())
+            .collect();
+        let holidays = holidays.join(",");
+        let config = |business_days| {
+            test_config(&[
+                ("METRICS_DAYS_TO_DISPLAY", "1"),
+                ("METRICS_WINDOW_SIZE", "7"),
+                ("BUSINESS_DAYS", business_days),
+                ("WEEKEND_DAYS", ""),
+                ("HOLIDAYS", &holidays),
+            ])
+        };
+        let opened = |business_days| async move {
+            let source = MockPullRequestSource::default()
+                .with_repo("acme/widgets", vec![pr(1, 2, None), pr(2, 12, None)]);
+            let service = MetricsService::with_source(&config(business_days), Arc::new(source));
+            let cached = service.get(repo_id()).await.unwrap();
+            cached
+                .window(7)
+                .unwrap()
+                .metrics
+                .time_series
+                .last()
+                .unwrap()
+                .opened
+        };
+
+        assert_eq!(opened("false").await, 1);
+        // Seven business days before today reach back past the ten days off.
+        assert_eq!(opened("true").await, 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_hits_share_metrics() {
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", vec![]);
+        let service = MetricsService::with_source(&test_config(&[]), Arc::new(source));
+
+        let first = service.get(repo_id()).await.unwrap();
+        let second = service.get(repo_id()).await.unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+    }
+
+    #[tokio::test]
+    async fn test_refresh_batch_records_each_repo() {
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", vec![]);
+        let service = MetricsService::with_source(&test_config(&[]), Arc::new(source.clone()));
+        let missing = RepoId {
+            owner: "acme".to_string(),
+            repo: "missing".to_string(),
+        };
+
+        service
+            .refresh_batch(&[repo_id(), missing.clone()], 1)
+            .await;
+
+        let statuses = service.refresh_statuses();
+        assert!(statuses[&repo_id()].last_success.is_some());
+        assert!(statuses[&missing].last_success.is_none());
+        assert!(statuses[&missing].last_error.is_some());
+        service.get(repo_id()).await.unwrap();
+        assert_eq!(
+            source.calls(),
+            2,
+            "refreshed repo should be served from cache"
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_transient_failures_are_retried() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .failing(2);
+        let service = MetricsService::with_source(&test_config(&[]), Arc::new(source.clone()));
+
+        service.get(repo_id()).await.unwrap();
+        assert_eq!(source.calls(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_public_repo_shared_between_users() {
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", vec![]);
+        let service = MetricsService::with_source(&test_config(&[]), Arc::new(source.clone()));
+
+        service
+            .get_for_user(repo_id(), &user("alice"))
+            .await
+            .unwrap();
+        service.get_for_user(repo_id(), &user("bob")).await.unwrap();
+        service.get(repo_id()).await.unwrap();
+        assert_eq!(source.calls(), 1);
+    }
+}
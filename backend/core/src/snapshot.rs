@@ -0,0 +1,86 @@
+//! Seed snapshots of the pull requests of popular repositories, so a new instance serves
+//! metrics at once while its first fetches from GitHub run in the background.
+//!
+//! A snapshot is written by `repoflow snapshot` and read at startup from `SNAPSHOT_URL` or
+//! `SNAPSHOT_FILE`. It holds pull requests rather than the metrics calculated from them, so every
+//! window size and filter can be served from it as from a fetch.
+
+use crate::config::AppConfig;
+use crate::domain::{GitHubPR, RepoId};
+use crate::http_client;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration as StdDuration;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    pub created_at: DateTime<Utc>,
+    pub repos: Vec<RepoSnapshot>,
+}
+
+/// One repository's pull requests as they were fetched.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RepoSnapshot {
+    pub repo: RepoId,
+    pub fetched_at: DateTime<Utc>,
+    /// Whether the page limit cut the fetch short.
+    #[serde(default)]
+    pub truncated: bool,
+    #[serde(default)]
+    pub unfetched: Option<u64>,
+    pub pull_requests: Vec<GitHubPR>,
+}
+
+/// Reads the snapshot `config` points at: from `snapshot_url` if set, falling back to
+/// `snapshot_file` if that fails. Returns `None` when neither is configured.
+pub async fn load(config: &AppConfig) -> anyhow::Result<Option<Snapshot>> {
+    if let Some(url) = &config.snapshot_url {
+        match download(config, url).await {
+            Ok(snapshot) => return Ok(Some(snapshot)),
+            Err(e) if config.snapshot_file.is_some() => {
+                tracing::warn!("Falling back to SNAPSHOT_FILE: {:#}", e);
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    match &config.snapshot_file {
+        Some(path) => read(path).await.map(Some),
+        None => Ok(None),
+    }
+}
+
+async fn download(config: &AppConfig, url: &str) -> anyhow::Result<Snapshot> {
+    let response = http_client::reqwest_client(config)?
+        .get(url)
+        .timeout(StdDuration::from_secs(config.request_timeout_seconds))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("failed to download the snapshot from {}", url))?;
+    let body = response
+        .bytes()
+        .await
+        .with_context(|| format!("failed to download the snapshot from {}", url))?;
+    serde_json::from_slice(&body).with_context(|| format!("invalid snapshot at {}", url))
+}
+
+async fn read(path: &Path) -> anyhow::Result<Snapshot> {
+    let contents = tokio::fs::read(path)
+        .await
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    serde_json::from_slice(&contents)
+        .with_context(|| format!("invalid snapshot {}", path.display()))
+}
+
+/// Writes `snapshot` to `path` as JSON, replacing any file there atomically.
+pub async fn write(snapshot: &Snapshot, path: &Path) -> anyhow::Result<()> {
+    let tmp = path.with_extension("tmp");
+    tokio::fs::write(&tmp, serde_json::to_vec(snapshot)?)
+        .await
+        .with_context(|| format!("failed to write {}", tmp.display()))?;
+    tokio::fs::rename(&tmp, path)
+        .await
+        .with_context(|| format!("failed to replace {}", path.display()))
+}
@@ -0,0 +1,1948 @@
+//! Where pull request data comes from.
+//!
+//! `MetricsService` only depends on the `PullRequestSource` trait, so the forge it talks to can be
+//! swapped out (e.g. for tests or other code hosts). `GitHubSource` is the production
+//! implementation backed by Octocrab.
+
+use crate::config::AppConfig;
+use crate::domain::{closing_issue_references, GitHubPR, MergeQueueEntry, PRState, RepoId};
+use crate::http_client;
+use crate::upstream::{ErrorClass, UpstreamError};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::stream::{self, StreamExt, TryStreamExt};
+use moka::future::Cache;
+use octocrab::models::pulls::PullRequest;
+use octocrab::{FromResponse, Octocrab, Page};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::Arc;
+use tokio::sync::Semaphore;
+
+/// Pull requests fetched for one repository.
+#[derive(Clone, Debug, Default)]
+pub struct FetchedPullRequests {
+    /// Pull requests created at or after the requested cutoff, newest first.
+    pub pull_requests: Vec<GitHubPR>,
+    /// Whether the page limit was reached before the cutoff, leaving older pull requests in the
+    /// window unfetched.
+    pub truncated: bool,
+    /// When truncated, an upper bound on the pull requests left unfetched, if the provider
+    /// reports enough to tell. It can include pull requests older than the cutoff.
+    pub unfetched: Option<u64>,
+    /// When each review comment posted at or after the cutoff was created, newest first, if
+    /// review comments were fetched.
+    pub review_comments: Option<Vec<DateTime<Utc>>>,
+}
+
+/// A repository's branches, for spotting ones nobody has touched in a while.
+#[derive(Clone, Debug, Default)]
+pub struct FetchedBranches {
+    /// How many branches the repository has.
+    pub total: u64,
+    /// When the head commit of each fetched branch was committed. Shorter than `total` when the
+    /// page limit was reached first.
+    pub last_commits: Vec<DateTime<Utc>>,
+}
+
+/// Counts of a repository's open security work. A count is `None` when the credentials can't
+/// see it, e.g. when Dependabot alerts are disabled or need a permission the token lacks.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SecurityAlerts {
+    /// Open Dependabot alerts.
+    pub open_dependabot_alerts: Option<u64>,
+    /// Security advisories the repository has published.
+    pub published_advisories: Option<u64>,
+    /// False when the page limit was reached before every alert or advisory was counted.
+    pub complete: bool,
+}
+
+/// A repository's releases and milestone due dates, for overlaying its cadence on a calendar.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RepoCalendar {
+    /// Published releases, newest first.
+    pub releases: Vec<Release>,
+    /// Milestones with a due date, open or closed.
+    pub milestones: Vec<Milestone>,
+    /// False when the page limit was reached before every release or milestone was fetched.
+    pub complete: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Release {
+    pub tag_name: String,
+    pub name: Option<String>,
+    pub published_at: DateTime<Utc>,
+    pub url: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Milestone {
+    pub number: u64,
+    pub title: String,
+    pub due_on: DateTime<Utc>,
+    pub closed: bool,
+    pub url: String,
+}
+
+/// A provider's request quotas.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RateLimit {
+    /// The quota most requests count against.
+    pub core: Rate,
+    /// The separate, smaller quota of search requests.
+    pub search: Rate,
+}
+
+/// One request quota.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Rate {
+    /// Requests allowed per period.
+    pub limit: u64,
+    pub used: u64,
+    pub remaining: u64,
+    /// When the period ends, in seconds since the Unix epoch.
+    pub reset: u64,
+}
+
+impl From<octocrab::models::RateLimit> for RateLimit {
+    fn from(limits: octocrab::models::RateLimit) -> Self {
+        let rate = |rate: octocrab::models::Rate| Rate {
+            limit: rate.limit as u64,
+            used: rate.used as u64,
+            remaining: rate.remaining as u64,
+            reset: rate.reset,
+        };
+        Self {
+            core: rate(limits.resources.core),
+            search: rate(limits.resources.search),
+        }
+    }
+}
+
+/// A provider of pull request history for repositories.
+#[async_trait]
+pub trait PullRequestSource: Send + Sync {
+    /// Fetches pull requests created at or after `since`, newest first, reading at most
+    /// `max_pages` pages from the provider.
+    async fn pull_requests(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> anyhow::Result<FetchedPullRequests>;
+
+    /// Fetches pull requests for several repositories, returning results in the same order.
+    /// Sources that can't batch requests fetch each repository concurrently.
+    async fn batch_pull_requests(
+        &self,
+        repo_ids: &[RepoId],
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> Vec<anyhow::Result<FetchedPullRequests>> {
+        futures::future::join_all(
+            repo_ids
+                .iter()
+                .map(|repo_id| self.pull_requests(repo_id, since, max_pages)),
+        )
+        .await
+    }
+
+    /// Counts the pull requests created at or after `since` without fetching them, if the
+    /// provider can.
+    async fn pull_request_count(
+        &self,
+        _repo_id: &RepoId,
+        _since: DateTime<Utc>,
+    ) -> anyhow::Result<Option<u64>> {
+        Ok(None)
+    }
+
+    /// Fetches the repository's branches, reading at most `max_pages` pages, if the provider
+    /// can.
+    async fn branches(
+        &self,
+        _repo_id: &RepoId,
+        _max_pages: u32,
+    ) -> anyhow::Result<Option<FetchedBranches>> {
+        Ok(None)
+    }
+
+    /// Counts the repository's open Dependabot alerts and published security advisories,
+    /// reading at most `max_pages` pages of each, if the provider can.
+    async fn security_alerts(
+        &self,
+        _repo_id: &RepoId,
+        _max_pages: u32,
+    ) -> anyhow::Result<Option<SecurityAlerts>> {
+        Ok(None)
+    }
+
+    /// Fetches the repository's releases and milestones, reading at most `max_pages` pages of
+    /// each, if the provider can.
+    async fn calendar(
+        &self,
+        _repo_id: &RepoId,
+        _max_pages: u32,
+    ) -> anyhow::Result<Option<RepoCalendar>> {
+        Ok(None)
+    }
+
+    /// Returns whether the repository is publicly visible. Unknown visibility is reported as
+    /// private.
+    async fn is_public(&self, repo_id: &RepoId) -> anyhow::Result<bool>;
+
+    /// Returns a source that acts on behalf of a signed-in user holding `token`.
+    fn for_user(&self, token: &str) -> anyhow::Result<Arc<dyn PullRequestSource>>;
+
+    /// Checks that the provider is reachable and accepts our credentials.
+    async fn check_access(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    /// The provider's current rate limits, if it has any.
+    async fn rate_limit(&self) -> anyhow::Result<Option<RateLimit>> {
+        Ok(None)
+    }
+}
+
+/// Each page fetched in parallel must be covered by this many remaining requests, so a single
+/// repository can't burn through the last of the rate limit in one burst.
+const RATE_LIMIT_PER_CONCURRENT_PAGE: usize = 100;
+
+/// GitHub's search API never returns more than this many results for one query.
+const SEARCH_RESULT_LIMIT: u64 = 1000;
+
+/// How many merged pull requests' details are kept between fetches.
+const MERGED_DETAILS_CAPACITY: u64 = 100_000;
+
+/// Identifies one version of a pull request: its repository, number and last update.
+type MergedDetailsKey = (RepoId, u64, DateTime<Utc>);
+
+/// Logs a detail of a pull request that couldn't be fetched, leaving it unknown.
+fn known<T>(repo_id: &RepoId, number: u64, detail: &str, result: anyhow::Result<T>) -> Option<T> {
+    result
+        .inspect_err(|e| tracing::warn!("Failed to fetch {detail} of {repo_id}#{number}: {e:#}"))
+        .ok()
+}
+
+/// What was fetched for one merged pull request beyond the pull request itself.
+#[derive(Clone, Default)]
+struct MergedDetails {
+    approvals: Option<u32>,
+    merge_queue: Option<Vec<MergeQueueEntry>>,
+}
+
+#[derive(Serialize)]
+struct SearchParams<'a> {
+    q: &'a str,
+    sort: &'static str,
+    order: &'static str,
+    per_page: u8,
+    page: u32,
+}
+
+/// The subset of a `search/issues` response we use. Octocrab's `Issue` model omits
+/// `pull_request.merged_at`, which the search API does return.
+#[derive(Deserialize)]
+struct SearchPage {
+    total_count: u64,
+    items: Vec<SearchItem>,
+}
+
+/// A search result. Its `id` is the issue's, not the pull request's, so it isn't read.
+#[derive(Deserialize)]
+struct SearchItem {
+    number: u64,
+    #[serde(default)]
+    title: String,
+    user: Option<SearchUser>,
+    #[serde(default)]
+    labels: Vec<NamedLabel>,
+    state: String,
+    created_at: DateTime<Utc>,
+    updated_at: Option<DateTime<Utc>>,
+    closed_at: Option<DateTime<Utc>>,
+    body: Option<String>,
+    pull_request: Option<SearchPullRequest>,
+}
+
+#[derive(Deserialize)]
+struct SearchUser {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct NamedLabel {
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct SearchPullRequest {
+    merged_at: Option<DateTime<Utc>>,
+}
+
+impl From<SearchItem> for GitHubPR {
+    fn from(item: SearchItem) -> Self {
+        let merged_at = item.pull_request.and_then(|pr| pr.merged_at);
+        let state = match (merged_at, item.state.as_str()) {
+            (Some(_), _) => PRState::Merged,
+            (None, "open") => PRState::Open,
+            (None, "closed") => PRState::Closed,
+            _ => PRState::Unknown,
+        };
+        GitHubPR {
+            id: item.number,
+            number: item.number,
+            title: item.title,
+            author: item.user.map(|user| user.login),
+            labels: item.labels.into_iter().map(|label| label.name).collect(),
+            created_at: item.created_at,
+            merged_at,
+            closed_at: item.closed_at,
+            updated_at: item.updated_at,
+            state,
+            closes_issues: item
+                .body
+                .as_deref()
+                .map(closing_issue_references)
+                .unwrap_or_default(),
+            approvals: None,
+            merge_queue: None,
+        }
+    }
+}
+
+/// The fields requested for each page of a repository's pull requests.
+fn graphql_pr_fields(approvals: bool, merge_queue: bool) -> String {
+    // Each reviewer's latest approving or change-requesting review; ten is plenty to tell
+    // zero, one and several approvals apart.
+    let reviews = if approvals {
+        " latestOpinionatedReviews(first: 10) { nodes { state } }"
+    } else {
+        ""
+    };
+    let queue = if merge_queue {
+        " timelineItems(itemTypes: [ADDED_TO_MERGE_QUEUE_EVENT, REMOVED_FROM_MERGE_QUEUE_EVENT], first: 50) { nodes { __typename ... on AddedToMergeQueueEvent { createdAt } ... on RemovedFromMergeQueueEvent { createdAt } } }"
+    } else {
+        ""
+    };
+    format!(
+        "totalCount pageInfo {{ hasNextPage endCursor }} nodes {{ databaseId number title author {{ login }} labels(first: 20) {{ nodes {{ name }} }} createdAt mergedAt closedAt updatedAt state body{reviews}{queue} }}"
+    )
+}
+
+/// One event from the REST issue timeline; only merge queue events are used.
+#[derive(Deserialize)]
+struct TimelineEvent {
+    event: Option<String>,
+    created_at: Option<DateTime<Utc>>,
+}
+
+/// Pairs merge queue events, `(added, at)`, into stays in the queue. A stay with no removal
+/// ended with the merge, or hasn't ended.
+fn merge_queue_entries(mut events: Vec<(bool, DateTime<Utc>)>) -> Vec<MergeQueueEntry> {
+    events.sort_by_key(|(_, at)| *at);
+    let mut entries: Vec<MergeQueueEntry> = Vec::new();
+    for (added, at) in events {
+        match entries.last_mut() {
+            Some(entry) if !added && entry.dequeued_at.is_none() => entry.dequeued_at = Some(at),
+            _ if added => entries.push(MergeQueueEntry {
+                enqueued_at: at,
+                dequeued_at: None,
+            }),
+            // A removal without a matching addition, e.g. one beyond the events fetched.
+            _ => {}
+        }
+    }
+    entries
+}
+
+/// One reviewer's review of a pull request, from the REST reviews endpoint.
+#[derive(Deserialize)]
+struct ReviewItem {
+    user: Option<ReviewUser>,
+    state: String,
+}
+
+#[derive(Deserialize)]
+struct ReviewUser {
+    id: u64,
+}
+
+/// Counts the reviewers whose latest opinion, in review order, is an approval. Comments don't
+/// change an opinion; a dismissal withdraws it.
+fn count_approvals(reviews: &[ReviewItem]) -> u32 {
+    let mut opinions = HashMap::new();
+    for review in reviews {
+        let Some(user) = &review.user else {
+            continue;
+        };
+        match review.state.as_str() {
+            "APPROVED" | "CHANGES_REQUESTED" => {
+                opinions.insert(user.id, review.state == "APPROVED");
+            }
+            "DISMISSED" => {
+                opinions.remove(&user.id);
+            }
+            _ => {}
+        }
+    }
+    opinions.values().filter(|approved| **approved).count() as u32
+}
+
+/// The subset of a pull request review comment we use.
+#[derive(Deserialize)]
+struct ReviewComment {
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Serialize)]
+struct ReviewCommentParams {
+    sort: &'static str,
+    direction: &'static str,
+    since: DateTime<Utc>,
+    per_page: u8,
+    page: u32,
+}
+
+#[derive(Serialize)]
+struct StateParams {
+    state: &'static str,
+    per_page: u8,
+}
+
+#[derive(Serialize)]
+struct PerPageParams {
+    per_page: u8,
+}
+
+#[derive(Serialize)]
+struct ListParams {
+    state: &'static str,
+    sort: &'static str,
+    direction: &'static str,
+    per_page: u8,
+    page: u32,
+}
+
+/// A page of results, with the core rate limit GitHub reported as remaining after it.
+struct RateLimitedPage<T> {
+    page: Page<T>,
+    remaining: Option<usize>,
+}
+
+#[async_trait]
+impl<T: serde::de::DeserializeOwned> octocrab::FromResponse for RateLimitedPage<T> {
+    async fn from_response<B>(response: http::Response<B>) -> octocrab::Result<Self>
+    where
+        B: http_body::Body<Data = bytes::Bytes, Error = octocrab::Error> + Send,
+    {
+        let remaining = response
+            .headers()
+            .get("x-ratelimit-remaining")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.parse().ok());
+        Ok(Self {
+            page: Page::from_response(response).await?,
+            remaining,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct GitHubRelease {
+    tag_name: String,
+    name: Option<String>,
+    draft: bool,
+    /// Unset for drafts.
+    published_at: Option<DateTime<Utc>>,
+    html_url: String,
+}
+
+#[derive(Deserialize)]
+struct GitHubMilestone {
+    number: u64,
+    title: String,
+    state: String,
+    due_on: Option<DateTime<Utc>>,
+    html_url: String,
+}
+
+#[derive(Deserialize)]
+struct GraphQlResponse {
+    data: Option<HashMap<String, Option<GraphQlRepository>>>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlError {
+    message: String,
+    #[serde(default)]
+    path: Vec<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlRepository {
+    pull_requests: GraphQlConnection,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlConnection {
+    total_count: u64,
+    page_info: GraphQlPageInfo,
+    nodes: Vec<GraphQlPullRequest>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlPageInfo {
+    has_next_page: bool,
+    end_cursor: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlPullRequest {
+    database_id: Option<u64>,
+    created_at: DateTime<Utc>,
+    merged_at: Option<DateTime<Utc>>,
+    closed_at: Option<DateTime<Utc>>,
+    updated_at: Option<DateTime<Utc>>,
+    state: String,
+    #[serde(default)]
+    number: u64,
+    #[serde(default)]
+    title: String,
+    author: Option<GraphQlActor>,
+    labels: Option<GraphQlLabels>,
+    #[serde(default)]
+    body: String,
+    latest_opinionated_reviews: Option<GraphQlReviews>,
+    timeline_items: Option<GraphQlTimelineItems>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlActor {
+    login: String,
+}
+
+#[derive(Deserialize)]
+struct GraphQlLabels {
+    nodes: Vec<NamedLabel>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlTimelineItems {
+    nodes: Vec<GraphQlTimelineItem>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlTimelineItem {
+    #[serde(rename = "__typename")]
+    typename: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlReviews {
+    nodes: Vec<GraphQlReview>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlReview {
+    state: String,
+}
+
+impl From<GraphQlPullRequest> for GitHubPR {
+    fn from(pr: GraphQlPullRequest) -> Self {
+        let state = match pr.state.as_str() {
+            "MERGED" => PRState::Merged,
+            "OPEN" => PRState::Open,
+            "CLOSED" => PRState::Closed,
+            _ => PRState::Unknown,
+        };
+        GitHubPR {
+            id: pr.database_id.unwrap_or_default(),
+            number: pr.number,
+            title: pr.title,
+            author: pr.author.map(|author| author.login),
+            labels: pr
+                .labels
+                .map(|labels| labels.nodes.into_iter().map(|label| label.name).collect())
+                .unwrap_or_default(),
+            created_at: pr.created_at,
+            merged_at: pr.merged_at,
+            closed_at: pr.closed_at,
+            updated_at: pr.updated_at,
+            state,
+            closes_issues: closing_issue_references(&pr.body),
+            approvals: pr.latest_opinionated_reviews.map(|reviews| {
+                reviews
+                    .nodes
+                    .iter()
+                    .filter(|review| review.state == "APPROVED")
+                    .count() as u32
+            }),
+            merge_queue: pr.timeline_items.map(|items| {
+                merge_queue_entries(
+                    items
+                        .nodes
+                        .into_iter()
+                        .map(|item| (item.typename == "AddedToMergeQueueEvent", item.created_at))
+                        .collect(),
+                )
+            }),
+        }
+    }
+}
+
+const BRANCHES_QUERY: &str = "query($owner: String!, $name: String!, $cursor: String) { repository(owner: $owner, name: $name) { refs(refPrefix: \"refs/heads/\", first: 100, after: $cursor) { totalCount pageInfo { hasNextPage endCursor } nodes { target { ... on Commit { committedDate } } } } } }";
+
+#[derive(Deserialize)]
+struct GraphQlBranchesResponse {
+    data: Option<GraphQlBranchesData>,
+    #[serde(default)]
+    errors: Vec<GraphQlError>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlBranchesData {
+    repository: Option<GraphQlBranchesRepository>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlBranchesRepository {
+    refs: GraphQlRefs,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlRefs {
+    total_count: u64,
+    page_info: GraphQlPageInfo,
+    nodes: Vec<GraphQlRef>,
+}
+
+#[derive(Deserialize)]
+struct GraphQlRef {
+    target: Option<GraphQlCommit>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphQlCommit {
+    committed_date: Option<DateTime<Utc>>,
+}
+
+/// Builds one GraphQL query fetching the next page of pull requests for each repository in
+/// `pending`, aliased as `r<index>`. Names and cursors are passed as variables, never inlined.
+fn batch_query(
+    repo_ids: &[RepoId],
+    pending: &[usize],
+    cursors: &[Option<String>],
+    per_page: u8,
+    pr_fields: &str,
+) -> serde_json::Value {
+    let mut params = Vec::new();
+    let mut fields = Vec::new();
+    let mut variables = serde_json::Map::new();
+    for &i in pending {
+        params.push(format!("$o{i}: String!, $n{i}: String!, $c{i}: String"));
+        fields.push(format!(
+            "r{i}: repository(owner: $o{i}, name: $n{i}) {{ pullRequests(first: {per_page}, after: $c{i}, orderBy: {{field: CREATED_AT, direction: DESC}}) {{ {pr_fields} }} }}"
+        ));
+        variables.insert(format!("o{i}"), repo_ids[i].owner.clone().into());
+        variables.insert(format!("n{i}"), repo_ids[i].repo.clone().into());
+        variables.insert(format!("c{i}"), cursors[i].clone().into());
+    }
+    serde_json::json!({
+        "query": format!("query({}) {{ {} }}", params.join(", "), fields.join(" ")),
+        "variables": variables,
+    })
+}
+
+/// Why the search API couldn't serve a window.
+enum SearchError {
+    /// Search can't be used for this request; the list API should be used instead.
+    Unsuitable(String),
+    Failed(anyhow::Error),
+}
+
+/// Reads pull requests from the GitHub REST API.
+pub struct GitHubSource {
+    octocrab: Octocrab,
+    config: AppConfig,
+    authenticated: bool,
+    per_page: u8,
+    page_concurrency: usize,
+    use_search: bool,
+    fetch_approvals: bool,
+    fetch_review_comments: bool,
+    fetch_merge_queue: bool,
+    /// Caps requests in flight across this source and every source derived from it for users.
+    permits: Arc<Semaphore>,
+    /// Details of merged pull requests, shared with every source derived from it for users, so
+    /// that each refresh only asks about pull requests that changed since.
+    merged_details: Cache<MergedDetailsKey, MergedDetails>,
+}
+
+impl GitHubSource {
+    /// Builds a client using the server token, or anonymous access when there is none.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Self> {
+        let token = config.github_token.as_ref().map(|t| t.expose());
+        Ok(Self {
+            octocrab: http_client::octocrab(config, token)?,
+            config: config.clone(),
+            authenticated: config.github_token.is_some(),
+            per_page: config.github_per_page.clamp(1, 100),
+            page_concurrency: config.github_page_concurrency.max(1),
+            use_search: config.github_use_search,
+            fetch_approvals: config.github_fetch_approvals,
+            fetch_review_comments: config.github_fetch_review_comments,
+            fetch_merge_queue: config.github_fetch_merge_queue,
+            permits: Arc::new(Semaphore::new(config.github_max_concurrent_requests.max(1))),
+            merged_details: Cache::new(MERGED_DETAILS_CAPACITY),
+        })
+    }
+
+    /// Runs a GitHub request once a slot under `GITHUB_MAX_CONCURRENT_REQUESTS` is free.
+    async fn limited<T>(&self, request: impl Future<Output = T>) -> T {
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .expect("the semaphore is never closed");
+        request.await
+    }
+
+    async fn fetch_page(
+        &self,
+        repo_id: &RepoId,
+        page: u32,
+    ) -> anyhow::Result<RateLimitedPage<PullRequest>> {
+        let route = format!("/repos/{}/{}/pulls", repo_id.owner, repo_id.repo);
+        let params = ListParams {
+            state: "all",
+            sort: "created",
+            direction: "desc",
+            per_page: self.per_page,
+            page,
+        };
+        Ok(self
+            .limited(self.octocrab.get(route, Some(&params)))
+            .await?)
+    }
+
+    /// Pages through the list API, newest first, until reaching `since`.
+    async fn list_pull_requests(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> anyhow::Result<FetchedPullRequests> {
+        let now = Utc::now();
+        let first_page = self.fetch_page(repo_id, 1).await?;
+        let total_pages = first_page.page.number_of_pages().unwrap_or(1);
+        let last_page = total_pages.min(max_pages);
+        let mut prs = Self::process_pr_page(&first_page.page);
+
+        // GitHub Enterprise instances with rate limiting disabled don't report a remaining count.
+        let concurrency = first_page
+            .remaining
+            .map_or(self.page_concurrency, |remaining| {
+                clamp_concurrency(self.page_concurrency, remaining)
+            });
+        let mut next_page = 2;
+
+        // Pages are fetched in batches no larger than the pages likely left before the cutoff, so
+        // a batch doesn't fetch far past it.
+        while next_page <= last_page && prs.last().is_none_or(|pr| pr.created_at >= since) {
+            let wanted = prs.last().map_or(concurrency as u32, |oldest| {
+                pages_to_cutoff(
+                    next_page - 1,
+                    now - oldest.created_at,
+                    oldest.created_at - since,
+                )
+            });
+            let batch_end = (next_page + wanted.min(concurrency as u32) - 1).min(last_page);
+            let pages: Vec<RateLimitedPage<PullRequest>> = stream::iter(next_page..=batch_end)
+                .map(|page| self.fetch_page(repo_id, page))
+                .buffered(concurrency)
+                .try_collect()
+                .await?;
+            for page in &pages {
+                prs.extend(Self::process_pr_page(&page.page));
+            }
+            next_page = batch_end + 1;
+        }
+
+        let truncated =
+            last_page < total_pages && prs.last().is_none_or(|pr| pr.created_at >= since);
+        // Every unread page holds at most a full page of pull requests.
+        let unfetched = truncated.then(|| (total_pages - last_page) as u64 * self.per_page as u64);
+        if truncated {
+            tracing::warn!(
+                "Reached the page limit ({}) for {} before the cutoff",
+                max_pages,
+                repo_id
+            );
+        }
+
+        // Clean up: remove any PRs that were in the last page but beyond the cutoff.
+        prs.retain(|pr| pr.created_at >= since);
+
+        Ok(FetchedPullRequests {
+            pull_requests: prs,
+            truncated,
+            unfetched,
+            review_comments: None,
+        })
+    }
+
+    /// Reads a page of search results. A 403 or 429 only makes search unsuitable when GitHub
+    /// says no requests remain or that a rate limit was exceeded; otherwise, e.g. for a token
+    /// without access, it's an ordinary failure.
+    async fn search_page(&self, params: &SearchParams<'_>) -> Result<SearchPage, SearchError> {
+        let failed = |e: octocrab::Error| SearchError::Failed(e.into());
+        let query = serde_urlencoded::to_string(params).expect("search parameters are strings");
+        let response = self
+            .limited(self.octocrab._get(format!("/search/issues?{query}")))
+            .await
+            .map_err(failed)?;
+        let none_remaining = response
+            .headers()
+            .get("x-ratelimit-remaining")
+            .is_some_and(|remaining| remaining == "0");
+        match octocrab::map_github_error(response).await {
+            Ok(response) => SearchPage::from_response(response).await.map_err(failed),
+            Err(octocrab::Error::GitHub { source, .. })
+                if matches!(source.status_code.as_u16(), 403 | 429)
+                    && (none_remaining || source.message.to_lowercase().contains("rate limit")) =>
+            {
+                Err(SearchError::Unsuitable(format!(
+                    "search quota exhausted ({})",
+                    source.message
+                )))
+            }
+            Err(e) => Err(failed(e)),
+        }
+    }
+
+    /// Fetches only the PRs created since the cutoff with a date-qualified search query.
+    async fn search_pull_requests(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> Result<FetchedPullRequests, SearchError> {
+        let query = window_query(repo_id, since);
+        let mut prs = Vec::new();
+        let mut total_count = 0;
+        let mut truncated = true;
+
+        for page in 1..=max_pages.max(1) {
+            let params = SearchParams {
+                q: &query,
+                sort: "created",
+                order: "desc",
+                per_page: self.per_page,
+                page,
+            };
+            let result = self.search_page(&params).await?;
+            if result.total_count > SEARCH_RESULT_LIMIT {
+                return Err(SearchError::Unsuitable(format!(
+                    "{} results exceed the search limit",
+                    result.total_count
+                )));
+            }
+
+            total_count = result.total_count;
+            let done = result.items.len() < self.per_page as usize
+                || (page as u64) * (self.per_page as u64) >= result.total_count;
+            prs.extend(result.items.into_iter().map(GitHubPR::from));
+            if done {
+                truncated = false;
+                break;
+            }
+        }
+
+        // The query only matches pull requests in the window, so this count is exact.
+        let unfetched = truncated.then(|| total_count.saturating_sub(prs.len() as u64));
+        Ok(FetchedPullRequests {
+            pull_requests: prs,
+            truncated,
+            unfetched,
+            review_comments: None,
+        })
+    }
+
+    /// Pages through several repositories' pull requests together, one GraphQL request per page.
+    async fn graphql_pull_requests(
+        &self,
+        repo_ids: &[RepoId],
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> Vec<anyhow::Result<FetchedPullRequests>> {
+        let mut prs: Vec<Vec<GitHubPR>> = vec![Vec::new(); repo_ids.len()];
+        let mut cursors: Vec<Option<String>> = vec![None; repo_ids.len()];
+        let mut total_counts: Vec<u64> = vec![0; repo_ids.len()];
+        let mut outcomes: Vec<Option<anyhow::Result<()>>> = repo_ids.iter().map(|_| None).collect();
+
+        for _ in 0..max_pages.max(1) {
+            let pending: Vec<usize> = (0..repo_ids.len())
+                .filter(|&i| outcomes[i].is_none())
+                .collect();
+            if pending.is_empty() {
+                break;
+            }
+
+            let query = batch_query(
+                repo_ids,
+                &pending,
+                &cursors,
+                self.per_page,
+                &graphql_pr_fields(self.fetch_approvals, self.fetch_merge_queue),
+            );
+            let response: GraphQlResponse = match self.limited(self.octocrab.graphql(&query)).await
+            {
+                Ok(response) => response,
+                Err(e) => {
+                    let message = e.to_string();
+                    for i in pending {
+                        outcomes[i] = Some(Err(anyhow::anyhow!("{}", message)));
+                    }
+                    break;
+                }
+            };
+            let mut data = response.data.unwrap_or_default();
+
+            for i in pending {
+                let alias = format!("r{}", i);
+                let Some(repository) = data.remove(&alias).flatten() else {
+                    let message = response
+                        .errors
+                        .iter()
+                        .find(|e| e.path.first().and_then(|p| p.as_str()) == Some(alias.as_str()))
+                        .or(response.errors.first())
+                        .map_or("repository not found", |e| e.message.as_str());
+                    outcomes[i] = Some(Err(anyhow::anyhow!(
+                        "GraphQL query for {} failed: {}",
+                        repo_ids[i],
+                        message
+                    )));
+                    continue;
+                };
+
+                let connection = repository.pull_requests;
+                total_counts[i] = connection.total_count;
+                prs[i].extend(connection.nodes.into_iter().map(GitHubPR::from));
+                let reached_cutoff = prs[i].last().is_some_and(|pr| pr.created_at < since);
+                if reached_cutoff || !connection.page_info.has_next_page {
+                    outcomes[i] = Some(Ok(()));
+                }
+                cursors[i] = connection.page_info.end_cursor;
+            }
+        }
+
+        prs.into_iter()
+            .zip(outcomes)
+            .zip(total_counts)
+            .map(|((mut prs, outcome), total_count)| {
+                // Still pending after the last page means the page limit cut it short.
+                let truncated = outcome.is_none();
+                outcome.unwrap_or(Ok(()))?;
+                let unfetched = truncated.then(|| total_count.saturating_sub(prs.len() as u64));
+                prs.retain(|pr| pr.created_at >= since);
+                Ok(FetchedPullRequests {
+                    pull_requests: prs,
+                    truncated,
+                    unfetched,
+                    review_comments: None,
+                })
+            })
+            .collect()
+    }
+
+    /// Records the approvals and merge queue stays of each merged pull request, as configured,
+    /// with a request per merged pull request for each unless an unchanged pull request's
+    /// details are cached. A detail that can't be fetched is left unknown for that pull request
+    /// rather than failing the whole fetch.
+    async fn add_merged_details(&self, repo_id: &RepoId, prs: &mut [GitHubPR]) {
+        let keys: Vec<(u64, Option<DateTime<Utc>>)> = prs
+            .iter()
+            .filter(|pr| pr.merged_at.is_some())
+            .map(|pr| (pr.number, pr.updated_at))
+            .collect();
+        let details: Vec<MergedDetails> = stream::iter(keys)
+            .map(|(number, updated_at)| async move {
+                let key = updated_at.map(|updated_at| (repo_id.clone(), number, updated_at));
+                let cached = match &key {
+                    Some(key) => self.merged_details.get(key).await,
+                    None => None,
+                };
+                match cached {
+                    Some(details) => details,
+                    None => self.fetch_merged_details(repo_id, number, key).await,
+                }
+            })
+            .buffered(self.page_concurrency)
+            .collect()
+            .await;
+        let merged = prs.iter_mut().filter(|pr| pr.merged_at.is_some());
+        for (pr, details) in merged.zip(details) {
+            pr.approvals = details.approvals;
+            pr.merge_queue = details.merge_queue;
+        }
+    }
+
+    /// Fetches one merged pull request's details, caching them under `key` only if every
+    /// configured detail was fetched.
+    async fn fetch_merged_details(
+        &self,
+        repo_id: &RepoId,
+        number: u64,
+        key: Option<MergedDetailsKey>,
+    ) -> MergedDetails {
+        let approvals = if self.fetch_approvals {
+            Some(self.approvals(repo_id, number).await)
+        } else {
+            None
+        };
+        let merge_queue = if self.fetch_merge_queue {
+            Some(self.merge_queue(repo_id, number).await)
+        } else {
+            None
+        };
+        let complete = !matches!(approvals, Some(Err(_))) && !matches!(merge_queue, Some(Err(_)));
+        let details = MergedDetails {
+            approvals: approvals.and_then(|result| known(repo_id, number, "approvals", result)),
+            merge_queue: merge_queue
+                .and_then(|result| known(repo_id, number, "merge queue events", result)),
+        };
+        if let (true, Some(key)) = (complete, key) {
+            self.merged_details.insert(key, details.clone()).await;
+        }
+        details
+    }
+
+    /// Counts approvals across every page of the pull request's reviews, up to the page limit.
+    async fn approvals(&self, repo_id: &RepoId, number: u64) -> anyhow::Result<u32> {
+        let route = format!(
+            "/repos/{}/{}/pulls/{}/reviews",
+            repo_id.owner, repo_id.repo, number
+        );
+        let params = PerPageParams { per_page: 100 };
+        let (reviews, _) = self
+            .fetch_pages::<ReviewItem>(&route, &params, self.config.max_github_api_pages)
+            .await?;
+        Ok(count_approvals(&reviews))
+    }
+
+    /// Reads the merge queue stays from every page of the pull request's timeline, up to the
+    /// page limit.
+    async fn merge_queue(
+        &self,
+        repo_id: &RepoId,
+        number: u64,
+    ) -> anyhow::Result<Vec<MergeQueueEntry>> {
+        let route = format!(
+            "/repos/{}/{}/issues/{}/timeline",
+            repo_id.owner, repo_id.repo, number
+        );
+        let params = PerPageParams { per_page: 100 };
+        let (events, _) = self
+            .fetch_pages::<TimelineEvent>(&route, &params, self.config.max_github_api_pages)
+            .await?;
+        Ok(merge_queue_entries(
+            events
+                .into_iter()
+                .filter_map(|event| {
+                    let added = match event.event.as_deref()? {
+                        "added_to_merge_queue" => true,
+                        "removed_from_merge_queue" => false,
+                        _ => return None,
+                    };
+                    Some((added, event.created_at?))
+                })
+                .collect(),
+        ))
+    }
+
+    /// Pages through the repository's review comments, newest first, until reaching `since`.
+    ///
+    /// GitHub's `since` filter matches comments updated since then, so older comments that were
+    /// edited recently are dropped here.
+    async fn review_comments(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> anyhow::Result<Vec<DateTime<Utc>>> {
+        let route = format!("/repos/{}/{}/pulls/comments", repo_id.owner, repo_id.repo);
+        let mut created = Vec::new();
+        for page in 1..=max_pages.max(1) {
+            let params = ReviewCommentParams {
+                sort: "created",
+                direction: "desc",
+                since,
+                per_page: self.per_page,
+                page,
+            };
+            let comments: Vec<ReviewComment> = self
+                .limited(self.octocrab.get(&route, Some(&params)))
+                .await?;
+            let done = comments.len() < self.per_page as usize
+                || comments.last().is_some_and(|c| c.created_at < since);
+            created.extend(comments.into_iter().map(|c| c.created_at));
+            if done {
+                break;
+            }
+        }
+        created.retain(|at| *at >= since);
+        Ok(created)
+    }
+
+    /// Counts the items at `route` by following its pages, returning the count and whether the
+    /// last page was reached within `max_pages`. `None` means the credentials can't see them.
+    async fn count_pages(
+        &self,
+        route: &str,
+        state: &'static str,
+        max_pages: u32,
+    ) -> anyhow::Result<Option<(u64, bool)>> {
+        let params = StateParams {
+            state,
+            per_page: self.per_page,
+        };
+        let first: Result<Page<serde::de::IgnoredAny>, _> =
+            self.limited(self.octocrab.get(route, Some(&params))).await;
+        let mut page = match first {
+            Ok(page) => page,
+            Err(e) => {
+                let e = anyhow::Error::from(e);
+                if crate::upstream::classify(&e) == ErrorClass::Unauthorized {
+                    tracing::debug!("{} is not accessible: {}", route, e);
+                    return Ok(None);
+                }
+                return Err(e);
+            }
+        };
+        let mut count = page.items.len() as u64;
+        for _ in 1..max_pages.max(1) {
+            match self.limited(self.octocrab.get_page(&page.next)).await? {
+                Some(next) => {
+                    count += next.items.len() as u64;
+                    page = next;
+                }
+                None => return Ok(Some((count, true))),
+            }
+        }
+        Ok(Some((count, page.next.is_none())))
+    }
+
+    /// Fetches the items at `route` by following its pages, returning them and whether the last
+    /// page was reached within `max_pages`.
+    async fn fetch_pages<T: serde::de::DeserializeOwned>(
+        &self,
+        route: &str,
+        params: &impl Serialize,
+        max_pages: u32,
+    ) -> anyhow::Result<(Vec<T>, bool)> {
+        let mut page: Page<T> = self.limited(self.octocrab.get(route, Some(params))).await?;
+        let mut items = std::mem::take(&mut page.items);
+        for _ in 1..max_pages.max(1) {
+            match self.limited(self.octocrab.get_page(&page.next)).await? {
+                Some(mut next) => {
+                    items.append(&mut next.items);
+                    page = next;
+                }
+                None => return Ok((items, true)),
+            }
+        }
+        Ok((items, page.next.is_none()))
+    }
+
+    /// Adds what the configuration asks for beyond the pull requests themselves.
+    async fn add_details(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        max_pages: u32,
+        fetched: &mut FetchedPullRequests,
+    ) -> anyhow::Result<()> {
+        if self.fetch_approvals || self.fetch_merge_queue {
+            self.add_merged_details(repo_id, &mut fetched.pull_requests)
+                .await;
+        }
+        if self.fetch_review_comments {
+            fetched.review_comments = Some(self.review_comments(repo_id, since, max_pages).await?);
+        }
+        Ok(())
+    }
+
+    /// Converts a page of pull requests to our internal type.
+    fn process_pr_page(page: &Page<PullRequest>) -> Vec<GitHubPR> {
+        page.items
+            .iter()
+            .filter_map(|pr| {
+                let created_at = pr.created_at?;
+
+                let state = if pr.merged_at.is_some() {
+                    PRState::Merged
+                } else {
+                    match pr.state {
+                        Some(octocrab::models::IssueState::Open) => PRState::Open,
+                        Some(octocrab::models::IssueState::Closed) => PRState::Closed,
+                        Some(_) => PRState::Unknown,
+                        None => PRState::Unknown,
+                    }
+                };
+
+                Some(GitHubPR {
+                    id: pr.id.into_inner(),
+                    number: pr.number,
+                    title: pr.title.clone().unwrap_or_default(),
+                    author: pr.user.as_ref().map(|user| user.login.clone()),
+                    labels: pr
+                        .labels
+                        .iter()
+                        .flatten()
+                        .map(|label| label.name.clone())
+                        .collect(),
+                    created_at,
+                    merged_at: pr.merged_at,
+                    closed_at: pr.closed_at,
+                    updated_at: pr.updated_at,
+                    state,
+                    closes_issues: pr
+                        .body
+                        .as_deref()
+                        .map(closing_issue_references)
+                        .unwrap_or_default(),
+                    approvals: None,
+                    merge_queue: None,
+                })
+            })
+            .collect()
+    }
+}
+
+#[async_trait]
+impl PullRequestSource for GitHubSource {
+    async fn pull_requests(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> anyhow::Result<FetchedPullRequests> {
+        let mut fetched = None;
+        if self.use_search {
+            match self.search_pull_requests(repo_id, since, max_pages).await {
+                Ok(prs) => fetched = Some(prs),
+                Err(SearchError::Unsuitable(reason)) => {
+                    tracing::debug!("Using the list API for {}: {}", repo_id, reason);
+                }
+                Err(SearchError::Failed(e)) => return Err(e),
+            }
+        }
+        let mut fetched = match fetched {
+            Some(fetched) => fetched,
+            None => self.list_pull_requests(repo_id, since, max_pages).await?,
+        };
+        self.add_details(repo_id, since, max_pages, &mut fetched)
+            .await?;
+        Ok(fetched)
+    }
+
+    async fn batch_pull_requests(
+        &self,
+        repo_ids: &[RepoId],
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> Vec<anyhow::Result<FetchedPullRequests>> {
+        // GraphQL is unavailable to anonymous clients.
+        if !self.authenticated {
+            return futures::future::join_all(
+                repo_ids
+                    .iter()
+                    .map(|repo_id| self.pull_requests(repo_id, since, max_pages)),
+            )
+            .await;
+        }
+        let mut results = self.graphql_pull_requests(repo_ids, since, max_pages).await;
+        // Review comments aren't part of the batched query.
+        if self.fetch_review_comments {
+            for (repo_id, result) in repo_ids.iter().zip(&mut results) {
+                if let Ok(fetched) = result {
+                    match self.review_comments(repo_id, since, max_pages).await {
+                        Ok(comments) => fetched.review_comments = Some(comments),
+                        Err(e) => *result = Err(e),
+                    }
+                }
+            }
+        }
+        results
+    }
+
+    /// A search for the window with a single result per page reports the total cheaply.
+    async fn pull_request_count(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Option<u64>> {
+        let query = window_query(repo_id, since);
+        let params = SearchParams {
+            q: &query,
+            sort: "created",
+            order: "desc",
+            per_page: 1,
+            page: 1,
+        };
+        let result: SearchPage = self
+            .limited(self.octocrab.get("/search/issues", Some(&params)))
+            .await?;
+        Ok(Some(result.total_count))
+    }
+
+    /// Branch head dates aren't in the REST branch list, so this needs GraphQL and therefore a
+    /// token.
+    async fn branches(
+        &self,
+        repo_id: &RepoId,
+        max_pages: u32,
+    ) -> anyhow::Result<Option<FetchedBranches>> {
+        if !self.authenticated {
+            return Ok(None);
+        }
+        let mut fetched = FetchedBranches::default();
+        let mut cursor: Option<String> = None;
+        for _ in 0..max_pages.max(1) {
+            let query = serde_json::json!({
+                "query": BRANCHES_QUERY,
+                "variables": {"owner": repo_id.owner, "name": repo_id.repo, "cursor": cursor},
+            });
+            let response: GraphQlBranchesResponse =
+                self.limited(self.octocrab.graphql(&query)).await?;
+            let Some(repository) = response.data.and_then(|data| data.repository) else {
+                let message = response
+                    .errors
+                    .first()
+                    .map_or("repository not found", |e| e.message.as_str());
+                return Err(UpstreamError::new(
+                    ErrorClass::NotFound,
+                    format!("GraphQL query for {} failed: {}", repo_id, message),
+                )
+                .into());
+            };
+            let refs = repository.refs;
+            fetched.total = refs.total_count;
+            fetched.last_commits.extend(
+                refs.nodes
+                    .into_iter()
+                    .filter_map(|node| node.target.and_then(|target| target.committed_date)),
+            );
+            if !refs.page_info.has_next_page {
+                break;
+            }
+            cursor = refs.page_info.end_cursor;
+        }
+        Ok(Some(fetched))
+    }
+
+    /// Both lists need a token with access to the repository's security features.
+    async fn security_alerts(
+        &self,
+        repo_id: &RepoId,
+        max_pages: u32,
+    ) -> anyhow::Result<Option<SecurityAlerts>> {
+        if !self.authenticated {
+            return Ok(None);
+        }
+        let base = format!("/repos/{}/{}", repo_id.owner, repo_id.repo);
+        let alerts_route = format!("{base}/dependabot/alerts");
+        let advisories_route = format!("{base}/security-advisories");
+        let (alerts, advisories) = futures::try_join!(
+            self.count_pages(&alerts_route, "open", max_pages),
+            self.count_pages(&advisories_route, "published", max_pages),
+        )?;
+        Ok(Some(SecurityAlerts {
+            open_dependabot_alerts: alerts.map(|(count, _)| count),
+            published_advisories: advisories.map(|(count, _)| count),
+            complete: [alerts, advisories]
+                .iter()
+                .all(|counted| counted.is_none_or(|(_, complete)| complete)),
+        }))
+    }
+
+    async fn calendar(
+        &self,
+        repo_id: &RepoId,
+        max_pages: u32,
+    ) -> anyhow::Result<Option<RepoCalendar>> {
+        let base = format!("/repos/{}/{}", repo_id.owner, repo_id.repo);
+        let releases_route = format!("{base}/releases");
+        let milestones_route = format!("{base}/milestones");
+        let releases_params = PerPageParams {
+            per_page: self.per_page,
+        };
+        let milestones_params = StateParams {
+            state: "all",
+            per_page: self.per_page,
+        };
+        let ((releases, releases_complete), (milestones, milestones_complete)) = futures::try_join!(
+            self.fetch_pages::<GitHubRelease>(&releases_route, &releases_params, max_pages),
+            self.fetch_pages::<GitHubMilestone>(&milestones_route, &milestones_params, max_pages),
+        )?;
+        Ok(Some(RepoCalendar {
+            releases: releases
+                .into_iter()
+                .filter(|release| !release.draft)
+                .filter_map(|release| {
+                    Some(Release {
+                        published_at: release.published_at?,
+                        tag_name: release.tag_name,
+                        name: release.name.filter(|name| !name.is_empty()),
+                        url: release.html_url,
+                    })
+                })
+                .collect(),
+            milestones: milestones
+                .into_iter()
+                .filter_map(|milestone| {
+                    Some(Milestone {
+                        due_on: milestone.due_on?,
+                        number: milestone.number,
+                        title: milestone.title,
+                        closed: milestone.state == "closed",
+                        url: milestone.html_url,
+                    })
+                })
+                .collect(),
+            complete: releases_complete && milestones_complete,
+        }))
+    }
+
+    async fn is_public(&self, repo_id: &RepoId) -> anyhow::Result<bool> {
+        let repos = self.octocrab.repos(&repo_id.owner, &repo_id.repo);
+        let repository = self.limited(repos.get()).await?;
+        Ok(repository.private == Some(false))
+    }
+
+    fn for_user(&self, token: &str) -> anyhow::Result<Arc<dyn PullRequestSource>> {
+        Ok(Arc::new(Self {
+            octocrab: http_client::octocrab(&self.config, Some(token))?,
+            config: self.config.clone(),
+            authenticated: true,
+            per_page: self.per_page,
+            page_concurrency: self.page_concurrency,
+            use_search: self.use_search,
+            fetch_approvals: self.fetch_approvals,
+            fetch_review_comments: self.fetch_review_comments,
+            fetch_merge_queue: self.fetch_merge_queue,
+            permits: self.permits.clone(),
+            merged_details: self.merged_details.clone(),
+        }))
+    }
+
+    /// Logs the authenticated identity and remaining quota. Only a rejected token is an error;
+    /// connectivity problems are logged so that a transient outage doesn't block startup.
+    async fn check_access(&self) -> anyhow::Result<()> {
+        let rate_limit = match self.octocrab.ratelimit().get().await {
+            Ok(rate_limit) => rate_limit,
+            Err(octocrab::Error::GitHub { source, .. })
+                if source.status_code == http::StatusCode::UNAUTHORIZED =>
+            {
+                anyhow::bail!(
+                    "GITHUB_TOKEN was rejected by GitHub ({}). It may be expired or revoked",
+                    source.message
+                );
+            }
+            Err(e) => {
+                tracing::warn!("Could not reach GitHub during startup check: {}", e);
+                return Ok(());
+            }
+        };
+        let core = rate_limit.resources.core;
+
+        if !self.authenticated {
+            tracing::info!(
+                "Using anonymous GitHub access: {}/{} requests remaining",
+                core.remaining,
+                core.limit
+            );
+            return Ok(());
+        }
+
+        match self.octocrab.current().user().await {
+            Ok(user) => tracing::info!(
+                "Authenticated to GitHub as {}: {}/{} requests remaining",
+                user.login,
+                core.remaining,
+                core.limit
+            ),
+            // Fine-grained and app tokens may not be allowed to read the user profile.
+            Err(e) => tracing::info!(
+                "GitHub token accepted ({}/{} requests remaining), but its identity is unavailable: {}",
+                core.remaining,
+                core.limit,
+                e
+            ),
+        }
+        Ok(())
+    }
+
+    /// This call does not count against the rate limits it reports.
+    async fn rate_limit(&self) -> anyhow::Result<Option<RateLimit>> {
+        Ok(Some(
+            self.limited(self.octocrab.ratelimit().get()).await?.into(),
+        ))
+    }
+}
+
+/// A search query matching the repository's pull requests created at or after `since`.
+fn window_query(repo_id: &RepoId, since: DateTime<Utc>) -> String {
+    format!(
+        "repo:{} type:pr created:>={}",
+        repo_id,
+        since.format("%Y-%m-%dT%H:%M:%SZ")
+    )
+}
+
+/// Estimates how many more pages reach back `remaining` further, when `fetched` pages reached back
+/// `covered`, assuming pull requests keep being opened at the same rate. At least one.
+fn pages_to_cutoff(fetched: u32, covered: chrono::Duration, remaining: chrono::Duration) -> u32 {
+    let covered = covered.num_seconds().max(1) as f64;
+    let remaining = remaining.num_seconds().max(0) as f64;
+    ((fetched as f64 * remaining / covered).ceil() as u32).max(1)
+}
+
+/// Limits page concurrency to one page per `RATE_LIMIT_PER_CONCURRENT_PAGE` remaining requests.
+fn clamp_concurrency(configured: usize, remaining: usize) -> usize {
+    configured
+        .min(remaining / RATE_LIMIT_PER_CONCURRENT_PAGE)
+        .max(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_search_item_conversion() {
+        let items: Vec<SearchItem> = serde_json::from_str(
+            r#"[
+                {"id": 101, "number": 1, "state": "closed", "created_at": "2024-01-01T00:00:00Z",
+                 "closed_at": "2024-01-02T00:00:00Z", "body": "Fixes #4",
+                 "pull_request": {"merged_at": "2024-01-02T00:00:00Z"}},
+                {"id": 102, "number": 2, "state": "closed", "created_at": "2024-01-01T00:00:00Z",
+                 "closed_at": "2024-01-03T00:00:00Z", "body": null,
+                 "pull_request": {"merged_at": null}},
+                {"id": 103, "number": 3, "state": "open", "created_at": "2024-01-01T00:00:00Z",
+                 "closed_at": null, "pull_request": {}}
+            ]"#,
+        )
+        .unwrap();
+        let prs: Vec<GitHubPR> = items.into_iter().map(GitHubPR::from).collect();
+        let states: Vec<PRState> = prs.iter().map(|pr| pr.state).collect();
+        assert_eq!(states, [PRState::Merged, PRState::Closed, PRState::Open]);
+        let ids: Vec<u64> = prs.iter().map(|pr| pr.id).collect();
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(prs[0].closes_issues, [4]);
+        assert!(prs[1].closes_issues.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_only_rate_limits_exhaust_the_search_quota() {
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let forbidden = |message: &str, remaining: &str| {
+            ResponseTemplate::new(403)
+                .insert_header("x-ratelimit-remaining", remaining)
+                .set_body_json(serde_json::json!({"message": message}))
+        };
+        let cases = [
+            (
+                forbidden("Resource not accessible by integration", "10"),
+                false,
+            ),
+            (forbidden("Forbidden", "0"), true),
+            (
+                forbidden("You have exceeded a secondary rate limit", "10"),
+                true,
+            ),
+        ];
+        for (response, exhausted) in cases {
+            let github = MockServer::start().await;
+            Mock::given(method("GET"))
+                .and(path("/search/issues"))
+                .respond_with(response)
+                .mount(&github)
+                .await;
+            let config = crate::test_support::test_config(&[("GITHUB_API_URL", &github.uri())]);
+            let source = GitHubSource::new(&config).unwrap();
+            let repo_id = RepoId {
+                owner: "acme".to_string(),
+                repo: "widgets".to_string(),
+            };
+            let result = source.search_pull_requests(&repo_id, Utc::now(), 1).await;
+            assert_eq!(matches!(result, Err(SearchError::Unsuitable(_))), exhausted);
+        }
+    }
+
+    #[test]
+    fn test_batch_query_uses_variables() {
+        let repos = vec![
+            RepoId {
+                owner: "facebook".to_string(),
+                repo: "react".to_string(),
+            },
+            RepoId {
+                owner: "rust-lang".to_string(),
+                repo: "rust\"){evil}".to_string(),
+            },
+        ];
+        let cursors = vec![None, Some("Y3Vyc29y".to_string())];
+        let query = batch_query(&repos, &[1], &cursors, 50, &graphql_pr_fields(false, false));
+
+        let text = query["query"].as_str().unwrap();
+        assert!(text.starts_with("query($o1: String!, $n1: String!, $c1: String)"));
+        assert!(text.contains("r1: repository(owner: $o1, name: $n1)"));
+        assert!(text.contains("first: 50, after: $c1"));
+        assert!(!text.contains("r0:"));
+        assert!(!text.contains("evil"));
+        assert!(!text.contains("latestOpinionatedReviews"));
+        assert!(!text.contains("timelineItems"));
+        assert!(graphql_pr_fields(true, true).contains("ADDED_TO_MERGE_QUEUE_EVENT"));
+        assert_eq!(query["variables"]["n1"], "rust\"){evil}");
+        assert_eq!(query["variables"]["c1"], "Y3Vyc29y");
+    }
+
+    #[test]
+    fn test_graphql_response_parsing() {
+        let response: GraphQlResponse = serde_json::from_str(
+            r#"{
+                "data": {
+                    "r0": {"pullRequests": {
+                        "totalCount": 1,
+                        "pageInfo": {"hasNextPage": true, "endCursor": "abc"},
+                        "nodes": [{"databaseId": 7, "createdAt": "2024-01-01T00:00:00Z",
+                                   "mergedAt": null, "closedAt": null, "state": "OPEN",
+                                   "body": "Closes #9", "latestOpinionatedReviews":
+                                       {"nodes": [{"state": "APPROVED"}, {"state": "CHANGES_REQUESTED"}]},
+                                   "timelineItems": {"nodes": [
+                                       {"__typename": "AddedToMergeQueueEvent", "createdAt": "2024-01-02T00:00:00Z"}]}}]
+                    }},
+                    "r1": null
+                },
+                "errors": [{"message": "Could not resolve to a Repository", "path": ["r1"]}]
+            }"#,
+        )
+        .unwrap();
+        let mut data = response.data.unwrap();
+        let repo = data.remove("r0").flatten().unwrap();
+        assert_eq!(
+            repo.pull_requests.page_info.end_cursor.as_deref(),
+            Some("abc")
+        );
+        let pr = GitHubPR::from(repo.pull_requests.nodes.into_iter().next().unwrap());
+        assert_eq!((pr.id, pr.state), (7, PRState::Open));
+        assert_eq!(pr.closes_issues, [9]);
+        assert_eq!(pr.approvals, Some(1));
+        assert_eq!(pr.merge_queue.unwrap().len(), 1);
+        assert!(data.remove("r1").flatten().is_none());
+        assert_eq!(response.errors[0].path[0], "r1");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_requests_share_concurrency_limit() {
+        let config = crate::test_support::test_config(&[("GITHUB_MAX_CONCURRENT_REQUESTS", "1")]);
+        let source = GitHubSource::new(&config).unwrap();
+        let user_source = GitHubSource {
+            octocrab: source.octocrab.clone(),
+            config: config.clone(),
+            authenticated: true,
+            per_page: source.per_page,
+            page_concurrency: source.page_concurrency,
+            use_search: false,
+            fetch_approvals: false,
+            fetch_review_comments: false,
+            fetch_merge_queue: false,
+            permits: source.permits.clone(),
+            merged_details: source.merged_details.clone(),
+        };
+        // A sleep's deadline is set when it is created, so create it only once running.
+        let request = || async { tokio::time::sleep(std::time::Duration::from_secs(10)).await };
+
+        let start = tokio::time::Instant::now();
+        tokio::join!(source.limited(request()), user_source.limited(request()));
+        assert_eq!(start.elapsed(), std::time::Duration::from_secs(20));
+    }
+
+    #[test]
+    fn test_count_approvals() {
+        let reviews: Vec<ReviewItem> = serde_json::from_str(
+            r#"[
+                {"user": {"id": 1}, "state": "CHANGES_REQUESTED"},
+                {"user": {"id": 1}, "state": "APPROVED"},
+                {"user": {"id": 1}, "state": "COMMENTED"},
+                {"user": {"id": 2}, "state": "APPROVED"},
+                {"user": {"id": 2}, "state": "DISMISSED"},
+                {"user": {"id": 3}, "state": "APPROVED"},
+                {"user": {"id": 3}, "state": "CHANGES_REQUESTED"},
+                {"user": null, "state": "APPROVED"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(count_approvals(&reviews), 1);
+        assert_eq!(count_approvals(&[]), 0);
+    }
+
+    #[tokio::test]
+    async fn test_merged_details_are_paged_cached_and_tolerate_failures() {
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let github = MockServer::start().await;
+        let reviews = |n: u64| format!("/repos/acme/widgets/pulls/{n}/reviews");
+        let approved = |user: u64| serde_json::json!({"user": {"id": user}, "state": "APPROVED"});
+        let next = format!(
+            "<{}{}?per_page=100&page=2>; rel=\"next\"",
+            github.uri(),
+            reviews(1)
+        );
+        Mock::given(method("GET"))
+            .and(path(reviews(1)))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json([approved(2)]))
+            .expect(1)
+            .mount(&github)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(reviews(1)))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("link", next.as_str())
+                    .set_body_json([approved(1)]),
+            )
+            .expect(1)
+            .mount(&github)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(reviews(2)))
+            .respond_with(ResponseTemplate::new(404))
+            .expect(2)
+            .mount(&github)
+            .await;
+
+        let config = crate::test_support::test_config(&[
+            ("GITHUB_API_URL", &github.uri()),
+            ("GITHUB_FETCH_APPROVALS", "true"),
+            ("MAX_GITHUB_API_PAGES", "5"),
+        ]);
+        let source = GitHubSource::new(&config).unwrap();
+        let repo_id = RepoId {
+            owner: "acme".to_string(),
+            repo: "widgets".to_string(),
+        };
+        let merged = |number| GitHubPR {
+            number,
+            merged_at: Some(Utc::now()),
+            updated_at: Some(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap()),
+            state: PRState::Merged,
+            ..Default::default()
+        };
+        for _ in 0..2 {
+            let mut prs = vec![merged(1), merged(2)];
+            source.add_merged_details(&repo_id, &mut prs).await;
+            assert_eq!(prs[0].approvals, Some(2));
+            assert_eq!(prs[1].approvals, None);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_merge_queue_events_are_paged_and_tolerate_failures() {
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let github = MockServer::start().await;
+        let timeline = |n: u64| format!("/repos/acme/widgets/issues/{n}/timeline");
+        let event = |event: &str, at: &str| serde_json::json!({"event": event, "created_at": at});
+        let next = format!(
+            "<{}{}?per_page=100&page=2>; rel=\"next\"",
+            github.uri(),
+            timeline(1)
+        );
+        Mock::given(method("GET"))
+            .and(path(timeline(1)))
+            .and(query_param("page", "2"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json([event("removed_from_merge_queue", "2024-01-01T02:00:00Z")]),
+            )
+            .mount(&github)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(timeline(1)))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("link", next.as_str())
+                    .set_body_json([event("added_to_merge_queue", "2024-01-01T01:00:00Z")]),
+            )
+            .mount(&github)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(timeline(2)))
+            .respond_with(ResponseTemplate::new(404))
+            .mount(&github)
+            .await;
+
+        let config = crate::test_support::test_config(&[
+            ("GITHUB_API_URL", &github.uri()),
+            ("GITHUB_FETCH_MERGE_QUEUE", "true"),
+            ("MAX_GITHUB_API_PAGES", "5"),
+        ]);
+        let source = GitHubSource::new(&config).unwrap();
+        let repo_id = RepoId {
+            owner: "acme".to_string(),
+            repo: "widgets".to_string(),
+        };
+        let merged = |number| GitHubPR {
+            number,
+            merged_at: Some(Utc::now()),
+            state: PRState::Merged,
+            ..Default::default()
+        };
+        let mut prs = vec![merged(1), merged(2)];
+        source.add_merged_details(&repo_id, &mut prs).await;
+        let at = |h: u32| Utc.with_ymd_and_hms(2024, 1, 1, h, 0, 0).unwrap();
+        assert_eq!(
+            prs[0].merge_queue,
+            Some(vec![MergeQueueEntry {
+                enqueued_at: at(1),
+                dequeued_at: Some(at(2)),
+            }])
+        );
+        assert_eq!(prs[1].merge_queue, None);
+    }
+
+    #[test]
+    fn test_merge_queue_entries() {
+        let at = |h: u32| Utc.with_ymd_and_hms(2024, 1, 1, h, 0, 0).unwrap();
+        // Out of order, with a stray removal first and a final stay ended by the merge.
+        let entries = merge_queue_entries(vec![
+            (false, at(3)),
+            (true, at(1)),
+            (false, at(0)),
+            (true, at(5)),
+        ]);
+        assert_eq!(
+            entries,
+            vec![
+                MergeQueueEntry {
+                    enqueued_at: at(1),
+                    dequeued_at: Some(at(3)),
+                },
+                MergeQueueEntry {
+                    enqueued_at: at(5),
+                    dequeued_at: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_pages_to_cutoff() {
+        let days = chrono::Duration::days;
+        assert_eq!(pages_to_cutoff(1, days(2), days(1)), 1);
+        assert_eq!(pages_to_cutoff(2, days(1), days(10)), 20);
+        assert_eq!(pages_to_cutoff(3, days(0), days(0)), 1);
+    }
+
+    #[tokio::test]
+    async fn test_list_pages_stop_near_the_cutoff() {
+        use wiremock::matchers::{method, path, query_param};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let github = MockServer::start().await;
+        let now = Utc::now();
+        let pull = |number: u64, days_ago: i64| {
+            serde_json::json!({
+                "url": format!("https://api.github.com/repos/acme/widgets/pulls/{number}"),
+                "id": number,
+                "number": number,
+                "state": "open",
+                "created_at": (now - chrono::Duration::days(days_ago)).to_rfc3339(),
+                "head": {"ref": "feature", "sha": "abc"},
+                "base": {"ref": "main", "sha": "def"},
+            })
+        };
+        let pulls = "/repos/acme/widgets/pulls";
+        let last = format!("<{}{pulls}?page=10>; rel=\"last\"", github.uri());
+        Mock::given(method("GET"))
+            .and(path("/rate_limit"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&github)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(pulls))
+            .and(query_param("page", "1"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("link", last.as_str())
+                    .insert_header("x-ratelimit-remaining", "5000")
+                    .set_body_json([pull(1, 1), pull(2, 2)]),
+            )
+            .mount(&github)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(pulls))
+            .and(query_param("page", "2"))
+            .respond_with(ResponseTemplate::new(200).set_body_json([pull(3, 3), pull(4, 4)]))
+            .expect(1)
+            .mount(&github)
+            .await;
+        Mock::given(method("GET"))
+            .and(path(pulls))
+            .respond_with(ResponseTemplate::new(200).set_body_json([pull(5, 5)]))
+            .expect(0)
+            .mount(&github)
+            .await;
+
+        let config = crate::test_support::test_config(&[
+            ("GITHUB_API_URL", &github.uri()),
+            ("GITHUB_PER_PAGE", "2"),
+            ("GITHUB_PAGE_CONCURRENCY", "10"),
+            ("MAX_GITHUB_API_PAGES", "10"),
+        ]);
+        let source = GitHubSource::new(&config).unwrap();
+        let repo_id = RepoId {
+            owner: "acme".to_string(),
+            repo: "widgets".to_string(),
+        };
+        let since = now - chrono::Duration::hours(3 * 24 + 12);
+        let fetched = source
+            .list_pull_requests(&repo_id, since, 10)
+            .await
+            .unwrap();
+        let numbers: Vec<u64> = fetched.pull_requests.iter().map(|pr| pr.number).collect();
+        assert_eq!(numbers, [1, 2, 3]);
+        assert!(!fetched.truncated);
+    }
+
+    #[test]
+    fn test_clamp_concurrency() {
+        assert_eq!(clamp_concurrency(15, 5000), 15);
+        assert_eq!(clamp_concurrency(15, 450), 4);
+        assert_eq!(clamp_concurrency(15, 20), 1);
+        assert_eq!(clamp_concurrency(15, 0), 1);
+    }
+}
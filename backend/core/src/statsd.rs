@@ -0,0 +1,155 @@
+//! Pushes summary metrics of refreshed repositories to a StatsD or DogStatsD agent, so existing
+//! monitoring can alert on flow regressions.
+//!
+//! Metrics are gauges sent over UDP, one datagram per repository. Sends never block or fail a
+//! refresh; a lost datagram only means the agent keeps the previous value a little longer.
+
+use crate::config::{AppConfig, StatsdFormat};
+use crate::domain::RepoId;
+use crate::metrics::{Completeness, SummaryMetrics};
+use anyhow::Context;
+use std::net::{ToSocketAddrs, UdpSocket};
+
+pub struct StatsdExporter {
+    socket: UdpSocket,
+    format: StatsdFormat,
+    prefix: String,
+}
+
+impl StatsdExporter {
+    /// Creates an exporter, or returns `None` when `STATSD_ADDR` is unset.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Option<Self>> {
+        let Some(addr) = &config.statsd_addr else {
+            return Ok(None);
+        };
+        // Resolved once, as agents normally have a fixed address and lookups would block sends.
+        let target = addr
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .with_context(|| format!("failed to resolve STATSD_ADDR {}", addr))?;
+        let local = if target.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+        let socket = UdpSocket::bind(local).context("failed to bind a UDP socket for StatsD")?;
+        socket.connect(target)?;
+        socket.set_nonblocking(true)?;
+        Ok(Some(Self {
+            socket,
+            format: config.statsd_format,
+            prefix: config.statsd_prefix.clone(),
+        }))
+    }
+
+    /// Sends the summary of `repo_id`'s default window.
+    pub fn export(&self, repo_id: &RepoId, summary: &SummaryMetrics, completeness: &Completeness) {
+        let packet = self.packet(repo_id, summary, completeness);
+        if let Err(e) = self.socket.send(packet.as_bytes()) {
+            tracing::warn!("Failed to send metrics for {} to StatsD: {}", repo_id, e);
+        }
+    }
+
+    fn packet(
+        &self,
+        repo_id: &RepoId,
+        summary: &SummaryMetrics,
+        completeness: &Completeness,
+    ) -> String {
+        let gauges = [
+            ("opened", summary.current_opened as i64),
+            ("merged", summary.current_merged as i64),
+            ("fast_merged", summary.current_fast_merged as i64),
+            ("spread", summary.current_spread),
+            ("merge_rate", summary.merge_rate.into()),
+            ("issue_link_rate", summary.issue_link_rate.into()),
+            ("issues_closed", summary.current_issues_closed as i64),
+            ("widening", summary.is_widening.into()),
+            ("completeness", completeness.score.into()),
+        ];
+        let lines: Vec<String> = match self.format {
+            // Dots separate name segments, so ones in repository names would add levels.
+            StatsdFormat::Statsd => gauges
+                .iter()
+                .map(|(name, value)| {
+                    format!(
+                        "{}.{}.{}.{}:{}|g",
+                        self.prefix,
+                        repo_id.owner.replace('.', "_"),
+                        repo_id.repo.replace('.', "_"),
+                        name,
+                        value
+                    )
+                })
+                .collect(),
+            StatsdFormat::Dogstatsd => gauges
+                .iter()
+                .map(|(name, value)| {
+                    format!("{}.{}:{}|g|#repo:{}", self.prefix, name, value, repo_id)
+                })
+                .collect(),
+        };
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_config;
+
+    fn summary() -> SummaryMetrics {
+        SummaryMetrics {
+            current_opened: 12,
+            current_merged: 9,
+            current_spread: 3,
+            is_widening: true,
+            ..Default::default()
+        }
+    }
+
+    fn completeness() -> Completeness {
+        crate::metrics::completeness(21, false, None, Vec::new())
+    }
+
+    #[test]
+    fn test_statsd_names_carry_the_repo() {
+        let config = test_config(&[("STATSD_ADDR", "127.0.0.1:8125")]);
+        let exporter = StatsdExporter::new(&config).unwrap().unwrap();
+        let repo_id: RepoId = "vercel/next.js".parse().unwrap();
+        let packet = exporter.packet(&repo_id, &summary(), &completeness());
+        let lines: Vec<&str> = packet.lines().collect();
+        assert_eq!(lines[0], "repoflow.vercel.next_js.opened:12|g");
+        assert!(lines.contains(&"repoflow.vercel.next_js.widening:1|g"));
+    }
+
+    #[test]
+    fn test_dogstatsd_sends_to_the_agent() {
+        let agent = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let addr = agent.local_addr().unwrap().to_string();
+        let config = test_config(&[
+            ("STATSD_ADDR", addr.as_str()),
+            ("STATSD_FORMAT", "dogstatsd"),
+            ("STATSD_PREFIX", "flow"),
+        ]);
+        let exporter = StatsdExporter::new(&config).unwrap().unwrap();
+        exporter.export(
+            &"acme/widgets".parse().unwrap(),
+            &summary(),
+            &completeness(),
+        );
+
+        let mut buf = [0; 2048];
+        let len = agent.recv(&mut buf).unwrap();
+        let packet = std::str::from_utf8(&buf[..len]).unwrap();
+        assert!(packet.starts_with("flow.opened:12|g|#repo:acme/widgets\n"));
+        assert!(packet.contains("flow.spread:3|g|#repo:acme/widgets"));
+        assert!(packet.ends_with("flow.completeness:100|g|#repo:acme/widgets"));
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert!(StatsdExporter::new(&test_config(&[])).unwrap().is_none());
+    }
+}
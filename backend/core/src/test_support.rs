@@ -0,0 +1,274 @@
+//! Fixtures for exercising the engine without calling GitHub.
+
+use crate::config::AppConfig;
+use crate::domain::{GitHubPR, PRState, RepoId};
+use crate::source::{
+    FetchedBranches, FetchedPullRequests, PullRequestSource, RateLimit, RepoCalendar,
+    SecurityAlerts,
+};
+use crate::upstream::{ErrorClass, UpstreamError};
+use async_trait::async_trait;
+use chrono::{DateTime, Duration, Utc};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+/// An in-memory `PullRequestSource` serving canned pull requests.
+#[derive(Clone, Default)]
+pub struct MockPullRequestSource {
+    repos: HashMap<RepoId, Vec<GitHubPR>>,
+    private: bool,
+    truncated: Option<u64>,
+    failures: Arc<AtomicUsize>,
+    rate_limit_reset: Option<u64>,
+    delay: Option<std::time::Duration>,
+    calls: Arc<AtomicUsize>,
+    counts: bool,
+    last_max_pages: Arc<AtomicU32>,
+    review_comments: Option<Vec<DateTime<Utc>>>,
+    branches: Option<Vec<DateTime<Utc>>>,
+    security_alerts: Option<SecurityAlerts>,
+    calendar: Option<RepoCalendar>,
+}
+
+impl MockPullRequestSource {
+    /// Serves `prs` for the repository `owner/repo`.
+    pub fn with_repo(mut self, repo: &str, prs: Vec<GitHubPR>) -> Self {
+        let repo_id: RepoId = repo.parse().expect("expected owner/repo");
+        self.repos.insert(repo_id, prs);
+        self
+    }
+
+    /// Reports every repository as private to signed-in users.
+    pub fn private(mut self) -> Self {
+        self.private = true;
+        self
+    }
+
+    /// Reports every fetch as cut short by the page limit, leaving `unfetched` pull requests.
+    pub fn truncated(mut self, unfetched: u64) -> Self {
+        self.truncated = Some(unfetched);
+        self
+    }
+
+    /// Fails the next `n` fetches with a transient error.
+    pub fn failing(self, n: usize) -> Self {
+        self.failures.store(n, Ordering::SeqCst);
+        self
+    }
+
+    /// Fails every fetch as rate limited, with the core limit resetting at `reset` (a Unix time).
+    pub fn rate_limited(mut self, reset: u64) -> Self {
+        self.rate_limit_reset = Some(reset);
+        self
+    }
+
+    /// Takes `delay` to serve each fetch.
+    pub fn slow(mut self, delay: std::time::Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+
+    /// Reports how many pull requests each repository has, as GitHub's search does.
+    pub fn counting(mut self) -> Self {
+        self.counts = true;
+        self
+    }
+
+    /// Serves review comments posted at `times` along with every repository's pull requests.
+    pub fn with_review_comments(mut self, times: Vec<DateTime<Utc>>) -> Self {
+        self.review_comments = Some(times);
+        self
+    }
+
+    /// Serves branches whose heads were committed at `times` for every known repository.
+    pub fn with_branches(mut self, times: Vec<DateTime<Utc>>) -> Self {
+        self.branches = Some(times);
+        self
+    }
+
+    /// Serves `alerts` as the security alerts of every known repository.
+    pub fn with_security_alerts(mut self, alerts: SecurityAlerts) -> Self {
+        self.security_alerts = Some(alerts);
+        self
+    }
+
+    /// Serves `calendar` as the releases and milestones of every known repository.
+    pub fn with_calendar(mut self, calendar: RepoCalendar) -> Self {
+        self.calendar = Some(calendar);
+        self
+    }
+
+    /// The page limit of the most recent fetch.
+    pub fn last_max_pages(&self) -> u32 {
+        self.last_max_pages.load(Ordering::SeqCst)
+    }
+
+    /// Number of pull request fetches served so far, including those made for users.
+    pub fn calls(&self) -> usize {
+        self.calls.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl PullRequestSource for MockPullRequestSource {
+    async fn pull_requests(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+        max_pages: u32,
+    ) -> anyhow::Result<FetchedPullRequests> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        self.last_max_pages.store(max_pages, Ordering::SeqCst);
+        if let Some(delay) = self.delay {
+            tokio::time::sleep(delay).await;
+        }
+        let failing = self
+            .failures
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok();
+        if self.rate_limit_reset.is_some() {
+            return Err(UpstreamError::new(ErrorClass::RateLimited, "rate limit exceeded").into());
+        }
+        if failing {
+            return Err(UpstreamError::new(ErrorClass::Transient, "simulated outage").into());
+        }
+        let prs = self.repos.get(repo_id).ok_or_else(|| {
+            UpstreamError::new(ErrorClass::NotFound, format!("no fixture for {}", repo_id))
+        })?;
+        Ok(FetchedPullRequests {
+            pull_requests: prs
+                .iter()
+                .filter(|pr| pr.created_at >= since)
+                .cloned()
+                .collect(),
+            truncated: self.truncated.is_some(),
+            unfetched: self.truncated,
+            review_comments: self
+                .review_comments
+                .as_ref()
+                .map(|times| times.iter().copied().filter(|at| *at >= since).collect()),
+        })
+    }
+
+    async fn pull_request_count(
+        &self,
+        repo_id: &RepoId,
+        since: DateTime<Utc>,
+    ) -> anyhow::Result<Option<u64>> {
+        Ok(self.counts.then(|| {
+            self.repos.get(repo_id).map_or(0, |prs| {
+                prs.iter().filter(|pr| pr.created_at >= since).count() as u64
+            })
+        }))
+    }
+
+    async fn branches(
+        &self,
+        repo_id: &RepoId,
+        _max_pages: u32,
+    ) -> anyhow::Result<Option<FetchedBranches>> {
+        let Some(times) = &self.branches else {
+            return Ok(None);
+        };
+        if !self.repos.contains_key(repo_id) {
+            return Err(UpstreamError::new(
+                ErrorClass::NotFound,
+                format!("no fixture for {}", repo_id),
+            )
+            .into());
+        }
+        Ok(Some(FetchedBranches {
+            total: times.len() as u64,
+            last_commits: times.clone(),
+        }))
+    }
+
+    async fn security_alerts(
+        &self,
+        repo_id: &RepoId,
+        _max_pages: u32,
+    ) -> anyhow::Result<Option<SecurityAlerts>> {
+        if self.security_alerts.is_some() && !self.repos.contains_key(repo_id) {
+            return Err(UpstreamError::new(
+                ErrorClass::NotFound,
+                format!("no fixture for {}", repo_id),
+            )
+            .into());
+        }
+        Ok(self.security_alerts.clone())
+    }
+
+    async fn calendar(
+        &self,
+        repo_id: &RepoId,
+        _max_pages: u32,
+    ) -> anyhow::Result<Option<RepoCalendar>> {
+        if self.calendar.is_some() && !self.repos.contains_key(repo_id) {
+            return Err(UpstreamError::new(
+                ErrorClass::NotFound,
+                format!("no fixture for {}", repo_id),
+            )
+            .into());
+        }
+        Ok(self.calendar.clone())
+    }
+
+    async fn is_public(&self, _repo_id: &RepoId) -> anyhow::Result<bool> {
+        Ok(!self.private)
+    }
+
+    fn for_user(&self, _token: &str) -> anyhow::Result<Arc<dyn PullRequestSource>> {
+        Ok(Arc::new(self.clone()))
+    }
+
+    async fn rate_limit(&self) -> anyhow::Result<Option<RateLimit>> {
+        let mut limits = RateLimit::default();
+        limits.core.remaining = if self.rate_limit_reset.is_some() {
+            0
+        } else {
+            5000
+        };
+        limits.core.reset = self.rate_limit_reset.unwrap_or_default();
+        limits.search.remaining = 30;
+        Ok(Some(limits))
+    }
+}
+
+/// A pull request opened `opened_days_ago` and, if given, merged `merged_days_ago`.
+pub fn pr(id: u64, opened_days_ago: i64, merged_days_ago: Option<i64>) -> GitHubPR {
+    let now = Utc::now();
+    let merged_at = merged_days_ago.map(|d| now - Duration::days(d));
+    GitHubPR {
+        id,
+        number: id,
+        created_at: now - Duration::days(opened_days_ago),
+        merged_at,
+        closed_at: merged_at,
+        state: if merged_at.is_some() {
+            PRState::Merged
+        } else {
+            PRState::Open
+        },
+        ..Default::default()
+    }
+}
+
+/// Configuration with small windows and no popular repositories, plus any `overrides`.
+pub fn test_config(overrides: &[(&str, &str)]) -> AppConfig {
+    let defaults = [
+        ("PR_FETCH_DAYS", "90"),
+        ("MAX_GITHUB_API_PAGES", "1"),
+        ("METRICS_DAYS_TO_DISPLAY", "30"),
+        ("METRICS_WINDOW_SIZE", "30"),
+        ("CACHE_TTL_SECONDS", "3600"),
+        ("CACHE_MAX_CAPACITY", "100"),
+        ("POPULAR_REPOS", ""),
+    ];
+    let vars = defaults
+        .iter()
+        .filter(|(k, _)| !overrides.iter().any(|(o, _)| o == k))
+        .chain(overrides)
+        .map(|(k, v)| (k.to_string(), v.to_string()));
+    envy::from_iter(vars).expect("invalid test config")
+}
@@ -0,0 +1,439 @@
+//! Classification of failures from the pull request source.
+//!
+//! Only transient failures (network errors and 5xx responses) are worth retrying; a 404 or a
+//! rejected token will fail the same way every time. The class also determines the status code
+//! returned to our own clients. When transient failures keep coming, a circuit breaker stops
+//! calling the source for a while instead of adding load to an outage.
+
+use crate::source::RateLimit;
+use chrono::{DateTime, Utc};
+use http::StatusCode;
+use std::fmt;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::Duration as StdDuration;
+use tokio::time::Instant;
+
+/// Delay before the first retry; doubled for each subsequent attempt.
+const RETRY_BASE_DELAY: StdDuration = StdDuration::from_millis(500);
+
+/// Longest delay between retries, however many there have been.
+const RETRY_MAX_DELAY: StdDuration = StdDuration::from_secs(30);
+
+/// How long clients are told to wait when no exhausted limit reports a reset time, as with
+/// GitHub's secondary rate limits. GitHub recommends waiting at least a minute.
+const DEFAULT_RETRY_AFTER_SECONDS: u64 = 60;
+
+/// How long clients are told to wait when too many cold fetches are queued, about as long as a
+/// typical fetch takes.
+pub const OVERLOADED_RETRY_AFTER_SECONDS: u64 = 5;
+
+/// The kind of upstream failure, which decides whether it is retried and how it is reported.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// The repository doesn't exist or isn't visible with the credentials used.
+    NotFound,
+    /// The credentials were rejected or lack permission.
+    Unauthorized,
+    /// The rate limit for the credentials is exhausted.
+    RateLimited,
+    /// The provider rejected the request as invalid (e.g. 422).
+    Invalid,
+    /// A network error or server-side failure that may succeed if repeated.
+    Transient,
+    /// The provider answered with a body that couldn't be parsed, e.g. an HTML error page from a
+    /// proxy in front of it.
+    BadResponse,
+    /// Too many uncached repositories are already being fetched to start another.
+    Overloaded,
+    /// The source failed repeatedly, so calls to it are paused for a cooldown.
+    CircuitOpen,
+    /// Anything else.
+    Unknown,
+}
+
+impl ErrorClass {
+    pub fn is_retryable(self) -> bool {
+        self == ErrorClass::Transient
+    }
+
+    /// The machine-readable error code reported to API clients for this class.
+    pub fn code(self) -> &'static str {
+        match self {
+            ErrorClass::NotFound => "repo_not_found",
+            ErrorClass::Unauthorized => "repo_access_denied",
+            ErrorClass::RateLimited => "github_rate_limited",
+            ErrorClass::Invalid => "invalid_repo_request",
+            ErrorClass::Transient => "github_unavailable",
+            ErrorClass::BadResponse => "github_bad_response",
+            ErrorClass::Overloaded => "fetch_queue_full",
+            ErrorClass::CircuitOpen => "github_circuit_open",
+            ErrorClass::Unknown => "internal_error",
+        }
+    }
+
+    /// The status code and message reported to API clients for this class.
+    pub fn response(self) -> (StatusCode, &'static str) {
+        match self {
+            ErrorClass::NotFound => (StatusCode::NOT_FOUND, "Repository Not Found"),
+            ErrorClass::Unauthorized => (StatusCode::FORBIDDEN, "Access to repository denied"),
+            ErrorClass::RateLimited => {
+                (StatusCode::TOO_MANY_REQUESTS, "GitHub Rate Limit Exceeded")
+            }
+            ErrorClass::Invalid => (StatusCode::BAD_REQUEST, "Invalid repository request"),
+            ErrorClass::Transient => (StatusCode::BAD_GATEWAY, "GitHub is unavailable"),
+            ErrorClass::BadResponse => (
+                StatusCode::BAD_GATEWAY,
+                "GitHub returned an unexpected response",
+            ),
+            ErrorClass::Overloaded => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "Too many repositories are being fetched; try again shortly",
+            ),
+            ErrorClass::CircuitOpen => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "GitHub keeps failing; requests to it are paused briefly",
+            ),
+            ErrorClass::Unknown => (StatusCode::INTERNAL_SERVER_ERROR, "Internal Server Error"),
+        }
+    }
+}
+
+/// An error raised by a source that knows its class up front, such as a missing recording.
+#[derive(Debug)]
+pub struct UpstreamError {
+    pub class: ErrorClass,
+    pub message: String,
+}
+
+impl UpstreamError {
+    pub fn new(class: ErrorClass, message: impl Into<String>) -> Self {
+        Self {
+            class,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for UpstreamError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for UpstreamError {}
+
+/// Classifies an HTTP error response from GitHub.
+pub fn classify_status(status: StatusCode, message: &str) -> ErrorClass {
+    match status.as_u16() {
+        404 | 410 => ErrorClass::NotFound,
+        429 => ErrorClass::RateLimited,
+        // GitHub reports primary and secondary rate limits as 403 as well as missing permissions.
+        403 if message.to_lowercase().contains("rate limit") => ErrorClass::RateLimited,
+        401 | 403 => ErrorClass::Unauthorized,
+        400 | 422 => ErrorClass::Invalid,
+        500..=599 => ErrorClass::Transient,
+        _ => ErrorClass::Unknown,
+    }
+}
+
+/// Classifies an error returned by a `PullRequestSource`.
+pub fn classify(error: &anyhow::Error) -> ErrorClass {
+    if let Some(error) = error.downcast_ref::<UpstreamError>() {
+        return error.class;
+    }
+    match error.downcast_ref::<octocrab::Error>() {
+        Some(octocrab::Error::GitHub { source, .. }) => {
+            classify_status(source.status_code, &source.message)
+        }
+        Some(octocrab::Error::Hyper { .. } | octocrab::Error::Service { .. }) => {
+            ErrorClass::Transient
+        }
+        Some(octocrab::Error::Serde { .. } | octocrab::Error::Json { .. }) => {
+            ErrorClass::BadResponse
+        }
+        _ => ErrorClass::Unknown,
+    }
+}
+
+/// Seconds until a rate-limited request may succeed, from the reset time of whichever limit is
+/// exhausted.
+pub fn retry_after_seconds(limits: Option<&RateLimit>, now: DateTime<Utc>) -> u64 {
+    let reset = limits.and_then(|limits| {
+        [&limits.core, &limits.search]
+            .into_iter()
+            .filter(|rate| rate.remaining == 0)
+            .map(|rate| rate.reset)
+            .max()
+    });
+    match reset {
+        Some(reset) => (reset as i64 - now.timestamp()).max(1) as u64,
+        None => DEFAULT_RETRY_AFTER_SECONDS,
+    }
+}
+
+/// Runs `attempt` until it succeeds, fails with a non-retryable error, or `max_retries` retries
+/// have been made, backing off exponentially between attempts.
+pub async fn retry<T, F, Fut>(max_retries: u32, mut attempt: F) -> anyhow::Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = anyhow::Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        match attempt().await {
+            Ok(value) => return Ok(value),
+            Err(e) if retries < max_retries && classify(&e).is_retryable() => {
+                let delay = retry_delay(retries);
+                tracing::warn!("Retrying in {:?} after transient error: {}", delay, e);
+                tokio::time::sleep(delay).await;
+                retries += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// The backoff before retry number `retries + 1`.
+fn retry_delay(retries: u32) -> StdDuration {
+    RETRY_BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(retries))
+        .min(RETRY_MAX_DELAY)
+}
+
+/// Fails calls fast once `threshold` have failed transiently in a row, until `cooldown` has
+/// passed. Then one call is let through as a probe: its success closes the circuit again, and its
+/// failure reopens it for another cooldown. A threshold of zero never opens the circuit.
+pub struct CircuitBreaker {
+    threshold: u32,
+    cooldown: StdDuration,
+    circuit: Mutex<Circuit>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Circuit {
+    Closed {
+        failures: u32,
+    },
+    Open {
+        until: Instant,
+    },
+    /// A probe is in flight; if it's abandoned, another is let through after `until`.
+    HalfOpen {
+        until: Instant,
+    },
+}
+
+impl CircuitBreaker {
+    pub fn new(threshold: u32, cooldown: StdDuration) -> Self {
+        Self {
+            threshold,
+            cooldown,
+            circuit: Mutex::new(Circuit::Closed { failures: 0 }),
+        }
+    }
+
+    /// Runs `attempt` unless the circuit is open, recording whether it failed transiently.
+    pub async fn call<T>(
+        &self,
+        attempt: impl Future<Output = anyhow::Result<T>>,
+    ) -> anyhow::Result<T> {
+        self.admit()?;
+        let result = attempt.await;
+        let failed = result.as_ref().is_err_and(|e| classify(e).is_retryable());
+        self.record(failed);
+        result
+    }
+
+    fn admit(&self) -> anyhow::Result<()> {
+        let mut circuit = self.circuit.lock().expect("circuit lock poisoned");
+        match *circuit {
+            Circuit::Closed { .. } => Ok(()),
+            Circuit::Open { until } | Circuit::HalfOpen { until } if Instant::now() >= until => {
+                tracing::info!("Probing GitHub after its cooldown");
+                *circuit = Circuit::HalfOpen {
+                    until: Instant::now() + self.cooldown,
+                };
+                Ok(())
+            }
+            _ => Err(UpstreamError::new(
+                ErrorClass::CircuitOpen,
+                "GitHub failed repeatedly; not calling it until the cooldown ends",
+            )
+            .into()),
+        }
+    }
+
+    fn record(&self, failed: bool) {
+        if self.threshold == 0 {
+            return;
+        }
+        let mut circuit = self.circuit.lock().expect("circuit lock poisoned");
+        let failures = match *circuit {
+            Circuit::Closed { failures } if failed => failures + 1,
+            Circuit::Closed { .. } => 0,
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } if failed => self.threshold,
+            Circuit::Open { .. } | Circuit::HalfOpen { .. } => {
+                tracing::info!("GitHub recovered; closing the circuit");
+                0
+            }
+        };
+        *circuit = if failures >= self.threshold {
+            if matches!(*circuit, Circuit::Closed { .. }) {
+                tracing::warn!(
+                    "Pausing GitHub requests for {:?} after {} failures in a row",
+                    self.cooldown,
+                    failures
+                );
+            }
+            Circuit::Open {
+                until: Instant::now() + self.cooldown,
+            }
+        } else {
+            Circuit::Closed { failures }
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    #[test]
+    fn test_classify_status() {
+        let class =
+            |code: u16, message| classify_status(StatusCode::from_u16(code).unwrap(), message);
+        assert_eq!(class(404, "Not Found"), ErrorClass::NotFound);
+        assert_eq!(class(401, "Bad credentials"), ErrorClass::Unauthorized);
+        assert_eq!(
+            class(403, "API rate limit exceeded for user"),
+            ErrorClass::RateLimited
+        );
+        assert_eq!(
+            class(403, "Resource not accessible"),
+            ErrorClass::Unauthorized
+        );
+        assert_eq!(class(422, "Validation Failed"), ErrorClass::Invalid);
+        assert_eq!(class(502, "Bad Gateway"), ErrorClass::Transient);
+        assert!(!ErrorClass::NotFound.is_retryable());
+    }
+
+    #[test]
+    fn test_classify_error() {
+        let typed = anyhow::Error::new(UpstreamError::new(ErrorClass::NotFound, "gone"));
+        assert_eq!(classify(&typed), ErrorClass::NotFound);
+        assert_eq!(classify(&anyhow::anyhow!("boom")), ErrorClass::Unknown);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_retry_only_transient_errors() {
+        let attempts = AtomicU32::new(0);
+        let result = retry(2, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(UpstreamError::new(ErrorClass::Transient, "timeout").into())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+
+        let attempts = AtomicU32::new(0);
+        let result = retry(2, || async {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(UpstreamError::new(ErrorClass::NotFound, "missing").into())
+        })
+        .await;
+        assert!(result.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_retry_delay_is_capped() {
+        assert_eq!(retry_delay(0), RETRY_BASE_DELAY);
+        assert_eq!(retry_delay(2), StdDuration::from_secs(2));
+        assert_eq!(retry_delay(40), RETRY_MAX_DELAY);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_circuit_opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new(2, StdDuration::from_secs(30));
+        let attempts = AtomicU32::new(0);
+        let attempts = &attempts;
+        let call = |class| {
+            breaker.call(async move {
+                attempts.fetch_add(1, Ordering::SeqCst);
+                Err::<(), _>(UpstreamError::new(class, "failed").into())
+            })
+        };
+
+        // Only transient failures count; any other answer shows GitHub is up and resets the count.
+        assert!(call(ErrorClass::Transient).await.is_err());
+        assert!(call(ErrorClass::NotFound).await.is_err());
+        assert!(call(ErrorClass::Transient).await.is_err());
+        assert!(call(ErrorClass::Transient).await.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+
+        let error = call(ErrorClass::Transient).await.unwrap_err();
+        assert_eq!(classify(&error), ErrorClass::CircuitOpen);
+        tokio::time::advance(StdDuration::from_secs(29)).await;
+        assert!(call(ErrorClass::Transient).await.is_err());
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_half_open_circuit_lets_one_probe_through() {
+        let breaker = CircuitBreaker::new(1, StdDuration::from_secs(30));
+        let fail =
+            || async { Err::<(), _>(UpstreamError::new(ErrorClass::Transient, "502").into()) };
+        assert!(breaker.call(fail()).await.is_err());
+        tokio::time::advance(StdDuration::from_secs(30)).await;
+
+        // A failed probe reopens the circuit for another cooldown.
+        assert!(breaker.call(fail()).await.is_err());
+        assert!(matches!(
+            *breaker.circuit.lock().unwrap(),
+            Circuit::Open { .. }
+        ));
+        tokio::time::advance(StdDuration::from_secs(30)).await;
+
+        // Calls made while the probe is in flight fail fast; its success closes the circuit.
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+        let probe = breaker.call(async {
+            released.await.unwrap();
+            anyhow::Ok(())
+        });
+        let waiting = async {
+            tokio::task::yield_now().await;
+            let error = breaker.call(async { anyhow::Ok(()) }).await.unwrap_err();
+            assert_eq!(classify(&error), ErrorClass::CircuitOpen);
+            release.send(()).unwrap();
+        };
+        let (probed, ()) = tokio::join!(probe, waiting);
+        assert!(probed.is_ok());
+        assert_eq!(
+            *breaker.circuit.lock().unwrap(),
+            Circuit::Closed { failures: 0 }
+        );
+        assert!(breaker.call(async { anyhow::Ok(()) }).await.is_ok());
+    }
+
+    #[test]
+    fn test_retry_after_seconds() {
+        let now = Utc::now();
+        let mut limits = RateLimit::default();
+        limits.core.remaining = 10;
+        limits.search.remaining = 10;
+        assert_eq!(
+            retry_after_seconds(Some(&limits), now),
+            DEFAULT_RETRY_AFTER_SECONDS
+        );
+        assert_eq!(retry_after_seconds(None, now), DEFAULT_RETRY_AFTER_SECONDS);
+
+        limits.core.remaining = 0;
+        limits.core.reset = now.timestamp() as u64 + 300;
+        assert_eq!(retry_after_seconds(Some(&limits), now), 300);
+
+        limits.core.reset = now.timestamp() as u64 - 5;
+        assert_eq!(retry_after_seconds(Some(&limits), now), 1);
+    }
+}
@@ -0,0 +1,417 @@
+//! Operator-only endpoints under `/api/v1/admin`.
+//!
+//! All routes require `Authorization: Bearer <ADMIN_TOKEN>` and are only mounted when
+//! `ADMIN_TOKEN` is configured.
+
+use crate::api_keys::UsageReport;
+use crate::audit::{AuditEntry, AuditQuery};
+use crate::build_info::BuildInfo;
+use crate::error::ApiError;
+use crate::extract::{Json, Path, Query};
+use crate::popularity::RepoViews;
+use crate::telemetry::RouteSummary;
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    middleware::{self, Next},
+    response::Response,
+    routing::{delete, get, post},
+    Router,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use repoflow_core::business_days::InvalidCalendar;
+use repoflow_core::config::{PopularRepo, Secret};
+use repoflow_core::domain::RepoId;
+use repoflow_core::popular::{Added, ListFull};
+use repoflow_core::service::{Maintenance, RefreshStatus};
+use repoflow_core::source::RateLimit;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// Routes for operators, guarded by the admin token.
+pub fn router(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/status", get(get_status))
+        .route("/admin/audit", get(get_audit_log))
+        .route("/admin/config", get(get_config))
+        .route("/admin/keys/{id}/usage", get(get_key_usage))
+        .route("/admin/popularity", get(get_popularity))
+        .route("/admin/popular-repos", post(add_popular_repo))
+        .route(
+            "/admin/popular-repos/{owner}/{repo}",
+            delete(remove_popular_repo),
+        )
+        .route("/admin/popular-repos/removed", get(get_removed_repos))
+        .route(
+            "/admin/popular-repos/{owner}/{repo}/restore",
+            post(restore_popular_repo),
+        )
+        .route(
+            "/admin/maintenance",
+            get(get_maintenance).post(set_maintenance),
+        )
+        .route("/admin/holidays", get(get_holidays).put(put_holidays))
+        .merge(crate::teams::admin_router())
+        .route_layer(crate::concurrency_limit(
+            state.config.admin_concurrency_limit,
+        ))
+        .route_layer(middleware::from_fn_with_state(state, require_admin))
+}
+
+#[derive(Serialize)]
+struct StatusResponse {
+    started_at: DateTime<Utc>,
+    uptime_seconds: i64,
+    build: BuildInfo,
+    cache: CacheStatus,
+    refresh: RefreshReport,
+    github_rate_limit: Option<RateLimit>,
+    routes: Vec<RouteSummary>,
+}
+
+#[derive(Serialize)]
+struct CacheStatus {
+    metrics_entries: u64,
+    sessions: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct RefreshReport {
+    queue_depth: usize,
+    /// Present while refreshes are paused for maintenance.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maintenance: Option<Maintenance>,
+    repos: Vec<RepoRefreshStatus>,
+}
+
+/// The body of a request entering or leaving maintenance.
+#[derive(Deserialize)]
+struct MaintenanceRequest {
+    enabled: bool,
+    /// Shown to users while maintenance lasts, e.g. "GitHub is degraded".
+    reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct MaintenanceResponse {
+    enabled: bool,
+    #[serde(flatten)]
+    maintenance: Option<Maintenance>,
+}
+
+/// The holidays the time series flags, from `HOLIDAYS` and the holiday calendar.
+#[derive(Serialize)]
+struct HolidaysResponse {
+    /// Whether windows skip them, with the weekend.
+    business_days: bool,
+    holidays: Vec<NaiveDate>,
+}
+
+#[derive(Serialize)]
+struct RepoRefreshStatus {
+    repo: String,
+    #[serde(flatten)]
+    status: RefreshStatus,
+}
+
+/// A removed popular repository that can still be restored.
+#[derive(Serialize)]
+struct RemovedRepoResponse {
+    #[serde(flatten)]
+    popular: PopularRepo,
+    removed_at: DateTime<Utc>,
+    /// When it can no longer be restored.
+    forgotten_at: DateTime<Utc>,
+}
+
+/// How often a repository is requested, and whether it's already kept warm.
+#[derive(Serialize)]
+struct RepoPopularity {
+    #[serde(flatten)]
+    views: RepoViews,
+    popular: bool,
+}
+
+/// Returns whether the request carries the configured admin bearer token.
+pub fn is_admin(state: &AppState, headers: &HeaderMap) -> bool {
+    let Some(expected) = state.config.admin_token.as_ref().map(Secret::expose) else {
+        return false;
+    };
+    headers
+        .get(AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .is_some_and(|token| constant_time_eq(token.as_bytes(), expected.as_bytes()))
+}
+
+/// Compares secrets without short-circuiting, so response timing doesn't leak a matching prefix.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+async fn require_admin(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    if !is_admin(&state, request.headers()) {
+        return Err(ApiError::unauthorized("Admin token required"));
+    }
+    Ok(next.run(request).await)
+}
+
+async fn get_maintenance(State(state): State<Arc<AppState>>) -> Json<MaintenanceResponse> {
+    let maintenance = state.service.maintenance();
+    Json(MaintenanceResponse {
+        enabled: maintenance.is_some(),
+        maintenance,
+    })
+}
+
+/// Pauses or resumes background refreshes and outgoing notifications. Entering maintenance again
+/// only updates the reason, keeping when it started.
+async fn set_maintenance(
+    State(state): State<Arc<AppState>>,
+    Json(request): Json<MaintenanceRequest>,
+) -> Json<MaintenanceResponse> {
+    let maintenance = request.enabled.then(|| Maintenance {
+        since: state
+            .service
+            .maintenance()
+            .map_or_else(Utc::now, |current| current.since),
+        reason: request
+            .reason
+            .map(|reason| reason.trim().to_string())
+            .filter(|reason| !reason.is_empty()),
+    });
+    state.service.set_maintenance(maintenance.clone());
+    Json(MaintenanceResponse {
+        enabled: maintenance.is_some(),
+        maintenance,
+    })
+}
+
+async fn get_holidays(State(state): State<Arc<AppState>>) -> Json<HolidaysResponse> {
+    Json(HolidaysResponse {
+        business_days: state.config.business_days,
+        holidays: state.service.business_days().holidays().collect(),
+    })
+}
+
+/// Replaces the holiday calendar with the iCalendar file in the body.
+async fn put_holidays(
+    State(state): State<Arc<AppState>>,
+    ics: String,
+) -> Result<Json<HolidaysResponse>, ApiError> {
+    match state.service.set_holiday_calendar(&ics).await {
+        Ok(business_days) => Ok(Json(HolidaysResponse {
+            business_days: state.config.business_days,
+            holidays: business_days.holidays().collect(),
+        })),
+        Err(e) if e.is::<InvalidCalendar>() => Err(ApiError::bad_request(format!(
+            "Invalid holiday calendar: {}",
+            e
+        ))),
+        Err(e) => {
+            tracing::error!("Failed to save the holiday calendar: {:#}", e);
+            Err(ApiError::internal())
+        }
+    }
+}
+
+async fn get_status(State(state): State<Arc<AppState>>) -> Json<StatusResponse> {
+    let statuses = state.service.refresh_statuses();
+    let repos = state
+        .service
+        .popular_repos()
+        .await
+        .iter()
+        .map(|popular| RepoRefreshStatus {
+            repo: popular.id.to_string(),
+            status: statuses.get(&popular.id).cloned().unwrap_or_default(),
+        })
+        .collect();
+
+    let github_rate_limit = match state.service.rate_limit().await {
+        Ok(limits) => limits,
+        Err(e) => {
+            tracing::warn!("Failed to fetch GitHub rate limit: {}", e);
+            None
+        }
+    };
+
+    let now = Utc::now();
+    Json(StatusResponse {
+        started_at: state.started_at,
+        uptime_seconds: (now - state.started_at).num_seconds(),
+        build: BuildInfo::current(),
+        cache: CacheStatus {
+            metrics_entries: state.service.cache_entry_count(),
+            sessions: state.auth.as_ref().map(|auth| auth.session_count()),
+        },
+        refresh: RefreshReport {
+            queue_depth: state.service.refresh_queue_depth(),
+            maintenance: state.service.maintenance(),
+            repos,
+        },
+        github_rate_limit,
+        routes: state.telemetry.summaries(),
+    })
+}
+
+/// The effective configuration with secrets redacted. `popular_repos` reflects changes made at
+/// runtime rather than the list loaded at startup.
+async fn get_config(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, ApiError> {
+    let internal_error = |e: serde_json::Error| {
+        tracing::error!("Failed to serialize configuration: {}", e);
+        ApiError::internal()
+    };
+    let mut config = serde_json::to_value(&state.config).map_err(internal_error)?;
+    config["popular_repos"] =
+        serde_json::to_value(state.service.popular_repos().await).map_err(internal_error)?;
+    Ok(Json(config))
+}
+
+async fn get_audit_log(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<AuditQuery>,
+) -> Json<Vec<AuditEntry>> {
+    Json(state.audit.query(&query).await)
+}
+
+async fn get_key_usage(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<UsageReport>, ApiError> {
+    state
+        .api_keys
+        .usage(&id, Utc::now().date_naive())
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("API key not found"))
+}
+
+/// Repositories by how often they're requested, to guide which belong on the popular list.
+async fn get_popularity(State(state): State<Arc<AppState>>) -> Json<Vec<RepoPopularity>> {
+    let popular: HashSet<RepoId> = state
+        .service
+        .popular_repos()
+        .await
+        .into_iter()
+        .map(|popular| popular.id)
+        .collect();
+    Json(
+        state
+            .popularity
+            .most_viewed()
+            .into_iter()
+            .map(|views| RepoPopularity {
+                popular: popular.contains(&views.repo),
+                views,
+            })
+            .collect(),
+    )
+}
+
+async fn add_popular_repo(
+    State(state): State<Arc<AppState>>,
+    Json(popular): Json<PopularRepo>,
+) -> Result<(StatusCode, Json<PopularRepo>), ApiError> {
+    let problems = popular.id.naming_problems();
+    if !problems.is_empty() {
+        return Err(ApiError::bad_request(format!(
+            "'{}' is not a valid owner/repo: {}",
+            popular.id,
+            problems.join("; ")
+        )));
+    }
+    if popular.max_pages == Some(0) {
+        return Err(ApiError::bad_request("max_pages must be nonzero"));
+    }
+
+    match state.service.add_popular_repo(popular.clone()).await {
+        Ok(Added::New) => Ok((StatusCode::CREATED, Json(popular))),
+        Ok(Added::Updated) => Ok((StatusCode::OK, Json(popular))),
+        Err(e) if e.is::<ListFull>() => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "popular_repos_full",
+            e.to_string(),
+        )),
+        Err(e) => {
+            tracing::error!("Failed to add popular repo {}: {:#}", popular.id, e);
+            Err(ApiError::internal())
+        }
+    }
+}
+
+async fn remove_popular_repo(
+    State(state): State<Arc<AppState>>,
+    Path(repo_id): Path<RepoId>,
+) -> Result<StatusCode, ApiError> {
+    match state.service.remove_popular_repo(&repo_id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::not_found(
+            "Repository is not a popular repository",
+        )),
+        Err(e) => {
+            tracing::error!("Failed to remove popular repo {}: {:#}", repo_id, e);
+            Err(ApiError::internal())
+        }
+    }
+}
+
+async fn get_removed_repos(State(state): State<Arc<AppState>>) -> Json<Vec<RemovedRepoResponse>> {
+    let retention =
+        Duration::from_std(state.config.removed_repo_retention()).unwrap_or(Duration::MAX);
+    Json(
+        state
+            .service
+            .removed_repos()
+            .iter()
+            .map(|removed| RemovedRepoResponse {
+                popular: removed.popular.clone(),
+                removed_at: removed.removed_at,
+                forgotten_at: removed
+                    .removed_at
+                    .checked_add_signed(retention)
+                    .unwrap_or(DateTime::<Utc>::MAX_UTC),
+            })
+            .collect(),
+    )
+}
+
+async fn restore_popular_repo(
+    State(state): State<Arc<AppState>>,
+    Path(repo_id): Path<RepoId>,
+) -> Result<Json<PopularRepo>, ApiError> {
+    match state.service.restore_popular_repo(&repo_id).await {
+        Ok(Some(popular)) => Ok(Json(popular)),
+        Ok(None) => Err(ApiError::not_found(
+            "Repository was not removed recently enough to restore",
+        )),
+        Err(e) if e.is::<ListFull>() => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "popular_repos_full",
+            e.to_string(),
+        )),
+        Err(e) => {
+            tracing::error!("Failed to restore popular repo {}: {:#}", repo_id, e);
+            Err(ApiError::internal())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"secret2"));
+    }
+}
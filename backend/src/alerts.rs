@@ -0,0 +1,181 @@
+//! Alert rules as API resources. The rules themselves, and their evaluation after each refresh,
+//! are in `repoflow_core::alerts`.
+//!
+//! Rules hold webhook URLs and integration keys, which are credentials, so every route takes the
+//! admin token.
+
+use crate::error::ApiError;
+use crate::extract::{Json, Path, Query};
+use crate::AppState;
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::get,
+    Router,
+};
+use repoflow_core::alerts::{
+    self, AlertChannel, AlertMetric, AlertRule, Comparator, Evaluation, QuietHours, RulesFull,
+};
+use repoflow_core::domain::RepoId;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const DEFAULT_HISTORY_LIMIT: usize = 100;
+const MAX_HISTORY_LIMIT: usize = 1_000;
+
+/// Filters accepted by the history endpoint.
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Only return checks of this `owner/repo`.
+    repo: Option<String>,
+    /// Maximum number of checks to return, most recent first.
+    limit: Option<usize>,
+}
+
+/// The body of a request creating or replacing a rule.
+#[derive(Deserialize)]
+struct AlertRuleRequest {
+    repo: Option<RepoId>,
+    metric: AlertMetric,
+    comparator: Comparator,
+    threshold: f64,
+    channel: AlertChannel,
+    #[serde(default = "alerts::default_cooldown_minutes")]
+    cooldown_minutes: u64,
+    quiet_hours: Option<QuietHours>,
+}
+
+impl AlertRuleRequest {
+    /// The rule this request describes, under `id`, if it's valid.
+    fn into_rule(self, id: String) -> Result<AlertRule, ApiError> {
+        let rule = AlertRule {
+            id,
+            repo: self.repo,
+            metric: self.metric,
+            comparator: self.comparator,
+            threshold: self.threshold,
+            channel: self.channel,
+            cooldown_minutes: self.cooldown_minutes,
+            quiet_hours: self.quiet_hours,
+        };
+        let problems = rule.problems();
+        if !problems.is_empty() {
+            return Err(ApiError::bad_request(problems.join("; ")));
+        }
+        Ok(rule)
+    }
+}
+
+/// Routes for alert rules, served under the API prefix.
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/alerts", get(list_rules).post(create_rule))
+        .route(
+            "/alerts/{id}",
+            get(get_rule).put(replace_rule).delete(delete_rule),
+        )
+        .route("/alerts/{id}/history", get(get_rule_history))
+}
+
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    if crate::admin::is_admin(state, headers) {
+        Ok(())
+    } else {
+        Err(ApiError::unauthorized("Admin token required"))
+    }
+}
+
+fn store_error(id: &str, e: anyhow::Error) -> ApiError {
+    if e.is::<RulesFull>() {
+        return ApiError::new(StatusCode::CONFLICT, "alert_rules_full", e.to_string());
+    }
+    tracing::error!("Failed to save alert rule {}: {:#}", id, e);
+    ApiError::internal()
+}
+
+async fn list_rules(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Result<Json<Vec<AlertRule>>, ApiError> {
+    require_admin(&state, &headers)?;
+    Ok(Json(state.service.alert_rules().list().await))
+}
+
+async fn get_rule(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<Json<AlertRule>, ApiError> {
+    require_admin(&state, &headers)?;
+    state
+        .service
+        .alert_rules()
+        .get(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("Alert rule not found"))
+}
+
+async fn create_rule(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<AlertRuleRequest>,
+) -> Result<(StatusCode, Json<AlertRule>), ApiError> {
+    require_admin(&state, &headers)?;
+    let rule = request.into_rule(format!("{:016x}", rand::random::<u64>()))?;
+    match state.service.alert_rules().put(rule.clone()).await {
+        Ok(_) => Ok((StatusCode::CREATED, Json(rule))),
+        Err(e) => Err(store_error(&rule.id, e)),
+    }
+}
+
+async fn replace_rule(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<AlertRuleRequest>,
+) -> Result<Json<AlertRule>, ApiError> {
+    require_admin(&state, &headers)?;
+    let rules = state.service.alert_rules();
+    if rules.get(&id).await.is_none() {
+        return Err(ApiError::not_found("Alert rule not found"));
+    }
+    let rule = request.into_rule(id)?;
+    match rules.put(rule.clone()).await {
+        Ok(_) => Ok(Json(rule)),
+        Err(e) => Err(store_error(&rule.id, e)),
+    }
+}
+
+async fn delete_rule(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    require_admin(&state, &headers)?;
+    match state.service.alert_rules().delete(&id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::not_found("Alert rule not found")),
+        Err(e) => Err(store_error(&id, e)),
+    }
+}
+
+/// Every recent check of a rule, with the value it saw and whether it fired, for tuning
+/// thresholds.
+async fn get_rule_history(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<Vec<Evaluation>>, ApiError> {
+    require_admin(&state, &headers)?;
+    let rules = state.service.alert_rules();
+    if rules.get(&id).await.is_none() {
+        return Err(ApiError::not_found("Alert rule not found"));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .min(MAX_HISTORY_LIMIT);
+    Ok(Json(rules.history(&id, query.repo.as_deref(), limit).await))
+}
@@ -0,0 +1,221 @@
+//! Per-API-key daily quotas and usage tracking.
+//!
+//! Consumers identify themselves with an `X-API-Key` header. Each key has a daily request quota
+//! that resets at midnight UTC, so one team can't exhaust the shared GitHub budget for everyone.
+
+use crate::admin::constant_time_eq;
+use crate::error::ApiError;
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use repoflow_core::config::ApiKey;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+
+pub const API_KEY_HEADER: &str = "x-api-key";
+const QUOTA_REMAINING_HEADER: &str = "x-quota-remaining";
+const USAGE_HISTORY_DAYS: i64 = 30;
+
+/// Request count for a single day.
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct DailyUsage {
+    pub date: NaiveDate,
+    pub requests: u64,
+}
+
+/// Usage report for one API key.
+#[derive(Debug, Serialize, Clone)]
+pub struct UsageReport {
+    pub id: String,
+    pub daily_quota: u64,
+    /// Requests made today (UTC).
+    pub today: u64,
+    /// Requests per day for the last 30 days, oldest first.
+    pub history: Vec<DailyUsage>,
+}
+
+/// Outcome of charging a request against a key's quota.
+#[derive(Debug, PartialEq)]
+pub enum QuotaCheck {
+    /// The request is allowed; contains the number of requests left today.
+    Allowed(u64),
+    Exceeded,
+}
+
+/// The configured API keys and their usage counters.
+pub struct ApiKeyRegistry {
+    keys: Vec<ApiKey>,
+    usage: Mutex<HashMap<String, BTreeMap<NaiveDate, u64>>>,
+}
+
+impl ApiKeyRegistry {
+    pub fn new(keys: Vec<ApiKey>) -> Self {
+        Self {
+            keys,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Finds the key matching the presented secret.
+    pub fn authenticate(&self, secret: &str) -> Option<&ApiKey> {
+        self.keys
+            .iter()
+            .find(|k| constant_time_eq(k.key.expose().as_bytes(), secret.as_bytes()))
+    }
+
+    /// Returns the key presented in the request headers, if it is valid.
+    pub fn key_from_headers(&self, headers: &HeaderMap) -> Option<&ApiKey> {
+        let secret = headers.get(API_KEY_HEADER)?.to_str().ok()?;
+        self.authenticate(secret)
+    }
+
+    /// Counts a request against the key's quota for `today`, unless the quota is already spent.
+    pub fn charge(&self, key: &ApiKey, today: NaiveDate) -> QuotaCheck {
+        let mut usage = self.usage.lock().expect("api key usage lock poisoned");
+        let days = usage.entry(key.id.clone()).or_default();
+        days.retain(|date, _| *date > today - Duration::days(USAGE_HISTORY_DAYS));
+
+        let count = days.entry(today).or_insert(0);
+        if *count >= key.daily_quota {
+            return QuotaCheck::Exceeded;
+        }
+        *count += 1;
+        QuotaCheck::Allowed(key.daily_quota - *count)
+    }
+
+    /// Builds the usage report for the key with the given ID.
+    pub fn usage(&self, id: &str, today: NaiveDate) -> Option<UsageReport> {
+        let key = self.keys.iter().find(|k| k.id == id)?;
+        let usage = self.usage.lock().expect("api key usage lock poisoned");
+        let days = usage.get(id);
+
+        let history = (0..USAGE_HISTORY_DAYS)
+            .rev()
+            .map(|offset| {
+                let date = today - Duration::days(offset);
+                DailyUsage {
+                    date,
+                    requests: days.and_then(|d| d.get(&date)).copied().unwrap_or(0),
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Some(UsageReport {
+            id: key.id.clone(),
+            daily_quota: key.daily_quota,
+            today: history.last().map_or(0, |d| d.requests),
+            history,
+        })
+    }
+}
+
+/// Seconds until quotas reset at the next midnight UTC.
+fn seconds_until_utc_midnight(now: DateTime<Utc>) -> u64 {
+    let midnight = (now.date_naive() + Duration::days(1))
+        .and_hms_opt(0, 0, 0)
+        .expect("midnight is a valid time")
+        .and_utc();
+    (midnight - now).num_seconds().max(1) as u64
+}
+
+/// Middleware enforcing API key authentication and daily quotas on repository endpoints.
+pub async fn enforce_quota(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let presented = request.headers().contains_key(API_KEY_HEADER);
+    let Some(key) = state.api_keys.key_from_headers(request.headers()) else {
+        if presented {
+            return Err(ApiError::unauthorized("Invalid API key"));
+        }
+        if state.config.require_api_key {
+            return Err(ApiError::unauthorized("API key required"));
+        }
+        return Ok(next.run(request).await);
+    };
+
+    match state.api_keys.charge(key, Utc::now().date_naive()) {
+        QuotaCheck::Allowed(remaining) => {
+            let mut response = next.run(request).await;
+            response
+                .headers_mut()
+                .insert(QUOTA_REMAINING_HEADER, HeaderValue::from(remaining));
+            Ok(response)
+        }
+        QuotaCheck::Exceeded => {
+            tracing::warn!(key_id = %key.id, "API key exceeded its daily quota");
+            Err(ApiError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "quota_exceeded",
+                "Daily API key quota exceeded",
+            )
+            .with_retry_after(seconds_until_utc_midnight(Utc::now())))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repoflow_core::config::Secret;
+
+    fn registry() -> ApiKeyRegistry {
+        ApiKeyRegistry::new(vec![ApiKey {
+            id: "payments".to_string(),
+            key: Secret::new("sk_payments"),
+            daily_quota: 2,
+        }])
+    }
+
+    #[test]
+    fn test_quota_resets_daily() {
+        let registry = registry();
+        let key = registry.authenticate("sk_payments").unwrap().clone();
+        let day1 = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let day2 = day1.succ_opt().unwrap();
+
+        assert_eq!(registry.charge(&key, day1), QuotaCheck::Allowed(1));
+        assert_eq!(registry.charge(&key, day1), QuotaCheck::Allowed(0));
+        assert_eq!(registry.charge(&key, day1), QuotaCheck::Exceeded);
+        assert_eq!(registry.charge(&key, day2), QuotaCheck::Allowed(1));
+    }
+
+    #[test]
+    fn test_usage_report() {
+        let registry = registry();
+        let key = registry.authenticate("sk_payments").unwrap().clone();
+        let day1 = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let day2 = day1.succ_opt().unwrap();
+        registry.charge(&key, day1);
+        registry.charge(&key, day1);
+        registry.charge(&key, day2);
+
+        let report = registry.usage("payments", day2).unwrap();
+        assert_eq!(report.today, 1);
+        assert_eq!(report.history.len(), USAGE_HISTORY_DAYS as usize);
+        assert_eq!(
+            report.history[report.history.len() - 2],
+            DailyUsage {
+                date: day1,
+                requests: 2
+            }
+        );
+        assert!(registry.usage("unknown", day2).is_none());
+        assert!(registry.authenticate("wrong").is_none());
+    }
+
+    #[test]
+    fn test_seconds_until_utc_midnight() {
+        let now = DateTime::parse_from_rfc3339("2024-01-01T23:00:00Z")
+            .unwrap()
+            .to_utc();
+        assert_eq!(seconds_until_utc_midnight(now), 3600);
+    }
+}
@@ -0,0 +1,283 @@
+//! Append-only audit log of API activity.
+//!
+//! Every API and auth request is recorded with the acting identity, the endpoint, the repository
+//! it targeted, and the resulting status. Entries are appended as JSON lines to `AUDIT_LOG_PATH`
+//! when configured, and the most recent entries are kept in memory for queries.
+
+use crate::admin;
+use crate::client_ip::ClientIp;
+use crate::jobs;
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use repoflow_core::jsonl::JsonLines;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+
+const IN_MEMORY_CAPACITY: usize = 10_000;
+const DEFAULT_QUERY_LIMIT: usize = 100;
+const MAX_QUERY_LIMIT: usize = 1_000;
+
+/// A single recorded request.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AuditEntry {
+    /// When the request completed.
+    pub timestamp: DateTime<Utc>,
+    /// Who made the request: `admin`, `key:<id>` for API keys, `user:<login>` for signed-in
+    /// GitHub users, or `anonymous`.
+    pub actor: String,
+    /// The address of the client, if known.
+    pub client_ip: Option<String>,
+    /// The HTTP method.
+    pub method: String,
+    /// The request path, without the query string.
+    pub path: String,
+    /// The `owner/repo` the request targeted, if any.
+    pub repo: Option<String>,
+    /// The HTTP status code of the response.
+    pub status: u16,
+}
+
+/// Filters accepted by the audit query endpoint.
+#[derive(Debug, Deserialize, Default)]
+pub struct AuditQuery {
+    /// Only return entries made by this actor.
+    pub actor: Option<String>,
+    /// Only return entries targeting this `owner/repo`.
+    pub repo: Option<String>,
+    /// Maximum number of entries to return, most recent first.
+    pub limit: Option<usize>,
+}
+
+impl AuditQuery {
+    fn matches(&self, entry: &AuditEntry) -> bool {
+        self.actor.as_ref().is_none_or(|a| &entry.actor == a)
+            && self
+                .repo
+                .as_ref()
+                .is_none_or(|r| entry.repo.as_ref() == Some(r))
+    }
+
+    fn limit(&self) -> usize {
+        self.limit
+            .unwrap_or(DEFAULT_QUERY_LIMIT)
+            .min(MAX_QUERY_LIMIT)
+    }
+}
+
+/// Records and queries audit entries. Queries are served from the latest entries, kept in memory
+/// and loaded from the file at startup, so they never read the file.
+pub struct AuditLog {
+    file: Option<JsonLines>,
+    recent: Mutex<VecDeque<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// A log appending to `path`, if set, which is rotated once it would pass `max_bytes`.
+    pub fn new(path: Option<PathBuf>, max_bytes: u64) -> Self {
+        let file = path.map(|path| JsonLines::new(path, max_bytes));
+        let recent = match &file {
+            Some(file) => file
+                .read_tail(IN_MEMORY_CAPACITY)
+                .unwrap_or_else(|e| {
+                    tracing::error!(
+                        "Failed to read audit log from {}: {}",
+                        file.path().display(),
+                        e
+                    );
+                    Vec::new()
+                })
+                .into(),
+            None => VecDeque::new(),
+        };
+        Self {
+            file,
+            recent: Mutex::new(recent),
+        }
+    }
+
+    /// Appends an entry to the log.
+    pub async fn record(&self, entry: AuditEntry) {
+        if let Some(file) = &self.file {
+            if let Err(e) = file.append(&entry).await {
+                tracing::error!(
+                    "Failed to write audit entry to {}: {}",
+                    file.path().display(),
+                    e
+                );
+            }
+        }
+
+        let mut recent = self.recent.lock().await;
+        if recent.len() == IN_MEMORY_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(entry);
+    }
+
+    /// Returns the most recent entries matching the query, newest first, from the latest 10,000.
+    pub async fn query(&self, query: &AuditQuery) -> Vec<AuditEntry> {
+        self.recent
+            .lock()
+            .await
+            .iter()
+            .rev()
+            .filter(|e| query.matches(e))
+            .take(query.limit())
+            .cloned()
+            .collect()
+    }
+}
+
+/// Middleware that records every API and auth request to the audit log.
+pub async fn record(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let path = request.uri().path().to_string();
+    if !(path.starts_with("/api/") || path.starts_with("/auth/")) {
+        return next.run(request).await;
+    }
+    let path = jobs::redact_job_id(&path).unwrap_or(path);
+
+    let method = request.method().to_string();
+    let client_ip = request
+        .extensions()
+        .get::<ClientIp>()
+        .and_then(|ClientIp(ip)| ip.map(|ip| ip.to_string()));
+    let actor = actor(&state, request.headers()).await;
+
+    let response = next.run(request).await;
+
+    state
+        .audit
+        .record(AuditEntry {
+            timestamp: Utc::now(),
+            actor,
+            client_ip,
+            method,
+            repo: repo_from_path(&path),
+            path,
+            status: response.status().as_u16(),
+        })
+        .await;
+
+    response
+}
+
+/// Who made a request: "admin", "key:" and the API key's id, "user:" and the signed-in user's
+/// login, or "anonymous". The prefixes keep logins such as "admin" from passing for the others.
+async fn actor(state: &AppState, headers: &HeaderMap) -> String {
+    if admin::is_admin(state, headers) {
+        return "admin".to_string();
+    }
+
+    if let Some(key) = state.api_keys.key_from_headers(headers) {
+        return format!("key:{}", key.id);
+    }
+
+    if let Some(auth) = &state.auth {
+        if let Some(session) = auth.session(&CookieJar::from_headers(headers)).await {
+            return format!("user:{}", session.login);
+        }
+    }
+
+    "anonymous".to_string()
+}
+
+/// Extracts `owner/repo` from paths of the form `/api[/v1]/repos/{owner}/{repo}/...`.
+fn repo_from_path(path: &str) -> Option<String> {
+    let path = path.strip_prefix("/api")?;
+    let path = path.strip_prefix("/v1").unwrap_or(path);
+    let mut segments = path.strip_prefix("/repos/")?.split('/');
+    let owner = segments.next()?;
+    let repo = segments.next()?;
+    // `/api/repos/popular` has no repository segment.
+    if owner.is_empty() || repo.is_empty() {
+        return None;
+    }
+    Some(format!("{}/{}", owner, repo))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(actor: &str, repo: Option<&str>) -> AuditEntry {
+        AuditEntry {
+            timestamp: Utc::now(),
+            actor: actor.to_string(),
+            client_ip: None,
+            method: "GET".to_string(),
+            path: "/api/health".to_string(),
+            repo: repo.map(str::to_string),
+            status: 200,
+        }
+    }
+
+    #[test]
+    fn test_repo_from_path() {
+        assert_eq!(
+            repo_from_path("/api/repos/facebook/react/metrics").as_deref(),
+            Some("facebook/react")
+        );
+        assert_eq!(
+            repo_from_path("/api/v1/repos/facebook/react/metrics").as_deref(),
+            Some("facebook/react")
+        );
+        assert_eq!(repo_from_path("/api/repos/popular"), None);
+        assert_eq!(repo_from_path("/api/health"), None);
+    }
+
+    #[tokio::test]
+    async fn test_query_filters_newest_first() {
+        let log = AuditLog::new(None, 0);
+        log.record(entry("user:alice", Some("a/b"))).await;
+        log.record(entry("user:bob", Some("a/b"))).await;
+        log.record(entry("user:alice", Some("c/d"))).await;
+
+        let query = AuditQuery {
+            actor: Some("user:alice".to_string()),
+            ..Default::default()
+        };
+        let entries = log.query(&query).await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].repo.as_deref(), Some("c/d"));
+
+        let query = AuditQuery {
+            repo: Some("a/b".to_string()),
+            limit: Some(1),
+            ..Default::default()
+        };
+        let entries = log.query(&query).await;
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].actor, "user:bob");
+    }
+
+    #[tokio::test]
+    async fn test_file_backed_log_round_trips() {
+        let path =
+            std::env::temp_dir().join(format!("repoflow-audit-{}.log", rand::random::<u64>()));
+        let log = AuditLog::new(Some(path.clone()), 0);
+        log.record(entry("user:alice", None)).await;
+        log.record(entry("user:bob", None)).await;
+
+        let entries = log.query(&AuditQuery::default()).await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].actor, "user:bob");
+
+        // A restart loads the latest entries back from the file.
+        let restarted = AuditLog::new(Some(path.clone()), 0);
+        let entries = restarted.query(&AuditQuery::default()).await;
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].actor, "user:bob");
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
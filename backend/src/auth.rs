@@ -0,0 +1,488 @@
+//! GitHub OAuth login and cookie-based sessions.
+//!
+//! Implements the OAuth web application flow: `/auth/login` redirects the browser to GitHub,
+//! `/auth/callback` exchanges the returned code for an access token and starts a session that is
+//! identified by an HTTP-only cookie. Sessions are held in memory and expire after a fixed TTL.
+//! The login's `state` is also set in a short-lived cookie, so a callback is only accepted from
+//! the browser that started the login.
+//!
+//! The user's access token is kept encrypted with a per-process key so that it is only ever in
+//! plaintext while a fetch on their behalf is being made.
+
+use crate::admin::constant_time_eq;
+use crate::error::ApiError;
+use crate::extract::{Json, Query};
+use crate::AppState;
+use aes_gcm::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Aes256Gcm, Nonce,
+};
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::{header::ACCEPT, StatusCode},
+    response::{IntoResponse, Redirect},
+    routing::{get, post},
+    Router,
+};
+use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
+use moka::future::Cache;
+use rand::{distributions::Alphanumeric, Rng};
+use repoflow_core::config::{AppConfig, Secret};
+use repoflow_core::http_client;
+use repoflow_core::service::UserCredentials;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+const SESSION_COOKIE: &str = "repoflow_session";
+const LOGIN_STATE_COOKIE: &str = "repoflow_login_state";
+/// `repo` is required so that signed-in users can view metrics for their private repositories.
+const OAUTH_SCOPE: &str = "read:user repo";
+const LOGIN_STATE_TTL: StdDuration = StdDuration::from_secs(10 * 60);
+const RANDOM_TOKEN_LEN: usize = 32;
+
+/// An authenticated GitHub user.
+#[derive(Clone, Debug)]
+pub struct Session {
+    /// The GitHub login of the signed-in user.
+    pub login: String,
+    /// The user's OAuth access token, encrypted with the service's token key.
+    encrypted_token: Vec<u8>,
+    /// The nonce used to encrypt `encrypted_token`.
+    nonce: Nonce<<Aes256Gcm as AeadCore>::NonceSize>,
+}
+
+/// Handles the OAuth flow and tracks active sessions.
+#[derive(Clone)]
+pub struct AuthService {
+    client_id: String,
+    client_secret: Secret,
+    redirect_url: String,
+    /// Where GitHub's OAuth endpoints live, derived from `github_oauth_url`.
+    authorize_url: String,
+    token_url: String,
+    http: reqwest::Client,
+    /// Builds the GitHub client used to look up who signed in, through any configured proxy.
+    config: AppConfig,
+    /// CSRF `state` values issued by `/auth/login` that have not been used yet.
+    pending_logins: Cache<String, ()>,
+    sessions: Cache<String, Session>,
+    /// Encrypts stored access tokens. Sessions live only in memory, so the key does too.
+    token_cipher: Aes256Gcm,
+}
+
+#[derive(Deserialize)]
+struct TokenResponse {
+    access_token: Option<String>,
+    error: Option<String>,
+    error_description: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct CallbackParams {
+    code: String,
+    state: String,
+}
+
+#[derive(Serialize)]
+struct MeResponse {
+    login: String,
+}
+
+impl AuthService {
+    /// Creates the service, or returns `None` when no OAuth App is configured. Fails if the
+    /// HTTP client can't be built, e.g. when `EXTRA_CA_CERT_PATH` can't be read.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Option<Self>> {
+        let (Some(client_id), Some(client_secret), Some(redirect_url)) = (
+            config.github_client_id.clone(),
+            config.github_client_secret.clone(),
+            config.oauth_redirect_url.clone(),
+        ) else {
+            return Ok(None);
+        };
+
+        let http =
+            http_client::reqwest_client(config).context("failed to build the OAuth client")?;
+
+        let oauth_url = config.github_oauth_url.trim_end_matches('/');
+        Ok(Some(Self {
+            client_id,
+            client_secret,
+            redirect_url,
+            authorize_url: format!("{}/login/oauth/authorize", oauth_url),
+            token_url: format!("{}/login/oauth/access_token", oauth_url),
+            http,
+            config: config.clone(),
+            pending_logins: Cache::builder().time_to_live(LOGIN_STATE_TTL).build(),
+            sessions: Cache::builder()
+                .time_to_live(StdDuration::from_secs(config.session_ttl_seconds))
+                .build(),
+            token_cipher: Aes256Gcm::new(&Aes256Gcm::generate_key(OsRng)),
+        }))
+    }
+
+    /// Builds the GitHub authorization URL for a new login attempt, with its `state`.
+    async fn authorize_url(&self) -> anyhow::Result<(reqwest::Url, String)> {
+        let state = random_token();
+        self.pending_logins.insert(state.clone(), ()).await;
+
+        let url = reqwest::Url::parse_with_params(
+            &self.authorize_url,
+            &[
+                ("client_id", self.client_id.as_str()),
+                ("redirect_uri", self.redirect_url.as_str()),
+                ("scope", OAUTH_SCOPE),
+                ("state", state.as_str()),
+            ],
+        )?;
+        Ok((url, state))
+    }
+
+    /// Consumes a login `state`, returning whether it was issued by this server, is still valid
+    /// and matches the state cookie of the browser that started the login.
+    async fn take_login_state(&self, state: &str, jar: &CookieJar) -> bool {
+        let started_here = jar
+            .get(LOGIN_STATE_COOKIE)
+            .is_some_and(|cookie| constant_time_eq(cookie.value().as_bytes(), state.as_bytes()));
+        started_here && self.pending_logins.remove(state).await.is_some()
+    }
+
+    /// Exchanges an authorization code for a token and creates a session, returning its ID.
+    async fn complete_login(&self, code: &str) -> anyhow::Result<(String, Session)> {
+        let response: TokenResponse = self
+            .http
+            .post(&self.token_url)
+            .header(ACCEPT, "application/json")
+            .form(&[
+                ("client_id", self.client_id.as_str()),
+                ("client_secret", self.client_secret.expose()),
+                ("code", code),
+                ("redirect_uri", self.redirect_url.as_str()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        let Some(access_token) = response.access_token else {
+            anyhow::bail!(
+                "GitHub rejected the authorization code: {}",
+                response
+                    .error_description
+                    .or(response.error)
+                    .unwrap_or_default()
+            );
+        };
+
+        let user = http_client::octocrab(&self.config, Some(&access_token))?
+            .current()
+            .user()
+            .await?;
+
+        let session = self.new_session(user.login, &access_token)?;
+        let session_id = random_token();
+        self.sessions
+            .insert(session_id.clone(), session.clone())
+            .await;
+
+        Ok((session_id, session))
+    }
+
+    fn new_session(&self, login: String, access_token: &str) -> anyhow::Result<Session> {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let encrypted_token = self
+            .token_cipher
+            .encrypt(&nonce, access_token.as_bytes())
+            .map_err(|_| anyhow::anyhow!("failed to encrypt access token"))?;
+        Ok(Session {
+            login,
+            encrypted_token,
+            nonce,
+        })
+    }
+
+    /// Looks up the session referenced by the request's session cookie, if any.
+    pub async fn session(&self, jar: &CookieJar) -> Option<Session> {
+        let session_id = jar.get(SESSION_COOKIE)?.value();
+        self.sessions.get(session_id).await
+    }
+
+    /// Approximate number of active sessions.
+    pub fn session_count(&self) -> u64 {
+        self.sessions.entry_count()
+    }
+
+    /// Returns the signed-in user's decrypted credentials for making fetches on their behalf.
+    pub async fn credentials(&self, jar: &CookieJar) -> Option<UserCredentials> {
+        let session = self.session(jar).await?;
+        let token = self
+            .token_cipher
+            .decrypt(&session.nonce, session.encrypted_token.as_slice())
+            .ok()
+            .and_then(|bytes| String::from_utf8(bytes).ok())?;
+        Some(UserCredentials {
+            login: session.login,
+            token,
+        })
+    }
+
+    fn session_cookie(&self, value: String) -> Cookie<'static> {
+        Cookie::build((SESSION_COOKIE, value))
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .secure(self.redirect_url.starts_with("https://"))
+            .build()
+    }
+
+    /// Binds a login's `state` to the browser that started it. Lax still sends it on GitHub's
+    /// top-level redirect back to the callback.
+    fn login_state_cookie(&self, state: String) -> Cookie<'static> {
+        Cookie::build((LOGIN_STATE_COOKIE, state))
+            .path("/")
+            .http_only(true)
+            .same_site(SameSite::Lax)
+            .secure(self.redirect_url.starts_with("https://"))
+            .max_age(LOGIN_STATE_TTL.try_into().unwrap_or_default())
+            .build()
+    }
+}
+
+/// Routes for the login flow. Only mounted when an OAuth App is configured.
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/auth/login", get(login))
+        .route("/auth/callback", get(callback))
+        .route("/auth/logout", post(logout))
+        .route("/auth/me", get(me))
+}
+
+fn auth_service(state: &AppState) -> Result<&AuthService, ApiError> {
+    state
+        .auth
+        .as_ref()
+        .ok_or_else(|| ApiError::not_found("OAuth login is not configured"))
+}
+
+fn random_token() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(RANDOM_TOKEN_LEN)
+        .map(char::from)
+        .collect()
+}
+
+async fn login(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, ApiError> {
+    let auth = auth_service(&state)?;
+    let (url, login_state) = auth.authorize_url().await.map_err(|e| {
+        tracing::error!("Failed to build OAuth authorize URL: {}", e);
+        ApiError::internal()
+    })?;
+    Ok((
+        jar.add(auth.login_state_cookie(login_state)),
+        Redirect::to(url.as_str()),
+    ))
+}
+
+async fn callback(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<CallbackParams>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, ApiError> {
+    let auth = auth_service(&state)?;
+
+    if !auth.take_login_state(&params.state, &jar).await {
+        return Err(ApiError::bad_request("Invalid or expired login state"));
+    }
+    let jar = jar.remove(Cookie::build(LOGIN_STATE_COOKIE).path("/"));
+
+    let (session_id, session) = auth.complete_login(&params.code).await.map_err(|e| {
+        tracing::error!("OAuth login failed: {}", e);
+        ApiError::new(
+            StatusCode::BAD_GATEWAY,
+            "login_failed",
+            "GitHub login failed",
+        )
+    })?;
+    tracing::info!(login = %session.login, "User signed in");
+
+    let home = format!("{}/", state.config.base_path);
+    Ok((
+        jar.add(auth.session_cookie(session_id)),
+        Redirect::to(&home),
+    ))
+}
+
+async fn logout(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<impl IntoResponse, ApiError> {
+    let auth = auth_service(&state)?;
+    if let Some(cookie) = jar.get(SESSION_COOKIE) {
+        auth.sessions.invalidate(cookie.value()).await;
+    }
+    Ok((
+        jar.remove(Cookie::build(SESSION_COOKIE).path("/")),
+        StatusCode::NO_CONTENT,
+    ))
+}
+
+async fn me(
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<Json<MeResponse>, ApiError> {
+    let auth = auth_service(&state)?;
+    let session = auth
+        .session(&jar)
+        .await
+        .ok_or_else(|| ApiError::unauthorized("Not signed in"))?;
+    Ok(Json(MeResponse {
+        login: session.login,
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn oauth_config(vars: &[(&str, &str)]) -> AppConfig {
+        let mut vars = vars.to_vec();
+        vars.extend([
+            ("GITHUB_CLIENT_ID", "client"),
+            ("GITHUB_CLIENT_SECRET", "secret"),
+            (
+                "OAUTH_REDIRECT_URL",
+                "https://repoflow.example/auth/callback",
+            ),
+        ]);
+        crate::test_support::test_config(&vars)
+    }
+
+    fn service() -> AuthService {
+        AuthService::new(&oauth_config(&[])).unwrap().unwrap()
+    }
+
+    #[test]
+    fn test_unreadable_ca_bundle_fails() {
+        let config = oauth_config(&[("EXTRA_CA_CERT_PATH", "/nonexistent/ca.pem")]);
+        assert!(AuthService::new(&config).is_err());
+    }
+
+    /// A `/user` response body for `login`.
+    fn user(login: &str) -> serde_json::Value {
+        let url = format!("https://github.example.com/{}", login);
+        let mut user = serde_json::json!({
+            "login": login,
+            "id": 1,
+            "node_id": "U_1",
+            "gravatar_id": "",
+            "type": "User",
+            "site_admin": false,
+        });
+        for field in [
+            "avatar_url",
+            "url",
+            "html_url",
+            "followers_url",
+            "following_url",
+            "gists_url",
+            "starred_url",
+            "subscriptions_url",
+            "organizations_url",
+            "repos_url",
+            "events_url",
+            "received_events_url",
+        ] {
+            user[field] = url.clone().into();
+        }
+        user
+    }
+
+    #[tokio::test]
+    async fn test_login_uses_the_configured_github() {
+        use wiremock::matchers::{header, method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let github = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/login/oauth/access_token"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"access_token": "gho_secret"})),
+            )
+            .mount(&github)
+            .await;
+        Mock::given(method("GET"))
+            .and(path("/api/v3/user"))
+            .and(header("authorization", "Bearer gho_secret"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(user("octocat")))
+            .mount(&github)
+            .await;
+        let api_url = format!("{}/api/v3", github.uri());
+        let auth = AuthService::new(&oauth_config(&[
+            ("GITHUB_OAUTH_URL", github.uri().as_str()),
+            ("GITHUB_API_URL", api_url.as_str()),
+        ]))
+        .unwrap()
+        .unwrap();
+
+        let (url, _) = auth.authorize_url().await.unwrap();
+        assert!(url
+            .as_str()
+            .starts_with(&format!("{}/login/oauth/authorize?", github.uri())));
+        let (_, session) = auth.complete_login("code").await.unwrap();
+        assert_eq!(session.login, "octocat");
+    }
+
+    #[tokio::test]
+    async fn test_login_state_is_single_use() {
+        let auth = service();
+        let (url, state) = auth.authorize_url().await.unwrap();
+        assert!(url
+            .query_pairs()
+            .any(|(k, v)| k == "state" && v == state.as_str()));
+        let jar = CookieJar::new().add(auth.login_state_cookie(state.clone()));
+
+        assert!(auth.take_login_state(&state, &jar).await);
+        assert!(!auth.take_login_state(&state, &jar).await);
+        assert!(!auth.take_login_state("forged", &jar).await);
+    }
+
+    #[tokio::test]
+    async fn test_login_state_must_match_the_browser_cookie() {
+        let auth = service();
+        let (_, state) = auth.authorize_url().await.unwrap();
+        let (_, other) = auth.authorize_url().await.unwrap();
+
+        // A victim's browser carries no state cookie, or one from a login of its own.
+        assert!(!auth.take_login_state(&state, &CookieJar::new()).await);
+        let victim = CookieJar::new().add(auth.login_state_cookie(other));
+        assert!(!auth.take_login_state(&state, &victim).await);
+        let attacker = CookieJar::new().add(auth.login_state_cookie(state.clone()));
+        assert!(auth.take_login_state(&state, &attacker).await);
+    }
+
+    #[tokio::test]
+    async fn test_session_lookup_by_cookie() {
+        let auth = service();
+        let session = auth
+            .new_session("octocat".to_string(), "gho_secret")
+            .unwrap();
+        assert!(!session
+            .encrypted_token
+            .windows(b"gho_secret".len())
+            .any(|w| w == b"gho_secret"));
+        auth.sessions.insert("abc".to_string(), session).await;
+
+        let jar = CookieJar::new().add(auth.session_cookie("abc".to_string()));
+        let credentials = auth.credentials(&jar).await.unwrap();
+        assert_eq!(credentials.login, "octocat");
+        assert_eq!(credentials.token, "gho_secret");
+        assert!(auth.credentials(&CookieJar::new()).await.is_none());
+    }
+}
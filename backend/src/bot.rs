@@ -0,0 +1,286 @@
+//! Answers `/repoflow report` comments on issues and pull requests of popular repositories with
+//! a comment summarizing the repository's flow.
+//!
+//! GitHub delivers the comments to `/api/v1/webhooks/github`, signed with `GITHUB_WEBHOOK_SECRET`.
+//! Replies are posted in the background, as fetching metrics can outlast GitHub's ten-second
+//! delivery timeout.
+
+use crate::error::ApiError;
+use crate::AppState;
+use axum::{
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use moka::future::Cache;
+use octocrab::Octocrab;
+use repoflow_core::config::{AppConfig, Secret};
+use repoflow_core::domain::RepoId;
+use repoflow_core::http_client;
+use repoflow_core::metrics::RepoMetricsResponse;
+use ring::hmac;
+use serde::Deserialize;
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+const COMMAND: &str = "/repoflow report";
+
+/// Deliveries remembered this long, so a redelivered comment isn't answered twice.
+const DELIVERY_TTL: StdDuration = StdDuration::from_secs(60 * 60);
+
+/// Posts replies to report commands.
+pub struct Responder {
+    github: Octocrab,
+    key: hmac::Key,
+    public_url: Option<String>,
+    deliveries: Cache<String, ()>,
+}
+
+impl Responder {
+    /// Creates a responder, or returns `None` when `GITHUB_WEBHOOK_SECRET` is unset.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Option<Self>> {
+        let Some(secret) = &config.github_webhook_secret else {
+            return Ok(None);
+        };
+        let token = config.github_token.as_ref().map(Secret::expose);
+        Ok(Some(Self {
+            github: http_client::octocrab(config, token)?,
+            key: hmac::Key::new(hmac::HMAC_SHA256, secret.expose().as_bytes()),
+            public_url: config
+                .public_url
+                .as_ref()
+                .map(|url| url.trim_end_matches('/').to_string()),
+            deliveries: Cache::builder()
+                .max_capacity(10_000)
+                .time_to_live(DELIVERY_TTL)
+                .build(),
+        }))
+    }
+
+    /// Whether `signature`, an `X-Hub-Signature-256` value, signs `body` with the secret.
+    fn verify(&self, signature: &str, body: &[u8]) -> bool {
+        signature
+            .strip_prefix("sha256=")
+            .and_then(decode_hex)
+            .is_some_and(|tag| hmac::verify(&self.key, body, &tag).is_ok())
+    }
+}
+
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct IssueCommentEvent {
+    action: String,
+    issue: Issue,
+    comment: Comment,
+    repository: Repository,
+}
+
+#[derive(Deserialize)]
+struct Issue {
+    number: u64,
+}
+
+#[derive(Deserialize)]
+struct Comment {
+    body: String,
+    user: User,
+}
+
+#[derive(Deserialize)]
+struct User {
+    #[serde(rename = "type")]
+    kind: String,
+}
+
+#[derive(Deserialize)]
+struct Repository {
+    name: String,
+    owner: Owner,
+}
+
+#[derive(Deserialize)]
+struct Owner {
+    login: String,
+}
+
+/// The webhook receiver, mounted when a webhook secret is configured.
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new().route("/webhooks/github", post(receive))
+}
+
+/// Whether `body` asks for a report: the command alone on one of its lines.
+fn is_command(body: &str) -> bool {
+    body.lines()
+        .any(|line| line.trim().eq_ignore_ascii_case(COMMAND))
+}
+
+async fn receive(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<StatusCode, ApiError> {
+    let Some(responder) = &state.bot else {
+        return Err(ApiError::not_found("Webhooks are not enabled"));
+    };
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    if !header("x-hub-signature-256").is_some_and(|sig| responder.verify(sig, &body)) {
+        return Err(ApiError::unauthorized("Invalid webhook signature"));
+    }
+    if header("x-github-event") != Some("issue_comment") {
+        // Including the ping GitHub sends when the webhook is created.
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    if state.service.maintenance().is_some() {
+        // Like alerts, commands arriving during maintenance are dropped rather than queued.
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    let event: IssueCommentEvent = serde_json::from_slice(&body)
+        .map_err(|e| ApiError::bad_request(format!("Invalid issue_comment payload: {}", e)))?;
+    // Bots are ignored so that two responders can't answer each other forever.
+    if event.action != "created"
+        || event.comment.user.kind == "Bot"
+        || !is_command(&event.comment.body)
+    {
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    let repo_id = RepoId {
+        owner: event.repository.owner.login,
+        repo: event.repository.name,
+    };
+    if state
+        .service
+        .popular_repos()
+        .await
+        .iter()
+        .all(|p| p.id != repo_id)
+    {
+        tracing::debug!(
+            "Ignoring report command in untracked repository {}",
+            repo_id
+        );
+        return Ok(StatusCode::NO_CONTENT);
+    }
+    if let Some(delivery) = header("x-github-delivery") {
+        let entry = responder.deliveries.entry(delivery.to_string());
+        if !entry.or_insert(()).await.is_fresh() {
+            return Ok(StatusCode::NO_CONTENT);
+        }
+    }
+
+    let number = event.issue.number;
+    tokio::spawn(async move { reply(&state, &repo_id, number).await });
+    Ok(StatusCode::ACCEPTED)
+}
+
+async fn reply(state: &Arc<AppState>, repo_id: &RepoId, number: u64) {
+    let Some(responder) = &state.bot else {
+        return;
+    };
+    let body = match crate::fetch_metrics(state, repo_id, None).await {
+        Ok(cached) => report(
+            repo_id,
+            &cached.default_window().metrics,
+            state.config.metrics_window_size,
+            responder.public_url.as_deref(),
+        ),
+        Err(e) => {
+            tracing::error!("Failed to fetch PRs for {} to report: {}", repo_id, e);
+            let (_, message) = repoflow_core::upstream::classify(&e).response();
+            format!("RepoFlow couldn't report on {}: {}.", repo_id, message)
+        }
+    };
+    let route = format!(
+        "/repos/{}/{}/issues/{}/comments",
+        repo_id.owner, repo_id.repo, number
+    );
+    let posted: Result<serde::de::IgnoredAny, _> = responder
+        .github
+        .post(route, Some(&serde_json::json!({ "body": body })))
+        .await;
+    if let Err(e) = posted {
+        tracing::error!("Failed to comment on {}#{}: {}", repo_id, number, e);
+    }
+}
+
+/// The reply for `repo_id`, in GitHub-flavored Markdown.
+fn report(
+    repo_id: &RepoId,
+    metrics: &RepoMetricsResponse,
+    window_days: i64,
+    public_url: Option<&str>,
+) -> String {
+    let summary = &metrics.summary;
+    let mut out = format!(
+        "**RepoFlow report for {}** (rolling {}-day window)\n\n",
+        repo_id, window_days
+    );
+    out.push_str("| Opened | Merged | Spread | Merge rate |\n|---:|---:|---:|---:|\n");
+    let _ = writeln!(
+        out,
+        "| {} | {} | {:+}{} | {}% |",
+        summary.current_opened,
+        summary.current_merged,
+        summary.current_spread,
+        if summary.is_widening {
+            " (widening)"
+        } else {
+            ""
+        },
+        summary.merge_rate
+    );
+    if !metrics.insights.is_empty() {
+        out.push('\n');
+        for insight in &metrics.insights {
+            let _ = writeln!(out, "- {}", insight);
+        }
+    }
+    if let Some(url) = public_url {
+        let _ = write!(
+            out,
+            "\n[Chart]({}/api/v1/repos/{}/{}/chart.svg)\n",
+            url, repo_id.owner, repo_id.repo
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_config;
+
+    #[test]
+    fn test_command_must_be_its_own_line() {
+        assert!(is_command("/repoflow report"));
+        assert!(is_command("Thanks!\n  /RepoFlow Report  \n"));
+        assert!(!is_command("please run /repoflow report"));
+        assert!(!is_command("/repoflow reports"));
+    }
+
+    #[tokio::test]
+    async fn test_signatures_are_verified() {
+        let config = test_config(&[
+            ("GITHUB_TOKEN", "ghp_test"),
+            ("GITHUB_WEBHOOK_SECRET", "It's a Secret to Everybody"),
+        ]);
+        let responder = Responder::new(&config).unwrap().unwrap();
+        // The example from GitHub's webhook documentation.
+        let signature = "sha256=757107ea0eb2509fc211221cce984b8a37570b6d7586c22c46f4379c8b043e17";
+        assert!(responder.verify(signature, b"Hello, World!"));
+        assert!(!responder.verify(signature, b"Hello, World?"));
+        assert!(!responder.verify("sha256=zz", b"Hello, World!"));
+        assert!(!responder.verify(&signature[7..], b"Hello, World!"));
+    }
+}
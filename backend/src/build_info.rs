@@ -0,0 +1,34 @@
+//! Information about the running build, embedded at compile time by `build.rs`.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+
+/// Identifies exactly which build is serving traffic.
+#[derive(Debug, Serialize, Clone)]
+pub struct BuildInfo {
+    /// The crate version from `Cargo.toml`.
+    pub version: &'static str,
+    /// The git commit the binary was built from, or "unknown".
+    pub git_sha: &'static str,
+    /// When the binary was built.
+    pub build_timestamp: Option<DateTime<Utc>>,
+    /// Cargo features enabled at compile time.
+    pub features: Vec<&'static str>,
+}
+
+impl BuildInfo {
+    pub fn current() -> Self {
+        Self {
+            version: env!("CARGO_PKG_VERSION"),
+            git_sha: env!("REPOFLOW_GIT_SHA"),
+            build_timestamp: env!("REPOFLOW_BUILD_TIMESTAMP")
+                .parse()
+                .ok()
+                .and_then(|secs| DateTime::from_timestamp(secs, 0)),
+            features: env!("REPOFLOW_FEATURES")
+                .split(',')
+                .filter(|f| !f.is_empty())
+                .collect(),
+        }
+    }
+}
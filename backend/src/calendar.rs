@@ -0,0 +1,202 @@
+//! An iCalendar feed of a repository's releases and milestone due dates, for teams to overlay
+//! its cadence on their own calendars.
+//!
+//! Every event is all-day, dated in UTC, since GitHub's milestone due dates carry no meaningful
+//! time of day.
+
+use crate::error::ApiError;
+use crate::extract::Path;
+use crate::AppState;
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use repoflow_core::domain::RepoId;
+use repoflow_core::source::RepoCalendar;
+use std::sync::Arc;
+
+/// Longest content line, in octets, before it's folded onto the next (RFC 5545, section 3.1).
+const MAX_LINE_OCTETS: usize = 75;
+
+/// The feed, mounted with the other repository routes.
+pub fn repo_router() -> Router<Arc<AppState>> {
+    Router::new().route("/repos/{owner}/{repo}/calendar", get(get_repo_calendar))
+}
+
+async fn get_repo_calendar(
+    Path((owner, repo)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let repo_id = crate::parse_repo_id(&owner, &repo)?;
+    match state.service.calendar(&repo_id).await {
+        Ok(Some(calendar)) => Ok((
+            [(
+                CONTENT_TYPE,
+                HeaderValue::from_static("text/calendar; charset=utf-8"),
+            )],
+            render(&repo_id, &calendar, Utc::now()),
+        )
+            .into_response()),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "calendar_unavailable",
+            "Releases and milestones can't be listed in this mode",
+        )),
+        Err(e) => Err(crate::upstream_error(&state, &repo_id, "releases", None, e).await),
+    }
+}
+
+/// Renders `calendar` as an iCalendar document stamped at `now`.
+fn render(repo_id: &RepoId, calendar: &RepoCalendar, now: DateTime<Utc>) -> String {
+    let stamp = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut ics = String::new();
+    line(&mut ics, "BEGIN:VCALENDAR");
+    line(&mut ics, "VERSION:2.0");
+    line(&mut ics, "PRODID:-//RepoFlow//Repository calendar//EN");
+    line(&mut ics, "CALSCALE:GREGORIAN");
+    line(
+        &mut ics,
+        &format!(
+            "X-WR-CALNAME:{}",
+            escape(&format!("{} releases and milestones", repo_id))
+        ),
+    );
+    for release in &calendar.releases {
+        let summary = match &release.name {
+            Some(name) if name != &release.tag_name => {
+                format!("{} {} ({})", repo_id.repo, name, release.tag_name)
+            }
+            _ => format!("{} {}", repo_id.repo, release.tag_name),
+        };
+        event(
+            &mut ics,
+            &format!("release-{}", release.tag_name),
+            repo_id,
+            &stamp,
+            release.published_at.date_naive(),
+            &summary,
+            &release.url,
+        );
+    }
+    for milestone in &calendar.milestones {
+        let status = if milestone.closed { " (closed)" } else { "" };
+        event(
+            &mut ics,
+            &format!("milestone-{}", milestone.number),
+            repo_id,
+            &stamp,
+            milestone.due_on.date_naive(),
+            &format!(
+                "{} milestone due: {}{}",
+                repo_id.repo, milestone.title, status
+            ),
+            &milestone.url,
+        );
+    }
+    line(&mut ics, "END:VCALENDAR");
+    ics
+}
+
+fn event(
+    ics: &mut String,
+    id: &str,
+    repo_id: &RepoId,
+    stamp: &str,
+    date: NaiveDate,
+    summary: &str,
+    url: &str,
+) {
+    line(ics, "BEGIN:VEVENT");
+    // Stable across fetches, so calendar apps update events instead of duplicating them.
+    line(
+        ics,
+        &format!(
+            "UID:{}",
+            escape(&format!(
+                "{}-{}-{}@repoflow",
+                id, repo_id.owner, repo_id.repo
+            ))
+        ),
+    );
+    line(ics, &format!("DTSTAMP:{}", stamp));
+    line(
+        ics,
+        &format!("DTSTART;VALUE=DATE:{}", date.format("%Y%m%d")),
+    );
+    if let Some(next) = date.succ_opt() {
+        line(ics, &format!("DTEND;VALUE=DATE:{}", next.format("%Y%m%d")));
+    }
+    line(ics, &format!("SUMMARY:{}", escape(summary)));
+    line(ics, &format!("URL:{}", url));
+    line(ics, "END:VEVENT");
+}
+
+/// Appends a content line, folded to `MAX_LINE_OCTETS` and terminated by CRLF.
+fn line(ics: &mut String, content: &str) {
+    let mut octets = 0;
+    for c in content.chars() {
+        // Continuation lines start with a space, which counts towards their length.
+        if octets + c.len_utf8() > MAX_LINE_OCTETS {
+            ics.push_str("\r\n ");
+            octets = 1;
+        }
+        ics.push(c);
+        octets += c.len_utf8();
+    }
+    ics.push_str("\r\n");
+}
+
+/// Escapes the characters TEXT values give meaning to.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace('\n', "\\n")
+        .replace('\r', "")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repoflow_core::source::{Milestone, Release};
+
+    #[test]
+    fn test_render_events() {
+        let date = |s: &str| format!("{}T12:00:00Z", s).parse().unwrap();
+        let calendar = RepoCalendar {
+            releases: vec![Release {
+                tag_name: "v1.2.0".to_string(),
+                name: Some("Spring, at last".to_string()),
+                published_at: date("2026-03-02"),
+                url: "https://github.com/acme/widgets/releases/tag/v1.2.0".to_string(),
+            }],
+            milestones: vec![Milestone {
+                number: 7,
+                title: "Q2 ".repeat(30),
+                due_on: date("2026-06-30"),
+                closed: false,
+                url: "https://github.com/acme/widgets/milestone/7".to_string(),
+            }],
+            complete: true,
+        };
+        let ics = render(
+            &"acme/widgets".parse().unwrap(),
+            &calendar,
+            date("2026-03-03"),
+        );
+
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\nVERSION:2.0\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert!(ics.contains("UID:release-v1.2.0-acme-widgets@repoflow\r\n"));
+        assert!(ics.contains("DTSTART;VALUE=DATE:20260302\r\nDTEND;VALUE=DATE:20260303\r\n"));
+        assert!(ics.contains("SUMMARY:widgets Spring\\, at last (v1.2.0)\r\n"));
+        assert!(ics.contains("DTSTAMP:20260303T120000Z\r\n"));
+        assert!(ics.contains("SUMMARY:widgets milestone due: Q2 Q2"));
+        assert!(ics.split("\r\n").all(|line| line.len() <= MAX_LINE_OCTETS));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+    }
+}
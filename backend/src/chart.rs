@@ -0,0 +1,190 @@
+//! Server-side PNG charts of a single metric, for integrations whose targets can't show SVG:
+//! Slack unfurls, email clients and GitHub READMEs served through its image proxy.
+//!
+//! Charts carry no text, so rendering needs no fonts; the metric and window are in the URL.
+
+use crate::error::ApiError;
+use crate::extract::{Path, Query};
+use crate::AppState;
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderValue},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use plotters::prelude::*;
+use repoflow_core::metrics::FlowMetricsResponse;
+use serde::Deserialize;
+use std::sync::Arc;
+
+const WIDTH: u32 = 640;
+const HEIGHT: u32 = 240;
+const BASELINE_COLOR: RGBColor = RGBColor(203, 213, 225);
+
+#[derive(Deserialize)]
+struct ChartParams {
+    /// `opened`, `merged` or `spread`. Defaults to `spread`.
+    metric: Option<String>,
+    window: Option<i64>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Metric {
+    Opened,
+    Merged,
+    Spread,
+}
+
+impl Metric {
+    fn parse(name: &str) -> Option<Self> {
+        match name {
+            "opened" => Some(Self::Opened),
+            "merged" => Some(Self::Merged),
+            "spread" => Some(Self::Spread),
+            _ => None,
+        }
+    }
+
+    fn value(self, point: &FlowMetricsResponse) -> i64 {
+        match self {
+            Self::Opened => point.opened as i64,
+            Self::Merged => point.merged as i64,
+            Self::Spread => point.spread,
+        }
+    }
+
+    /// Opened and merged match the SVG chart.
+    fn color(self) -> RGBColor {
+        match self {
+            Self::Opened => RGBColor(37, 99, 235),
+            Self::Merged => RGBColor(22, 163, 74),
+            Self::Spread => RGBColor(217, 119, 6),
+        }
+    }
+}
+
+/// The chart, mounted with the other repository routes.
+pub fn repo_router() -> Router<Arc<AppState>> {
+    Router::new().route("/repos/{owner}/{repo}/chart.png", get(get_repo_chart_png))
+}
+
+async fn get_repo_chart_png(
+    Path((owner, repo)): Path<(String, String)>,
+    Query(params): Query<ChartParams>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let repo_id = crate::parse_repo_id(&owner, &repo)?;
+    let metric = match params.metric.as_deref() {
+        None => Metric::Spread,
+        Some(name) => Metric::parse(name).ok_or_else(|| {
+            ApiError::bad_request(format!(
+                "Unknown metric '{}'; expected opened, merged or spread",
+                name
+            ))
+        })?,
+    };
+    let window_days = crate::window_days(&state, params.window)?;
+    // Images are embedded where no one is signed in, so only public data is drawn.
+    let cached = match crate::fetch_metrics(&state, &repo_id, None).await {
+        Ok(cached) => cached,
+        Err(e) => return Err(crate::upstream_error(&state, &repo_id, "PRs", None, e).await),
+    };
+    // Every entry holds each of `window_sizes()`, which was checked above.
+    let Some(window) = cached.window(window_days) else {
+        tracing::error!(
+            "Cached metrics for {} lack a {}-day window",
+            repo_id,
+            window_days
+        );
+        return Err(ApiError::internal());
+    };
+    let png = render(&window.metrics.time_series, metric).map_err(|e| {
+        tracing::error!(
+            "Failed to render the {:?} chart of {}: {}",
+            metric,
+            repo_id,
+            e
+        );
+        ApiError::internal()
+    })?;
+    Ok(([(CONTENT_TYPE, HeaderValue::from_static("image/png"))], png).into_response())
+}
+
+/// Draws `metric` across `series` as a PNG, with a baseline at zero when the line crosses it.
+fn render(series: &[FlowMetricsResponse], metric: Metric) -> anyhow::Result<Vec<u8>> {
+    let values: Vec<i64> = series.iter().map(|p| metric.value(p)).collect();
+    let min = values.iter().copied().min().unwrap_or(0).min(0);
+    let max = values.iter().copied().max().unwrap_or(0).max(min + 1);
+
+    let mut pixels = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+    {
+        let root = BitMapBackend::with_buffer(&mut pixels, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE).map_err(|e| anyhow::anyhow!("{}", e))?;
+        let mut chart = ChartBuilder::on(&root)
+            .margin(8)
+            .build_cartesian_2d(0..values.len().max(2) - 1, min..max)
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        if min < 0 {
+            chart
+                .draw_series(LineSeries::new(
+                    [(0, 0), (values.len().max(2) - 1, 0)],
+                    BASELINE_COLOR.stroke_width(1),
+                ))
+                .map_err(|e| anyhow::anyhow!("{}", e))?;
+        }
+        chart
+            .draw_series(LineSeries::new(
+                values.iter().copied().enumerate(),
+                metric.color().stroke_width(2),
+            ))
+            .map_err(|e| anyhow::anyhow!("{}", e))?;
+        root.present().map_err(|e| anyhow::anyhow!("{}", e))?;
+    }
+
+    let mut png = Vec::new();
+    let mut encoder = png::Encoder::new(&mut png, WIDTH, HEIGHT);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    let mut writer = encoder.write_header()?;
+    writer.write_image_data(&pixels)?;
+    writer.finish()?;
+    Ok(png)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decode(png: &[u8]) -> (png::OutputInfo, Vec<u8>) {
+        let mut reader = png::Decoder::new(std::io::Cursor::new(png))
+            .read_info()
+            .unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut pixels).unwrap();
+        (info, pixels)
+    }
+
+    #[test]
+    fn test_render_draws_the_metric() {
+        let series: Vec<FlowMetricsResponse> = (0..10)
+            .map(|day| FlowMetricsResponse {
+                date: format!("2026-03-{:02}", day + 1),
+                spread: day - 4,
+                ..Default::default()
+            })
+            .collect();
+        let (info, pixels) = decode(&render(&series, Metric::Spread).unwrap());
+        assert_eq!((info.width, info.height), (WIDTH, HEIGHT));
+        let has = |RGBColor(r, g, b): RGBColor| pixels.chunks(3).any(|px| px == [r, g, b]);
+        assert!(has(Metric::Spread.color()));
+        assert!(has(BASELINE_COLOR));
+        assert!(!has(Metric::Opened.color()));
+    }
+
+    #[test]
+    fn test_render_empty_series() {
+        let (info, _) = decode(&render(&[], Metric::Opened).unwrap());
+        assert_eq!(info.width, WIDTH);
+    }
+}
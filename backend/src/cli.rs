@@ -0,0 +1,339 @@
+//! Command-line interface. Without a subcommand the server runs as usual; the subcommands do one
+//! piece of work and exit, for scripts and CI that don't want to run the server.
+
+use crate::encoding::{Encodable, Fields};
+use crate::narrative::Narrator;
+use crate::report::{self, ReportEntry};
+use anyhow::Context;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use repoflow_core::config::AppConfig;
+use repoflow_core::domain::RepoId;
+use repoflow_core::metrics::RepoMetricsResponse;
+use repoflow_core::service::MetricsService;
+use repoflow_core::snapshot::{self, Snapshot};
+use repoflow_core::upstream;
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(
+    name = "repoflow",
+    version,
+    about = "Pull request flow metrics for GitHub repositories"
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Fetch metrics for one repository, print them and exit.
+    Fetch(FetchArgs),
+    /// Render every tracked repository into a static HTML report.
+    ExportSite(ExportSiteArgs),
+    /// Fetch every popular repository into a snapshot that new instances serve at startup.
+    Snapshot(SnapshotArgs),
+}
+
+#[derive(Args)]
+pub struct FetchArgs {
+    /// The repository, as owner/repo.
+    pub repo: String,
+    /// Rolling window size in days. Defaults to METRICS_WINDOW_SIZE.
+    #[arg(long)]
+    pub window: Option<i64>,
+    #[arg(long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+}
+
+#[derive(Args)]
+pub struct ExportSiteArgs {
+    /// Directory to write the report into. It is created if missing.
+    #[arg(long, default_value = "dist-report")]
+    pub out: PathBuf,
+}
+
+#[derive(Args)]
+pub struct SnapshotArgs {
+    /// File to write the snapshot to, for SNAPSHOT_FILE or SNAPSHOT_URL.
+    #[arg(long, default_value = "snapshot.json")]
+    pub out: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Csv,
+    Table,
+}
+
+/// Runs a one-shot `command` and returns what to print.
+pub async fn run(command: Command, config: AppConfig) -> anyhow::Result<String> {
+    match command {
+        Command::Fetch(args) => fetch(config, &args).await,
+        Command::ExportSite(args) => export_site(config, &args).await,
+        Command::Snapshot(args) => write_snapshot(config, &args).await,
+    }
+}
+
+/// Fetches the metrics requested by `args` with `config` and renders them for printing.
+pub async fn fetch(mut config: AppConfig, args: &FetchArgs) -> anyhow::Result<String> {
+    let repo_id: RepoId = args
+        .repo
+        .parse()
+        .with_context(|| format!("invalid repository '{}'", args.repo))?;
+    if let Some(window) = args.window {
+        config.metrics_window_size = window;
+        config.validate()?;
+    }
+
+    let service = MetricsService::without_refresh(&config)?;
+    let metrics = service.get(repo_id.clone()).await?;
+    if !metrics.complete {
+        tracing::warn!(
+            "{} has more pull requests than MAX_GITHUB_API_PAGES allows fetching; metrics are incomplete",
+            repo_id
+        );
+    }
+    render(&metrics.default_window().metrics, args.format)
+}
+
+/// Writes the report for every tracked repository to `args.out`. Repositories that can't be
+/// fetched are listed as unavailable rather than failing the whole export.
+pub async fn export_site(config: AppConfig, args: &ExportSiteArgs) -> anyhow::Result<String> {
+    let service = MetricsService::without_refresh(&config)?;
+    let narrator = Narrator::new(&config)?;
+    write_report(
+        &service,
+        config.metrics_window_size,
+        &args.out,
+        narrator.as_ref(),
+    )
+    .await
+}
+
+/// Writes a snapshot of every popular repository to `args.out`. Repositories that can't be
+/// fetched are left out, to be fetched by instances as usual.
+pub async fn write_snapshot(config: AppConfig, args: &SnapshotArgs) -> anyhow::Result<String> {
+    let service = MetricsService::without_refresh(&config)?;
+    let repos = service.popular_repos().await;
+    if repos.is_empty() {
+        anyhow::bail!("no repositories to snapshot; set POPULAR_REPOS or POPULAR_REPOS_FILE");
+    }
+
+    let fetched = futures::future::join_all(repos.into_iter().map(|repo| {
+        let service = &service;
+        async move {
+            match service.get(repo.id.clone()).await {
+                Ok(metrics) => Some(metrics.to_snapshot(repo.id)),
+                Err(e) => {
+                    tracing::warn!("Leaving {} out of the snapshot: {:#}", repo.id, e);
+                    None
+                }
+            }
+        }
+    }))
+    .await;
+    let snapshot = Snapshot {
+        created_at: chrono::Utc::now(),
+        repos: fetched.into_iter().flatten().collect(),
+    };
+    snapshot::write(&snapshot, &args.out).await?;
+    Ok(format!(
+        "Wrote {} repositories to {}",
+        snapshot.repos.len(),
+        args.out.display()
+    ))
+}
+
+async fn write_report(
+    service: &MetricsService,
+    window_days: i64,
+    out: &Path,
+    narrator: Option<&Narrator>,
+) -> anyhow::Result<String> {
+    let repos = service.popular_repos().await;
+    if repos.is_empty() {
+        anyhow::bail!("no repositories to report on; set POPULAR_REPOS or POPULAR_REPOS_FILE");
+    }
+
+    let entries = futures::future::join_all(repos.into_iter().map(|repo| {
+        async move {
+            match service.get(repo.id.clone()).await {
+                Ok(metrics) => {
+                    let metrics_for_window = &metrics.default_window().metrics;
+                    // A missing narrative shouldn't hold up or fail the report.
+                    let narrative = match narrator {
+                        Some(narrator) => narrator
+                            .narrate(&repo.id, metrics_for_window, window_days)
+                            .await
+                            .inspect_err(|e| {
+                                tracing::warn!("No narrative for {}: {:#}", repo.id, e)
+                            })
+                            .ok()
+                            .map(|narrative| narrative.to_string()),
+                        None => None,
+                    };
+                    ReportEntry {
+                        repo,
+                        metrics: Ok(metrics_for_window.clone()),
+                        complete: metrics.complete,
+                        narrative,
+                    }
+                }
+                Err(e) => {
+                    tracing::warn!("Failed to fetch {}: {:#}", repo.id, e);
+                    // The report may be published, so it gets the public message only.
+                    let (_, message) = upstream::classify(&e).response();
+                    ReportEntry {
+                        repo,
+                        metrics: Err(message.to_string()),
+                        complete: false,
+                        narrative: None,
+                    }
+                }
+            }
+        }
+    }))
+    .await;
+
+    let html = report::render(&entries, window_days, chrono::Utc::now());
+    tokio::fs::create_dir_all(out)
+        .await
+        .with_context(|| format!("failed to create {}", out.display()))?;
+    let index = out.join("index.html");
+    tokio::fs::write(&index, html)
+        .await
+        .with_context(|| format!("failed to write {}", index.display()))?;
+    Ok(format!(
+        "Wrote a report on {} repositories to {}",
+        entries.len(),
+        index.display()
+    ))
+}
+
+fn render(metrics: &RepoMetricsResponse, format: OutputFormat) -> anyhow::Result<String> {
+    match format {
+        OutputFormat::Json => Ok(serde_json::to_string_pretty(metrics)?),
+        OutputFormat::Csv => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            if let Some(result) = metrics.write_csv(&mut writer, &Fields::default()) {
+                result.map_err(anyhow::Error::msg)?;
+            }
+            Ok(String::from_utf8(writer.into_inner()?)?)
+        }
+        OutputFormat::Table => Ok(table(metrics)),
+    }
+}
+
+fn table(metrics: &RepoMetricsResponse) -> String {
+    let summary = &metrics.summary;
+    let mut out = format!(
+        "opened {}, merged {}, spread {}, merge rate {}%{}\n\n",
+        summary.current_opened,
+        summary.current_merged,
+        summary.current_spread,
+        summary.merge_rate,
+        if summary.is_widening {
+            " (widening)"
+        } else {
+            ""
+        }
+    );
+    out.push_str(&format!(
+        "{:<10}  {:>6}  {:>6}  {:>6}  {:>6}  {:>6}\n",
+        "date", "opened", "merged", "closed", "spread", "open"
+    ));
+    for point in &metrics.time_series {
+        out.push_str(&format!(
+            "{:<10}  {:>6}  {:>6}  {:>6}  {:>6}  {:>6}\n",
+            point.date, point.opened, point.merged, point.closed, point.spread, point.open_count
+        ));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{pr, test_config, MockPullRequestSource};
+    use std::sync::Arc;
+
+    async fn metrics() -> RepoMetricsResponse {
+        let config = test_config(&[("METRICS_DAYS_TO_DISPLAY", "1")]);
+        let source = MockPullRequestSource::default()
+            .with_repo("owner/repo", vec![pr(1, 5, Some(1)), pr(2, 3, None)]);
+        let service = MetricsService::with_source(&config, Arc::new(source));
+        let cached = service.get("owner/repo".parse().unwrap()).await.unwrap();
+        cached.default_window().metrics.clone()
+    }
+
+    #[test]
+    fn test_parse_args() {
+        let cli =
+            Cli::try_parse_from(["repoflow", "fetch", "owner/repo", "--window", "7"]).unwrap();
+        let Some(Command::Fetch(args)) = cli.command else {
+            panic!("expected fetch");
+        };
+        assert_eq!(args.window, Some(7));
+        assert_eq!(args.format, OutputFormat::Table);
+
+        assert!(Cli::try_parse_from(["repoflow"]).unwrap().command.is_none());
+        let cli = Cli::try_parse_from(["repoflow", "export-site"]).unwrap();
+        let Some(Command::ExportSite(args)) = cli.command else {
+            panic!("expected export-site");
+        };
+        assert_eq!(args.out, PathBuf::from("dist-report"));
+        let cli = Cli::try_parse_from(["repoflow", "snapshot", "--out", "seed.json"]).unwrap();
+        let Some(Command::Snapshot(args)) = cli.command else {
+            panic!("expected snapshot");
+        };
+        assert_eq!(args.out, PathBuf::from("seed.json"));
+        assert!(Cli::try_parse_from(["repoflow", "fetch", "o/r", "--format", "xml"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_write_report() {
+        let config = test_config(&[("POPULAR_REPOS", "owner/repo,owner/missing")]);
+        let source =
+            MockPullRequestSource::default().with_repo("owner/repo", vec![pr(1, 5, Some(1))]);
+        let service = MetricsService::with_source(&config, Arc::new(source));
+        let out = std::env::temp_dir().join(format!("repoflow-report-{}", rand::random::<u64>()));
+
+        let message = write_report(&service, 30, &out, None).await.unwrap();
+        assert!(message.starts_with("Wrote a report on 2 repositories"));
+        let html = std::fs::read_to_string(out.join("index.html")).unwrap();
+        assert!(html.contains("<h2>owner/repo</h2>"));
+        assert!(html.contains("Unavailable: Repository Not Found"));
+        std::fs::remove_dir_all(out).unwrap();
+
+        let empty = MetricsService::with_source(
+            &test_config(&[]),
+            Arc::new(MockPullRequestSource::default()),
+        );
+        assert!(write_report(&empty, 30, Path::new("unused"), None)
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_render() {
+        let metrics = metrics().await;
+
+        let json: serde_json::Value =
+            serde_json::from_str(&render(&metrics, OutputFormat::Json).unwrap()).unwrap();
+        assert_eq!(json["summary"]["current_opened"], 2);
+
+        let csv = render(&metrics, OutputFormat::Csv).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("date,opened,merged,closed,spread,open_count,fast_merged,backlog_merged,merged_closing_issues,issues_closed,merged_unapproved,merged_one_approval,merged_two_plus_approvals,review_comments,median_queue_hours,median_review_hours,is_holiday")
+        );
+        assert_eq!(lines.count(), 2);
+
+        let table = render(&metrics, OutputFormat::Table).unwrap();
+        assert!(table.starts_with("opened 2, merged 1, spread 1, merge rate 50%"));
+        assert_eq!(table.lines().count(), 5);
+    }
+}
@@ -0,0 +1,163 @@
+//! Resolution of the real client IP behind trusted reverse proxies.
+//!
+//! When RepoFlow runs behind a load balancer, the TCP peer is the proxy rather than the user.
+//! Forwarding headers (`Forwarded` or `X-Forwarded-For`) are only honored when the peer is listed
+//! in `TRUSTED_PROXIES`, and hops are walked right-to-left past any further trusted proxies so a
+//! client can't spoof its address by sending its own header.
+
+use crate::listener::ClientAddr;
+use crate::AppState;
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::HeaderMap,
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+/// The resolved address of the end user, stored in request extensions.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ClientIp(pub Option<IpAddr>);
+
+fn is_trusted(ip: IpAddr, trusted: &[IpNet]) -> bool {
+    trusted.iter().any(|net| net.contains(&ip))
+}
+
+/// Determines the client IP given the connection's peer and the request's forwarding headers.
+///
+/// A peer of `None` is a Unix socket connection, which can only come from a local proxy and is
+/// therefore always trusted.
+pub fn resolve(peer: Option<IpAddr>, headers: &HeaderMap, trusted: &[IpNet]) -> Option<IpAddr> {
+    if peer.is_some_and(|ip| !is_trusted(ip, trusted)) {
+        return peer;
+    }
+
+    let hops = forwarded_hops(headers);
+    hops.iter()
+        .rev()
+        .copied()
+        .find(|ip| !is_trusted(*ip, trusted))
+        .or_else(|| hops.first().copied())
+        .or(peer)
+}
+
+/// Parses client addresses from `Forwarded` (preferred) or `X-Forwarded-For`, in hop order.
+fn forwarded_hops(headers: &HeaderMap) -> Vec<IpAddr> {
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("forwarded")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.trim().split_once('=')?;
+                key.eq_ignore_ascii_case("for")
+                    .then(|| parse_node(value.trim_matches('"')))
+                    .flatten()
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|node| parse_node(node.trim()))
+        .collect()
+}
+
+/// Parses a node that may be a bare IP, `ip:port`, or a bracketed IPv6 address with a port.
+fn parse_node(node: &str) -> Option<IpAddr> {
+    node.parse::<IpAddr>()
+        .ok()
+        .or_else(|| node.parse::<SocketAddr>().ok().map(|a| a.ip()))
+        .or_else(|| {
+            node.strip_prefix('[')
+                .and_then(|n| n.strip_suffix(']'))
+                .and_then(|n| n.parse().ok())
+        })
+}
+
+/// Middleware that records the resolved `ClientIp` in the request extensions.
+pub async fn resolve_client_ip(
+    State(state): State<Arc<AppState>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<ClientAddr>>()
+        .map(|ConnectInfo(ClientAddr(addr))| addr.map(|a| a.ip()));
+
+    // Without connect info (e.g. in tests) there is no peer to vouch for the headers.
+    let client_ip = match peer {
+        Some(peer) => resolve(peer, request.headers(), &state.config.trusted_proxies),
+        None => None,
+    };
+    request.extensions_mut().insert(ClientIp(client_ip));
+
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (k, v) in pairs {
+            headers.append(*k, v.parse().unwrap());
+        }
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_headers() {
+        let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        let h = headers(&[("x-forwarded-for", "1.2.3.4")]);
+        assert_eq!(
+            resolve(Some(ip("8.8.8.8")), &h, &trusted),
+            Some(ip("8.8.8.8"))
+        );
+    }
+
+    #[test]
+    fn test_trusted_peer_uses_rightmost_untrusted_hop() {
+        let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        let h = headers(&[("x-forwarded-for", "6.6.6.6, 1.2.3.4, 10.0.0.2")]);
+        assert_eq!(
+            resolve(Some(ip("10.0.0.1")), &h, &trusted),
+            Some(ip("1.2.3.4"))
+        );
+    }
+
+    #[test]
+    fn test_forwarded_header_preferred() {
+        let trusted = vec!["10.0.0.0/8".parse().unwrap()];
+        let h = headers(&[
+            ("forwarded", "for=\"[2001:db8::1]:4711\";proto=https"),
+            ("x-forwarded-for", "1.2.3.4"),
+        ]);
+        assert_eq!(
+            resolve(Some(ip("10.0.0.1")), &h, &trusted),
+            Some(ip("2001:db8::1"))
+        );
+    }
+
+    #[test]
+    fn test_unix_socket_peer_is_trusted() {
+        let h = headers(&[("x-forwarded-for", "1.2.3.4")]);
+        assert_eq!(resolve(None, &h, &[]), Some(ip("1.2.3.4")));
+        assert_eq!(resolve(None, &HeaderMap::new(), &[]), None);
+    }
+}
@@ -0,0 +1,600 @@
+//! Content negotiation for API responses.
+//!
+//! Handlers extract a `Format` from the `Accept` header and return `Encoded` values, which are
+//! serialized as JSON (the default), MessagePack, or CSV. CSV needs a tabular shape, so only
+//! types that provide one through `Encodable::write_csv` support it.
+//!
+//! Handlers may also extract `Fields` from the `fields` query parameter, a comma-separated list of
+//! dotted paths (e.g., `summary,time_series.date`), to trim the response to just those fields.
+//! Paths through arrays apply to every element.
+
+use crate::error::ApiError;
+use axum::{
+    body::{Body, Bytes},
+    extract::{FromRequestParts, Query},
+    http::{header, request::Parts, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+};
+use futures::stream;
+use repoflow_core::metrics::{Heatmap, RepoMetricsResponse};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::io::Write;
+
+const JSON: &str = "application/json";
+const CSV: &str = "text/csv; charset=utf-8";
+const MESSAGE_PACK: &str = "application/msgpack";
+
+/// A response encoding supported by the API.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    Csv,
+    MessagePack,
+}
+
+impl Format {
+    fn from_media_type(media_type: &str) -> Option<Self> {
+        match media_type {
+            "application/json" | "application/*" | "*/*" => Some(Format::Json),
+            "text/csv" => Some(Format::Csv),
+            "application/msgpack" | "application/x-msgpack" | "application/vnd.msgpack" => {
+                Some(Format::MessagePack)
+            }
+            _ => None,
+        }
+    }
+
+    /// Picks the supported format the client prefers most, honoring `q` weights.
+    /// Returns `None` if the client accepts none of them.
+    pub fn negotiate(accept: &str) -> Option<Self> {
+        let mut candidates: Vec<(f32, &str)> = accept
+            .split(',')
+            .filter_map(|range| {
+                let mut params = range.split(';').map(str::trim);
+                let media_type = params.next().filter(|m| !m.is_empty())?;
+                let q = params
+                    .find_map(|p| p.strip_prefix("q="))
+                    .and_then(|q| q.parse().ok())
+                    .unwrap_or(1.0);
+                Some((q, media_type))
+            })
+            .filter(|(q, _)| *q > 0.0)
+            .collect();
+        if candidates.is_empty() {
+            return Some(Format::Json);
+        }
+        // Stable, so equally weighted types keep the client's order.
+        candidates.sort_by(|a, b| b.0.total_cmp(&a.0));
+        candidates
+            .into_iter()
+            .find_map(|(_, media_type)| Format::from_media_type(&media_type.to_lowercase()))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Format {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let Some(accept) = parts.headers.get(header::ACCEPT) else {
+            return Ok(Format::Json);
+        };
+        accept
+            .to_str()
+            .ok()
+            .and_then(Format::negotiate)
+            .ok_or_else(not_acceptable)
+    }
+}
+
+fn not_acceptable() -> ApiError {
+    ApiError::new(
+        StatusCode::NOT_ACCEPTABLE,
+        "not_acceptable",
+        "Supported formats: application/json, text/csv, application/msgpack",
+    )
+}
+
+/// The fields a client asked for, as a tree of nested field names.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Fields(BTreeMap<String, Fields>);
+
+impl Fields {
+    /// Whether no fields were selected, meaning the whole value is wanted.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Parses a comma-separated list of dotted paths. A leaf selects the whole field.
+    pub fn parse(list: &str) -> Self {
+        let mut root = Fields::default();
+        for path in list.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let mut node = &mut root;
+            let mut names = path.split('.').peekable();
+            while let Some(name) = names.next() {
+                let selects_whole = node.0.get(name).is_some_and(|child| child.0.is_empty());
+                node = node.0.entry(name.to_string()).or_default();
+                if selects_whole {
+                    // An earlier path already selected all of this field.
+                    break;
+                }
+                if names.peek().is_none() {
+                    node.0.clear();
+                }
+            }
+        }
+        root
+    }
+
+    /// The selection below `name`: `None` if `name` isn't selected, and an empty selection if
+    /// all of it is.
+    pub fn get(&self, name: &str) -> Option<&Fields> {
+        self.0.get(name)
+    }
+
+    /// Keeps only the selected fields of `value`, failing on names it doesn't have.
+    pub fn project(&self, value: Value) -> Result<Value, String> {
+        if self.0.is_empty() {
+            return Ok(value);
+        }
+        match value {
+            Value::Array(items) => items
+                .into_iter()
+                .map(|item| self.project(item))
+                .collect::<Result<_, _>>()
+                .map(Value::Array),
+            Value::Object(mut object) => {
+                let mut selected = Map::new();
+                for (name, fields) in &self.0 {
+                    let field = object
+                        .remove(name)
+                        .ok_or_else(|| format!("Unknown field '{}'", name))?;
+                    selected.insert(name.clone(), fields.project(field)?);
+                }
+                Ok(Value::Object(selected))
+            }
+            _ => Err(format!(
+                "Cannot select '{}' from a plain value",
+                self.0.keys().next().map(String::as_str).unwrap_or_default()
+            )),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct FieldsParam {
+    fields: Option<String>,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for Fields {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(param) = Query::<FieldsParam>::from_request_parts(parts, state)
+            .await
+            .map_err(|e| ApiError::bad_request(e.body_text()))?;
+        Ok(param
+            .fields
+            .as_deref()
+            .map(Fields::parse)
+            .unwrap_or_default())
+    }
+}
+
+/// Why a value couldn't be encoded.
+#[derive(Debug)]
+pub enum EncodeError {
+    /// The client selected fields the value doesn't have.
+    Selection(String),
+    /// The value itself failed to serialize.
+    Serialization(String),
+}
+
+/// A value that can be returned in any negotiated format.
+pub trait Encodable: Serialize {
+    /// Serializes just the selected `fields` of the value.
+    fn select(&self, fields: &Fields) -> Result<Value, EncodeError> {
+        let value =
+            serde_json::to_value(self).map_err(|e| EncodeError::Serialization(e.to_string()))?;
+        fields.project(value).map_err(EncodeError::Selection)
+    }
+
+    /// Writes the selected `fields` of the value as CSV, or returns `None` if it has no tabular
+    /// form. An empty selection means every field.
+    fn write_csv(
+        &self,
+        _writer: &mut csv::Writer<Vec<u8>>,
+        _fields: &Fields,
+    ) -> Option<Result<(), String>> {
+        None
+    }
+}
+
+impl<T: Encodable> Encodable for &T {
+    fn select(&self, fields: &Fields) -> Result<Value, EncodeError> {
+        (**self).select(fields)
+    }
+
+    fn write_csv(
+        &self,
+        writer: &mut csv::Writer<Vec<u8>>,
+        fields: &Fields,
+    ) -> Option<Result<(), String>> {
+        (**self).write_csv(writer, fields)
+    }
+}
+
+/// In CSV, a metrics response is its time series, one row per day.
+impl Encodable for RepoMetricsResponse {
+    fn write_csv(
+        &self,
+        writer: &mut csv::Writer<Vec<u8>>,
+        fields: &Fields,
+    ) -> Option<Result<(), String>> {
+        let columns = fields.get("time_series").cloned().unwrap_or_default();
+        Some(write_rows(writer, &self.time_series, &columns))
+    }
+}
+
+/// In CSV, a heatmap is one row per weekday and hour.
+impl Encodable for Heatmap {
+    fn write_csv(
+        &self,
+        writer: &mut csv::Writer<Vec<u8>>,
+        fields: &Fields,
+    ) -> Option<Result<(), String>> {
+        let columns = fields.get("cells").cloned().unwrap_or_default();
+        Some(write_rows(writer, &self.cells, &columns))
+    }
+}
+
+/// Wraps response data with metadata about it. Field selection and CSV apply to the data only,
+/// so the metadata is always present in JSON and MessagePack.
+#[derive(Serialize)]
+pub struct Envelope<T, M> {
+    pub data: T,
+    pub meta: M,
+}
+
+impl<T: Encodable, M: Serialize> Encodable for Envelope<T, M> {
+    fn select(&self, fields: &Fields) -> Result<Value, EncodeError> {
+        let meta = serde_json::to_value(&self.meta)
+            .map_err(|e| EncodeError::Serialization(e.to_string()))?;
+        Ok(serde_json::json!({
+            "data": self.data.select(fields)?,
+            "meta": meta,
+        }))
+    }
+
+    fn write_csv(
+        &self,
+        writer: &mut csv::Writer<Vec<u8>>,
+        fields: &Fields,
+    ) -> Option<Result<(), String>> {
+        self.data.write_csv(writer, fields)
+    }
+}
+
+/// Writes `rows` as CSV, restricted to `columns` when that selects specific ones.
+pub fn write_rows<R: Serialize>(
+    writer: &mut csv::Writer<Vec<u8>>,
+    rows: &[R],
+    columns: &Fields,
+) -> Result<(), String> {
+    if columns.0.is_empty() {
+        return rows
+            .iter()
+            .try_for_each(|row| writer.serialize(row))
+            .map_err(|e| e.to_string());
+    }
+
+    writer
+        .write_record(columns.0.keys())
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let row = serde_json::to_value(row).map_err(|e| e.to_string())?;
+        let mut record = Vec::with_capacity(columns.0.len());
+        for name in columns.0.keys() {
+            match row.get(name) {
+                Some(Value::String(s)) => record.push(s.clone()),
+                Some(value) => record.push(value.to_string()),
+                None => return Err(format!("Unknown field '{}'", name)),
+            }
+        }
+        writer.write_record(&record).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// A response body serialized in the negotiated format, optionally trimmed to selected fields.
+pub struct Encoded<T> {
+    format: Format,
+    value: T,
+    fields: Fields,
+}
+
+impl<T> Encoded<T> {
+    pub fn new(format: Format, value: T) -> Self {
+        Self {
+            format,
+            value,
+            fields: Fields::default(),
+        }
+    }
+
+    pub fn with_fields(mut self, fields: Fields) -> Self {
+        self.fields = fields;
+        self
+    }
+}
+
+fn to_json<T: Serialize>(value: &T) -> Result<(&'static str, Vec<u8>), EncodeError> {
+    serde_json::to_vec(value)
+        .map(|body| (JSON, body))
+        .map_err(|e| EncodeError::Serialization(e.to_string()))
+}
+
+fn to_message_pack<T: Serialize>(value: &T) -> Result<(&'static str, Vec<u8>), EncodeError> {
+    rmp_serde::to_vec_named(value)
+        .map(|body| (MESSAGE_PACK, body))
+        .map_err(|e| EncodeError::Serialization(e.to_string()))
+}
+
+impl<T: Encodable> IntoResponse for Encoded<T> {
+    fn into_response(self) -> Response {
+        let Encoded {
+            format,
+            value,
+            fields,
+        } = self;
+        let encoded = match format {
+            Format::Json if fields.0.is_empty() => to_json(&value),
+            Format::MessagePack if fields.0.is_empty() => to_message_pack(&value),
+            Format::Json => value.select(&fields).and_then(|v| to_json(&v)),
+            Format::MessagePack => value.select(&fields).and_then(|v| to_message_pack(&v)),
+            Format::Csv => {
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                match value.write_csv(&mut writer, &fields) {
+                    None => return not_acceptable().into_response(),
+                    Some(Err(message)) => Err(EncodeError::Selection(message)),
+                    Some(Ok(())) => writer
+                        .into_inner()
+                        .map(|body| (CSV, body))
+                        .map_err(|e| EncodeError::Serialization(e.error().to_string())),
+                }
+            }
+        };
+
+        match encoded {
+            Ok((content_type, body)) => (
+                [(header::CONTENT_TYPE, HeaderValue::from_static(content_type))],
+                body,
+            )
+                .into_response(),
+            Err(EncodeError::Selection(message)) => ApiError::bad_request(message).into_response(),
+            Err(EncodeError::Serialization(e)) => {
+                tracing::error!("Failed to encode {:?} response: {}", format, e);
+                ApiError::internal().into_response()
+            }
+        }
+    }
+}
+
+/// A JSON `Envelope` whose data was serialized ahead of time, so hot paths can reuse the same
+/// bytes for every request instead of re-serializing the data each time.
+pub struct PreEncodedEnvelope<M> {
+    /// The data's JSON serialization.
+    pub data: Bytes,
+    pub meta: M,
+}
+
+impl<M: Serialize> IntoResponse for PreEncodedEnvelope<M> {
+    fn into_response(self) -> Response {
+        let meta = match serde_json::to_vec(&self.meta) {
+            Ok(meta) => meta,
+            Err(e) => {
+                tracing::error!("Failed to encode response metadata: {}", e);
+                return ApiError::internal().into_response();
+            }
+        };
+        let mut suffix = Vec::with_capacity(meta.len() + 10);
+        suffix.extend_from_slice(b",\"meta\":");
+        suffix.extend_from_slice(&meta);
+        suffix.push(b'}');
+
+        let body = if self.data.len() < STREAMING_THRESHOLD {
+            let mut body = Vec::with_capacity(self.data.len() + suffix.len() + 8);
+            body.extend_from_slice(b"{\"data\":");
+            body.extend_from_slice(&self.data);
+            body.extend_from_slice(&suffix);
+            Body::from(body)
+        } else {
+            // Sent as chunks around the shared bytes rather than copied into one buffer.
+            let chunks = [
+                Bytes::from_static(b"{\"data\":"),
+                self.data,
+                Bytes::from(suffix),
+            ];
+            Body::from_stream(stream::iter(chunks.map(Ok::<_, Infallible>)))
+        };
+        (
+            [(header::CONTENT_TYPE, HeaderValue::from_static(JSON))],
+            body,
+        )
+            .into_response()
+    }
+}
+
+/// Bodies at least this large are streamed in chunks rather than built in memory up front.
+pub const STREAMING_THRESHOLD: usize = 64 * 1024;
+
+/// Size of each chunk sent while streaming serialization.
+const STREAM_CHUNK_SIZE: usize = 16 * 1024;
+
+/// A JSON body serialized on a blocking thread while it is sent, so a large value never exists
+/// as a single serialized buffer. Serialization stops early if the client goes away.
+pub struct StreamedJson<T>(pub T);
+
+impl<T: Serialize + Send + 'static> IntoResponse for StreamedJson<T> {
+    fn into_response(self) -> Response {
+        // A couple of chunks in flight keeps the serializer just ahead of the socket.
+        let (sender, receiver) = tokio::sync::mpsc::channel(2);
+        let value = self.0;
+        tokio::task::spawn_blocking(move || {
+            let mut writer = ChunkWriter {
+                sender,
+                buffer: Vec::with_capacity(STREAM_CHUNK_SIZE),
+            };
+            let result = serde_json::to_writer(&mut writer, &value)
+                .map_err(std::io::Error::from)
+                .and_then(|()| writer.flush());
+            if let Err(e) = result {
+                // A closed channel means the client disconnected; nothing is left to tell.
+                if e.kind() != std::io::ErrorKind::BrokenPipe {
+                    tracing::error!("Failed to stream JSON response: {}", e);
+                    let _ = writer.sender.blocking_send(Err(e));
+                }
+            }
+        });
+        let chunks = stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|chunk| (chunk, receiver))
+        });
+        (
+            [(header::CONTENT_TYPE, HeaderValue::from_static(JSON))],
+            Body::from_stream(chunks),
+        )
+            .into_response()
+    }
+}
+
+/// Forwards serialized output to a response body in `STREAM_CHUNK_SIZE` pieces.
+struct ChunkWriter {
+    sender: tokio::sync::mpsc::Sender<std::io::Result<Bytes>>,
+    buffer: Vec<u8>,
+}
+
+impl Write for ChunkWriter {
+    fn write(&mut self, bytes: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(bytes);
+        if self.buffer.len() >= STREAM_CHUNK_SIZE {
+            self.flush()?;
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let chunk = std::mem::replace(&mut self.buffer, Vec::with_capacity(STREAM_CHUNK_SIZE));
+        self.sender
+            .blocking_send(Ok(chunk.into()))
+            .map_err(|_| std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_negotiate() {
+        assert_eq!(Format::negotiate(""), Some(Format::Json));
+        assert_eq!(Format::negotiate("text/csv"), Some(Format::Csv));
+        assert_eq!(
+            Format::negotiate("text/html, application/msgpack;q=0.9, */*;q=0.1"),
+            Some(Format::MessagePack)
+        );
+        assert_eq!(
+            Format::negotiate("text/csv;q=0.5, application/json"),
+            Some(Format::Json)
+        );
+        assert_eq!(Format::negotiate("text/html, image/png"), None);
+        assert_eq!(Format::negotiate("text/csv;q=0, */*"), Some(Format::Json));
+    }
+
+    #[test]
+    fn test_project_fields() {
+        let value = serde_json::json!({
+            "summary": {"merge_rate": 50, "is_widening": false},
+            "time_series": [
+                {"date": "2024-01-01", "spread": 1, "opened": 3},
+                {"date": "2024-01-02", "spread": 2, "opened": 4}
+            ]
+        });
+
+        let fields = Fields::parse("time_series.date, time_series.spread");
+        assert_eq!(
+            fields.project(value.clone()).unwrap(),
+            serde_json::json!({"time_series": [
+                {"date": "2024-01-01", "spread": 1},
+                {"date": "2024-01-02", "spread": 2}
+            ]})
+        );
+        assert_eq!(
+            Fields::parse("summary").project(value.clone()).unwrap(),
+            serde_json::json!({"summary": {"merge_rate": 50, "is_widening": false}})
+        );
+        assert_eq!(
+            Fields::parse("summary,summary.merge_rate"),
+            Fields::parse("summary")
+        );
+        assert!(Fields::parse("stars").project(value.clone()).is_err());
+        assert!(Fields::parse("summary.merge_rate.x")
+            .project(value)
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_pre_encoded_envelope_matches_envelope() {
+        let data = serde_json::json!({"summary": {"opened": 3}, "series": [1, 2]});
+        let pre_encoded = PreEncodedEnvelope {
+            data: Bytes::from(serde_json::to_vec(&data).unwrap()),
+            meta: serde_json::json!({"age": 5}),
+        }
+        .into_response();
+        assert_eq!(pre_encoded.headers()[header::CONTENT_TYPE], JSON);
+        let body = axum::body::to_bytes(pre_encoded.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let expected = serde_json::to_vec(&Envelope {
+            data,
+            meta: serde_json::json!({"age": 5}),
+        })
+        .unwrap();
+        assert_eq!(body, expected);
+    }
+
+    #[tokio::test]
+    async fn test_large_pre_encoded_envelope_is_streamed() {
+        let data = serde_json::json!({"series": vec![1; STREAMING_THRESHOLD]});
+        let response = PreEncodedEnvelope {
+            data: Bytes::from(serde_json::to_vec(&data).unwrap()),
+            meta: serde_json::json!({}),
+        }
+        .into_response();
+        assert!(response.headers().get(header::CONTENT_LENGTH).is_none());
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["data"], data);
+    }
+
+    #[tokio::test]
+    async fn test_streamed_json() {
+        let value = Envelope {
+            data: (0..10_000).collect::<Vec<u32>>(),
+            meta: "meta",
+        };
+        let expected = serde_json::to_vec(&value).unwrap();
+        let body =
+            axum::body::to_bytes(StreamedJson(value).into_response().into_body(), usize::MAX)
+                .await
+                .unwrap();
+        assert_eq!(body, expected);
+    }
+}
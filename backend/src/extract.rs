@@ -0,0 +1,37 @@
+//! Axum's `Json`, `Query` and `Path` extractors, rejecting malformed requests with the JSON error
+//! body every other failure uses rather than axum's plain text.
+
+use crate::error::ApiError;
+use axum::{
+    extract::{FromRequest, FromRequestParts},
+    http::Uri,
+    response::{IntoResponse, Response},
+};
+use serde::{de::DeserializeOwned, Serialize};
+
+/// A JSON request body, or a JSON response.
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(ApiError))]
+pub struct Json<T>(pub T);
+
+impl<T: Serialize> IntoResponse for Json<T> {
+    fn into_response(self) -> Response {
+        axum::Json(self.0).into_response()
+    }
+}
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Query), rejection(ApiError))]
+pub struct Query<T>(pub T);
+
+impl<T: DeserializeOwned> Query<T> {
+    /// Parses the query string of `uri`, for middleware that reads it without consuming the
+    /// request.
+    pub fn try_from_uri(uri: &Uri) -> Result<Self, ApiError> {
+        Ok(Self(axum::extract::Query::try_from_uri(uri)?.0))
+    }
+}
+
+#[derive(FromRequestParts)]
+#[from_request(via(axum::extract::Path), rejection(ApiError))]
+pub struct Path<T>(pub T);
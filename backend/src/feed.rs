@@ -0,0 +1,235 @@
+//! An Atom feed with a weekly summary of a repository's flow, for following its health from a
+//! feed reader.
+//!
+//! Entries cover weeks starting on `WEEK_START` (Monday to Sunday by default) that have ended, so
+//! each entry is written once and readers don't flag it as changed on every refresh. In
+//! business-day mode their counts are taken over the week's business days.
+
+use crate::error::ApiError;
+use crate::extract::Path;
+use crate::AppState;
+use axum::{
+    extract::State,
+    http::{header::CONTENT_TYPE, HeaderValue, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Datelike, NaiveDate, Utc, Weekday};
+use repoflow_core::domain::RepoId;
+use repoflow_core::metrics::FlowMetricsResponse;
+use std::fmt::Write;
+use std::sync::Arc;
+
+/// Days between the ends of consecutive weeks.
+const WEEK_DAYS: u64 = 7;
+
+/// One ended week of a repository's flow.
+struct WeeklySummary<'a> {
+    /// The last day of the week.
+    ended: NaiveDate,
+    week: &'a FlowMetricsResponse,
+    previous: Option<&'a FlowMetricsResponse>,
+}
+
+/// The feed, mounted with the other repository routes.
+pub fn repo_router() -> Router<Arc<AppState>> {
+    Router::new().route("/repos/{owner}/{repo}/feed.atom", get(get_repo_feed))
+}
+
+async fn get_repo_feed(
+    Path((owner, repo)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<Response, ApiError> {
+    let repo_id = crate::parse_repo_id(&owner, &repo)?;
+    // Feed readers can't sign in, so only what the server's token sees is published.
+    let cached = match crate::fetch_metrics(&state, &repo_id, None).await {
+        Ok(cached) => cached,
+        Err(e) => return Err(crate::upstream_error(&state, &repo_id, "PRs", None, e).await),
+    };
+    let Some(window) = cached.window(state.service.week_window()) else {
+        return Err(ApiError::new(
+            StatusCode::NOT_IMPLEMENTED,
+            "feed_unavailable",
+            "Weekly summaries need PR_FETCH_DAYS to exceed METRICS_DAYS_TO_DISPLAY by 7 days",
+        ));
+    };
+    let weeks = weekly_summaries(
+        &window.metrics.time_series,
+        cached.fetched_at.date_naive(),
+        state.config.week_start.pred(),
+    );
+    Ok((
+        [(
+            CONTENT_TYPE,
+            HeaderValue::from_static("application/atom+xml; charset=utf-8"),
+        )],
+        render(&repo_id, &weeks, cached.fetched_at),
+    )
+        .into_response())
+}
+
+/// The weeks in `series`, a week-long rolling window, that ended on `last_day` before `today`,
+/// newest first.
+fn weekly_summaries(
+    series: &[FlowMetricsResponse],
+    today: NaiveDate,
+    last_day: Weekday,
+) -> Vec<WeeklySummary<'_>> {
+    let by_date = |date: NaiveDate| {
+        let date = date.format("%Y-%m-%d").to_string();
+        series.iter().find(|point| point.date == date)
+    };
+    let mut weeks: Vec<WeeklySummary> = series
+        .iter()
+        .filter_map(|point| {
+            let ended = NaiveDate::parse_from_str(&point.date, "%Y-%m-%d").ok()?;
+            (ended.weekday() == last_day && ended < today).then(|| WeeklySummary {
+                ended,
+                week: point,
+                previous: ended
+                    .checked_sub_days(chrono::Days::new(WEEK_DAYS))
+                    .and_then(by_date),
+            })
+        })
+        .collect();
+    weeks.sort_by_key(|week| std::cmp::Reverse(week.ended));
+    weeks
+}
+
+/// Renders `weeks` as an Atom document last updated at `updated`.
+fn render(repo_id: &RepoId, weeks: &[WeeklySummary], updated: DateTime<Utc>) -> String {
+    let name = escape(&repo_id.to_string());
+    let mut atom = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+    atom.push_str("<feed xmlns=\"http://www.w3.org/2005/Atom\">\n");
+    let _ = writeln!(atom, "  <id>urn:repoflow:{}</id>", name);
+    let _ = writeln!(atom, "  <title>{} weekly flow</title>", name);
+    let _ = writeln!(
+        atom,
+        "  <link rel=\"alternate\" href=\"https://github.com/{}\"/>",
+        name
+    );
+    let _ = writeln!(atom, "  <updated>{}</updated>", updated.to_rfc3339());
+    atom.push_str("  <author><name>RepoFlow</name></author>\n");
+    for week in weeks {
+        let (year, number) = (week.ended.iso_week().year(), week.ended.iso_week().week());
+        // Written when the week ended, at the following midnight.
+        let written = (week.ended + chrono::Days::new(1))
+            .and_hms_opt(0, 0, 0)
+            .expect("midnight is valid")
+            .and_utc();
+        atom.push_str("  <entry>\n");
+        let _ = writeln!(
+            atom,
+            "    <id>urn:repoflow:{}:{}-W{:02}</id>",
+            name, year, number
+        );
+        let _ = writeln!(
+            atom,
+            "    <title>{}: week {} of {}</title>",
+            name, number, year
+        );
+        let _ = writeln!(atom, "    <updated>{}</updated>", written.to_rfc3339());
+        let _ = writeln!(atom, "    <summary>{}</summary>", escape(&summary(week)));
+        atom.push_str("  </entry>\n");
+    }
+    atom.push_str("</feed>\n");
+    atom
+}
+
+fn summary(week: &WeeklySummary) -> String {
+    let mut summary = format!(
+        "Week ending {}: {} opened, {} merged, spread {:+}",
+        week.ended.format("%Y-%m-%d"),
+        week.week.opened,
+        week.week.merged,
+        week.week.spread
+    );
+    if let Some(previous) = week.previous {
+        let change = week.week.spread - previous.spread;
+        let _ = write!(summary, " ({:+} on the week before)", change);
+    }
+    summary.push('.');
+    summary
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(date: &str, opened: usize, merged: usize) -> FlowMetricsResponse {
+        FlowMetricsResponse {
+            date: date.to_string(),
+            opened,
+            merged,
+            spread: opened as i64 - merged as i64,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_only_ended_weeks_are_published() {
+        // 2026-03-01 and 2026-03-08 are Sundays.
+        let series: Vec<FlowMetricsResponse> = (1..=10)
+            .map(|day| point(&format!("2026-03-{:02}", day), day, 2))
+            .collect();
+        let weeks = weekly_summaries(
+            &series,
+            NaiveDate::from_ymd_opt(2026, 3, 10).unwrap(),
+            Weekday::Sun,
+        );
+        let ended: Vec<String> = weeks.iter().map(|w| w.ended.to_string()).collect();
+        assert_eq!(ended, ["2026-03-08", "2026-03-01"]);
+        assert_eq!(
+            summary(&weeks[0]),
+            "Week ending 2026-03-08: 8 opened, 2 merged, spread +6 (+7 on the week before)."
+        );
+        assert_eq!(
+            summary(&weeks[1]),
+            "Week ending 2026-03-01: 1 opened, 2 merged, spread -1."
+        );
+
+        // A Sunday still in progress isn't published yet.
+        let weeks = weekly_summaries(
+            &series,
+            NaiveDate::from_ymd_opt(2026, 3, 8).unwrap(),
+            Weekday::Sun,
+        );
+        assert_eq!(weeks.len(), 1);
+
+        // Weeks starting on Sunday end on Saturday.
+        let weeks = weekly_summaries(
+            &series,
+            NaiveDate::from_ymd_opt(2026, 3, 10).unwrap(),
+            Weekday::Sat,
+        );
+        let ended: Vec<String> = weeks.iter().map(|w| w.ended.to_string()).collect();
+        assert_eq!(ended, ["2026-03-07"]);
+    }
+
+    #[test]
+    fn test_render_feed() {
+        let series = [point("2026-03-08", 4, 1)];
+        let weeks = weekly_summaries(
+            &series,
+            NaiveDate::from_ymd_opt(2026, 3, 9).unwrap(),
+            Weekday::Sun,
+        );
+        let atom = render(
+            &"acme/widgets".parse().unwrap(),
+            &weeks,
+            "2026-03-09T06:00:00Z".parse().unwrap(),
+        );
+        assert!(atom.contains("<id>urn:repoflow:acme/widgets:2026-W10</id>"));
+        assert!(atom.contains("<updated>2026-03-09T00:00:00+00:00</updated>"));
+        assert!(atom.contains("<summary>Week ending 2026-03-08: 4 opened, 1 merged, spread +3."));
+        assert_eq!(atom.matches("<entry>").count(), 1);
+    }
+}
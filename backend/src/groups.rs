@@ -0,0 +1,372 @@
+//! Named groups of repositories, such as a team's services, whose flow is reported as one.
+//!
+//! Anyone can read groups and their metrics; creating, changing and deleting them takes the admin
+//! token. Like the popular list, groups are saved to `REPO_GROUPS_FILE` when one is configured.
+
+use crate::encoding::{Encoded, Envelope, Fields, Format};
+use crate::error::ApiError;
+use crate::extract::{Json, Path, Query};
+use crate::AppState;
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use repoflow_core::config::RepoGroup;
+use repoflow_core::domain::RepoId;
+use repoflow_core::metrics;
+use repoflow_core::upstream;
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Groups kept at most.
+const MAX_GROUPS: usize = 100;
+
+/// Repositories in a group at most, as its metrics fetch every one of them.
+const MAX_GROUP_REPOS: usize = 25;
+
+const MAX_NAME_LENGTH: usize = 100;
+
+pub struct GroupStore {
+    groups: RwLock<Vec<RepoGroup>>,
+    file: Option<PathBuf>,
+}
+
+impl GroupStore {
+    pub fn new(groups: Vec<RepoGroup>, file: Option<PathBuf>) -> Self {
+        Self {
+            groups: RwLock::new(groups),
+            file,
+        }
+    }
+
+    async fn list(&self) -> Vec<RepoGroup> {
+        self.groups.read().await.clone()
+    }
+
+    async fn get(&self, id: &str) -> Option<RepoGroup> {
+        self.groups
+            .read()
+            .await
+            .iter()
+            .find(|group| group.id == id)
+            .cloned()
+    }
+
+    /// Adds `group`, or replaces the group with its id. Returns whether it was new, or `None` if
+    /// the store is full.
+    async fn put(&self, group: RepoGroup) -> anyhow::Result<Option<bool>> {
+        let mut groups = self.groups.write().await;
+        let mut updated = groups.clone();
+        let new = match updated.iter().position(|g| g.id == group.id) {
+            Some(index) => {
+                updated[index] = group;
+                false
+            }
+            None if updated.len() >= MAX_GROUPS => return Ok(None),
+            None => {
+                updated.push(group);
+                true
+            }
+        };
+        self.persist(&updated).await?;
+        *groups = updated;
+        Ok(Some(new))
+    }
+
+    /// Deletes a group, returning whether it existed.
+    async fn delete(&self, id: &str) -> anyhow::Result<bool> {
+        let mut groups = self.groups.write().await;
+        if !groups.iter().any(|group| group.id == id) {
+            return Ok(false);
+        }
+        let updated: Vec<RepoGroup> = groups.iter().filter(|g| g.id != id).cloned().collect();
+        self.persist(&updated).await?;
+        *groups = updated;
+        Ok(true)
+    }
+
+    /// Writes the groups to the configured file, replacing it atomically.
+    async fn persist(&self, groups: &[RepoGroup]) -> anyhow::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(groups)?)
+            .await
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, path)
+            .await
+            .with_context(|| format!("failed to replace {}", path.display()))
+    }
+}
+
+/// The body of a request creating or replacing a group.
+#[derive(Deserialize)]
+struct GroupRequest {
+    name: String,
+    repos: Vec<RepoId>,
+}
+
+impl GroupRequest {
+    /// The group this request describes, under `id`, if it's valid.
+    fn into_group(self, id: String) -> Result<RepoGroup, ApiError> {
+        let name = self.name.trim();
+        if name.is_empty() || name.chars().count() > MAX_NAME_LENGTH {
+            return Err(ApiError::bad_request(format!(
+                "name must be 1 to {} characters",
+                MAX_NAME_LENGTH
+            )));
+        }
+        let mut repos: Vec<RepoId> = Vec::with_capacity(self.repos.len());
+        for repo in self.repos {
+            let problems = repo.naming_problems();
+            if !problems.is_empty() {
+                return Err(ApiError::bad_request(format!(
+                    "'{}' is not a valid owner/repo: {}",
+                    repo,
+                    problems.join("; ")
+                )));
+            }
+            if !repos.contains(&repo) {
+                repos.push(repo);
+            }
+        }
+        if repos.is_empty() || repos.len() > MAX_GROUP_REPOS {
+            return Err(ApiError::bad_request(format!(
+                "repos must list 1 to {} repositories",
+                MAX_GROUP_REPOS
+            )));
+        }
+        Ok(RepoGroup {
+            id,
+            name: name.to_string(),
+            repos,
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct GroupMetricsParams {
+    /// Rolling window size in days; defaults to `METRICS_WINDOW_SIZE`.
+    window: Option<i64>,
+}
+
+/// Which members a group's metrics cover.
+#[derive(Serialize)]
+struct GroupMetricsMeta {
+    window_days: i64,
+    /// Members whose metrics are included.
+    repos: Vec<String>,
+    /// Members that couldn't be fetched, and so are left out.
+    unavailable: Vec<UnavailableRepo>,
+    /// False when a page limit cut any included member's fetch short.
+    data_complete: bool,
+    /// When the least recently fetched included member was fetched.
+    oldest_fetched_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Serialize)]
+struct UnavailableRepo {
+    repo: String,
+    error: String,
+}
+
+/// Routes for groups, served under the API prefix.
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/groups", get(list_groups).post(create_group))
+        .route(
+            "/groups/{id}",
+            get(get_group).put(replace_group).delete(delete_group),
+        )
+        .route("/groups/{id}/metrics", get(get_group_metrics))
+}
+
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), ApiError> {
+    if crate::admin::is_admin(state, headers) {
+        Ok(())
+    } else {
+        Err(ApiError::unauthorized("Admin token required"))
+    }
+}
+
+fn store_error(id: &str, e: anyhow::Error) -> ApiError {
+    tracing::error!("Failed to save group {}: {:#}", id, e);
+    ApiError::internal()
+}
+
+async fn list_groups(State(state): State<Arc<AppState>>) -> Json<Vec<RepoGroup>> {
+    Json(state.groups.list().await)
+}
+
+async fn get_group(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Result<Json<RepoGroup>, ApiError> {
+    state
+        .groups
+        .get(&id)
+        .await
+        .map(Json)
+        .ok_or_else(|| ApiError::not_found("Group not found"))
+}
+
+async fn create_group(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Json(request): Json<GroupRequest>,
+) -> Result<(StatusCode, Json<RepoGroup>), ApiError> {
+    require_admin(&state, &headers)?;
+    let group = request.into_group(format!("{:016x}", rand::random::<u64>()))?;
+    match state.groups.put(group.clone()).await {
+        Ok(Some(_)) => Ok((StatusCode::CREATED, Json(group))),
+        Ok(None) => Err(ApiError::new(
+            StatusCode::CONFLICT,
+            "groups_full",
+            format!("at most {} groups are allowed", MAX_GROUPS),
+        )),
+        Err(e) => Err(store_error(&group.id, e)),
+    }
+}
+
+async fn replace_group(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+    Json(request): Json<GroupRequest>,
+) -> Result<Json<RepoGroup>, ApiError> {
+    require_admin(&state, &headers)?;
+    if state.groups.get(&id).await.is_none() {
+        return Err(ApiError::not_found("Group not found"));
+    }
+    let group = request.into_group(id)?;
+    match state.groups.put(group.clone()).await {
+        Ok(_) => Ok(Json(group)),
+        Err(e) => Err(store_error(&group.id, e)),
+    }
+}
+
+async fn delete_group(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+    Path(id): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    require_admin(&state, &headers)?;
+    match state.groups.delete(&id).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::not_found("Group not found")),
+        Err(e) => Err(store_error(&id, e)),
+    }
+}
+
+/// The combined flow of a group's members. Members that can't be fetched are listed in the
+/// metadata and left out, unless none can be.
+async fn get_group_metrics(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    Query(params): Query<GroupMetricsParams>,
+    format: Format,
+    fields: Fields,
+) -> Result<Response, ApiError> {
+    let group = state
+        .groups
+        .get(&id)
+        .await
+        .ok_or_else(|| ApiError::not_found("Group not found"))?;
+    let window_days = crate::window_days(&state, params.window)?;
+
+    // Group dashboards are shared, so members are fetched without any user's token.
+    let results = futures::future::join_all(
+        group
+            .repos
+            .iter()
+            .map(|repo_id| crate::fetch_metrics(&state, repo_id, None)),
+    )
+    .await;
+    let mut included = Vec::new();
+    let mut unavailable = Vec::new();
+    let mut first_error = None;
+    for (repo_id, result) in group.repos.iter().zip(results) {
+        match result {
+            Ok(cached) => included.push((repo_id, cached)),
+            Err(e) => {
+                let (_, message) = upstream::classify(&e).response();
+                unavailable.push(UnavailableRepo {
+                    repo: repo_id.to_string(),
+                    error: message.to_string(),
+                });
+                first_error.get_or_insert((repo_id, e));
+            }
+        }
+    }
+    if included.is_empty() {
+        if let Some((repo_id, e)) = first_error {
+            return Err(crate::upstream_error(&state, repo_id, "PRs", None, e).await);
+        }
+    }
+
+    // Every entry holds each window size `window_days` accepts.
+    let members: Vec<&metrics::RepoMetricsResponse> = included
+        .iter()
+        .filter_map(|(_, cached)| Some(&cached.window(window_days)?.metrics))
+        .collect();
+    let meta = GroupMetricsMeta {
+        window_days,
+        repos: included
+            .iter()
+            .map(|(repo_id, _)| repo_id.to_string())
+            .collect(),
+        unavailable,
+        data_complete: included.iter().all(|(_, cached)| cached.complete),
+        oldest_fetched_at: included.iter().map(|(_, cached)| cached.fetched_at).min(),
+    };
+    let data = metrics::aggregate(&members);
+    Ok(Encoded::new(format, Envelope { data, meta })
+        .with_fields(fields)
+        .into_response())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(name: &str, repos: &[&str]) -> GroupRequest {
+        GroupRequest {
+            name: name.to_string(),
+            repos: repos.iter().map(|repo| repo.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_group_requests_are_validated() {
+        let group = request(
+            " Payments ",
+            &["acme/billing", "acme/billing", "acme/ledger"],
+        )
+        .into_group("g1".to_string())
+        .unwrap();
+        assert_eq!(group.name, "Payments");
+        assert_eq!(group.repos.len(), 2);
+
+        assert!(request("  ", &["acme/billing"])
+            .into_group("g1".to_string())
+            .is_err());
+        assert!(request("Payments", &[])
+            .into_group("g1".to_string())
+            .is_err());
+        let many: Vec<String> = (0..=MAX_GROUP_REPOS)
+            .map(|i| format!("acme/r{}", i))
+            .collect();
+        let many: Vec<&str> = many.iter().map(String::as_str).collect();
+        assert!(request("Payments", &many)
+            .into_group("g1".to_string())
+            .is_err());
+    }
+}
@@ -0,0 +1,124 @@
+//! HTTP caching headers for responses served from the metrics cache.
+//!
+//! Clients may reuse a response until the server-side entry would expire, and revalidate with
+//! `If-Modified-Since` afterwards, so repeat navigations don't refetch the whole payload.
+
+use crate::api_keys::API_KEY_HEADER;
+use axum::http::{header, HeaderMap, HeaderValue};
+use chrono::{DateTime, Utc};
+
+/// Formats a time as an HTTP date (RFC 9110 IMF-fixdate).
+fn http_date(time: DateTime<Utc>) -> String {
+    time.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
+
+/// Caching headers for data fetched at `fetched_at` that is cached for `ttl_seconds`.
+/// Responses to requests that presented credentials are marked private so shared caches don't
+/// serve them to anyone else.
+pub fn headers(
+    fetched_at: DateTime<Utc>,
+    ttl_seconds: u64,
+    private: bool,
+    now: DateTime<Utc>,
+) -> HeaderMap {
+    let age = (now - fetched_at).num_seconds().max(0) as u64;
+    let max_age = ttl_seconds.saturating_sub(age);
+    let scope = if private { "private" } else { "public" };
+
+    let mut headers = HeaderMap::new();
+    if let Ok(value) = HeaderValue::from_str(&format!("{}, max-age={}", scope, max_age)) {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    if let Ok(value) = HeaderValue::from_str(&http_date(fetched_at)) {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+    // The body depends on content negotiation, and on the caller's credentials when private.
+    headers.insert(
+        header::VARY,
+        HeaderValue::from_static("Accept, Cookie, X-Api-Key, Authorization"),
+    );
+    headers
+}
+
+/// Whether the response to a request must be private: when it presented a session cookie, an
+/// API key or other credentials, or when every request needs an API key.
+pub fn is_private(request_headers: &HeaderMap, require_api_key: bool) -> bool {
+    require_api_key
+        || [
+            header::COOKIE.as_str(),
+            API_KEY_HEADER,
+            header::AUTHORIZATION.as_str(),
+        ]
+        .into_iter()
+        .any(|name| request_headers.contains_key(name))
+}
+
+/// Whether the request's `If-Modified-Since` shows the client already has data fetched at
+/// `fetched_at`.
+pub fn is_not_modified(request_headers: &HeaderMap, fetched_at: DateTime<Utc>) -> bool {
+    request_headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok())
+        // HTTP dates have whole-second precision.
+        .is_some_and(|since| fetched_at.timestamp() <= since.timestamp())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_headers() {
+        let fetched_at = DateTime::parse_from_rfc3339("2024-01-01T12:00:00.5Z")
+            .unwrap()
+            .to_utc();
+        let headers = headers(fetched_at, 300, false, fetched_at + Duration::seconds(100));
+        assert_eq!(headers[header::CACHE_CONTROL], "public, max-age=200");
+        assert_eq!(
+            headers[header::LAST_MODIFIED],
+            "Mon, 01 Jan 2024 12:00:00 GMT"
+        );
+
+        let headers = super::headers(fetched_at, 300, true, fetched_at + Duration::hours(1));
+        assert_eq!(headers[header::CACHE_CONTROL], "private, max-age=0");
+        assert_eq!(
+            headers[header::VARY],
+            "Accept, Cookie, X-Api-Key, Authorization"
+        );
+    }
+
+    #[test]
+    fn test_is_private() {
+        assert!(!is_private(&HeaderMap::new(), false));
+        assert!(is_private(&HeaderMap::new(), true));
+        for name in ["cookie", API_KEY_HEADER, "authorization"] {
+            let mut request = HeaderMap::new();
+            request.insert(name, HeaderValue::from_static("secret"));
+            assert!(is_private(&request, false));
+        }
+    }
+
+    #[test]
+    fn test_is_not_modified() {
+        let fetched_at = DateTime::parse_from_rfc3339("2024-01-01T12:00:00.5Z")
+            .unwrap()
+            .to_utc();
+        let request = |since: &'static str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_MODIFIED_SINCE, HeaderValue::from_static(since));
+            headers
+        };
+        assert!(is_not_modified(
+            &request("Mon, 01 Jan 2024 12:00:00 GMT"),
+            fetched_at
+        ));
+        assert!(!is_not_modified(
+            &request("Mon, 01 Jan 2024 11:59:59 GMT"),
+            fetched_at
+        ));
+        assert!(!is_not_modified(&request("yesterday"), fetched_at));
+        assert!(!is_not_modified(&HeaderMap::new(), fetched_at));
+    }
+}
@@ -0,0 +1,395 @@
+//! Requests that outlive a deadline set by their client, finished in the background and collected
+//! later from `/api/v1/jobs/{id}`.
+//!
+//! Metrics requests set a deadline with an `X-Request-Deadline` header holding an RFC 3339
+//! timestamp, or a `timeout` query parameter in seconds. When it passes first, they get 202
+//! Accepted with the job's location instead of waiting on GitHub. Metrics requests with
+//! `async=true` start a job at once when the repository is uncached and estimated to need
+//! `ASYNC_FETCH_MIN_PAGES` pages. A job's result is only given to whoever started it: the
+//! signed-in user whose token fetched it, since it may hold a private repository, or else the API
+//! key the request was made with. Job IDs are
+//! unguessable too, and redacted from the audit log and request traces.
+//!
+//! Jobs run for at most `REQUEST_TIMEOUT_SECONDS`, like any request, and at most
+//! `MAX_RUNNING_JOBS` run at once. Past that, requests that would start one get 503.
+
+use crate::error::ApiError;
+use crate::extract::{Json, Path, Query};
+use crate::{request_id, AppState};
+use axum::{
+    body::Bytes,
+    extract::{OriginalUri, Request, State},
+    http::{header::LOCATION, HeaderMap, HeaderValue, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Utc};
+use moka::future::Cache;
+use repoflow_core::config::AppConfig;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+use tokio::sync::Semaphore;
+use tokio::task::JoinHandle;
+use tracing::Instrument;
+
+pub const DEADLINE_HEADER: &str = "x-request-deadline";
+
+/// Finished results are kept this long for their client to collect.
+const JOB_TTL: StdDuration = StdDuration::from_secs(10 * 60);
+
+/// Jobs kept at most, running or finished.
+const MAX_JOBS: u64 = 1_000;
+
+#[derive(Deserialize)]
+struct AsyncParams {
+    /// Whether the client would rather poll a job than wait on a long fetch.
+    #[serde(default, rename = "async")]
+    run_async: bool,
+}
+
+#[derive(Deserialize)]
+struct DeadlineParams {
+    /// Seconds the client will wait for a response.
+    timeout: Option<f64>,
+}
+
+/// A response produced after its client stopped waiting.
+struct Finished {
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+}
+
+#[derive(Clone)]
+enum Job {
+    Running,
+    Finished(Arc<Finished>),
+    /// The handler panicked or its response couldn't be read.
+    Failed,
+}
+
+/// A job and who started it.
+#[derive(Clone)]
+struct Entry {
+    /// Who started the job, as given by `job_owner`, the only one its result is given to.
+    owner: String,
+    job: Job,
+}
+
+/// What a client is told about a job that is still running.
+#[derive(Serialize)]
+struct RunningJob {
+    id: String,
+    status: &'static str,
+    location: String,
+}
+
+/// Jobs of the requests that passed their deadline.
+pub struct JobStore {
+    jobs: Cache<String, Entry>,
+    /// A permit for each job still running, which the stored results don't bound.
+    running: Arc<Semaphore>,
+    time_limit: StdDuration,
+}
+
+impl JobStore {
+    pub fn new(config: &AppConfig) -> Self {
+        Self {
+            jobs: Cache::builder()
+                .max_capacity(MAX_JOBS)
+                .time_to_live(JOB_TTL)
+                .build(),
+            running: Arc::new(Semaphore::new(config.max_running_jobs)),
+            time_limit: StdDuration::from_secs(config.request_timeout_seconds),
+        }
+    }
+
+    /// Runs `handler` in the background, for at most the request time limit, keeping its
+    /// response for `owner` to collect. Returns the job's ID, or 503 when too many jobs are
+    /// running.
+    pub async fn start(
+        self: &Arc<Self>,
+        owner: String,
+        handler: impl Future<Output = Response> + Send + 'static,
+    ) -> Result<String, ApiError> {
+        let permit = self.running.clone().try_acquire_owned().map_err(|_| {
+            ApiError::new(
+                StatusCode::SERVICE_UNAVAILABLE,
+                "too_many_jobs",
+                "Too many requests are running in the background. Try again shortly.",
+            )
+            // A slot frees up once the oldest job finishes, within the time limit.
+            .with_retry_after(self.time_limit.as_secs())
+        })?;
+        let id = format!("{:016x}", rand::random::<u64>());
+        let entry = Entry {
+            owner,
+            job: Job::Running,
+        };
+        self.jobs.insert(id.clone(), entry.clone()).await;
+        let (store, job_id) = (self.clone(), id.clone());
+        tokio::spawn(async move {
+            let handler = tokio::spawn(handler);
+            let abort = handler.abort_handle();
+            let finished = match tokio::time::timeout(store.time_limit, collect(handler)).await {
+                Ok(finished) => finished,
+                Err(_) => {
+                    abort.abort();
+                    tracing::warn!("Background request timed out after {:?}", store.time_limit);
+                    finish(crate::timed_out().into_response()).await
+                }
+            };
+            let job = match finished {
+                Ok(finished) => Job::Finished(Arc::new(finished)),
+                Err(e) => {
+                    tracing::error!("Background request failed: {:#}", e);
+                    Job::Failed
+                }
+            };
+            store.jobs.insert(job_id, Entry { job, ..entry }).await;
+            drop(permit);
+        });
+        Ok(id)
+    }
+}
+
+async fn collect(handler: JoinHandle<Response>) -> anyhow::Result<Finished> {
+    finish(handler.await?).await
+}
+
+async fn finish(response: Response) -> anyhow::Result<Finished> {
+    let (parts, body) = response.into_parts();
+    Ok(Finished {
+        status: parts.status,
+        headers: parts.headers,
+        body: axum::body::to_bytes(body, usize::MAX).await?,
+    })
+}
+
+/// How long the client of `request` will wait, if it set a deadline.
+pub fn client_timeout(
+    request: &Request,
+    now: DateTime<Utc>,
+) -> Result<Option<StdDuration>, ApiError> {
+    if let Some(deadline) = request.headers().get(DEADLINE_HEADER) {
+        let deadline = deadline
+            .to_str()
+            .ok()
+            .and_then(|value| DateTime::parse_from_rfc3339(value).ok())
+            .ok_or_else(|| {
+                ApiError::bad_request(format!("{} must be an RFC 3339 timestamp", DEADLINE_HEADER))
+            })?;
+        return Ok(Some(
+            (deadline.with_timezone(&Utc) - now)
+                .to_std()
+                .unwrap_or_default(),
+        ));
+    }
+    let Ok(Query(params)) = Query::<DeadlineParams>::try_from_uri(request.uri()) else {
+        return Err(ApiError::bad_request("timeout must be a number of seconds"));
+    };
+    match params.timeout {
+        None => Ok(None),
+        Some(seconds) => StdDuration::try_from_secs_f64(seconds)
+            .map(Some)
+            .map_err(|_| ApiError::bad_request("timeout must be a number of seconds")),
+    }
+}
+
+/// The prefix, including any tenant's, of the API that `request` was made to, which serves its
+/// jobs.
+pub fn api_prefix(original: &Uri, request: &Request) -> String {
+    original
+        .path()
+        .strip_suffix(request.uri().path())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Route-level middleware answering `async=true` requests for an uncached repository with a job
+/// when fetching it looks long.
+pub async fn defer_cold_fetch(
+    State(state): State<Arc<AppState>>,
+    Path((owner, repo)): Path<(String, String)>,
+    OriginalUri(original): OriginalUri,
+    request: Request,
+    next: Next,
+) -> Response {
+    let run_async = Query::<AsyncParams>::try_from_uri(request.uri())
+        .is_ok_and(|Query(params)| params.run_async);
+    let Some(repo_id) = crate::parse_repo_id(&owner, &repo)
+        .ok()
+        .filter(|_| run_async)
+    else {
+        return next.run(request).await;
+    };
+    if state.service.cached(&repo_id).await.is_some()
+        || state.service.estimated_pages(&repo_id).await < state.config.async_fetch_min_pages
+    {
+        return next.run(request).await;
+    }
+    let api_prefix = api_prefix(&original, &request);
+    let owner = job_owner(&state, request.headers()).await;
+    let handler = request_id::propagate(next.run(request)).in_current_span();
+    match state.jobs.start(owner, handler).await {
+        Ok(id) => {
+            tracing::info!("Fetching {} in the background", repo_id);
+            accepted(&api_prefix, id)
+        }
+        Err(e) => e.into_response(),
+    }
+}
+
+/// Route-level middleware answering requests that outlive their client's deadline with a job.
+/// The deadline only counts when it's earlier than the request time limit.
+pub async fn defer_past_deadline(
+    State(state): State<Arc<AppState>>,
+    OriginalUri(original): OriginalUri,
+    request: Request,
+    next: Next,
+) -> Result<Response, ApiError> {
+    let limit = StdDuration::from_secs(state.config.request_timeout_seconds);
+    let Some(timeout) = client_timeout(&request, Utc::now())?.filter(|timeout| *timeout < limit)
+    else {
+        return Ok(next.run(request).await);
+    };
+    let api_prefix = api_prefix(&original, &request);
+    let owner = job_owner(&state, request.headers()).await;
+    // Polled here, and moved to a task only if it has to outlive the request.
+    let mut handler = Box::pin(request_id::propagate(next.run(request)).in_current_span());
+    if let Ok(response) = tokio::time::timeout(timeout, &mut handler).await {
+        return Ok(response);
+    }
+    let id = state.jobs.start(owner, handler).await?;
+    tracing::info!("Request passed its client's deadline; continuing in the background");
+    Ok(accepted(&api_prefix, id))
+}
+
+/// The response telling a client that its request continues as job `id`, which `api_prefix`
+/// serves.
+pub fn accepted(api_prefix: &str, id: String) -> Response {
+    let location = format!("{}/jobs/{}", api_prefix, id);
+    running(RunningJob {
+        id,
+        status: "running",
+        location,
+    })
+}
+
+fn running(job: RunningJob) -> Response {
+    let mut response = (StatusCode::ACCEPTED, Json(&job)).into_response();
+    if let Ok(location) = HeaderValue::from_str(&job.location) {
+        response.headers_mut().insert(LOCATION, location);
+    }
+    response
+}
+
+/// Job polling, served under the API prefix.
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new().route("/jobs/{id}", get(get_job))
+}
+
+/// Whose credentials a request is served with: "user:" and the login of the signed-in user whose
+/// token fetches for it, otherwise "key:" and the id of its API key, or "anonymous".
+async fn job_owner(state: &AppState, headers: &HeaderMap) -> String {
+    if let Some(auth) = &state.auth {
+        if let Some(credentials) = auth.credentials(&CookieJar::from_headers(headers)).await {
+            return format!("user:{}", credentials.login);
+        }
+    }
+    match state.api_keys.key_from_headers(headers) {
+        Some(key) => format!("key:{}", key.id),
+        None => "anonymous".to_string(),
+    }
+}
+
+/// `path` with the job ID replaced, if it's the path of a job, so that logging it doesn't
+/// reveal the ID.
+pub fn redact_job_id(path: &str) -> Option<String> {
+    let (prefix, id) = path.rsplit_once("/jobs/")?;
+    if id.is_empty() || id.contains('/') || !(prefix.ends_with("/api") || prefix.ends_with("/v1")) {
+        return None;
+    }
+    Some(format!("{}/jobs/{{id}}", prefix))
+}
+
+/// The job's response once it has finished, until then 202 like when it started. Jobs started
+/// by someone else are reported as not found.
+async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+) -> Result<Response, ApiError> {
+    let caller = job_owner(&state, &headers).await;
+    let job = state.jobs.jobs.get(&id).await.filter(|e| e.owner == caller);
+    match job.map(|entry| entry.job) {
+        None => Err(ApiError::not_found("Job not found")),
+        Some(Job::Running) => Ok(running(RunningJob {
+            location: uri.path().to_string(),
+            id,
+            status: "running",
+        })),
+        Some(Job::Failed) => Err(ApiError::internal()),
+        Some(Job::Finished(finished)) => {
+            let mut response = (finished.status, finished.body.clone()).into_response();
+            *response.headers_mut() = finished.headers.clone();
+            Ok(response)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+
+    #[test]
+    fn test_client_timeout() {
+        let now = "2026-03-01T12:00:00Z".parse().unwrap();
+        let timeout = |request: Request| client_timeout(&request, now);
+        let get = |uri: &str| Request::get(uri).body(Body::empty()).unwrap();
+        let with_deadline = |deadline: &str| {
+            Request::get("/repos/acme/widgets/metrics")
+                .header(DEADLINE_HEADER, deadline)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        assert_eq!(timeout(get("/repos/acme/widgets/metrics")).unwrap(), None);
+        assert_eq!(
+            timeout(get("/repos/acme/widgets/metrics?timeout=2.5")).unwrap(),
+            Some(StdDuration::from_millis(2_500))
+        );
+        assert!(timeout(get("/repos/acme/widgets/metrics?timeout=-1")).is_err());
+        assert!(timeout(get("/repos/acme/widgets/metrics?timeout=soon")).is_err());
+        assert_eq!(
+            timeout(with_deadline("2026-03-01T13:00:03+01:00")).unwrap(),
+            Some(StdDuration::from_secs(3))
+        );
+        assert_eq!(
+            timeout(with_deadline("2026-03-01T11:00:00Z")).unwrap(),
+            Some(StdDuration::ZERO)
+        );
+        assert!(timeout(with_deadline("in a minute")).is_err());
+    }
+
+    #[test]
+    fn test_redact_job_id() {
+        assert_eq!(
+            redact_job_id("/api/v1/jobs/0123456789abcdef").as_deref(),
+            Some("/api/v1/jobs/{id}")
+        );
+        assert_eq!(
+            redact_job_id("/payments/api/v1/jobs/0123456789abcdef").as_deref(),
+            Some("/payments/api/v1/jobs/{id}")
+        );
+        assert_eq!(redact_job_id("/api/v1/repos/acme/widgets/metrics"), None);
+        assert_eq!(redact_job_id("/api/v1/repos/jobs/widgets/metrics"), None);
+    }
+}
@@ -0,0 +1,2136 @@
+//! The RepoFlow HTTP server: the API, login, static frontend and middleware around the
+//! `repoflow-core` metrics engine. The binary only adds configuration loading, logging and the
+//! listener on top of [`create_app`].
+
+mod admin;
+mod alerts;
+mod api_keys;
+mod audit;
+mod auth;
+mod bot;
+mod build_info;
+mod calendar;
+mod chart;
+pub mod cli;
+mod client_ip;
+mod encoding;
+mod error;
+mod extract;
+mod feed;
+mod groups;
+mod http_cache;
+mod jobs;
+pub mod listener;
+mod narrative;
+mod popularity;
+mod portfolio;
+mod report;
+mod request_id;
+mod static_files;
+mod teams;
+mod telemetry;
+mod tenants;
+#[cfg(test)]
+mod test_support;
+
+use crate::extract::{Json, Path, Query};
+use anyhow::Context;
+use axum::{
+    extract::{OriginalUri, State},
+    http::HeaderValue,
+    middleware,
+    response::IntoResponse,
+    routing::get,
+    Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use config::{AppConfig, PopularRepo};
+use encoding::{Encoded, Envelope, Fields, Format, PreEncodedEnvelope, StreamedJson};
+use error::ApiError;
+use repoflow_core::domain::RepoId;
+use repoflow_core::{config, metrics, service, source, upstream};
+use serde::{Deserialize, Serialize};
+use service::MetricsService;
+use std::sync::Arc;
+use tower::layer::util::Identity;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::util::{option_layer, Either};
+use tower_http::trace::TraceLayer;
+
+#[derive(Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    service: &'static str,
+    version: &'static str,
+}
+
+#[derive(Serialize)]
+struct ReadinessResponse {
+    /// "ready", or "preloading" while popular repositories are still being fetched.
+    status: &'static str,
+}
+
+/// Query parameters accepted by the repository metrics endpoint.
+#[derive(Deserialize)]
+struct MetricsParams {
+    /// Optional rescaling of the time series for cross-repository comparison.
+    normalize: Option<metrics::Normalization>,
+    /// Rolling window size in days; defaults to `METRICS_WINDOW_SIZE`.
+    window: Option<i64>,
+    /// Counts only the pull requests opened by this team's members.
+    team: Option<String>,
+}
+
+/// Freshness of the data behind a metrics response.
+#[derive(Serialize)]
+struct MetricsMeta {
+    /// When the pull requests were fetched from GitHub.
+    fetched_at: chrono::DateTime<chrono::Utc>,
+    /// How long the metrics have been cached, in seconds.
+    cache_age_seconds: i64,
+    /// Size of the rolling window the counts cover, in days.
+    window_days: i64,
+    /// False when GitHub's page limit cut the fetch short, so older pull requests are missing.
+    data_complete: bool,
+    /// Machine-readable reasons the data may be inaccurate.
+    warnings: Vec<MetricsWarning>,
+    /// When truncated, at most this many pull requests were left unfetched.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unfetched_pull_requests: Option<u64>,
+    /// How much of the data was fetched, as a score and its ingredients.
+    completeness: metrics::Completeness,
+    /// Present while refreshes are paused for maintenance, for a banner explaining stale data.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    maintenance: Option<service::Maintenance>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case")]
+enum MetricsWarning {
+    /// `MAX_GITHUB_API_PAGES` was reached before the start of the fetch window.
+    TruncatedAtPageLimit,
+    /// The metrics come from the startup snapshot, until the repository is first fetched.
+    FromSnapshot,
+}
+
+/// Orderings for the popular repositories list, healthiest first.
+#[derive(Deserialize, Clone, Copy)]
+#[serde(rename_all = "snake_case")]
+enum PopularSort {
+    /// Smallest gap between opened and merged pull requests first.
+    Spread,
+    /// Highest share of opened pull requests merged first.
+    MergeRate,
+    /// Alphabetical by display name, or "owner/repo" when there is none.
+    Name,
+}
+
+/// Query parameters accepted by the popular repositories endpoint.
+#[derive(Deserialize)]
+struct PopularParams {
+    sort: Option<PopularSort>,
+    /// Maximum number of repositories to return.
+    limit: Option<usize>,
+    /// Comma-separated extra data to embed; only "summary" is supported.
+    include: Option<String>,
+}
+
+/// A popular repository, with its cached summary when requested.
+#[derive(Serialize)]
+struct PopularRepoResponse {
+    #[serde(flatten)]
+    repo: PopularRepo,
+    /// `null` when requested but the repository hasn't been fetched yet.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    summary: Option<Option<metrics::SummaryMetrics>>,
+}
+
+impl encoding::Encodable for Vec<PopularRepoResponse> {}
+
+impl encoding::Encodable for metrics::BranchStats {}
+
+impl encoding::Encodable for source::SecurityAlerts {}
+
+/// Shared application state accessible to all request handlers.
+struct AppState {
+    /// Service for querying repository metrics.
+    service: MetricsService,
+    /// Application configuration loaded from environment variables.
+    config: AppConfig,
+    /// GitHub OAuth login, present only when an OAuth App is configured.
+    auth: Option<auth::AuthService>,
+    /// Record of who accessed which endpoints.
+    audit: audit::AuditLog,
+    /// Consumer API keys and their quota usage.
+    api_keys: api_keys::ApiKeyRegistry,
+    /// When the server started, for uptime reporting.
+    started_at: chrono::DateTime<chrono::Utc>,
+    /// Per-route latency and error statistics.
+    telemetry: telemetry::RouteMetrics,
+    /// How often each repository is requested.
+    popularity: popularity::ViewCounter,
+    /// Named groups of repositories reported as one.
+    groups: groups::GroupStore,
+    /// Teams of GitHub users, for per-team views.
+    teams: teams::TeamStore,
+    /// Replies to report commands in issue comments, present only when webhooks are enabled.
+    bot: Option<bot::Responder>,
+    /// Requests still running after their client's deadline.
+    jobs: Arc<jobs::JobStore>,
+}
+
+impl AppState {
+    /// Initializes the application state, including the metrics service.
+    pub fn new(config: AppConfig) -> anyhow::Result<Self> {
+        let service = MetricsService::new(&config)?;
+        Self::with_service(config, service)
+    }
+
+    /// Initializes the application state around an existing metrics service.
+    pub fn with_service(config: AppConfig, service: MetricsService) -> anyhow::Result<Self> {
+        let auth = auth::AuthService::new(&config)?;
+        let audit = audit::AuditLog::new(config.audit_log_path.clone(), config.audit_log_max_bytes);
+        let api_keys = api_keys::ApiKeyRegistry::new(config.api_keys.clone());
+        let groups =
+            groups::GroupStore::new(config.repo_groups.clone(), config.repo_groups_file.clone());
+        let teams = teams::TeamStore::new(config.teams.clone(), config.teams_file.clone());
+        let bot = bot::Responder::new(&config).unwrap_or_else(|e| {
+            tracing::error!("Webhook responder disabled: {:#}", e);
+            None
+        });
+        let jobs = Arc::new(jobs::JobStore::new(&config));
+        Ok(Self {
+            service,
+            config,
+            auth,
+            audit,
+            api_keys,
+            started_at: chrono::Utc::now(),
+            telemetry: telemetry::RouteMetrics::default(),
+            popularity: popularity::ViewCounter::default(),
+            groups,
+            teams,
+            bot,
+            jobs,
+        })
+    }
+}
+
+/// Builds the server for `config`: the default app plus one per tenant, each with its own state.
+/// Fails if any of them can't reach GitHub.
+pub async fn create_app(config: AppConfig) -> anyhow::Result<Router> {
+    if config.github_token.is_none() && config.fetches_from_github() {
+        tracing::warn!("Running without GITHUB_TOKEN. Rate limits will be strict.");
+    }
+    if config.admin_token.is_some() && config.popular_repos_file.is_none() {
+        tracing::warn!(
+            "Running without POPULAR_REPOS_FILE. Popular repositories changed through the admin API will be lost on restart."
+        );
+    }
+    if config.admin_token.is_some() && config.alert_rules_file.is_none() {
+        tracing::warn!(
+            "Running without ALERT_RULES_FILE. Alert rules created through the API will be lost on restart."
+        );
+    }
+
+    let mut tenant_apps = Vec::new();
+    for tenant in &config.tenants {
+        if tenant.github_token.is_none() && config.fetches_from_github() {
+            tracing::warn!(
+                "Tenant '{}' has no github_token. Its rate limits will be strict.",
+                tenant.name
+            );
+        }
+        let state = start_state(config.for_tenant(tenant))
+            .await
+            .with_context(|| format!("tenant '{}'", tenant.name))?;
+        tenant_apps.push(tenants::TenantApp::new(
+            tenant,
+            &state.config,
+            app(state.clone()),
+        ));
+    }
+    let state = start_state(config).await?;
+
+    Ok(tenants::router(app(state), tenant_apps))
+}
+
+/// Initializes the state for `config` and checks that it can reach GitHub.
+async fn start_state(config: AppConfig) -> anyhow::Result<Arc<AppState>> {
+    let state = AppState::new(config).context("failed to initialize application state")?;
+    state
+        .service
+        .check_github_access()
+        .await
+        .context("GitHub self-check failed")?;
+    Ok(Arc::new(state))
+}
+
+/// Builds the complete HTTP application: API routes, auth, static files and middleware.
+fn app(state: Arc<AppState>) -> Router {
+    let api = api_routes(&state);
+
+    let mut app = Router::new()
+        .nest("/api/v1", api.clone())
+        .nest(
+            "/api",
+            api.layer(middleware::from_fn(deprecated_unversioned_api)),
+        )
+        .route("/metrics", get(telemetry::prometheus));
+
+    if state.auth.is_some() {
+        app = app.merge(auth::router());
+    } else {
+        tracing::info!("GitHub OAuth is not configured. Login is disabled.");
+    }
+
+    let app = app
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            telemetry::track,
+        ))
+        .merge(static_files::router(&state.config))
+        .with_state(state.clone())
+        // Inside the base path, so it sees the same paths whatever the app is mounted under.
+        .layer(middleware::from_fn_with_state(state.clone(), audit::record));
+    // Unlike `nest`, `nest_service` also routes "/base/" to the app's root.
+    let app = match state.config.base_path.as_str() {
+        "" => app,
+        base_path => Router::new().nest_service(base_path, app),
+    };
+
+    app.layer(TraceLayer::new_for_http().make_span_with(request_span))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            client_ip::resolve_client_ip,
+        ))
+        .layer(middleware::from_fn(request_id::assign))
+}
+
+/// Set, to when maintenance began, on API responses during maintenance.
+const MAINTENANCE_HEADER: &str = "x-maintenance";
+
+/// Routes served under the `/api/v1` prefix.
+fn api_routes(state: &Arc<AppState>) -> Router<Arc<AppState>> {
+    let repo_routes = Router::new()
+        .route(
+            "/repos/{owner}/{repo}/metrics",
+            get(get_repo_metrics)
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    jobs::defer_cold_fetch,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    state.clone(),
+                    jobs::defer_past_deadline,
+                )),
+        )
+        .route("/repos/{owner}/{repo}/branches", get(get_repo_branches))
+        .route("/repos/{owner}/{repo}/security", get(get_repo_security))
+        .route("/repos/{owner}/{repo}/heatmap", get(get_repo_heatmap))
+        .route("/repos/{owner}/{repo}/chart.svg", get(get_repo_chart))
+        .merge(teams::repo_router())
+        .merge(chart::repo_router())
+        .merge(calendar::repo_router())
+        .merge(feed::repo_router())
+        // Only the routes above name a repository.
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            popularity::count,
+        ))
+        .route("/repos/popular", get(get_popular_repos))
+        .merge(groups::router())
+        .merge(portfolio::router())
+        .merge(alerts::router())
+        .merge(jobs::router())
+        // Inside the timeout, so time spent waiting for a turn counts towards it.
+        .route_layer(concurrency_limit(state.config.metrics_concurrency_limit))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            request_timeout,
+        ))
+        .route_layer(middleware::from_fn_with_state(
+            state.clone(),
+            api_keys::enforce_quota,
+        ));
+
+    let mut api = Router::new()
+        .route("/health", get(health_check))
+        .route("/health/ready", get(readiness_check))
+        .route("/version", get(get_version))
+        .merge(repo_routes);
+
+    if state.bot.is_some() {
+        api = api.merge(bot::router());
+    }
+    if state.config.admin_token.is_some() {
+        api = api.merge(admin::router(state.clone()));
+    } else {
+        tracing::info!("ADMIN_TOKEN is not set. Admin endpoints are disabled.");
+    }
+
+    api.layer(middleware::from_fn_with_state(
+        state.clone(),
+        maintenance_banner,
+    ))
+}
+
+/// Caps the requests a route group serves at once at `limit`, or not at all when it's 0. Each group
+/// has its own limit, shared by all of its routes.
+fn concurrency_limit(limit: usize) -> Either<GlobalConcurrencyLimitLayer, Identity> {
+    option_layer((limit > 0).then(|| GlobalConcurrencyLimitLayer::new(limit)))
+}
+
+/// Marks responses from the legacy unversioned `/api/...` aliases as deprecated (RFC 9745),
+/// pointing clients at the `/api/v1/...` successor.
+async fn deprecated_unversioned_api(
+    OriginalUri(uri): OriginalUri,
+    request: axum::extract::Request,
+    next: middleware::Next,
+) -> axum::response::Response {
+    // The path below `/api`, and the prefix up to it, including any base path or tenant's.
+    let rest = request.uri().path().to_string();
+    let api_prefix = jobs::api_prefix(&uri, &request);
+    let mut response = next.run(request).await;
+
+    let successor = (!api_prefix.is_empty())
+        .then(|| format!("<{}/v1{}>; rel=\"successor-version\"", api_prefix, rest));
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Some(link) = successor.and_then(|link| HeaderValue::from_str(&link).ok()) {
+        headers.insert(axum::http::header::LINK, link);
+    }
+
+    response
+}
+
+/// Flags every API response with `x-maintenance` while refreshes are paused, so clients can show
+/// a banner without asking for the maintenance state.
+async fn maintenance_banner(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+    next: middleware::Next,
+) -> axum::response::Response {
+    let mut response = next.run(request).await;
+    if let Some(maintenance) = state.service.maintenance() {
+        if let Ok(since) = HeaderValue::from_str(&maintenance.since.to_rfc3339()) {
+            response.headers_mut().insert(MAINTENANCE_HEADER, since);
+        }
+    }
+    response
+}
+
+/// Responds with 504 when a handler runs longer than `REQUEST_TIMEOUT_SECONDS`, rather than
+/// holding the connection open while a slow GitHub fetch drags on.
+async fn request_timeout(
+    State(state): State<Arc<AppState>>,
+    request: axum::extract::Request,
+    next: middleware::Next,
+) -> Result<axum::response::Response, ApiError> {
+    let limit = std::time::Duration::from_secs(state.config.request_timeout_seconds);
+    tokio::time::timeout(limit, next.run(request))
+        .await
+        .map_err(|_| {
+            tracing::warn!("Request timed out after {:?}", limit);
+            timed_out()
+        })
+}
+
+/// The error for a request that ran longer than the time limit.
+fn timed_out() -> ApiError {
+    ApiError::new(
+        axum::http::StatusCode::GATEWAY_TIMEOUT,
+        "timeout",
+        "Fetching from GitHub is taking too long. The fetch continues in the background, so \
+         try again in a minute.",
+    )
+}
+
+fn request_span(request: &axum::extract::Request) -> tracing::Span {
+    let client_ip = request
+        .extensions()
+        .get::<client_ip::ClientIp>()
+        .and_then(|client_ip::ClientIp(ip)| *ip);
+    let request_id = request
+        .extensions()
+        .get::<request_id::RequestId>()
+        .map(|request_id::RequestId(id)| id.as_str());
+    tracing::debug_span!(
+        "request",
+        request_id = ?request_id,
+        method = %request.method(),
+        uri = %jobs::redact_job_id(request.uri().path())
+            .unwrap_or_else(|| request.uri().to_string()),
+        client_ip = ?client_ip,
+    )
+}
+
+async fn health_check() -> Json<HealthResponse> {
+    Json(HealthResponse {
+        status: "ok",
+        service: "repoflow-backend",
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+/// Reports whether the instance should receive traffic. With `READINESS_REQUIRES_PRELOAD`, that is
+/// once popular repositories are preloaded or served from the startup snapshot, or
+/// `READINESS_TIMEOUT_SECONDS` have passed.
+async fn readiness_check(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let waited = (chrono::Utc::now() - state.started_at).num_seconds();
+    let ready = !state.config.readiness_requires_preload
+        || state.service.preload_complete()
+        || state.service.seeded()
+        || waited >= state.config.readiness_timeout_seconds as i64;
+    if ready {
+        (
+            axum::http::StatusCode::OK,
+            Json(ReadinessResponse { status: "ready" }),
+        )
+    } else {
+        (
+            axum::http::StatusCode::SERVICE_UNAVAILABLE,
+            Json(ReadinessResponse {
+                status: "preloading",
+            }),
+        )
+    }
+}
+
+async fn get_version() -> Json<build_info::BuildInfo> {
+    Json(build_info::BuildInfo::current())
+}
+
+async fn get_popular_repos(
+    Query(params): Query<PopularParams>,
+    format: Format,
+    State(state): State<Arc<AppState>>,
+) -> Result<Encoded<Vec<PopularRepoResponse>>, ApiError> {
+    let mut include_summary = false;
+    for include in params.include.iter().flat_map(|s| s.split(',')) {
+        match include.trim() {
+            "summary" => include_summary = true,
+            other => {
+                return Err(ApiError::bad_request(format!(
+                    "Unknown include '{}'",
+                    other
+                )))
+            }
+        }
+    }
+
+    let popular_repos = state.service.popular_repos().await;
+    let mut keyed = Vec::with_capacity(popular_repos.len());
+    for popular in popular_repos {
+        let summary = if include_summary || params.sort.is_some() {
+            state.service.cached_summary(&popular.id).await
+        } else {
+            None
+        };
+        keyed.push((summary, popular));
+    }
+
+    // Repositories that haven't been fetched yet sort last under the metric orderings.
+    match params.sort {
+        Some(PopularSort::Spread) => keyed
+            .sort_by_key(|(summary, _)| summary.as_ref().map_or(i64::MAX, |s| s.current_spread)),
+        Some(PopularSort::MergeRate) => keyed
+            .sort_by_key(|(summary, _)| std::cmp::Reverse(summary.as_ref().map(|s| s.merge_rate))),
+        Some(PopularSort::Name) => keyed.sort_by_cached_key(|(_, popular)| {
+            popular
+                .display_name
+                .clone()
+                .unwrap_or_else(|| popular.id.to_string())
+                .to_lowercase()
+        }),
+        None => {}
+    }
+    if let Some(limit) = params.limit {
+        keyed.truncate(limit);
+    }
+
+    Ok(Encoded::new(
+        format,
+        keyed
+            .into_iter()
+            .map(|(summary, repo)| PopularRepoResponse {
+                repo,
+                summary: include_summary.then_some(summary),
+            })
+            .collect(),
+    ))
+}
+
+/// Rejected here rather than sent to GitHub, whose error for a malformed name is unhelpful.
+fn parse_repo_id(owner: &str, repo: &str) -> Result<RepoId, ApiError> {
+    RepoId::try_from((owner, repo)).map_err(|e| {
+        ApiError::new(
+            axum::http::StatusCode::BAD_REQUEST,
+            "invalid_repo_name",
+            format!("'{}/{}' is not a valid repository: {}", owner, repo, e),
+        )
+    })
+}
+
+/// Branch statistics, gated like security alerts so the server's token doesn't reveal private
+/// repositories to anyone who asks.
+async fn get_repo_branches(
+    Path((owner, repo)): Path<(String, String)>,
+    format: Format,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    headers: axum::http::HeaderMap,
+) -> Result<Encoded<metrics::BranchStats>, ApiError> {
+    let repo_id = parse_repo_id(&owner, &repo)?;
+    let user = github_caller(&state, &headers, &jar).await?;
+    match state.service.branch_stats(&repo_id, user.as_ref()).await {
+        Ok(Some(stats)) => Ok(Encoded::new(format, stats)),
+        Ok(None) => Err(ApiError::new(
+            axum::http::StatusCode::NOT_IMPLEMENTED,
+            "branches_unavailable",
+            "Branch statistics need a GITHUB_TOKEN",
+        )),
+        Err(e) => Err(upstream_error(&state, &repo_id, "branches", user.as_ref(), e).await),
+    }
+}
+
+/// Security alerts, which GitHub only shows a repository's admins. Admins of this server are
+/// served with its token; signed-in users with their own, so they only see what GitHub shows
+/// them. API keys carry no GitHub identity, so they aren't enough.
+async fn get_repo_security(
+    Path((owner, repo)): Path<(String, String)>,
+    format: Format,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    headers: axum::http::HeaderMap,
+) -> Result<Encoded<source::SecurityAlerts>, ApiError> {
+    let repo_id = parse_repo_id(&owner, &repo)?;
+    let user = github_caller(&state, &headers, &jar).await?;
+    match state.service.security_alerts(&repo_id, user.as_ref()).await {
+        Ok(Some(alerts)) => Ok(Encoded::new(format, alerts)),
+        Ok(None) => Err(ApiError::new(
+            axum::http::StatusCode::NOT_IMPLEMENTED,
+            "security_unavailable",
+            "Security alerts need a GITHUB_TOKEN",
+        )),
+        Err(e) => Err(upstream_error(&state, &repo_id, "security alerts", user.as_ref(), e).await),
+    }
+}
+
+/// Who a request fetching non-public repository data from GitHub is made for: the server, with
+/// its own token, for the admin token, or a signed-in user. Anyone else is refused.
+async fn github_caller(
+    state: &AppState,
+    headers: &axum::http::HeaderMap,
+    jar: &CookieJar,
+) -> Result<Option<service::UserCredentials>, ApiError> {
+    if admin::is_admin(state, headers) {
+        return Ok(None);
+    }
+    let user = match &state.auth {
+        Some(auth) => auth.credentials(jar).await,
+        None => None,
+    };
+    user.map(Some)
+        .ok_or_else(|| ApiError::unauthorized("Sign in or use the admin token"))
+}
+
+/// The rolling window size `requested`, or the configured one, if metrics are kept for it.
+fn window_days(state: &AppState, requested: Option<i64>) -> Result<i64, ApiError> {
+    let window_days = requested.unwrap_or(state.config.metrics_window_size);
+    let window_sizes = state.service.window_sizes();
+    if !window_sizes.contains(&window_days) {
+        let sizes: Vec<String> = window_sizes.iter().map(i64::to_string).collect();
+        return Err(ApiError::bad_request(format!(
+            "window must be one of {} days",
+            sizes.join(", ")
+        )));
+    }
+    Ok(window_days)
+}
+
+/// The error reported for a failed fetch of `what`, made with `user`'s token if given.
+async fn upstream_error(
+    state: &AppState,
+    repo_id: &RepoId,
+    what: &str,
+    user: Option<&service::UserCredentials>,
+    e: anyhow::Error,
+) -> ApiError {
+    tracing::error!("Failed to fetch {} for {}: {}", what, repo_id, e);
+    let class = upstream::classify(&e);
+    let error = ApiError::from(class);
+    match class {
+        upstream::ErrorClass::RateLimited => {
+            error.with_retry_after(state.service.retry_after(user).await)
+        }
+        upstream::ErrorClass::Overloaded => {
+            error.with_retry_after(upstream::OVERLOADED_RETRY_AFTER_SECONDS)
+        }
+        upstream::ErrorClass::CircuitOpen => {
+            error.with_retry_after(state.config.github_circuit_cooldown_seconds)
+        }
+        _ => error,
+    }
+}
+
+async fn get_repo_metrics(
+    Path((owner, repo)): Path<(String, String)>,
+    Query(params): Query<MetricsParams>,
+    format: Format,
+    fields: Fields,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+    headers: axum::http::HeaderMap,
+) -> Result<axum::response::Response, ApiError> {
+    let repo_id = parse_repo_id(&owner, &repo)?;
+
+    let window_days = window_days(&state, params.window)?;
+    let team_members = match &params.team {
+        Some(team) => Some(
+            state
+                .teams
+                .members(team)
+                .await
+                .ok_or_else(|| ApiError::not_found("Team not found"))?,
+        ),
+        None => None,
+    };
+
+    let credentials = match &state.auth {
+        Some(auth) => auth.credentials(&jar).await,
+        None => None,
+    };
+    match fetch_metrics(&state, &repo_id, credentials.clone()).await {
+        Ok(cached) => {
+            let now = chrono::Utc::now();
+            let cache_headers = http_cache::headers(
+                cached.fetched_at,
+                state.config.cache_ttl_seconds,
+                http_cache::is_private(&headers, state.config.require_api_key),
+                now,
+            );
+            if http_cache::is_not_modified(&headers, cached.fetched_at) {
+                return Ok((axum::http::StatusCode::NOT_MODIFIED, cache_headers).into_response());
+            }
+
+            // Every entry holds each of `window_sizes()`, which was checked above.
+            let Some(windowed) = cached.window(window_days) else {
+                tracing::error!(
+                    "Cached metrics for {} lack a {}-day window",
+                    repo_id,
+                    window_days
+                );
+                return Err(ApiError::internal());
+            };
+            let meta = MetricsMeta {
+                fetched_at: cached.fetched_at,
+                cache_age_seconds: (now - cached.fetched_at).num_seconds(),
+                window_days,
+                data_complete: cached.complete,
+                warnings: [
+                    (!cached.complete).then_some(MetricsWarning::TruncatedAtPageLimit),
+                    cached.from_snapshot.then_some(MetricsWarning::FromSnapshot),
+                ]
+                .into_iter()
+                .flatten()
+                .collect(),
+                unfetched_pull_requests: cached.unfetched,
+                completeness: cached.completeness.clone(),
+                maintenance: state.service.maintenance(),
+            };
+            tracing::debug!(repo_id = %repo_id, "Returning metrics");
+
+            // The common case can reuse the JSON serialized when the metrics were cached.
+            if format == Format::Json
+                && fields.is_empty()
+                && params.normalize.is_none()
+                && team_members.is_none()
+            {
+                let body = PreEncodedEnvelope {
+                    data: windowed.json.clone(),
+                    meta,
+                };
+                return Ok((cache_headers, body).into_response());
+            }
+
+            // A team's metrics are calculated from the cached pull requests as they're asked for.
+            let team_metrics;
+            let metrics = match &team_members {
+                Some(members) => {
+                    team_metrics = state.service.metrics_where(
+                        &cached,
+                        window_days,
+                        teams::authored_by(members),
+                    );
+                    &team_metrics
+                }
+                None => &windowed.metrics,
+            };
+
+            // Only normalization needs its own copy of the cached metrics.
+            let normalized;
+            let metrics = match params.normalize {
+                Some(mode) => {
+                    let mut metrics = metrics.clone();
+                    metrics.normalized =
+                        Some(metrics::normalize_series(&metrics.time_series, mode));
+                    // Long normalized series are serialized as they are sent instead.
+                    if format == Format::Json
+                        && fields.is_empty()
+                        && windowed.json.len() >= encoding::STREAMING_THRESHOLD
+                    {
+                        let body = StreamedJson(Envelope {
+                            data: metrics,
+                            meta,
+                        });
+                        return Ok((cache_headers, body).into_response());
+                    }
+                    normalized = metrics;
+                    &normalized
+                }
+                None => metrics,
+            };
+            let encoded = Encoded::new(
+                format,
+                Envelope {
+                    data: metrics,
+                    meta,
+                },
+            )
+            .with_fields(fields);
+            Ok((cache_headers, encoded).into_response())
+        }
+        Err(e) => Err(upstream_error(&state, &repo_id, "PRs", credentials.as_ref(), e).await),
+    }
+}
+
+async fn get_repo_heatmap(
+    Path((owner, repo)): Path<(String, String)>,
+    format: Format,
+    fields: Fields,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<axum::response::Response, ApiError> {
+    let repo_id = parse_repo_id(&owner, &repo)?;
+    let credentials = match &state.auth {
+        Some(auth) => auth.credentials(&jar).await,
+        None => None,
+    };
+    match fetch_metrics(&state, &repo_id, credentials.clone()).await {
+        Ok(cached) => {
+            let encoded = Encoded::new(format, &cached.heatmap).with_fields(fields);
+            Ok(encoded.into_response())
+        }
+        Err(e) => Err(upstream_error(&state, &repo_id, "PRs", credentials.as_ref(), e).await),
+    }
+}
+
+/// The opened and merged series as an SVG image, for embedding in comments and READMEs.
+async fn get_repo_chart(
+    Path((owner, repo)): Path<(String, String)>,
+    State(state): State<Arc<AppState>>,
+) -> Result<axum::response::Response, ApiError> {
+    let repo_id = parse_repo_id(&owner, &repo)?;
+    // Images are embedded where no one is signed in, so only public data is drawn.
+    match fetch_metrics(&state, &repo_id, None).await {
+        Ok(cached) => Ok((
+            [(
+                axum::http::header::CONTENT_TYPE,
+                HeaderValue::from_static("image/svg+xml"),
+            )],
+            report::chart(&cached.default_window().metrics.time_series),
+        )
+            .into_response()),
+        Err(e) => Err(upstream_error(&state, &repo_id, "PRs", None, e).await),
+    }
+}
+
+/// Fetches a repository's metrics, with the signed-in user's token when there is one.
+///
+/// The fetch runs in a task of its own so that a request timeout doesn't abandon it, and a retry
+/// can be served from the cache it fills.
+async fn fetch_metrics(
+    state: &Arc<AppState>,
+    repo_id: &RepoId,
+    credentials: Option<service::UserCredentials>,
+) -> anyhow::Result<Arc<service::CachedMetrics>> {
+    let (state, repo_id) = (state.clone(), repo_id.clone());
+    tokio::spawn(async move {
+        match &credentials {
+            Some(user) => state.service.get_for_user(repo_id, user).await,
+            None => state.service.get(repo_id).await,
+        }
+    })
+    .await
+    .unwrap_or_else(|e| Err(e.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_support::{get_json, pr, send, test_app, test_config, MockPullRequestSource};
+    use axum::body::Body;
+    use axum::http::{Request, StatusCode};
+
+    #[tokio::test]
+    async fn test_health_check() {
+        let app = test_app(test_config(&[]), MockPullRequestSource::default());
+        let (status, body) = get_json(app, "/api/v1/health").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ok");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_readiness_waits_for_preload() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .slow(std::time::Duration::from_secs(10));
+        let config = test_config(&[
+            ("POPULAR_REPOS", "acme/widgets"),
+            ("READINESS_REQUIRES_PRELOAD", "true"),
+        ]);
+        let app = test_app(config, source);
+
+        let (status, body) = get_json(app.clone(), "/api/v1/health/ready").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "preloading");
+
+        tokio::time::sleep(std::time::Duration::from_secs(11)).await;
+        let (status, body) = get_json(app, "/api/v1/health/ready").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_readiness_without_preload_gate() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .slow(std::time::Duration::from_secs(10));
+        let preloading = [("POPULAR_REPOS", "acme/widgets")];
+
+        let app = test_app(test_config(&preloading), source.clone());
+        let (status, _) = get_json(app, "/api/v1/health/ready").await;
+        assert_eq!(status, StatusCode::OK);
+
+        // A timeout that has already passed stops the preload from holding back traffic.
+        let config = test_config(&[
+            preloading[0],
+            ("READINESS_REQUIRES_PRELOAD", "true"),
+            ("READINESS_TIMEOUT_SECONDS", "0"),
+        ]);
+        let (status, _) = get_json(test_app(config, source), "/api/v1/health/ready").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_unversioned_alias_is_deprecated() {
+        let app = test_app(test_config(&[]), MockPullRequestSource::default());
+        let request = Request::get("/api/health").body(Body::empty()).unwrap();
+        let (status, headers, _) = send(app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["deprecation"], "true");
+        assert_eq!(
+            headers["link"],
+            "</api/v1/health>; rel=\"successor-version\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_unversioned_alias_under_base_path() {
+        let config = test_config(&[("BASE_PATH", "/repoflow")]);
+        let app = test_app(config, MockPullRequestSource::default());
+        let request = Request::get("/repoflow/api/health")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, _) = send(app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            headers["link"],
+            "</repoflow/api/v1/health>; rel=\"successor-version\""
+        );
+    }
+
+    #[tokio::test]
+    async fn test_popular_repos() {
+        let config = test_config(&[("POPULAR_REPOS", "facebook/react")]);
+        let app = test_app(config, MockPullRequestSource::default());
+        let (status, body) = get_json(app, "/api/v1/repos/popular").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["owner"], "facebook");
+        assert_eq!(body[0]["repo"], "react");
+        assert!(body[0].get("display_name").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_popular_repos_sorted_by_cached_metrics() {
+        let config = test_config(&[("POPULAR_REPOS", "acme/backlog,acme/healthy,acme/cold")]);
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/backlog", vec![pr(1, 5, None), pr(2, 4, None)])
+            .with_repo("acme/healthy", vec![pr(3, 5, Some(2))]);
+        let app = test_app(config, source);
+        get_json(app.clone(), "/api/v1/repos/acme/backlog/metrics").await;
+        get_json(app.clone(), "/api/v1/repos/acme/healthy/metrics").await;
+
+        let names = |body: serde_json::Value| -> Vec<String> {
+            body.as_array()
+                .unwrap()
+                .iter()
+                .map(|r| r["repo"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let (_, body) = get_json(app.clone(), "/api/v1/repos/popular?sort=spread").await;
+        assert_eq!(names(body), ["healthy", "backlog", "cold"]);
+        let (_, body) = get_json(app.clone(), "/api/v1/repos/popular?sort=name&limit=2").await;
+        assert_eq!(names(body), ["backlog", "cold"]);
+        let (status, _) = get_json(app, "/api/v1/repos/popular?sort=stars").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_popular_repos_include_summary() {
+        let config = test_config(&[("POPULAR_REPOS", "acme/widgets,acme/cold")]);
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 5, Some(2)), pr(2, 3, None)]);
+        let app = test_app(config, source);
+        get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics").await;
+
+        let (status, body) = get_json(app.clone(), "/api/v1/repos/popular?include=summary").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body[0]["summary"]["current_opened"], 2);
+        assert_eq!(body[0]["summary"]["merge_rate"], 50);
+        assert!(body[1]["summary"].is_null());
+
+        let (_, body) = get_json(app.clone(), "/api/v1/repos/popular").await;
+        assert!(body[0].get("summary").is_none());
+        let (status, _) = get_json(app, "/api/v1/repos/popular?include=everything").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_admin_manages_popular_repos() {
+        let config = test_config(&[("ADMIN_TOKEN", "admin-secret")]);
+        let app = test_app(config, MockPullRequestSource::default());
+        let admin = |method: &str, uri: &str, body: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("authorization", "Bearer admin-secret")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let add = admin(
+            "POST",
+            "/api/v1/admin/popular-repos",
+            r#"{"owner": "acme", "repo": "widgets", "display_name": "Widgets"}"#,
+        );
+        let (status, _, _) = send(app.clone(), add).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let (_, body) = get_json(app.clone(), "/api/v1/repos/popular").await;
+        assert_eq!(body[0]["display_name"], "Widgets");
+
+        let invalid = admin(
+            "POST",
+            "/api/v1/admin/popular-repos",
+            r#"{"owner": "-acme", "repo": "widgets"}"#,
+        );
+        let (status, _, _) = send(app.clone(), invalid).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+
+        let remove = || admin("DELETE", "/api/v1/admin/popular-repos/acme/widgets", "");
+        let (status, _, _) = send(app.clone(), remove()).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _, _) = send(app.clone(), remove()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (_, body) = get_json(app.clone(), "/api/v1/repos/popular").await;
+        assert_eq!(body, serde_json::json!([]));
+
+        let removed = admin("GET", "/api/v1/admin/popular-repos/removed", "");
+        let (_, _, body) = send(app.clone(), removed).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body[0]["display_name"], "Widgets");
+        let restore = || {
+            admin(
+                "POST",
+                "/api/v1/admin/popular-repos/acme/widgets/restore",
+                "",
+            )
+        };
+        let (status, _, _) = send(app.clone(), restore()).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = get_json(app.clone(), "/api/v1/repos/popular").await;
+        assert_eq!(body[0]["display_name"], "Widgets");
+        let (status, _, _) = send(app, restore()).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_admin_popularity() {
+        let config = test_config(&[
+            ("ADMIN_TOKEN", "admin-secret"),
+            ("POPULAR_REPOS", "acme/widgets"),
+        ]);
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .with_repo("acme/gadgets", vec![]);
+        let app = test_app(config, source);
+        for uri in [
+            "/api/v1/repos/acme/gadgets/metrics",
+            "/api/v1/repos/acme/gadgets/heatmap",
+            "/api/v1/repos/acme/widgets/metrics",
+            "/api/v1/repos/acme/missing/metrics",
+        ] {
+            get_json(app.clone(), uri).await;
+        }
+
+        let request = Request::get("/api/admin/popularity")
+            .header("authorization", "Bearer admin-secret")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let repos = body.as_array().unwrap();
+        assert_eq!(repos.len(), 2);
+        assert_eq!(repos[0]["repo"], "gadgets");
+        assert_eq!(repos[0]["requests"], 2);
+        assert_eq!(repos[0]["popular"], false);
+        assert_eq!(repos[1]["popular"], true);
+    }
+
+    #[tokio::test]
+    async fn test_repo_groups() {
+        let config = test_config(&[("ADMIN_TOKEN", "admin-secret")]);
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/billing", vec![pr(1, 5, Some(1)), pr(2, 3, None)])
+            .with_repo("acme/ledger", vec![pr(3, 2, None)]);
+        let app = test_app(config, source);
+        let request = |method: &str, uri: &str, body: &str, admin: bool| {
+            let mut builder = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json");
+            if admin {
+                builder = builder.header("authorization", "Bearer admin-secret");
+            }
+            builder.body(Body::from(body.to_string())).unwrap()
+        };
+        let body = r#"{"name": "Payments", "repos": [
+            {"owner": "acme", "repo": "billing"},
+            {"owner": "acme", "repo": "ledger"},
+            {"owner": "acme", "repo": "missing"}
+        ]}"#;
+
+        let (status, _, _) = send(app.clone(), request("POST", "/api/groups", body, false)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _, created) =
+            send(app.clone(), request("POST", "/api/groups", body, true)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let created: serde_json::Value = serde_json::from_slice(&created).unwrap();
+        let id = created["id"].as_str().unwrap();
+
+        let (status, body) = get_json(app.clone(), &format!("/api/groups/{}/metrics", id)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["summary"]["current_opened"], 3);
+        assert_eq!(body["data"]["summary"]["current_merged"], 1);
+        assert_eq!(body["meta"]["repos"].as_array().unwrap().len(), 2);
+        assert_eq!(body["meta"]["unavailable"][0]["repo"], "acme/missing");
+
+        let rename = r#"{"name": "Billing", "repos": [{"owner": "acme", "repo": "billing"}]}"#;
+        let uri = format!("/api/v1/groups/{}", id);
+        let (status, _, _) = send(app.clone(), request("PUT", &uri, rename, true)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, body) = get_json(app.clone(), "/api/v1/groups").await;
+        assert_eq!(body[0]["name"], "Billing");
+        let (status, _, _) = send(app.clone(), request("DELETE", &uri, "", true)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _) = get_json(app, &uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_cold_fetches_are_bounded() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .with_repo("acme/gadgets", vec![])
+            .slow(std::time::Duration::from_secs(10));
+        let app = test_app(test_config(&[("MAX_QUEUED_COLD_FETCHES", "1")]), source);
+
+        let first = tokio::spawn(get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics"));
+        tokio::task::yield_now().await;
+        let request = Request::get("/api/v1/repos/acme/gadgets/metrics")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, _) = send(app.clone(), request).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(headers["retry-after"], "5");
+
+        assert_eq!(first.await.unwrap().0, StatusCode::OK);
+        // The cached repository is served while the other one is fetched.
+        let second = tokio::spawn(get_json(app.clone(), "/api/v1/repos/acme/gadgets/metrics"));
+        tokio::task::yield_now().await;
+        let (status, _) = get_json(app, "/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(second.await.unwrap().0, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_mode() {
+        let config = test_config(&[("ADMIN_TOKEN", "admin-secret")]);
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", vec![]);
+        let app = test_app(config, source);
+        let maintenance = |body: &str| {
+            Request::post("/api/admin/maintenance")
+                .header("authorization", "Bearer admin-secret")
+                .header("content-type", "application/json")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+
+        let (status, _, body) = send(
+            app.clone(),
+            maintenance(r#"{"enabled": true, "reason": "GitHub is degraded"}"#),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["enabled"], true);
+        let request = Request::get("/api/v1/repos/acme/widgets/metrics")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(app.clone(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers.contains_key("x-maintenance"));
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["meta"]["maintenance"]["reason"], "GitHub is degraded");
+
+        let (_, _, body) = send(app.clone(), maintenance(r#"{"enabled": false}"#)).await;
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body, serde_json::json!({"enabled": false}));
+        let request = Request::get("/api/v1/health").body(Body::empty()).unwrap();
+        let (_, headers, _) = send(app, request).await;
+        assert!(!headers.contains_key("x-maintenance"));
+    }
+
+    #[tokio::test]
+    async fn test_holiday_calendar_upload() {
+        let path =
+            std::env::temp_dir().join(format!("repoflow-holidays-{}.ics", rand::random::<u64>()));
+        let config = test_config(&[
+            ("ADMIN_TOKEN", "admin-secret"),
+            ("HOLIDAYS_FILE", path.to_str().unwrap()),
+        ]);
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", vec![]);
+        let app = test_app(config, source);
+        let upload = |ics: String| {
+            Request::put("/api/admin/holidays")
+                .header("authorization", "Bearer admin-secret")
+                .header("content-type", "text/calendar")
+                .body(Body::from(ics))
+                .unwrap()
+        };
+        let yesterday = (chrono::Utc::now() - chrono::Duration::days(1)).date_naive();
+        let ics = format!(
+            "BEGIN:VCALENDAR\r\nBEGIN:VEVENT\r\nDTSTART;VALUE=DATE:{}\r\nEND:VEVENT\r\nEND:VCALENDAR\r\n",
+            yesterday.format("%Y%m%d")
+        );
+
+        let (status, _, body) = send(app.clone(), upload(ics.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["holidays"], serde_json::json!([yesterday.to_string()]));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), ics);
+        let (status, body) = get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+        let holidays: Vec<&str> = body["data"]["time_series"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .filter(|point| point["is_holiday"] == true)
+            .map(|point| point["date"].as_str().unwrap())
+            .collect();
+        assert_eq!(holidays, [yesterday.to_string()]);
+
+        let (status, _, _) = send(app, upload("not a calendar".to_string())).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), ics);
+        let _ = std::fs::remove_file(path);
+    }
+
+    #[tokio::test]
+    async fn test_alert_rules() {
+        let config = test_config(&[("ADMIN_TOKEN", "admin-secret")]);
+        let app = test_app(config, MockPullRequestSource::default());
+        let request = |method: &str, uri: &str, body: &str| {
+            Request::builder()
+                .method(method)
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("authorization", "Bearer admin-secret")
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let body = r#"{"repo": {"owner": "acme", "repo": "widgets"}, "metric": "spread",
+            "comparator": "above", "threshold": 10,
+            "channel": {"type": "webhook", "url": "https://hooks.example.com/flow"}}"#;
+
+        let (status, _) = get_json(app.clone(), "/api/v1/alerts").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let invalid = body.replace("https://", "hooks://");
+        let (status, _, _) = send(app.clone(), request("POST", "/api/alerts", &invalid)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _, created) = send(app.clone(), request("POST", "/api/alerts", body)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let created: serde_json::Value = serde_json::from_slice(&created).unwrap();
+        assert_eq!(created["cooldown_minutes"], 60);
+        let uri = format!("/api/v1/alerts/{}", created["id"].as_str().unwrap());
+
+        let lower = body.replace("above", "below");
+        let (status, _, _) = send(app.clone(), request("PUT", &uri, &lower)).await;
+        assert_eq!(status, StatusCode::OK);
+        let (_, _, listed) = send(app.clone(), request("GET", "/api/v1/alerts", "")).await;
+        let listed: serde_json::Value = serde_json::from_slice(&listed).unwrap();
+        assert_eq!(listed[0]["comparator"], "below");
+        let history = format!("{}/history?repo=acme/widgets", uri);
+        let (status, _, checks) = send(app.clone(), request("GET", &history, "")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(checks, b"[]");
+        let (status, _, _) = send(app.clone(), request("DELETE", &uri, "")).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _, _) = send(app.clone(), request("GET", &uri, "")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _, _) = send(app, request("GET", &history, "")).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_team_views() {
+        let config = test_config(&[("ADMIN_TOKEN", "admin-secret")]);
+        let mut prs = vec![pr(1, 5, Some(1)), pr(2, 3, None), pr(3, 2, None)];
+        prs[0].author = Some("Alice".to_string());
+        prs[1].author = Some("bob".to_string());
+        let app = test_app(
+            config,
+            MockPullRequestSource::default().with_repo("acme/widgets", prs),
+        );
+        let define = |members: &str| {
+            Request::put("/api/v1/admin/teams/payments")
+                .header("authorization", "Bearer admin-secret")
+                .header("content-type", "application/json")
+                .body(Body::from(format!(r#"{{"members": {}}}"#, members)))
+                .unwrap()
+        };
+
+        let (status, _, _) = send(app.clone(), define(r#"["alice", "ALICE"]"#)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        let (status, _, _) = send(app.clone(), define(r#"["alice", "bob", "carol"]"#)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let uri = "/api/v1/repos/acme/widgets/metrics?team=payments";
+        let (status, body) = get_json(app.clone(), uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["summary"]["current_opened"], 2);
+        assert_eq!(body["data"]["longest_open"], serde_json::json!([]));
+        let uri = "/api/v1/repos/acme/widgets/metrics?team=search";
+        let (status, _) = get_json(app.clone(), uri).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, body) = get_json(app, "/api/v1/repos/acme/widgets/teams").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["teams"][0]["team"], "payments");
+        assert_eq!(body["teams"][0]["summary"]["current_merged"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_repo_metrics() {
+        let source = MockPullRequestSource::default().with_repo(
+            "acme/widgets",
+            vec![pr(1, 10, Some(5)), pr(2, 8, None), pr(3, 3, Some(1))],
+        );
+        let app = test_app(test_config(&[]), source.clone());
+
+        let (status, body) = get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["summary"]["current_opened"], 3);
+        assert_eq!(body["data"]["summary"]["current_merged"], 2);
+        assert_eq!(body["data"]["time_series"].as_array().unwrap().len(), 31);
+        assert!(body["data"].get("normalized").is_none());
+        assert_eq!(body["meta"]["window_days"], 30);
+        assert_eq!(body["meta"]["data_complete"], true);
+        assert_eq!(body["meta"]["warnings"], serde_json::json!([]));
+        assert!(body["meta"].get("unfetched_pull_requests").is_none());
+        assert!(body["meta"]["cache_age_seconds"].as_i64().unwrap() >= 0);
+
+        let (status, body) = get_json(
+            app,
+            "/api/v1/repos/acme/widgets/metrics?normalize=percent_change",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body["data"]["normalized"].is_array());
+        assert_eq!(
+            source.calls(),
+            1,
+            "second request should be served from cache"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_repo_metrics_caching_headers() {
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", vec![]);
+        let app = test_app(test_config(&[]), source);
+
+        let request = Request::get("/api/v1/repos/acme/widgets/metrics")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, _) = send(app.clone(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(headers["cache-control"]
+            .to_str()
+            .unwrap()
+            .starts_with("public, max-age="));
+        let last_modified = headers["last-modified"].clone();
+
+        let request = Request::get("/api/v1/repos/acme/widgets/metrics")
+            .header("if-modified-since", last_modified)
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::NOT_MODIFIED);
+        assert!(headers.contains_key("last-modified"));
+        assert!(body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_repo_metrics_window_variants() {
+        let source = MockPullRequestSource::default().with_repo(
+            "acme/widgets",
+            vec![pr(1, 3, None), pr(2, 10, None), pr(3, 20, None)],
+        );
+        let app = test_app(test_config(&[]), source.clone());
+
+        let (status, body) =
+            get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics?window=7").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["meta"]["window_days"], 7);
+        assert_eq!(body["data"]["summary"]["current_opened"], 1);
+
+        let (_, body) = get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(body["meta"]["window_days"], 30);
+        assert_eq!(body["data"]["summary"]["current_opened"], 3);
+        assert_eq!(source.calls(), 1, "every window comes from one fetch");
+
+        // 90 days plus the 30 displayed would reach past the 90 days of fetched pull requests.
+        let (status, body) = get_json(app, "/api/v1/repos/acme/widgets/metrics?window=90").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["message"], "window must be one of 7, 30 days");
+    }
+
+    #[tokio::test]
+    async fn test_long_series_are_streamed() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 10, Some(5)), pr(2, 3, None)]);
+        let config = test_config(&[
+            ("PR_FETCH_DAYS", "1200"),
+            ("METRICS_DAYS_TO_DISPLAY", "1000"),
+        ]);
+        let app = test_app(config, source);
+
+        for uri in [
+            "/api/v1/repos/acme/widgets/metrics",
+            "/api/v1/repos/acme/widgets/metrics?normalize=percent_change",
+        ] {
+            let request = Request::get(uri).body(Body::empty()).unwrap();
+            let (status, headers, body) = send(app.clone(), request).await;
+            assert_eq!(status, StatusCode::OK);
+            assert!(headers.get("content-length").is_none(), "{}", uri);
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+            assert_eq!(body["data"]["time_series"].as_array().unwrap().len(), 1001);
+            assert_eq!(body["meta"]["window_days"], 30);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_repo_metrics_reports_truncation() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 3, None)])
+            .truncated(250);
+        let app = test_app(test_config(&[]), source);
+        let (_, body) = get_json(app, "/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(body["meta"]["data_complete"], false);
+        assert_eq!(
+            body["meta"]["warnings"],
+            serde_json::json!(["truncated_at_page_limit"])
+        );
+        assert_eq!(body["meta"]["unfetched_pull_requests"], 250);
+        assert_eq!(body["meta"]["completeness"]["estimated_pull_requests"], 251);
+        assert_eq!(body["meta"]["completeness"]["score"], 20);
+    }
+
+    #[tokio::test]
+    async fn test_repo_metrics_content_negotiation() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 10, Some(5)), pr(2, 3, None)]);
+        let app = test_app(test_config(&[]), source);
+        let get = |accept: &str| {
+            Request::get("/api/v1/repos/acme/widgets/metrics")
+                .header("accept", accept)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let (status, headers, body) = send(app.clone(), get("text/csv")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["content-type"], "text/csv; charset=utf-8");
+        let csv = String::from_utf8(body).unwrap();
+        let mut lines = csv.lines();
+        assert_eq!(
+            lines.next(),
+            Some("date,opened,merged,closed,spread,open_count,fast_merged,backlog_merged,merged_closing_issues,issues_closed,merged_unapproved,merged_one_approval,merged_two_plus_approvals,review_comments,median_queue_hours,median_review_hours,is_holiday")
+        );
+        assert_eq!(lines.count(), 31);
+
+        let (status, headers, body) = send(app.clone(), get("application/msgpack")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["content-type"], "application/msgpack");
+        let decoded: serde_json::Value = rmp_serde::from_slice(&body).unwrap();
+        assert_eq!(decoded["data"]["summary"]["current_opened"], 2);
+
+        let (status, _, _) = send(app.clone(), get("image/png")).await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+
+        let trimmed_csv = Request::get(
+            "/api/v1/repos/acme/widgets/metrics?fields=time_series.date,time_series.spread",
+        )
+        .header("accept", "text/csv")
+        .body(Body::empty())
+        .unwrap();
+        let (_, _, body) = send(app.clone(), trimmed_csv).await;
+        let csv = String::from_utf8(body).unwrap();
+        assert_eq!(csv.lines().next(), Some("date,spread"));
+        let popular_csv = Request::get("/api/v1/repos/popular")
+            .header("accept", "text/csv")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, _) = send(app, popular_csv).await;
+        assert_eq!(status, StatusCode::NOT_ACCEPTABLE);
+    }
+
+    #[tokio::test]
+    async fn test_repo_metrics_field_selection() {
+        let source =
+            MockPullRequestSource::default().with_repo("acme/widgets", vec![pr(1, 3, None)]);
+        let app = test_app(test_config(&[]), source);
+
+        let (status, body) = get_json(
+            app.clone(),
+            "/api/v1/repos/acme/widgets/metrics?fields=summary",
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["summary"]["current_opened"], 1);
+        assert!(body["data"].get("time_series").is_none());
+        assert!(body["meta"]["fetched_at"].is_string());
+
+        let (_, body) = get_json(
+            app.clone(),
+            "/api/v1/repos/acme/widgets/metrics?fields=time_series.date",
+        )
+        .await;
+        assert_eq!(body["data"]["time_series"][0].as_object().unwrap().len(), 1);
+
+        let (status, _) = get_json(app, "/api/v1/repos/acme/widgets/metrics?fields=stars").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn test_repo_metrics_not_found() {
+        let app = test_app(test_config(&[]), MockPullRequestSource::default());
+        let request = Request::get("/api/v1/repos/acme/missing/metrics")
+            .header("x-request-id", "trace-42")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(headers["x-request-id"], "trace-42");
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "code": "repo_not_found",
+                "message": "Repository Not Found",
+                "retry_after": null,
+                "request_id": "trace-42",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_response_has_retry_after() {
+        let reset = chrono::Utc::now().timestamp() as u64 + 120;
+        let source = MockPullRequestSource::default().rate_limited(reset);
+        let app = test_app(test_config(&[]), source);
+        let request = Request::get("/api/v1/repos/acme/widgets/metrics")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let retry_after: u64 = headers["retry-after"].to_str().unwrap().parse().unwrap();
+        assert!((115..=120).contains(&retry_after), "{}", retry_after);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "github_rate_limited");
+        assert_eq!(body["retry_after"], retry_after);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_client_deadline_starts_a_job() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 5, None)])
+            .slow(std::time::Duration::from_secs(10));
+        let app = test_app(test_config(&[("API_KEYS", "ci:sk_ci:100")]), source);
+
+        let request = Request::get("/api/v1/repos/acme/widgets/metrics?timeout=2")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(app.clone(), request).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let location = headers["location"].to_str().unwrap().to_string();
+        assert_eq!(body["location"], location.as_str());
+        assert!(location.starts_with("/api/v1/jobs/"), "{}", location);
+
+        let (status, body) = get_json(app.clone(), &location).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body["status"], "running");
+        tokio::time::sleep(std::time::Duration::from_secs(10)).await;
+        let (status, body) = get_json(app.clone(), &location).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["summary"]["current_opened"], 1);
+        // Only whoever started a job can collect it.
+        let request = Request::get(&location)
+            .header("x-api-key", "sk_ci")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, _) = send(app.clone(), request).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let deadline = chrono::Utc::now() + chrono::Duration::seconds(2);
+        let request = Request::get("/api/v1/repos/acme/widgets/metrics")
+            .header("x-request-deadline", deadline.to_rfc3339())
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, _) = send(app.clone(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, _) = get_json(app, "/api/v1/jobs/0123456789abcdef").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_background_jobs_are_bounded() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .with_repo("acme/gadgets", vec![])
+            .slow(std::time::Duration::from_secs(60));
+        let app = test_app(test_config(&[("MAX_RUNNING_JOBS", "1")]), source);
+
+        let uri = "/api/v1/repos/acme/widgets/metrics?timeout=1";
+        let (status, headers, _) =
+            send(app.clone(), Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let location = headers["location"].to_str().unwrap().to_string();
+        let uri = "/api/v1/repos/acme/gadgets/metrics?timeout=1";
+        let (status, headers, _) =
+            send(app.clone(), Request::get(uri).body(Body::empty()).unwrap()).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(headers["retry-after"], "30");
+        // Only metrics requests become jobs.
+        let (status, _) = get_json(app.clone(), "/api/v1/repos/popular?timeout=0").await;
+        assert_eq!(status, StatusCode::OK);
+
+        // Jobs are held to the request time limit, which frees their slot.
+        tokio::time::sleep(std::time::Duration::from_secs(31)).await;
+        let (status, body) = get_json(app.clone(), &location).await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["code"], "timeout");
+        let (status, _) = get_json(app, uri).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_async_fetch_of_large_repos() {
+        let large: Vec<_> = (1..=4).map(|id| pr(id, 5, None)).collect();
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/large", large.clone())
+            .with_repo("acme/huge", large)
+            .with_repo("acme/small", vec![pr(5, 5, None)])
+            .counting()
+            .slow(std::time::Duration::from_secs(10));
+        let config = test_config(&[
+            ("MAX_GITHUB_API_PAGES_CEILING", "50"),
+            ("GITHUB_PER_PAGE", "1"),
+            ("MAX_RUNNING_JOBS", "1"),
+        ]);
+        let app = test_app(config, source);
+
+        let (status, headers, _) = send(
+            app.clone(),
+            Request::get("/api/v1/repos/acme/large/metrics?async=true")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        let location = headers["location"].to_str().unwrap().to_string();
+        // Async fetches share the cap on running jobs.
+        let (status, _) = get_json(app.clone(), "/api/v1/repos/acme/huge/metrics?async=true").await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        tokio::time::sleep(std::time::Duration::from_secs(11)).await;
+        let (status, body) = get_json(app.clone(), &location).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["summary"]["current_opened"], 4);
+
+        // Small and cached repositories are answered directly.
+        for repo in ["small", "large"] {
+            let uri = format!("/api/v1/repos/acme/{}/metrics?async=true", repo);
+            let (status, _) = get_json(app.clone(), &uri).await;
+            assert_eq!(status, StatusCode::OK, "{}", repo);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_route_groups_have_their_own_concurrency_limits() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .slow(std::time::Duration::from_secs(10));
+        let config = test_config(&[
+            ("METRICS_CONCURRENCY_LIMIT", "1"),
+            ("ADMIN_TOKEN", "admin-secret"),
+        ]);
+        let app = test_app(config, source);
+
+        let first = tokio::spawn(get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics"));
+        tokio::task::yield_now().await;
+        // Listing popular repositories is quick, but it's in the same group as the fetch.
+        let waiting = get_json(app.clone(), "/api/v1/repos/popular");
+        let waiting = tokio::time::timeout(std::time::Duration::from_secs(5), waiting).await;
+        assert!(waiting.is_err(), "the listing should wait its turn");
+
+        let (status, _) = get_json(app.clone(), "/api/v1/health").await;
+        assert_eq!(status, StatusCode::OK);
+        let request = Request::get("/api/v1/admin/status")
+            .header("authorization", "Bearer admin-secret")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, _) = send(app.clone(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(first.await.unwrap().0, StatusCode::OK);
+        let (status, _) = get_json(app, "/api/v1/repos/popular").await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_slow_fetch_times_out() {
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .slow(std::time::Duration::from_secs(60));
+        let app = test_app(
+            test_config(&[("REQUEST_TIMEOUT_SECONDS", "5")]),
+            source.clone(),
+        );
+
+        let (status, body) = get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(status, StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(body["code"], "timeout");
+
+        // The abandoned fetch still completes and fills the cache.
+        tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+        let (status, _) = get_json(app, "/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(source.calls(), 1);
+    }
+
+    /// Like `get_json`, with the admin token of `ADMIN_TOKEN=admin-secret`.
+    async fn get_json_as_admin(app: axum::Router, uri: &str) -> (StatusCode, serde_json::Value) {
+        let request = Request::get(uri)
+            .header("authorization", "Bearer admin-secret")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, body) = send(app, request).await;
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_repo_branches() {
+        let now = chrono::Utc::now();
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .with_branches(vec![
+                now - chrono::Duration::days(2),
+                now - chrono::Duration::days(120),
+            ]);
+        let config = test_config(&[("ADMIN_TOKEN", "admin-secret")]);
+        let app = test_app(config.clone(), source);
+
+        let uri = "/api/v1/repos/acme/widgets/branches";
+        let (status, body) = get_json_as_admin(app.clone(), uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["total"], 2);
+        assert_eq!(body["stale"], 1);
+        assert_eq!(body["stale_after_days"], 90);
+        let (status, _) = get_json(app.clone(), uri).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, body) = get_json_as_admin(app, "/api/v1/repos/acme/missing/branches").await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body["code"], "repo_not_found");
+
+        let app = test_app(config, MockPullRequestSource::default());
+        let (status, body) = get_json_as_admin(app, uri).await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(body["code"], "branches_unavailable");
+    }
+
+    #[tokio::test]
+    async fn test_repo_heatmap() {
+        let source =
+            MockPullRequestSource::default().with_repo("acme/widgets", vec![pr(1, 3, Some(1))]);
+        let app = test_app(test_config(&[]), source);
+
+        let (status, body) = get_json(app.clone(), "/api/v1/repos/acme/widgets/heatmap").await;
+        assert_eq!(status, StatusCode::OK);
+        let cells = body["cells"].as_array().unwrap();
+        assert_eq!(cells.len(), 168);
+        let total = |key: &str| cells.iter().map(|c| c[key].as_u64().unwrap()).sum::<u64>();
+        assert_eq!((total("opened"), total("merged")), (1, 1));
+
+        let request = Request::get("/api/v1/repos/acme/widgets/heatmap")
+            .header("accept", "text/csv")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        let csv = String::from_utf8(body).unwrap();
+        assert!(csv.starts_with("weekday,hour,opened,merged\n0,0,"));
+        assert_eq!(csv.lines().count(), 169);
+    }
+
+    #[tokio::test]
+    async fn test_repo_security() {
+        let alerts = repoflow_core::source::SecurityAlerts {
+            open_dependabot_alerts: None,
+            published_advisories: Some(3),
+            complete: true,
+        };
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .with_security_alerts(alerts);
+        let config = test_config(&[("ADMIN_TOKEN", "admin-secret")]);
+        let app = test_app(config.clone(), source);
+        let uri = "/api/v1/repos/acme/widgets/security";
+        let (status, body) = get_json_as_admin(app.clone(), uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["open_dependabot_alerts"], serde_json::Value::Null);
+        assert_eq!(body["published_advisories"], 3);
+        let (status, _) = get_json(app, uri).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let app = test_app(config, MockPullRequestSource::default());
+        let (status, body) = get_json_as_admin(app, uri).await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(body["code"], "security_unavailable");
+    }
+
+    #[tokio::test]
+    async fn test_repo_calendar() {
+        let calendar = repoflow_core::source::RepoCalendar {
+            releases: vec![repoflow_core::source::Release {
+                tag_name: "v1.0.0".to_string(),
+                name: None,
+                published_at: chrono::Utc::now(),
+                url: "https://github.com/acme/widgets/releases/tag/v1.0.0".to_string(),
+            }],
+            milestones: Vec::new(),
+            complete: true,
+        };
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![])
+            .with_calendar(calendar);
+        let app = test_app(test_config(&[]), source);
+        let request = Request::get("/api/v1/repos/acme/widgets/calendar")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["content-type"], "text/calendar; charset=utf-8");
+        let ics = String::from_utf8(body).unwrap();
+        assert!(ics.contains("SUMMARY:widgets v1.0.0\r\n"));
+
+        let app = test_app(test_config(&[]), MockPullRequestSource::default());
+        let (status, body) = get_json(app, "/api/v1/repos/acme/widgets/calendar").await;
+        assert_eq!(status, StatusCode::NOT_IMPLEMENTED);
+        assert_eq!(body["code"], "calendar_unavailable");
+    }
+
+    #[tokio::test]
+    async fn test_repo_feed() {
+        let source =
+            MockPullRequestSource::default().with_repo("acme/widgets", vec![pr(1, 10, Some(2))]);
+        let app = test_app(test_config(&[]), source);
+        let request = Request::get("/api/repos/acme/widgets/feed.atom")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            headers["content-type"],
+            "application/atom+xml; charset=utf-8"
+        );
+        let atom = String::from_utf8(body).unwrap();
+        assert!(atom.contains("<title>acme/widgets weekly flow</title>"));
+        assert!(atom.matches("<entry>").count() >= 4);
+    }
+
+    #[tokio::test]
+    async fn test_repo_chart_png() {
+        let source =
+            MockPullRequestSource::default().with_repo("acme/widgets", vec![pr(1, 10, Some(2))]);
+        let app = test_app(test_config(&[]), source);
+        let request = Request::get("/api/repos/acme/widgets/chart.png?metric=merged")
+            .body(Body::empty())
+            .unwrap();
+        let (status, headers, body) = send(app.clone(), request).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["content-type"], "image/png");
+        assert!(body.starts_with(b"\x89PNG\r\n\x1a\n"));
+
+        let (status, body) =
+            get_json(app, "/api/v1/repos/acme/widgets/chart.png?metric=closed").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["message"].as_str().unwrap().contains("closed"));
+    }
+
+    #[tokio::test]
+    async fn test_portfolio_summary() {
+        let config = test_config(&[("POPULAR_REPOS", "acme/widgets,acme/gadgets")]);
+        let source = MockPullRequestSource::default()
+            .with_repo("acme/widgets", vec![pr(1, 3, Some(1)), pr(2, 2, None)])
+            .with_repo("acme/gadgets", vec![]);
+        let app = test_app(config, source);
+        let (status, _) = get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let (status, body) = get_json(app, "/api/v1/portfolio/summary").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["data"]["total_open"], 1);
+        assert_eq!(body["data"]["weighted_merge_rate"], 50);
+        assert_eq!(body["meta"]["pending"], serde_json::json!(["acme/gadgets"]));
+    }
+
+    #[tokio::test]
+    async fn test_report_command_gets_a_reply() {
+        use ring::hmac;
+        use wiremock::matchers::{method, path};
+        use wiremock::{Mock, MockServer, ResponseTemplate};
+
+        let github = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/repos/acme/widgets/issues/7/comments"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({"id": 1})))
+            .expect(1)
+            .mount(&github)
+            .await;
+        let config = test_config(&[
+            ("GITHUB_API_URL", github.uri().as_str()),
+            ("GITHUB_TOKEN", "ghp_test"),
+            ("GITHUB_WEBHOOK_SECRET", "hook-secret"),
+            ("POPULAR_REPOS", "acme/widgets"),
+            ("PUBLIC_URL", "https://flow.example.com/"),
+        ]);
+        let source =
+            MockPullRequestSource::default().with_repo("acme/widgets", vec![pr(1, 3, Some(1))]);
+        let app = test_app(config, source);
+
+        let key = hmac::Key::new(hmac::HMAC_SHA256, b"hook-secret");
+        let deliver = |repo: &str, signed: bool| {
+            let body = serde_json::json!({
+                "action": "created",
+                "issue": {"number": 7},
+                "comment": {"body": "/repoflow report", "user": {"login": "alice", "type": "User"}},
+                "repository": {"name": repo, "owner": {"login": "acme"}},
+            })
+            .to_string();
+            let tag = hmac::sign(&key, body.as_bytes());
+            let hex: String = tag.as_ref().iter().map(|b| format!("{:02x}", b)).collect();
+            let signature = if signed { hex } else { "00".repeat(32) };
+            Request::post("/api/v1/webhooks/github")
+                .header("x-github-event", "issue_comment")
+                .header("x-github-delivery", format!("delivery-{}", repo))
+                .header("x-hub-signature-256", format!("sha256={}", signature))
+                .body(Body::from(body))
+                .unwrap()
+        };
+
+        let (status, _, _) = send(app.clone(), deliver("widgets", false)).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (status, _, _) = send(app.clone(), deliver("gadgets", true)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _, _) = send(app.clone(), deliver("widgets", true)).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        // A redelivery isn't answered again.
+        let (status, _, _) = send(app, deliver("widgets", true)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+
+        let mut requests = Vec::new();
+        for _ in 0..50 {
+            requests = github.received_requests().await.unwrap();
+            if !requests.is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+        }
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let comment = body["body"].as_str().unwrap();
+        assert!(comment.contains("**RepoFlow report for acme/widgets**"));
+        assert!(comment.contains("(https://flow.example.com/api/v1/repos/acme/widgets/chart.svg)"));
+    }
+
+    #[tokio::test]
+    async fn test_bad_query_parameters_get_a_json_error() {
+        let app = test_app(test_config(&[]), MockPullRequestSource::default());
+        let (status, body) = get_json(app, "/api/v1/repos/popular?limit=lots").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_query");
+        let message = body["message"].as_str().unwrap();
+        assert!(message.contains("limit"), "{}", message);
+    }
+
+    #[tokio::test]
+    async fn test_repo_metrics_rejects_invalid_names() {
+        let source = MockPullRequestSource::default();
+        let app = test_app(test_config(&[]), source.clone());
+        let (status, body) = get_json(app, "/api/v1/repos/-acme/wid%20gets/metrics").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["code"], "invalid_repo_name");
+        let message = body["message"].as_str().unwrap();
+        assert!(message.contains("hyphen"), "{}", message);
+        assert!(message.contains("may only contain"), "{}", message);
+        assert_eq!(source.calls(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_repo_metrics_requires_api_key() {
+        let config = test_config(&[("API_KEYS", "ci:sk_ci:1"), ("REQUIRE_API_KEY", "true")]);
+        let source = MockPullRequestSource::default().with_repo("acme/widgets", vec![]);
+        let app = test_app(config, source);
+
+        let (status, _) = get_json(app.clone(), "/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let request = || {
+            Request::get("/api/v1/repos/acme/widgets/metrics")
+                .header("x-api-key", "sk_ci")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let (status, headers, _) = send(app.clone(), request()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["x-quota-remaining"], "0");
+        let (status, headers, body) = send(app, request()).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(body["code"], "quota_exceeded");
+        assert_eq!(
+            body["retry_after"].as_u64().unwrap().to_string(),
+            headers["retry-after"].to_str().unwrap()
+        );
+        assert!(body["request_id"].is_string());
+    }
+
+    /// A frontend build with an index and one asset, in a fresh temporary directory.
+    fn static_dir() -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("repoflow-dist-{}", rand::random::<u64>()));
+        std::fs::create_dir_all(dir.join("assets")).unwrap();
+        std::fs::write(dir.join("index.html"), "<html><head></head></html>").unwrap();
+        std::fs::write(dir.join("assets/app.js"), "console.log(1)").unwrap();
+        dir
+    }
+
+    #[tokio::test]
+    async fn test_serves_frontend_under_base_path() {
+        let dir = static_dir();
+        let config = test_config(&[
+            ("STATIC_DIR", dir.to_str().unwrap()),
+            ("BASE_PATH", "/repoflow/"),
+        ]);
+        let app = test_app(config, MockPullRequestSource::default());
+        let get = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                let (status, _, body) =
+                    send(app, Request::get(uri).body(Body::empty()).unwrap()).await;
+                (status, String::from_utf8(body).unwrap())
+            }
+        };
+
+        let (status, body) = get("/repoflow/").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(r#"<base href="/repoflow/">"#));
+        assert!(get("/repoflow").await.1.contains("<base"));
+        assert_eq!(get("/repoflow/assets/app.js").await.1, "console.log(1)");
+        // Unknown paths fall back to the app so deep links work.
+        assert!(get("/repoflow/some/page").await.1.contains("<base"));
+        assert_eq!(get("/repoflow/api/v1/health").await.0, StatusCode::OK);
+        assert_eq!(get("/api/v1/health").await.0, StatusCode::NOT_FOUND);
+        assert_eq!(get("/assets/app.js").await.0, StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_audit_log_under_base_path() {
+        let config = test_config(&[("BASE_PATH", "/repoflow"), ("ADMIN_TOKEN", "admin-secret")]);
+        let app = test_app(
+            config,
+            MockPullRequestSource::default().with_repo("acme/widgets", vec![]),
+        );
+        let (status, _) =
+            get_json(app.clone(), "/repoflow/api/v1/repos/acme/widgets/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+
+        let request = Request::get("/repoflow/api/v1/admin/audit")
+            .header("authorization", "Bearer admin-secret")
+            .body(Body::empty())
+            .unwrap();
+        let (status, _, body) = send(app, request).await;
+        assert_eq!(status, StatusCode::OK);
+        let entries: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        let entry = &entries.as_array().unwrap().last().unwrap();
+        assert_eq!(entry["path"], "/api/v1/repos/acme/widgets/metrics");
+        assert_eq!(entry["repo"], "acme/widgets");
+    }
+
+    #[tokio::test]
+    async fn test_spa_fallback_can_be_disabled() {
+        let dir = static_dir();
+        let config = test_config(&[
+            ("STATIC_DIR", dir.to_str().unwrap()),
+            ("SPA_FALLBACK", "false"),
+        ]);
+        let app = test_app(config, MockPullRequestSource::default());
+        let status = |uri: &'static str| {
+            let app = app.clone();
+            async move {
+                send(app, Request::get(uri).body(Body::empty()).unwrap())
+                    .await
+                    .0
+            }
+        };
+
+        assert_eq!(status("/").await, StatusCode::OK);
+        assert_eq!(status("/assets/app.js").await, StatusCode::OK);
+        assert_eq!(status("/some/page").await, StatusCode::NOT_FOUND);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
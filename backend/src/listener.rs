@@ -0,0 +1,219 @@
+//! Binding the server's listening socket.
+//!
+//! The server listens on plain TCP by default. When `TLS_CERT_PATH` and `TLS_KEY_PATH` are set it
+//! terminates HTTPS itself with rustls, so small deployments don't need a reverse proxy. When
+//! `LISTEN_SOCKET` is set it listens on a Unix domain socket instead, for use behind nginx.
+
+use axum::extract::connect_info::Connected;
+use axum::serve::{IncomingStream, Listener};
+use repoflow_core::config::AppConfig;
+use rustls_pki_types::pem::PemObject;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_rustls::rustls::{self, ServerConfig};
+use tokio_rustls::{server::TlsStream, TlsAcceptor};
+
+const TLS_HANDSHAKE_TIMEOUT: StdDuration = StdDuration::from_secs(10);
+const TLS_ACCEPT_BACKLOG: usize = 128;
+const ACCEPT_ERROR_BACKOFF: StdDuration = StdDuration::from_secs(1);
+
+/// The socket the server accepts connections on.
+pub enum AppListener {
+    Tcp(TcpListener),
+    Tls(TlsListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener),
+}
+
+/// The remote address of a connection, available to handlers as `ConnectInfo<ClientAddr>`
+/// regardless of the listener type.
+#[derive(Clone, Copy, Debug)]
+pub struct ClientAddr(pub Option<SocketAddr>);
+
+impl Connected<IncomingStream<'_, TcpListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        Self(Some(*stream.remote_addr()))
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for ClientAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        Self(Some(*stream.remote_addr()))
+    }
+}
+
+/// Unix socket peers have no IP address; the proxy in front is expected to forward it.
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for ClientAddr {
+    fn connect_info(_stream: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        Self(None)
+    }
+}
+
+/// A TCP listener that yields connections only once their TLS handshake has completed.
+///
+/// Handshakes run in their own tasks so that a slow or malicious client can't stall `accept`
+/// for everyone else.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    connections: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    pub fn new(tcp: TcpListener, acceptor: TlsAcceptor) -> io::Result<Self> {
+        let local_addr = tcp.local_addr()?;
+        let (tx, connections) = mpsc::channel(TLS_ACCEPT_BACKLOG);
+
+        tokio::spawn(async move {
+            loop {
+                let (stream, addr) = match tcp.accept().await {
+                    Ok(conn) => conn,
+                    Err(e) => {
+                        tracing::error!("Failed to accept TCP connection: {}", e);
+                        tokio::time::sleep(ACCEPT_ERROR_BACKOFF).await;
+                        continue;
+                    }
+                };
+
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await
+                    {
+                        Ok(Ok(tls)) => {
+                            let _ = tx.send((tls, addr)).await;
+                        }
+                        Ok(Err(e)) => tracing::debug!("TLS handshake with {} failed: {}", addr, e),
+                        Err(_) => tracing::debug!("TLS handshake with {} timed out", addr),
+                    }
+                });
+            }
+        });
+
+        Ok(Self {
+            local_addr,
+            connections,
+        })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.connections.recv().await {
+            Some(conn) => conn,
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+/// Binds the listener described by the configuration, exiting the process on failure.
+pub async fn get_listener(config: &AppConfig) -> AppListener {
+    if let Some(path) = &config.listen_socket {
+        if config.tls_cert_path.is_some() || config.tls_key_path.is_some() {
+            tracing::error!("TLS is not supported on LISTEN_SOCKET. Exiting.");
+            std::process::exit(1);
+        }
+        return bind_unix_socket(path);
+    }
+
+    let port_str = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
+    let port = match port_str.parse::<u16>() {
+        Ok(p) => p,
+        Err(e) => {
+            tracing::error!("Invalid PORT value '{}': {}. Exiting.", port_str, e);
+            std::process::exit(1);
+        }
+    };
+
+    let acceptor = match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert), Some(key)) => match tls_acceptor(cert, key) {
+            Ok(acceptor) => Some(acceptor),
+            Err(e) => {
+                tracing::error!("Failed to load TLS certificate: {}. Exiting.", e);
+                std::process::exit(1);
+            }
+        },
+        (None, None) => None,
+        _ => {
+            tracing::error!("TLS_CERT_PATH and TLS_KEY_PATH must be set together. Exiting.");
+            std::process::exit(1);
+        }
+    };
+
+    let addr = SocketAddr::from(([0, 0, 0, 0], port));
+    let tcp = TcpListener::bind(addr)
+        .await
+        .expect("failed to bind TCP listener");
+
+    match acceptor {
+        Some(acceptor) => {
+            tracing::info!("Server listening on {} (HTTPS)", addr);
+            AppListener::Tls(TlsListener::new(tcp, acceptor).expect("failed to start TLS listener"))
+        }
+        None => {
+            tracing::info!("Server listening on {}", addr);
+            AppListener::Tcp(tcp)
+        }
+    }
+}
+
+#[cfg(unix)]
+fn bind_unix_socket(path: &Path) -> AppListener {
+    use std::os::unix::fs::FileTypeExt;
+
+    // A socket file left behind by a previous run would make bind fail with "address in use".
+    if std::fs::metadata(path).is_ok_and(|m| m.file_type().is_socket()) {
+        if let Err(e) = std::fs::remove_file(path) {
+            tracing::error!(
+                "Failed to remove stale socket {}: {}. Exiting.",
+                path.display(),
+                e
+            );
+            std::process::exit(1);
+        }
+    }
+
+    match tokio::net::UnixListener::bind(path) {
+        Ok(listener) => {
+            tracing::info!("Server listening on unix:{}", path.display());
+            AppListener::Unix(listener)
+        }
+        Err(e) => {
+            tracing::error!("Failed to bind {}: {}. Exiting.", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn bind_unix_socket(_path: &Path) -> AppListener {
+    tracing::error!("LISTEN_SOCKET is only supported on Unix platforms. Exiting.");
+    std::process::exit(1);
+}
+
+fn tls_acceptor(cert_path: &Path, key_path: &Path) -> anyhow::Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(cert_path)?.collect::<Result<Vec<_>, _>>()?;
+    let key = PrivateKeyDer::from_pem_file(key_path)?;
+
+    let mut config =
+        ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(certs, key)?;
+    config.alpn_protocols = vec![b"http/1.1".to_vec()];
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
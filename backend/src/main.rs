@@ -1,50 +1,40 @@
-mod config;
-mod metrics;
-mod querier;
-
-use axum::{
-    extract::{Path, State},
-    routing::get,
-    Json, Router,
-};
-use config::{AppConfig, RepoId};
-use querier::MetricsQuerier;
-use serde::Serialize;
-use std::net::SocketAddr;
-use std::sync::Arc;
-use tower_http::services::{ServeDir, ServeFile};
-use tower_http::trace::TraceLayer;
+use backend::cli;
+use backend::listener::{self, AppListener, ClientAddr};
+use clap::Parser;
+use repoflow_core::config::{self, AppConfig};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
-#[derive(Serialize)]
-struct HealthResponse {
-    status: &'static str,
-    service: &'static str,
-    version: &'static str,
-}
-
-/// Shared application state accessible to all request handlers.
-struct AppState {
-    /// Service for querying repository metrics.
-    querier: MetricsQuerier,
-    /// Application configuration loaded from environment variables.
-    config: AppConfig,
-}
-
-impl AppState {
-    /// Initializes the application state, including the metrics querier.
-    pub fn new(config: AppConfig) -> anyhow::Result<Self> {
-        let querier = MetricsQuerier::new(&config)?;
-        Ok(Self { querier, config })
-    }
-}
-
 #[tokio::main]
 async fn main() {
-    // Load environment variables from .env file if it exists
-    dotenvy::dotenv().ok();
+    let cli = cli::Cli::parse();
+    let profile = config::Profile::from_env();
+    // Loaded before tracing is initialized so that `.env` can set RUST_LOG.
+    let env_files = profile
+        .as_ref()
+        .map(|profile| profile.load_dotenv())
+        .unwrap_or_default();
+
+    if cli.command.is_some() {
+        // Logs would otherwise be mixed into the output.
+        init_tracing(
+            "backend=warn,repoflow_core=warn",
+            BoxMakeWriter::new(std::io::stderr),
+        );
+    } else {
+        init_tracing(
+            "backend=debug,repoflow_core=debug,tower_http=debug",
+            BoxMakeWriter::new(std::io::stdout),
+        );
+    }
 
-    init_tracing();
+    if let Err(e) = profile {
+        tracing::error!("Failed to load configuration: {}. Exiting.", e);
+        std::process::exit(1);
+    }
+    for file in env_files {
+        tracing::info!("Loaded environment from {}", file.display());
+    }
 
     let config = match AppConfig::from_env() {
         Ok(c) => c,
@@ -54,124 +44,80 @@ async fn main() {
         }
     };
 
-    if config.github_token.is_none() {
-        tracing::warn!("Running without GITHUB_TOKEN. Rate limits will be strict.");
+    if let Some(command) = cli.command {
+        match cli::run(command, config).await {
+            Ok(output) => println!("{}", output.trim_end()),
+            Err(e) => {
+                tracing::error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
     }
 
-    let state = match AppState::new(config) {
-        Ok(s) => Arc::new(s),
+    let app = match backend::create_app(config.clone()).await {
+        Ok(app) => app,
         Err(e) => {
-            tracing::error!("Failed to initialize application state: {}. Exiting.", e);
+            tracing::error!("Failed to start: {:#}. Exiting.", e);
             std::process::exit(1);
         }
     };
-
-    let serve_dir = ServeDir::new("dist").not_found_service(ServeFile::new("dist/index.html"));
-
-    let app = Router::new()
-        .route("/api/health", get(health_check))
-        .route("/api/repos/popular", get(get_popular_repos))
-        .route("/api/repos/{owner}/{repo}/metrics", get(get_repo_metrics))
-        .fallback_service(serve_dir)
-        .layer(TraceLayer::new_for_http())
-        .with_state(state);
-
-    let listener = get_listener().await;
-
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
-        .await
-        .expect("failed to start server");
+    let listener = listener::get_listener(&config).await;
+
+    let result = match listener {
+        AppListener::Tcp(listener) => {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<ClientAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_signal())
+            .await
+        }
+        AppListener::Tls(listener) => {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<ClientAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_signal())
+            .await
+        }
+        #[cfg(unix)]
+        AppListener::Unix(listener) => {
+            axum::serve(
+                listener,
+                app.into_make_service_with_connect_info::<ClientAddr>(),
+            )
+            .with_graceful_shutdown(shutdown_signal())
+            .await
+        }
+    };
+    result.expect("failed to start server");
 }
 
-fn init_tracing() {
+fn init_tracing(default_filter: &str, writer: BoxMakeWriter) {
     let filter = tracing_subscriber::EnvFilter::try_from_default_env()
-        .unwrap_or_else(|_| "backend=debug,tower_http=debug".into());
+        .unwrap_or_else(|_| default_filter.into());
 
     let log_format = std::env::var("LOG_FORMAT").unwrap_or_default();
 
     if log_format == "json" {
         tracing_subscriber::registry()
             .with(filter)
-            .with(tracing_subscriber::fmt::layer().json().with_ansi(false))
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .with_ansi(false)
+                    .with_writer(writer),
+            )
             .init();
     } else {
         tracing_subscriber::registry()
             .with(filter)
-            .with(tracing_subscriber::fmt::layer())
+            .with(tracing_subscriber::fmt::layer().with_writer(writer))
             .init();
     }
 }
 
-async fn get_listener() -> tokio::net::TcpListener {
-    let port_str = std::env::var("PORT").unwrap_or_else(|_| "3000".to_string());
-    let port = match port_str.parse::<u16>() {
-        Ok(p) => p,
-        Err(e) => {
-            tracing::error!("Invalid PORT value '{}': {}. Exiting.", port_str, e);
-            std::process::exit(1);
-        }
-    };
-
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    tracing::info!("Server listening on {}", addr);
-
-    tokio::net::TcpListener::bind(addr)
-        .await
-        .expect("failed to bind TCP listener")
-}
-
-async fn health_check() -> Json<HealthResponse> {
-    Json(HealthResponse {
-        status: "ok",
-        service: "repoflow-backend",
-        version: env!("CARGO_PKG_VERSION"),
-    })
-}
-
-async fn get_popular_repos(State(state): State<Arc<AppState>>) -> Json<Vec<RepoId>> {
-    Json(state.config.popular_repos.clone())
-}
-
-async fn get_repo_metrics(
-    Path(repo_id): Path<RepoId>,
-    State(state): State<Arc<AppState>>,
-) -> Result<Json<metrics::RepoMetricsResponse>, (axum::http::StatusCode, String)> {
-    match state.querier.get(repo_id.clone()).await {
-        Ok(metrics) => {
-            tracing::debug!(repo_id = %repo_id, "Returning metrics");
-            Ok(Json(metrics))
-        }
-        Err(e) => {
-            tracing::error!("Failed to fetch PRs for {}: {}", repo_id, e);
-
-            if let Some(octocrab::Error::GitHub { source, .. }) =
-                e.downcast_ref::<octocrab::Error>()
-            {
-                // TODO(#29): Refactor this brittle string matching.
-                // We should inspect the raw HTTP status code or use a strongly-typed error variant if available.
-                if source.message.to_lowercase().contains("rate limit") {
-                    return Err((
-                        axum::http::StatusCode::TOO_MANY_REQUESTS,
-                        "GitHub Rate Limit Exceeded".to_string(),
-                    ));
-                }
-                if source.message.to_lowercase().contains("not found") {
-                    return Err((
-                        axum::http::StatusCode::NOT_FOUND,
-                        "Repository Not Found".to_string(),
-                    ));
-                }
-            }
-
-            Err((
-                axum::http::StatusCode::INTERNAL_SERVER_ERROR,
-                "Internal Server Error".to_string(),
-            ))
-        }
-    }
-}
-
 async fn shutdown_signal() {
     let ctrl_c = async {
         if tokio::signal::ctrl_c().await.is_err() {
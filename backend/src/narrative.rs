@@ -0,0 +1,231 @@
+//! Short narrative paragraphs for reports, written by a language model behind an
+//! OpenAI-compatible chat completions endpoint.
+//!
+//! Only the computed summary and insights leave the server, never code or pull request contents.
+//! Narratives are cached by that input, so unchanged metrics don't cost another request, and a
+//! slow endpoint is abandoned after `NARRATIVE_TIMEOUT_SECONDS` rather than holding up the report.
+
+use anyhow::Context;
+use moka::future::Cache;
+use repoflow_core::config::{AppConfig, Secret};
+use repoflow_core::domain::RepoId;
+use repoflow_core::http_client;
+use repoflow_core::metrics::{RepoMetricsResponse, SummaryMetrics};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+
+const SYSTEM_PROMPT: &str = "You write one short paragraph, at most three sentences, describing \
+a software repository's pull request flow for an engineering report. Use only the figures given, \
+and don't speculate about causes.";
+
+/// Narratives kept at most this long, so a changed prompt or model is picked up eventually.
+const CACHE_TTL: StdDuration = StdDuration::from_secs(24 * 60 * 60);
+
+const MAX_TOKENS: u32 = 200;
+
+/// What the model is told about a repository.
+#[derive(Serialize)]
+struct NarrativeInput<'a> {
+    repository: String,
+    window_days: i64,
+    summary: &'a SummaryMetrics,
+    insights: &'a [String],
+}
+
+#[derive(Serialize)]
+struct ChatRequest<'a> {
+    model: &'a str,
+    messages: [ChatMessage<'a>; 2],
+    max_tokens: u32,
+}
+
+#[derive(Serialize)]
+struct ChatMessage<'a> {
+    role: &'static str,
+    content: &'a str,
+}
+
+#[derive(Deserialize)]
+struct ChatResponse {
+    choices: Vec<ChatChoice>,
+}
+
+#[derive(Deserialize)]
+struct ChatChoice {
+    message: ChatReply,
+}
+
+#[derive(Deserialize)]
+struct ChatReply {
+    content: Option<String>,
+}
+
+/// Writes narratives through the configured endpoint.
+#[derive(Clone)]
+pub struct Narrator {
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<Secret>,
+    model: String,
+    timeout: StdDuration,
+    cache: Cache<String, Arc<str>>,
+}
+
+impl Narrator {
+    /// Creates a narrator, or returns `None` when narratives are disabled.
+    pub fn new(config: &AppConfig) -> anyhow::Result<Option<Self>> {
+        if !config.narrative_enabled {
+            return Ok(None);
+        }
+        let (Some(url), Some(model)) = (&config.narrative_api_url, &config.narrative_model) else {
+            anyhow::bail!("NARRATIVE_API_URL and NARRATIVE_MODEL are required for narratives");
+        };
+        Ok(Some(Self {
+            http: http_client::reqwest_client(config)?,
+            url: url.clone(),
+            api_key: config.narrative_api_key.clone(),
+            model: model.clone(),
+            timeout: StdDuration::from_secs(config.narrative_timeout_seconds),
+            cache: Cache::builder().time_to_live(CACHE_TTL).build(),
+        }))
+    }
+
+    /// A paragraph describing `metrics`, computed over `window_days`, for `repo_id`.
+    pub async fn narrate(
+        &self,
+        repo_id: &RepoId,
+        metrics: &RepoMetricsResponse,
+        window_days: i64,
+    ) -> anyhow::Result<Arc<str>> {
+        let input = serde_json::to_string(&NarrativeInput {
+            repository: repo_id.to_string(),
+            window_days,
+            summary: &metrics.summary,
+            insights: &metrics.insights,
+        })?;
+        if let Some(narrative) = self.cache.get(&input).await {
+            return Ok(narrative);
+        }
+
+        let request = ChatRequest {
+            model: &self.model,
+            messages: [
+                ChatMessage {
+                    role: "system",
+                    content: SYSTEM_PROMPT,
+                },
+                ChatMessage {
+                    role: "user",
+                    content: &input,
+                },
+            ],
+            max_tokens: MAX_TOKENS,
+        };
+        let mut builder = self.http.post(&self.url).json(&request);
+        if let Some(key) = &self.api_key {
+            builder = builder.bearer_auth(key.expose());
+        }
+        let response: ChatResponse = tokio::time::timeout(self.timeout, async {
+            builder.send().await?.error_for_status()?.json().await
+        })
+        .await
+        .context("the narrative endpoint timed out")?
+        .context("the narrative request failed")?;
+
+        let narrative: Arc<str> = response
+            .choices
+            .into_iter()
+            .find_map(|choice| choice.message.content)
+            .map(|content| content.trim().to_string())
+            .filter(|content| !content.is_empty())
+            .context("the narrative endpoint returned no text")?
+            .into();
+        self.cache.insert(input, narrative.clone()).await;
+        Ok(narrative)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::test_config;
+    use repoflow_core::metrics::calculate_metrics;
+    use wiremock::matchers::{body_partial_json, header, method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    fn narrator(server: &MockServer) -> Narrator {
+        let url = format!("{}/v1/chat/completions", server.uri());
+        let config = test_config(&[
+            ("NARRATIVE_ENABLED", "true"),
+            ("NARRATIVE_API_URL", url.as_str()),
+            ("NARRATIVE_API_KEY", "sk-test"),
+            ("NARRATIVE_MODEL", "small-model"),
+            ("NARRATIVE_TIMEOUT_SECONDS", "1"),
+        ]);
+        Narrator::new(&config).unwrap().unwrap()
+    }
+
+    fn metrics() -> RepoMetricsResponse {
+        let mut metrics = calculate_metrics(
+            &[],
+            chrono::Duration::days(1),
+            chrono::Duration::days(30),
+            chrono::Utc::now(),
+        );
+        metrics.insights = vec!["Backlog grew by 5 PRs".to_string()];
+        metrics
+    }
+
+    #[tokio::test]
+    async fn test_narratives_are_cached() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/v1/chat/completions"))
+            .and(header("authorization", "Bearer sk-test"))
+            .and(body_partial_json(
+                serde_json::json!({"model": "small-model"}),
+            ))
+            .respond_with(ResponseTemplate::new(200).set_body_json(serde_json::json!({
+                "choices": [{"message": {"role": "assistant", "content": " Flow is steady. "}}]
+            })))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let narrator = narrator(&server);
+        let repo_id: RepoId = "acme/widgets".parse().unwrap();
+
+        let narrative = narrator.narrate(&repo_id, &metrics(), 30).await.unwrap();
+        assert_eq!(&*narrative, "Flow is steady.");
+        narrator.narrate(&repo_id, &metrics(), 30).await.unwrap();
+
+        let requests = server.received_requests().await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        let input = body["messages"][1]["content"].as_str().unwrap();
+        assert!(input.contains("Backlog grew by 5 PRs"));
+        assert!(!input.contains("time_series"));
+    }
+
+    #[tokio::test]
+    async fn test_slow_endpoint_times_out() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"choices": []}))
+                    .set_delay(StdDuration::from_secs(3)),
+            )
+            .mount(&server)
+            .await;
+        let error = narrator(&server)
+            .narrate(&"acme/widgets".parse().unwrap(), &metrics(), 30)
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("timed out"));
+    }
+
+    #[test]
+    fn test_disabled_by_default() {
+        assert!(Narrator::new(&test_config(&[])).unwrap().is_none());
+    }
+}
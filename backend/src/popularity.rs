@@ -0,0 +1,112 @@
+//! How often each repository's endpoints are requested, so operators can see which repositories
+//! are actually viewed when choosing the popular list and how often to refresh it.
+//!
+//! Counts are kept in memory per app state, so each tenant has its own and they reset on restart.
+
+use crate::extract::Path;
+use crate::AppState;
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use repoflow_core::domain::RepoId;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Repositories tracked at most, so requests for arbitrary names can't grow the table unbounded.
+const MAX_TRACKED_REPOS: usize = 10_000;
+
+/// How often one repository has been requested.
+#[derive(Clone, Debug, Serialize)]
+pub struct RepoViews {
+    #[serde(flatten)]
+    pub repo: RepoId,
+    /// Successful requests for any of its endpoints since startup.
+    pub requests: u64,
+    pub last_requested_at: DateTime<Utc>,
+}
+
+/// Request counts per repository.
+#[derive(Default)]
+pub struct ViewCounter {
+    repos: RwLock<HashMap<RepoId, RepoViews>>,
+}
+
+impl ViewCounter {
+    /// Counts a request for `repo` made `at`. Once `MAX_TRACKED_REPOS` are tracked, only those
+    /// already seen are counted.
+    pub fn record(&self, repo: RepoId, at: DateTime<Utc>) {
+        let mut repos = self.repos.write().expect("view counter lock poisoned");
+        if let Some(views) = repos.get_mut(&repo) {
+            views.requests += 1;
+            views.last_requested_at = at;
+        } else if repos.len() < MAX_TRACKED_REPOS {
+            let views = RepoViews {
+                repo: repo.clone(),
+                requests: 1,
+                last_requested_at: at,
+            };
+            repos.insert(repo, views);
+        }
+    }
+
+    /// Every tracked repository, most requested first.
+    pub fn most_viewed(&self) -> Vec<RepoViews> {
+        let mut views: Vec<RepoViews> = self
+            .repos
+            .read()
+            .expect("view counter lock poisoned")
+            .values()
+            .cloned()
+            .collect();
+        views.sort_by(|a, b| {
+            b.requests
+                .cmp(&a.requests)
+                .then_with(|| (&a.repo.owner, &a.repo.repo).cmp(&(&b.repo.owner, &b.repo.repo)))
+        });
+        views
+    }
+}
+
+/// Route-level middleware counting successful requests for the repository in the path. Failed
+/// ones aren't counted, as they're more often typos and probes than real interest.
+pub async fn count(
+    State(state): State<Arc<AppState>>,
+    Path((owner, repo)): Path<(String, String)>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    let status = response.status();
+    if status.is_success() || status == axum::http::StatusCode::NOT_MODIFIED {
+        state.popularity.record(RepoId { owner, repo }, Utc::now());
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn repo_id(name: &str) -> RepoId {
+        name.parse().unwrap()
+    }
+
+    #[test]
+    fn test_most_viewed_first() {
+        let counter = ViewCounter::default();
+        let now = Utc::now();
+        counter.record(repo_id("acme/widgets"), now);
+        counter.record(repo_id("acme/gadgets"), now);
+        counter.record(repo_id("acme/gadgets"), now);
+        counter.record(repo_id("acme/bolts"), now);
+
+        let views = counter.most_viewed();
+        let order: Vec<String> = views.iter().map(|v| v.repo.to_string()).collect();
+        assert_eq!(order, ["acme/gadgets", "acme/bolts", "acme/widgets"]);
+        assert_eq!(views[0].requests, 2);
+    }
+}
@@ -0,0 +1,233 @@
+//! Organization-wide totals across every tracked repository, for a "state of engineering" widget
+//! on a landing page.
+//!
+//! Only cached metrics are summarized: the popular list is kept fresh in the background, and a
+//! landing page shouldn't wait on GitHub. Repositories not fetched yet are listed as pending.
+
+use crate::encoding::{Encodable, Encoded, Envelope, Fields, Format};
+use crate::error::ApiError;
+use crate::extract::Query;
+use crate::AppState;
+use axum::{
+    extract::State,
+    response::{IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use chrono::{DateTime, Utc};
+use repoflow_core::config::PopularRepo;
+use repoflow_core::metrics::FlowMetricsResponse;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+/// Repositories listed as top movers at most.
+const TOP_MOVERS: usize = 5;
+
+#[derive(Deserialize)]
+struct PortfolioParams {
+    /// Rolling window size in days; defaults to `METRICS_WINDOW_SIZE`.
+    window: Option<i64>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct PortfolioSummary {
+    /// Pull requests open across every summarized repository.
+    total_open: usize,
+    /// Pull requests opened in the current rolling window.
+    total_opened: usize,
+    /// Pull requests merged in the current rolling window.
+    total_merged: usize,
+    /// The percentage of opened pull requests that were merged, counting every repository's pull
+    /// requests alike, so busy repositories weigh more than quiet ones.
+    weighted_merge_rate: u32,
+    /// The repositories whose spread changed most over the last window, largest change first.
+    top_movers: Vec<Mover>,
+}
+
+impl Encodable for PortfolioSummary {}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct Mover {
+    repo: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    display_name: Option<String>,
+    spread: i64,
+    /// The spread now minus the spread one window ago; positive when the gap is widening.
+    spread_change: i64,
+}
+
+#[derive(Serialize)]
+struct PortfolioMeta {
+    window_days: i64,
+    /// Tracked repositories that haven't been fetched yet, and so are left out.
+    pending: Vec<String>,
+    /// False when a page limit cut any summarized repository's fetch short.
+    data_complete: bool,
+    /// When the least recently fetched summarized repository was fetched.
+    oldest_fetched_at: Option<DateTime<Utc>>,
+}
+
+/// The summary, served under the API prefix.
+pub fn router() -> Router<Arc<AppState>> {
+    Router::new().route("/portfolio/summary", get(get_portfolio_summary))
+}
+
+async fn get_portfolio_summary(
+    State(state): State<Arc<AppState>>,
+    Query(params): Query<PortfolioParams>,
+    format: Format,
+    fields: Fields,
+) -> Result<Response, ApiError> {
+    let window_days = crate::window_days(&state, params.window)?;
+    let mut included = Vec::new();
+    let mut pending = Vec::new();
+    for popular in state.service.popular_repos().await {
+        match state.service.cached(&popular.id).await {
+            Some(cached) => included.push((popular, cached)),
+            None => pending.push(popular.id.to_string()),
+        }
+    }
+
+    // Every entry holds each window size `window_days` accepts.
+    let series: Vec<(&PopularRepo, &[FlowMetricsResponse])> = included
+        .iter()
+        .filter_map(|(popular, cached)| {
+            Some((
+                popular,
+                cached.window(window_days)?.metrics.time_series.as_slice(),
+            ))
+        })
+        .collect();
+    let meta = PortfolioMeta {
+        window_days,
+        pending,
+        data_complete: included.iter().all(|(_, cached)| cached.complete),
+        oldest_fetched_at: included.iter().map(|(_, cached)| cached.fetched_at).min(),
+    };
+    let data = summarize(&series, window_days);
+    Ok(Encoded::new(format, Envelope { data, meta })
+        .with_fields(fields)
+        .into_response())
+}
+
+/// Sums the latest day of each repository's `window_days` series, and ranks repositories by how
+/// far their spread moved since one window before it.
+fn summarize(
+    repos: &[(&PopularRepo, &[FlowMetricsResponse])],
+    window_days: i64,
+) -> PortfolioSummary {
+    let mut summary = PortfolioSummary {
+        total_open: 0,
+        total_opened: 0,
+        total_merged: 0,
+        weighted_merge_rate: 0,
+        top_movers: Vec::new(),
+    };
+    for (popular, series) in repos {
+        let Some(latest) = series.last() else {
+            continue;
+        };
+        summary.total_open += latest.open_count;
+        summary.total_opened += latest.opened;
+        summary.total_merged += latest.merged;
+        let earlier = series
+            .len()
+            .checked_sub(1 + window_days as usize)
+            .and_then(|index| series.get(index))
+            .or(series.first());
+        if let Some(earlier) = earlier {
+            summary.top_movers.push(Mover {
+                repo: popular.id.to_string(),
+                display_name: popular.display_name.clone(),
+                spread: latest.spread,
+                spread_change: latest.spread - earlier.spread,
+            });
+        }
+    }
+    if summary.total_opened > 0 {
+        summary.weighted_merge_rate =
+            ((summary.total_merged as f64 / summary.total_opened as f64) * 100.0).round() as u32;
+    }
+    summary.top_movers.retain(|mover| mover.spread_change != 0);
+    summary
+        .top_movers
+        .sort_by_key(|mover| std::cmp::Reverse(mover.spread_change.abs()));
+    summary.top_movers.truncate(TOP_MOVERS);
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn series(points: &[(usize, usize, usize)]) -> Vec<FlowMetricsResponse> {
+        points
+            .iter()
+            .enumerate()
+            .map(|(day, &(opened, merged, open_count))| FlowMetricsResponse {
+                date: format!("2026-03-{:02}", day + 1),
+                opened,
+                merged,
+                spread: opened as i64 - merged as i64,
+                open_count,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn popular(id: &str, display_name: Option<&str>) -> PopularRepo {
+        PopularRepo {
+            id: id.parse().unwrap(),
+            display_name: display_name.map(str::to_string),
+            category: None,
+            max_pages: None,
+        }
+    }
+
+    #[test]
+    fn test_summarize_weighs_by_volume() {
+        let busy = series(&[(10, 10, 40), (20, 10, 50), (90, 60, 60)]);
+        let quiet = series(&[(2, 0, 3), (1, 1, 3), (0, 0, 4)]);
+        let steady = series(&[(5, 5, 1), (5, 5, 1), (5, 5, 1)]);
+        let summary = summarize(
+            &[
+                (&popular("acme/busy", Some("Busy")), &busy),
+                (&popular("acme/quiet", None), &quiet),
+                (&popular("acme/steady", None), &steady),
+            ],
+            1,
+        );
+        assert_eq!(summary.total_open, 65);
+        assert_eq!((summary.total_opened, summary.total_merged), (95, 65));
+        // (60 + 0 + 5) / (90 + 0 + 5), where averaging each rate would give (67 + 0 + 100) / 3.
+        assert_eq!(summary.weighted_merge_rate, 68);
+        assert_eq!(
+            summary.top_movers,
+            [Mover {
+                repo: "acme/busy".to_string(),
+                display_name: Some("Busy".to_string()),
+                spread: 30,
+                spread_change: 20,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_movers_compare_against_one_window_ago() {
+        let widening = series(&[(1, 1, 0), (3, 1, 0), (5, 1, 0), (9, 1, 0)]);
+        let narrowing = series(&[(9, 0, 0), (1, 1, 0), (1, 1, 0), (1, 1, 0)]);
+        let summary = summarize(
+            &[
+                (&popular("acme/widening", None), &widening),
+                (&popular("acme/narrowing", None), &narrowing),
+            ],
+            3,
+        );
+        let movers: Vec<(&str, i64)> = summary
+            .top_movers
+            .iter()
+            .map(|m| (m.repo.as_str(), m.spread_change))
+            .collect();
+        assert_eq!(movers, [("acme/narrowing", -9), ("acme/widening", 8)]);
+    }
+}
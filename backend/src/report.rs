@@ -0,0 +1,226 @@
+//! A self-contained static HTML report of the tracked repositories, for teams that publish a
+//! nightly artifact instead of running the server. Charts are inline SVG so the page needs no
+//! scripts or network access to view.
+
+use chrono::{DateTime, Utc};
+use repoflow_core::config::PopularRepo;
+use repoflow_core::metrics::{FlowMetricsResponse, RepoMetricsResponse};
+use std::fmt::Write;
+
+const CHART_WIDTH: f64 = 640.0;
+const CHART_HEIGHT: f64 = 160.0;
+const OPENED_COLOR: &str = "#2563eb";
+const MERGED_COLOR: &str = "#16a34a";
+
+/// One tracked repository's section of the report.
+pub struct ReportEntry {
+    pub repo: PopularRepo,
+    /// The metrics, or why they couldn't be fetched.
+    pub metrics: Result<RepoMetricsResponse, String>,
+    /// Whether the metrics cover every pull request in the fetch window.
+    pub complete: bool,
+    /// A model-written description of the metrics, when narratives are enabled.
+    pub narrative: Option<String>,
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// A line per series, scaled so the largest value across both reaches the top of the chart.
+pub fn chart(series: &[FlowMetricsResponse]) -> String {
+    let max = series
+        .iter()
+        .map(|p| p.opened.max(p.merged))
+        .max()
+        .unwrap_or(0)
+        .max(1) as f64;
+    let step = CHART_WIDTH / (series.len().max(2) - 1) as f64;
+    let points = |value: fn(&FlowMetricsResponse) -> usize| {
+        series
+            .iter()
+            .enumerate()
+            .map(|(i, p)| {
+                let y = CHART_HEIGHT - value(p) as f64 / max * CHART_HEIGHT;
+                format!("{:.1},{:.1}", i as f64 * step, y)
+            })
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {w} {h}" width="{w}" height="{h}" role="img" aria-label="Opened and merged pull requests per rolling window">
+<polyline fill="none" stroke="{oc}" stroke-width="2" points="{opened}"/>
+<polyline fill="none" stroke="{mc}" stroke-width="2" points="{merged}"/>
+</svg>"#,
+        w = CHART_WIDTH,
+        h = CHART_HEIGHT,
+        oc = OPENED_COLOR,
+        mc = MERGED_COLOR,
+        opened = points(|p| p.opened),
+        merged = points(|p| p.merged),
+    )
+}
+
+fn section(out: &mut String, entry: &ReportEntry) {
+    let id = entry.repo.id.to_string();
+    let name = entry.repo.display_name.as_deref().unwrap_or(&id);
+    let _ = writeln!(out, "<section>\n<h2>{}</h2>", escape(name));
+    if entry.repo.display_name.is_some() {
+        let _ = writeln!(out, "<p class=\"muted\">{}</p>", escape(&id));
+    }
+
+    match &entry.metrics {
+        Err(error) => {
+            let _ = writeln!(out, "<p class=\"error\">Unavailable: {}</p>", escape(error));
+        }
+        Ok(metrics) => {
+            let summary = &metrics.summary;
+            let _ = writeln!(
+                out,
+                "<p>Opened <strong>{}</strong> · Merged <strong>{}</strong> · Spread <strong>{}</strong> · Merge rate <strong>{}%</strong>{}</p>",
+                summary.current_opened,
+                summary.current_merged,
+                summary.current_spread,
+                summary.merge_rate,
+                if summary.is_widening { " · widening" } else { "" },
+            );
+            if !entry.complete {
+                out.push_str(
+                    "<p class=\"muted\">Not every pull request could be fetched, so these figures are incomplete.</p>\n",
+                );
+            }
+            if let Some(narrative) = &entry.narrative {
+                let _ = writeln!(out, "<p>{}</p>", escape(narrative));
+            }
+            if !metrics.insights.is_empty() {
+                out.push_str("<ul>\n");
+                for insight in &metrics.insights {
+                    let _ = writeln!(out, "<li>{}</li>", escape(insight));
+                }
+                out.push_str("</ul>\n");
+            }
+            out.push_str(&chart(&metrics.time_series));
+            out.push('\n');
+        }
+    }
+    out.push_str("</section>\n");
+}
+
+/// Renders the report page for `entries`, generated at `generated_at`.
+pub fn render(entries: &[ReportEntry], window_days: i64, generated_at: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    let _ = write!(
+        out,
+        r#"<!doctype html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>RepoFlow report</title>
+<style>
+body {{ font-family: system-ui, sans-serif; max-width: 720px; margin: 2rem auto; color: #111827; }}
+section {{ border-top: 1px solid #e5e7eb; padding: 1rem 0; }}
+.muted {{ color: #6b7280; }}
+.error {{ color: #b91c1c; }}
+.key span {{ margin-right: 1rem; }}
+</style>
+</head>
+<body>
+<h1>RepoFlow report</h1>
+<p class="muted">Rolling {window}-day windows, generated {generated}.</p>
+<p class="key"><span style="color: {oc}">■ Opened</span><span style="color: {mc}">■ Merged</span></p>
+"#,
+        window = window_days,
+        generated = generated_at.format("%Y-%m-%d %H:%M UTC"),
+        oc = OPENED_COLOR,
+        mc = MERGED_COLOR,
+    );
+    for entry in entries {
+        section(&mut out, entry);
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use repoflow_core::domain::RepoId;
+    use repoflow_core::metrics::calculate_metrics;
+
+    fn repo(owner: &str, repo: &str) -> PopularRepo {
+        PopularRepo::from(RepoId::try_from((owner, repo)).unwrap())
+    }
+
+    #[test]
+    fn test_escape() {
+        assert_eq!(
+            escape(r#"<a href="x">Tom & Jerry's</a>"#),
+            "&lt;a href=&quot;x&quot;&gt;Tom &amp; Jerry&#39;s&lt;/a&gt;"
+        );
+    }
+
+    #[test]
+    fn test_chart_scales_to_largest_value() {
+        let point = |opened, merged| FlowMetricsResponse {
+            opened,
+            merged,
+            ..Default::default()
+        };
+        let svg = chart(&[point(0, 0), point(4, 2)]);
+        assert!(svg.contains(r#"points="0.0,160.0 640.0,0.0""#));
+        assert!(svg.contains(r#"points="0.0,160.0 640.0,80.0""#));
+        // A single point or an all-zero series must not divide by zero.
+        assert!(!chart(&[point(0, 0)]).contains("NaN"));
+    }
+
+    #[test]
+    fn test_render() {
+        let now = Utc::now();
+        let mut metrics = calculate_metrics(
+            &[],
+            chrono::Duration::days(3),
+            chrono::Duration::days(30),
+            now,
+        );
+        metrics.insights = vec!["Backlog grew by 5 PRs".to_string()];
+        let entries = vec![
+            ReportEntry {
+                repo: PopularRepo {
+                    display_name: Some("<Script>".to_string()),
+                    ..repo("owner", "repo")
+                },
+                metrics: Ok(metrics),
+                complete: false,
+                narrative: Some("Flow is <steady>.".to_string()),
+            },
+            ReportEntry {
+                repo: repo("owner", "missing"),
+                metrics: Err("repository not found".to_string()),
+                complete: true,
+                narrative: None,
+            },
+        ];
+
+        let html = render(&entries, 30, now);
+        assert!(html.contains("<h2>&lt;Script&gt;</h2>"));
+        assert!(html.contains("owner/repo"));
+        assert!(html.contains("incomplete"));
+        assert!(html.contains("<li>Backlog grew by 5 PRs</li>"));
+        assert!(html.contains("<p>Flow is &lt;steady&gt;.</p>"));
+        assert!(html.contains("<h2>owner/missing</h2>"));
+        assert!(html.contains("Unavailable: repository not found"));
+        assert_eq!(html.matches("<svg").count(), 1);
+    }
+}
@@ -0,0 +1,92 @@
+//! Request IDs for correlating client-visible errors with server logs.
+//!
+//! A client may supply its own `X-Request-Id` (e.g. from an upstream proxy); otherwise one is
+//! generated. The ID is echoed in the response header, recorded on the request's tracing span and
+//! included in error bodies.
+
+use axum::{
+    extract::Request,
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use rand::{distributions::Alphanumeric, Rng};
+use std::future::Future;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longer client-supplied IDs are replaced rather than echoed into logs and responses.
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+const GENERATED_ID_LEN: usize = 16;
+
+/// The ID of the request being handled, stored in its extensions.
+#[derive(Clone, Debug)]
+pub struct RequestId(pub String);
+
+tokio::task_local! {
+    static CURRENT: RequestId;
+}
+
+/// The ID of the request whose handler is currently running, if any.
+pub fn current() -> Option<String> {
+    CURRENT.try_with(|id| id.0.clone()).ok()
+}
+
+/// Carries the current request's ID into `future`, for work spawned off the handler.
+pub fn propagate<F: Future>(future: F) -> impl Future<Output = F::Output> {
+    let id = current();
+    async move {
+        match id {
+            Some(id) => CURRENT.scope(RequestId(id), future).await,
+            None => future.await,
+        }
+    }
+}
+
+fn is_acceptable(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || matches!(b, b'-' | b'_' | b'.'))
+}
+
+fn generate() -> String {
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(GENERATED_ID_LEN)
+        .map(char::from)
+        .collect()
+}
+
+/// Middleware assigning every request an ID.
+pub async fn assign(mut request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&REQUEST_ID_HEADER)
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| is_acceptable(id))
+        .map_or_else(generate, str::to_string);
+    let header = HeaderValue::from_str(&id).expect("request IDs are ASCII");
+    request.extensions_mut().insert(RequestId(id.clone()));
+
+    let mut response = CURRENT.scope(RequestId(id), next.run(request)).await;
+    response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_acceptable() {
+        assert!(is_acceptable("req-123_abc.1"));
+        assert!(is_acceptable(&generate()));
+        assert!(!is_acceptable(""));
+        assert!(!is_acceptable("has space"));
+        assert!(!is_acceptable("line\nbreak"));
+        assert!(!is_acceptable(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+    }
+}
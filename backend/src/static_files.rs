@@ -0,0 +1,68 @@
+//! Serves the built frontend from `STATIC_DIR`.
+//!
+//! `index.html` is served with a `<base href>` pointing at `BASE_PATH`, so the frontend's relative
+//! asset and API URLs resolve correctly both under a reverse-proxy prefix and from deep links.
+
+use axum::{
+    handler::HandlerWithoutStateExt,
+    http::StatusCode,
+    response::{Html, IntoResponse, Response},
+    routing::get,
+    Router,
+};
+use repoflow_core::config::AppConfig;
+use std::path::PathBuf;
+use tower_http::services::ServeDir;
+
+/// Routes serving the frontend: `/` and every path matching a file, plus the index for all other
+/// paths when `SPA_FALLBACK` is on.
+pub fn router<S: Clone + Send + Sync + 'static>(config: &AppConfig) -> Router<S> {
+    let dir = config.static_dir.clone();
+    let base_path = config.base_path.clone();
+    let index = move || index(dir.join("index.html"), base_path.clone());
+
+    let files = ServeDir::new(&config.static_dir).append_index_html_on_directories(false);
+    let router = Router::new().route("/", get(index.clone()));
+    let router = if config.spa_fallback {
+        router.fallback_service(files.not_found_service(index.into_service()))
+    } else {
+        router.fallback_service(files)
+    };
+    router.layer(crate::concurrency_limit(config.static_concurrency_limit))
+}
+
+async fn index(path: PathBuf, base_path: String) -> Response {
+    match tokio::fs::read_to_string(&path).await {
+        Ok(html) => Html(with_base(&html, &base_path)).into_response(),
+        Err(e) => {
+            tracing::warn!("Failed to read {}: {}", path.display(), e);
+            StatusCode::NOT_FOUND.into_response()
+        }
+    }
+}
+
+/// Inserts a `<base>` element at the start of `<head>`, before anything that loads URLs.
+fn with_base(html: &str, base_path: &str) -> String {
+    let base = format!("<base href=\"{}/\">", base_path);
+    match html.find("<head>") {
+        Some(start) => {
+            let end = start + "<head>".len();
+            format!("{}{}{}", &html[..end], base, &html[end..])
+        }
+        None => format!("{}{}", base, html),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_with_base() {
+        assert_eq!(
+            with_base("<html><head><title>x</title></head></html>", "/repoflow"),
+            "<html><head><base href=\"/repoflow/\"><title>x</title></head></html>"
+        );
+        assert_eq!(with_base("<p>bare</p>", ""), "<base href=\"/\"><p>bare</p>");
+    }
+}
@@ -0,0 +1,227 @@
+//! Teams: named sets of GitHub logins, for metrics of just a team's pull requests and a per-team
+//! breakdown of a repository.
+//!
+//! Only team-level numbers are reported. Teams need `MIN_TEAM_MEMBERS` people, and team views
+//! leave out the longest open pull requests, which name their authors.
+
+use crate::encoding::{Encodable, Encoded, Format};
+use crate::error::ApiError;
+use crate::extract::{Json, Path, Query};
+use crate::AppState;
+use anyhow::Context;
+use axum::{
+    extract::State,
+    http::StatusCode,
+    routing::{get, put},
+    Router,
+};
+use axum_extra::extract::cookie::CookieJar;
+use repoflow_core::config;
+use repoflow_core::domain::GitHubPR;
+use repoflow_core::metrics::SummaryMetrics;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::path::PathBuf;
+use std::sync::Arc;
+use tokio::sync::RwLock;
+
+/// Team definitions, saved to `TEAMS_FILE` when one is configured.
+pub struct TeamStore {
+    teams: RwLock<BTreeMap<String, Vec<String>>>,
+    file: Option<PathBuf>,
+}
+
+impl TeamStore {
+    pub fn new(teams: BTreeMap<String, Vec<String>>, file: Option<PathBuf>) -> Self {
+        Self {
+            teams: RwLock::new(teams),
+            file,
+        }
+    }
+
+    pub async fn members(&self, team: &str) -> Option<Vec<String>> {
+        self.teams.read().await.get(team).cloned()
+    }
+
+    async fn list(&self) -> BTreeMap<String, Vec<String>> {
+        self.teams.read().await.clone()
+    }
+
+    async fn put(&self, team: String, members: Vec<String>) -> anyhow::Result<()> {
+        let mut teams = self.teams.write().await;
+        let mut updated = teams.clone();
+        updated.insert(team, members);
+        self.persist(&updated).await?;
+        *teams = updated;
+        Ok(())
+    }
+
+    /// Deletes a team, returning whether it existed.
+    async fn delete(&self, team: &str) -> anyhow::Result<bool> {
+        let mut teams = self.teams.write().await;
+        if !teams.contains_key(team) {
+            return Ok(false);
+        }
+        let mut updated = teams.clone();
+        updated.remove(team);
+        self.persist(&updated).await?;
+        *teams = updated;
+        Ok(true)
+    }
+
+    /// Writes the teams to the configured file, replacing it atomically.
+    async fn persist(&self, teams: &BTreeMap<String, Vec<String>>) -> anyhow::Result<()> {
+        let Some(path) = &self.file else {
+            return Ok(());
+        };
+        let tmp = path.with_extension("tmp");
+        tokio::fs::write(&tmp, serde_json::to_vec_pretty(teams)?)
+            .await
+            .with_context(|| format!("failed to write {}", tmp.display()))?;
+        tokio::fs::rename(&tmp, path)
+            .await
+            .with_context(|| format!("failed to replace {}", path.display()))
+    }
+}
+
+/// Selects the pull requests opened by one of `members`. GitHub logins are case-insensitive.
+pub fn authored_by(members: &[String]) -> impl Fn(&GitHubPR) -> bool {
+    let logins: HashSet<String> = members.iter().map(|m| m.to_ascii_lowercase()).collect();
+    move |pr| {
+        pr.author
+            .as_ref()
+            .is_some_and(|author| logins.contains(&author.to_ascii_lowercase()))
+    }
+}
+
+#[derive(Deserialize)]
+struct TeamRequest {
+    members: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct BreakdownParams {
+    /// Rolling window size in days; defaults to `METRICS_WINDOW_SIZE`.
+    window: Option<i64>,
+}
+
+/// The flow of each team's pull requests in one repository.
+#[derive(Serialize)]
+struct TeamBreakdown {
+    window_days: i64,
+    teams: Vec<TeamFlow>,
+}
+
+#[derive(Serialize)]
+struct TeamFlow {
+    team: String,
+    summary: SummaryMetrics,
+}
+
+impl Encodable for TeamBreakdown {}
+
+/// The per-team breakdown of a repository, mounted with the other repository routes.
+pub fn repo_router() -> Router<Arc<AppState>> {
+    Router::new().route("/repos/{owner}/{repo}/teams", get(get_repo_teams))
+}
+
+/// Routes for defining teams, mounted with the other admin routes.
+pub fn admin_router() -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/teams", get(list_teams))
+        .route("/admin/teams/{team}", put(put_team).delete(delete_team))
+}
+
+async fn list_teams(State(state): State<Arc<AppState>>) -> Json<BTreeMap<String, Vec<String>>> {
+    Json(state.teams.list().await)
+}
+
+async fn put_team(
+    State(state): State<Arc<AppState>>,
+    Path(team): Path<String>,
+    Json(request): Json<TeamRequest>,
+) -> Result<StatusCode, ApiError> {
+    let problems = config::team_problems(&team, &request.members);
+    if !problems.is_empty() {
+        return Err(ApiError::bad_request(problems.join("; ")));
+    }
+    state
+        .teams
+        .put(team.clone(), request.members)
+        .await
+        .map_err(|e| {
+            tracing::error!("Failed to save team {}: {:#}", team, e);
+            ApiError::internal()
+        })?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+async fn delete_team(
+    State(state): State<Arc<AppState>>,
+    Path(team): Path<String>,
+) -> Result<StatusCode, ApiError> {
+    match state.teams.delete(&team).await {
+        Ok(true) => Ok(StatusCode::NO_CONTENT),
+        Ok(false) => Err(ApiError::not_found("Team not found")),
+        Err(e) => {
+            tracing::error!("Failed to delete team {}: {:#}", team, e);
+            Err(ApiError::internal())
+        }
+    }
+}
+
+/// Summaries of each team's pull requests in a repository.
+async fn get_repo_teams(
+    Path((owner, repo)): Path<(String, String)>,
+    Query(params): Query<BreakdownParams>,
+    format: Format,
+    State(state): State<Arc<AppState>>,
+    jar: CookieJar,
+) -> Result<Encoded<TeamBreakdown>, ApiError> {
+    let repo_id = crate::parse_repo_id(&owner, &repo)?;
+    let window_days = crate::window_days(&state, params.window)?;
+    let credentials = match &state.auth {
+        Some(auth) => auth.credentials(&jar).await,
+        None => None,
+    };
+    let cached = match crate::fetch_metrics(&state, &repo_id, credentials.clone()).await {
+        Ok(cached) => cached,
+        Err(e) => {
+            return Err(
+                crate::upstream_error(&state, &repo_id, "PRs", credentials.as_ref(), e).await,
+            )
+        }
+    };
+    let teams = state
+        .teams
+        .list()
+        .await
+        .into_iter()
+        .map(|(team, members)| TeamFlow {
+            summary: state
+                .service
+                .metrics_where(&cached, window_days, authored_by(&members))
+                .summary,
+            team,
+        })
+        .collect();
+    Ok(Encoded::new(format, TeamBreakdown { window_days, teams }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_authored_by_ignores_case() {
+        let keep = authored_by(&["Alice".to_string(), "bob".to_string()]);
+        let pr = |author: Option<&str>| GitHubPR {
+            author: author.map(str::to_string),
+            ..Default::default()
+        };
+        assert!(keep(&pr(Some("alice"))));
+        assert!(keep(&pr(Some("BOB"))));
+        assert!(!keep(&pr(Some("carol"))));
+        assert!(!keep(&pr(None)));
+    }
+}
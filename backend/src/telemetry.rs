@@ -0,0 +1,275 @@
+//! Per-route request latency histograms and error counters.
+//!
+//! Each matched route (e.g. `/api/v1/repos/{owner}/{repo}/metrics`) gets a fixed-bucket latency
+//! histogram and 4xx/5xx counters. They are exported in the Prometheus text format at `/metrics`
+//! and summarized, with estimated percentiles, in the admin status endpoint.
+
+use crate::AppState;
+use axum::{
+    extract::{MatchedPath, Request, State},
+    http::{header::CONTENT_TYPE, HeaderValue},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, RwLock};
+use std::time::Instant;
+
+/// Upper bounds (in seconds) of the latency histogram buckets.
+const BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct RouteKey {
+    method: String,
+    route: String,
+}
+
+#[derive(Clone, Debug, Default)]
+struct RouteStats {
+    /// Non-cumulative counts per bucket; the extra final slot counts observations above the
+    /// largest bound.
+    buckets: [u64; BUCKETS.len() + 1],
+    count: u64,
+    sum_seconds: f64,
+    client_errors: u64,
+    server_errors: u64,
+}
+
+impl RouteStats {
+    fn observe(&mut self, seconds: f64, status: u16) {
+        let bucket = BUCKETS
+            .iter()
+            .position(|bound| seconds <= *bound)
+            .unwrap_or(BUCKETS.len());
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_seconds += seconds;
+        match status {
+            400..=499 => self.client_errors += 1,
+            500..=599 => self.server_errors += 1,
+            _ => {}
+        }
+    }
+
+    /// Estimates a quantile as the upper bound of the bucket containing it.
+    fn quantile(&self, q: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let target = (q * self.count as f64).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (i, n) in self.buckets.iter().enumerate() {
+            seen += n;
+            if seen >= target {
+                return Some(BUCKETS.get(i).copied().unwrap_or(f64::INFINITY));
+            }
+        }
+        None
+    }
+}
+
+/// Summary of one route's latency and errors for the status endpoint.
+#[derive(Debug, Serialize)]
+pub struct RouteSummary {
+    pub method: String,
+    pub route: String,
+    pub requests: u64,
+    pub client_errors: u64,
+    pub server_errors: u64,
+    pub mean_seconds: f64,
+    /// Estimated from histogram buckets; `null` when above the largest bucket.
+    pub p50_seconds: Option<f64>,
+    pub p99_seconds: Option<f64>,
+}
+
+/// Collects latency and error statistics for every matched route.
+#[derive(Default)]
+pub struct RouteMetrics {
+    routes: RwLock<BTreeMap<RouteKey, RouteStats>>,
+}
+
+impl RouteMetrics {
+    pub fn observe(&self, method: &str, route: &str, seconds: f64, status: u16) {
+        let key = RouteKey {
+            method: method.to_string(),
+            route: route.to_string(),
+        };
+        self.routes
+            .write()
+            .expect("route metrics lock poisoned")
+            .entry(key)
+            .or_default()
+            .observe(seconds, status);
+    }
+
+    pub fn summaries(&self) -> Vec<RouteSummary> {
+        self.routes
+            .read()
+            .expect("route metrics lock poisoned")
+            .iter()
+            .map(|(key, stats)| RouteSummary {
+                method: key.method.clone(),
+                route: key.route.clone(),
+                requests: stats.count,
+                client_errors: stats.client_errors,
+                server_errors: stats.server_errors,
+                mean_seconds: stats.sum_seconds / stats.count.max(1) as f64,
+                p50_seconds: stats.quantile(0.5).filter(|v| v.is_finite()),
+                p99_seconds: stats.quantile(0.99).filter(|v| v.is_finite()),
+            })
+            .collect()
+    }
+
+    /// Renders all series in the Prometheus text exposition format.
+    pub fn render_prometheus(&self) -> String {
+        let routes = self.routes.read().expect("route metrics lock poisoned");
+        let mut out = String::new();
+
+        out.push_str(
+            "# HELP repoflow_http_request_duration_seconds HTTP request latency by route.\n",
+        );
+        out.push_str("# TYPE repoflow_http_request_duration_seconds histogram\n");
+        for (key, stats) in routes.iter() {
+            let labels = labels(key);
+            let mut cumulative = 0;
+            for (bound, n) in BUCKETS.iter().zip(stats.buckets.iter()) {
+                cumulative += n;
+                let _ = writeln!(
+                    out,
+                    "repoflow_http_request_duration_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, bound, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "repoflow_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, stats.count
+            );
+            let _ = writeln!(
+                out,
+                "repoflow_http_request_duration_seconds_sum{{{}}} {}",
+                labels, stats.sum_seconds
+            );
+            let _ = writeln!(
+                out,
+                "repoflow_http_request_duration_seconds_count{{{}}} {}",
+                labels, stats.count
+            );
+        }
+
+        out.push_str("# HELP repoflow_http_request_errors_total HTTP error responses by route.\n");
+        out.push_str("# TYPE repoflow_http_request_errors_total counter\n");
+        for (key, stats) in routes.iter() {
+            let labels = labels(key);
+            let _ = writeln!(
+                out,
+                "repoflow_http_request_errors_total{{{},class=\"4xx\"}} {}",
+                labels, stats.client_errors
+            );
+            let _ = writeln!(
+                out,
+                "repoflow_http_request_errors_total{{{},class=\"5xx\"}} {}",
+                labels, stats.server_errors
+            );
+        }
+
+        out
+    }
+}
+
+fn labels(key: &RouteKey) -> String {
+    format!(
+        "method=\"{}\",route=\"{}\"",
+        escape_label(&key.method),
+        escape_label(&key.route)
+    )
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Route-level middleware recording the latency and status of each matched request.
+pub async fn track(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response {
+    let Some(route) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|p| p.as_str().to_string())
+    else {
+        return next.run(request).await;
+    };
+    let method = request.method().to_string();
+    let start = Instant::now();
+
+    let response = next.run(request).await;
+
+    state.telemetry.observe(
+        &method,
+        &route,
+        start.elapsed().as_secs_f64(),
+        response.status().as_u16(),
+    );
+    response
+}
+
+/// Serves the Prometheus scrape endpoint.
+pub async fn prometheus(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    (
+        [(
+            CONTENT_TYPE,
+            HeaderValue::from_static("text/plain; version=0.0.4"),
+        )],
+        state.telemetry.render_prometheus(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantiles_from_buckets() {
+        let mut stats = RouteStats::default();
+        for _ in 0..98 {
+            stats.observe(0.003, 200);
+        }
+        stats.observe(0.2, 500);
+        stats.observe(20.0, 404);
+
+        assert_eq!(stats.quantile(0.5), Some(0.005));
+        assert_eq!(stats.quantile(0.99), Some(0.25));
+        assert_eq!(stats.quantile(1.0), Some(f64::INFINITY));
+        assert_eq!(stats.client_errors, 1);
+        assert_eq!(stats.server_errors, 1);
+        assert_eq!(RouteStats::default().quantile(0.5), None);
+    }
+
+    #[test]
+    fn test_render_prometheus() {
+        let metrics = RouteMetrics::default();
+        metrics.observe("GET", "/api/repos/{owner}/{repo}/metrics", 0.02, 200);
+        metrics.observe("GET", "/api/repos/{owner}/{repo}/metrics", 0.3, 500);
+
+        let text = metrics.render_prometheus();
+        let labels = r#"method="GET",route="/api/repos/{owner}/{repo}/metrics""#;
+        assert!(text.contains(&format!(
+            "repoflow_http_request_duration_seconds_bucket{{{},le=\"0.025\"}} 1",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "repoflow_http_request_duration_seconds_bucket{{{},le=\"+Inf\"}} 2",
+            labels
+        )));
+        assert!(text.contains(&format!(
+            "repoflow_http_request_errors_total{{{},class=\"5xx\"}} 1",
+            labels
+        )));
+    }
+}
@@ -0,0 +1,185 @@
+//! Serves several tenants from one deployment. Each tenant is a complete app with its own state,
+//! so caches, GitHub rate limits and API key quotas never mix; this module only picks which app
+//! handles a request, by `Host` header first and then by path prefix.
+
+use axum::extract::Request;
+use axum::http::header::HOST;
+use axum::Router;
+use repoflow_core::config::{AppConfig, Tenant};
+use std::sync::Arc;
+use tower::ServiceExt;
+
+/// A tenant's app and the requests it serves.
+pub struct TenantApp {
+    /// Lowercase hostnames, without a port.
+    pub hosts: Vec<String>,
+    /// The full path prefix, including `BASE_PATH`; empty for host-selected tenants.
+    pub base_path: String,
+    pub app: Router,
+}
+
+impl TenantApp {
+    /// Wraps `app`, built from `tenant_config` (see `AppConfig::for_tenant`), as `tenant`.
+    pub fn new(tenant: &Tenant, tenant_config: &AppConfig, app: Router) -> Self {
+        Self {
+            hosts: tenant
+                .hosts
+                .iter()
+                .map(|h| h.to_ascii_lowercase())
+                .collect(),
+            base_path: if tenant.path_prefix.is_empty() {
+                String::new()
+            } else {
+                tenant_config.base_path.clone()
+            },
+            app,
+        }
+    }
+}
+
+struct Tenants {
+    default: Router,
+    tenants: Vec<TenantApp>,
+}
+
+impl Tenants {
+    fn select(&self, request: &Request) -> &Router {
+        let host = request
+            .headers()
+            .get(HOST)
+            .and_then(|host| host.to_str().ok())
+            .or_else(|| request.uri().host())
+            .map(|host| {
+                host.split(':')
+                    .next()
+                    .unwrap_or_default()
+                    .to_ascii_lowercase()
+            });
+        if let Some(host) = host {
+            if let Some(tenant) = self.tenants.iter().find(|t| t.hosts.contains(&host)) {
+                return &tenant.app;
+            }
+        }
+
+        let path = request.uri().path();
+        self.tenants
+            .iter()
+            .filter(|t| !t.base_path.is_empty())
+            .filter(|t| {
+                path.strip_prefix(t.base_path.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+            })
+            .max_by_key(|t| t.base_path.len())
+            .map_or(&self.default, |t| &t.app)
+    }
+}
+
+/// Routes each request to the tenant it belongs to, or to `default` when it matches none.
+pub fn router(default: Router, tenants: Vec<TenantApp>) -> Router {
+    if tenants.is_empty() {
+        return default;
+    }
+    let tenants = Arc::new(Tenants { default, tenants });
+    Router::new().fallback_service(tower::service_fn(move |request: Request| {
+        let app = tenants.select(&request).clone();
+        app.oneshot(request)
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{get_json, pr, send, test_app, test_config, MockPullRequestSource};
+    use axum::body::Body;
+    use axum::http::StatusCode;
+
+    fn tenant(config: &AppConfig, tenant: Tenant, source: MockPullRequestSource) -> TenantApp {
+        let tenant_config = config.for_tenant(&tenant);
+        TenantApp::new(
+            &tenant,
+            &tenant_config,
+            test_app(tenant_config.clone(), source),
+        )
+    }
+
+    fn app() -> Router {
+        let config = test_config(&[("API_KEYS", "shared:sk_shared:1")]);
+        let tenant_json = |json: &str| serde_json::from_str::<Tenant>(json).unwrap();
+        let payments = tenant(
+            &config,
+            tenant_json(
+                r#"{"name": "payments", "hosts": ["Payments.example.com"], "api_keys": "shared:sk_shared:1"}"#,
+            ),
+            MockPullRequestSource::default().with_repo("acme/billing", vec![pr(1, 2, None)]),
+        );
+        let search = tenant(
+            &config,
+            tenant_json(r#"{"name": "search", "path_prefix": "/search"}"#),
+            MockPullRequestSource::default().with_repo("acme/index", vec![pr(1, 2, None)]),
+        );
+        let default = test_app(
+            config,
+            MockPullRequestSource::default().with_repo("acme/site", vec![pr(1, 2, None)]),
+        );
+        router(default, vec![payments, search])
+    }
+
+    async fn get_from(app: Router, host: &str, uri: &str, api_key: Option<&str>) -> StatusCode {
+        let mut request = Request::get(uri).header(HOST, host);
+        if let Some(key) = api_key {
+            request = request.header("x-api-key", key);
+        }
+        let (status, _, _) = send(app, request.body(Body::empty()).unwrap()).await;
+        status
+    }
+
+    #[tokio::test]
+    async fn test_selects_tenant_by_host_and_path() {
+        let app = app();
+        let metrics = |repo: &str| format!("/api/v1/repos/acme/{}/metrics", repo);
+
+        assert_eq!(
+            get_from(
+                app.clone(),
+                "payments.example.com:8080",
+                &metrics("billing"),
+                None
+            )
+            .await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get_from(app.clone(), "payments.example.com", &metrics("site"), None).await,
+            StatusCode::NOT_FOUND
+        );
+        assert_eq!(
+            get_from(app.clone(), "flow.example.com", &metrics("site"), None).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            get_from(app.clone(), "flow.example.com", &metrics("billing"), None).await,
+            StatusCode::NOT_FOUND
+        );
+
+        let (status, _) = get_json(app.clone(), "/search/api/v1/repos/acme/index/metrics").await;
+        assert_eq!(status, StatusCode::OK);
+        // Only whole segments match a prefix.
+        let (status, _) = get_json(app, "/searching/api/v1/repos/acme/index/metrics").await;
+        assert_ne!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_quotas_are_isolated() {
+        let app = app();
+        let uri = "/api/v1/repos/acme/site/metrics";
+        let billing = "/api/v1/repos/acme/billing/metrics";
+
+        let status = get_from(app.clone(), "flow.example.com", uri, Some("sk_shared")).await;
+        assert_eq!(status, StatusCode::OK);
+        let status = get_from(app.clone(), "flow.example.com", uri, Some("sk_shared")).await;
+        assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+        // The same key in another tenant has its own allowance.
+        let status = get_from(app, "payments.example.com", billing, Some("sk_shared")).await;
+        assert_eq!(status, StatusCode::OK);
+    }
+}
@@ -0,0 +1,40 @@
+//! Fixtures for exercising the HTTP API end-to-end without calling GitHub.
+
+pub use repoflow_core::test_support::*;
+
+use crate::AppState;
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use axum::Router;
+use repoflow_core::config::AppConfig;
+use repoflow_core::service::MetricsService;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+/// Builds the full application router on top of `source`.
+pub fn test_app(config: AppConfig, source: MockPullRequestSource) -> Router {
+    let service = MetricsService::with_source(&config, Arc::new(source));
+    let state = AppState::with_service(config, service).expect("valid test state");
+    crate::app(Arc::new(state))
+}
+
+/// Sends a request through the router and returns the status and raw body.
+pub async fn send(
+    app: Router,
+    request: Request<Body>,
+) -> (StatusCode, axum::http::HeaderMap, Vec<u8>) {
+    let response = app.oneshot(request).await.expect("router is infallible");
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .expect("failed to read body");
+    (status, headers, body.to_vec())
+}
+
+/// Issues a GET and parses the response body as JSON (`Null` if it isn't JSON).
+pub async fn get_json(app: Router, uri: &str) -> (StatusCode, serde_json::Value) {
+    let request = Request::get(uri).body(Body::empty()).unwrap();
+    let (status, _, body) = send(app, request).await;
+    (status, serde_json::from_slice(&body).unwrap_or_default())
+}
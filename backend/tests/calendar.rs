@@ -0,0 +1,93 @@
+//! Building a repository's calendar feed against a fake GitHub server.
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use repoflow_core::config::AppConfig;
+use serde_json::json;
+use tower::ServiceExt;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const RELEASES: &str = "/repos/acme/widgets/releases";
+const MILESTONES: &str = "/repos/acme/widgets/milestones";
+
+#[tokio::test]
+async fn test_releases_and_milestones_become_events() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(RELEASES))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([
+            {
+                "tag_name": "v2.0.0-rc1",
+                "name": null,
+                "draft": true,
+                "published_at": null,
+                "html_url": "https://github.com/acme/widgets/releases/tag/untagged",
+            },
+            {
+                "tag_name": "v1.0.0",
+                "name": "First stable",
+                "draft": false,
+                "published_at": "2026-03-02T15:00:00Z",
+                "html_url": "https://github.com/acme/widgets/releases/tag/v1.0.0",
+            },
+        ])))
+        .mount(&server)
+        .await;
+    let next = format!(r#"<{}{MILESTONES}?page=2>; rel="next""#, server.uri());
+    Mock::given(method("GET"))
+        .and(path(MILESTONES))
+        .and(query_param("state", "all"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("link", next.as_str())
+                .set_body_json(json!([{
+                    "number": 1,
+                    "title": "Beta",
+                    "state": "closed",
+                    "due_on": "2026-02-01T08:00:00Z",
+                    "html_url": "https://github.com/acme/widgets/milestone/1",
+                }])),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(MILESTONES))
+        .and(query_param("page", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{
+            "number": 2,
+            "title": "Someday",
+            "state": "open",
+            "due_on": null,
+            "html_url": "https://github.com/acme/widgets/milestone/2",
+        }])))
+        .mount(&server)
+        .await;
+
+    let vars = [
+        ("GITHUB_API_URL", server.uri().as_str()),
+        ("GITHUB_PER_PAGE", "1"),
+        ("GITHUB_MAX_RETRIES", "0"),
+        ("STATIC_DIR", "missing"),
+        ("APP_ENV", "development"),
+    ]
+    .map(|(k, v)| (k.to_string(), v.to_string()));
+    let config = AppConfig::from_vars(vars.into_iter()).unwrap();
+    let app = backend::create_app(config).await.unwrap();
+    let request = Request::get("/api/v1/repos/acme/widgets/calendar")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    let ics = String::from_utf8(body.to_vec()).unwrap();
+
+    assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+    assert!(ics.contains("SUMMARY:widgets First stable (v1.0.0)\r\n"));
+    assert!(ics.contains("DTSTART;VALUE=DATE:20260302\r\n"));
+    assert!(ics.contains("SUMMARY:widgets milestone due: Beta (closed)\r\n"));
+    assert!(!ics.contains("rc1"));
+    assert!(!ics.contains("Someday"));
+}
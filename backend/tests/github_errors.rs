@@ -0,0 +1,155 @@
+//! How failures from GitHub surface through the API, against a fake GitHub server.
+
+use axum::body::Body;
+use axum::http::{HeaderMap, Request, StatusCode};
+use repoflow_core::config::AppConfig;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const PULLS: &str = "/repos/acme/widgets/pulls";
+
+/// A fake GitHub answering the startup check, with `remaining` core requests left.
+async fn github(remaining: usize) -> MockServer {
+    let server = MockServer::start().await;
+    let rate = json!({
+        "limit": 60,
+        "used": 60 - remaining,
+        "remaining": remaining,
+        "reset": chrono::Utc::now().timestamp() + 120,
+    });
+    Mock::given(method("GET"))
+        .and(path("/rate_limit"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!({
+            "resources": {"core": rate, "search": rate},
+            "rate": rate,
+        })))
+        .mount(&server)
+        .await;
+    server
+}
+
+async fn get_metrics(server: &MockServer, max_pages: &str) -> (StatusCode, HeaderMap, Value) {
+    let vars = [
+        ("GITHUB_API_URL", server.uri().as_str()),
+        ("MAX_GITHUB_API_PAGES", max_pages),
+        ("GITHUB_PER_PAGE", "2"),
+        ("GITHUB_MAX_RETRIES", "0"),
+        ("CACHE_TTL_SECONDS", "60"),
+        ("STATIC_DIR", "missing"),
+        ("APP_ENV", "development"),
+    ]
+    .map(|(k, v)| (k.to_string(), v.to_string()));
+    let config = AppConfig::from_vars(vars.into_iter()).unwrap();
+    let app = backend::create_app(config).await.unwrap();
+
+    let request = Request::get("/api/v1/repos/acme/widgets/metrics")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, headers, serde_json::from_slice(&body).unwrap())
+}
+
+fn pull_request(id: u64) -> Value {
+    json!({
+        "url": format!("https://api.github.com/repos/acme/widgets/pulls/{}", id),
+        "id": id,
+        "number": id,
+        "state": "open",
+        "locked": false,
+        "maintainer_can_modify": false,
+        "created_at": chrono::Utc::now().to_rfc3339(),
+        "head": {"ref": "feature", "sha": "abc"},
+        "base": {"ref": "main", "sha": "def"},
+    })
+}
+
+#[tokio::test]
+async fn test_missing_repository_is_not_found() {
+    let server = github(60).await;
+    Mock::given(method("GET"))
+        .and(path(PULLS))
+        .respond_with(ResponseTemplate::new(404).set_body_json(json!({
+            "message": "Not Found",
+            "documentation_url": "https://docs.github.com/rest",
+        })))
+        .mount(&server)
+        .await;
+
+    let (status, _, body) = get_metrics(&server, "1").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+    assert_eq!(body["code"], "repo_not_found");
+    assert_eq!(body["message"], "Repository Not Found");
+}
+
+#[tokio::test]
+async fn test_rate_limit_is_too_many_requests() {
+    let server = github(0).await;
+    Mock::given(method("GET"))
+        .and(path(PULLS))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "message": "API rate limit exceeded for 203.0.113.7.",
+            "documentation_url": "https://docs.github.com/rest/rate-limit",
+        })))
+        .mount(&server)
+        .await;
+
+    let (status, headers, body) = get_metrics(&server, "1").await;
+    assert_eq!(status, StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(body["code"], "github_rate_limited");
+    // Taken from the exhausted limit's reset time, two minutes out.
+    let retry_after = body["retry_after"].as_u64().unwrap();
+    assert!((100..=120).contains(&retry_after), "{}", retry_after);
+    assert_eq!(headers["retry-after"], retry_after.to_string().as_str());
+}
+
+#[tokio::test]
+async fn test_malformed_json_is_bad_gateway() {
+    let server = github(60).await;
+    Mock::given(method("GET"))
+        .and(path(PULLS))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("content-type", "application/json")
+                .set_body_string(r#"[{"url": "https://api.github.com/repos/acme/wid"#),
+        )
+        .mount(&server)
+        .await;
+
+    let (status, _, body) = get_metrics(&server, "1").await;
+    assert_eq!(status, StatusCode::BAD_GATEWAY);
+    assert_eq!(body["code"], "github_bad_response");
+}
+
+#[tokio::test]
+async fn test_truncated_pages_are_reported() {
+    let server = github(60).await;
+    let next = format!(
+        r#"<{uri}{PULLS}?page=2>; rel="next", <{uri}{PULLS}?page=3>; rel="last""#,
+        uri = server.uri()
+    );
+    Mock::given(method("GET"))
+        .and(path(PULLS))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("link", next.as_str())
+                .set_body_json(json!([pull_request(2), pull_request(1)])),
+        )
+        .expect(1)
+        .mount(&server)
+        .await;
+
+    let (status, _, body) = get_metrics(&server, "1").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["data"]["summary"]["current_opened"], 2);
+    assert_eq!(body["meta"]["data_complete"], false);
+    assert_eq!(body["meta"]["warnings"], json!(["truncated_at_page_limit"]));
+    // Two unread pages of two.
+    assert_eq!(body["meta"]["unfetched_pull_requests"], 4);
+}
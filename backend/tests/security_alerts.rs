@@ -0,0 +1,96 @@
+//! Counting security alerts against a fake GitHub server.
+
+use axum::body::Body;
+use axum::http::{Request, StatusCode};
+use repoflow_core::config::AppConfig;
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use wiremock::matchers::{method, path, query_param};
+use wiremock::{Mock, MockServer, ResponseTemplate};
+
+const ALERTS: &str = "/repos/acme/widgets/dependabot/alerts";
+const ADVISORIES: &str = "/repos/acme/widgets/security-advisories";
+
+async fn get_security(server: &MockServer) -> (StatusCode, Value) {
+    let vars = [
+        ("GITHUB_API_URL", server.uri().as_str()),
+        ("GITHUB_TOKEN", "test-token"),
+        ("MAX_GITHUB_API_PAGES", "5"),
+        ("GITHUB_PER_PAGE", "2"),
+        ("GITHUB_MAX_RETRIES", "0"),
+        ("CACHE_TTL_SECONDS", "60"),
+        ("STATIC_DIR", "missing"),
+        ("APP_ENV", "development"),
+        ("ADMIN_TOKEN", "admin-secret"),
+    ]
+    .map(|(k, v)| (k.to_string(), v.to_string()));
+    let config = AppConfig::from_vars(vars.into_iter()).unwrap();
+    let app = backend::create_app(config).await.unwrap();
+
+    let request = Request::get("/api/v1/repos/acme/widgets/security")
+        .header("authorization", "Bearer admin-secret")
+        .body(Body::empty())
+        .unwrap();
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    (status, serde_json::from_slice(&body).unwrap())
+}
+
+#[tokio::test]
+async fn test_alerts_are_counted_across_pages() {
+    let server = MockServer::start().await;
+    let next = format!(r#"<{}{ALERTS}?page=2>; rel="next""#, server.uri());
+    Mock::given(method("GET"))
+        .and(path(ALERTS))
+        .and(query_param("state", "open"))
+        .respond_with(
+            ResponseTemplate::new(200)
+                .insert_header("link", next.as_str())
+                .set_body_json(json!([{"number": 3}, {"number": 2}])),
+        )
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(ALERTS))
+        .and(query_param("page", "2"))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{"number": 1}])))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(ADVISORIES))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([{"ghsa_id": "GHSA-1"}])))
+        .mount(&server)
+        .await;
+
+    let (status, body) = get_security(&server).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["open_dependabot_alerts"], 3);
+    assert_eq!(body["published_advisories"], 1);
+    assert_eq!(body["complete"], true);
+}
+
+#[tokio::test]
+async fn test_disabled_alerts_are_null() {
+    let server = MockServer::start().await;
+    Mock::given(method("GET"))
+        .and(path(ALERTS))
+        .respond_with(ResponseTemplate::new(403).set_body_json(json!({
+            "message": "Dependabot alerts are disabled for this repository.",
+            "documentation_url": "https://docs.github.com/rest",
+        })))
+        .mount(&server)
+        .await;
+    Mock::given(method("GET"))
+        .and(path(ADVISORIES))
+        .respond_with(ResponseTemplate::new(200).set_body_json(json!([])))
+        .mount(&server)
+        .await;
+
+    let (status, body) = get_security(&server).await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(body["open_dependabot_alerts"], Value::Null);
+    assert_eq!(body["published_advisories"], 0);
+}
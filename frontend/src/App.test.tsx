@@ -8,13 +8,22 @@ describe('App', () => {
     // Mock API calls
     vi.spyOn(api, 'fetchPopularRepos').mockResolvedValue([])
     const fetchSpy = vi.spyOn(api, 'fetchRepoMetrics').mockResolvedValue({
-      time_series: [],
-      summary: {
-        current_opened: 0,
-        current_merged: 0,
-        current_spread: 0,
-        merge_rate: 0,
-        is_widening: false,
+      data: {
+        time_series: [],
+        summary: {
+          current_opened: 0,
+          current_merged: 0,
+          current_spread: 0,
+          merge_rate: 0,
+          is_widening: false,
+        },
+      },
+      meta: {
+        fetched_at: '2024-01-01T00:00:00Z',
+        cache_age_seconds: 0,
+        window_days: 30,
+        data_complete: true,
+        warnings: [],
       },
     })
 
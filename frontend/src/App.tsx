@@ -1,6 +1,6 @@
 import { useState, useEffect, useCallback } from 'react'
 import type { JSX, ChangeEvent, FormEvent } from 'react'
-import type { FlowMetrics, SummaryMetrics, PopularRepo } from './types'
+import type { FlowMetrics, SummaryMetrics, PopularRepo, MetricsMeta } from './types'
 import { Input } from './components/ui/Input'
 import { Button } from './components/ui/Button'
 import { FlowChart } from './components/FlowChart'
@@ -10,7 +10,7 @@ import { PopularRepoChip } from './components/PopularRepoChip'
 import { TrendDirection } from './types'
 import { parseGitHubUrl } from './utils/parser'
 import { fetchRepoMetrics, fetchPopularRepos } from './utils/api'
-import { isSameRepo } from './utils/utils'
+import { formatAge, isSameRepo } from './utils/utils'
 import { Loader2, AlertCircle, Star } from 'lucide-react'
 
 function App(): JSX.Element {
@@ -18,6 +18,7 @@ function App(): JSX.Element {
   const [activeRepo, setActiveRepo] = useState<PopularRepo | null>(null)
   const [data, setData] = useState<FlowMetrics[]>([])
   const [summary, setSummary] = useState<SummaryMetrics | null>(null)
+  const [meta, setMeta] = useState<MetricsMeta | null>(null)
   const [loading, setLoading] = useState<boolean>(true)
   const [error, setError] = useState<string | null>(null)
   const [popularRepos, setPopularRepos] = useState<PopularRepo[]>([])
@@ -36,8 +37,9 @@ function App(): JSX.Element {
 
     try {
       const response = await fetchRepoMetrics(repoDetails.owner, repoDetails.repo)
-      setData(response.time_series)
-      setSummary(response.summary)
+      setData(response.data.time_series)
+      setSummary(response.data.summary)
+      setMeta(response.meta)
       setActiveRepo(repoDetails)
     } catch (err) {
       setError(err instanceof Error ? err.message : 'An unknown error occurred')
@@ -168,6 +170,14 @@ function App(): JSX.Element {
           </div>
         )}
 
+        {meta && (
+          <p className="mb-4 font-base text-gray-600">
+            Data as of {formatAge(meta.cache_age_seconds)}
+            {meta.warnings.includes('truncated_at_page_limit') &&
+              ` · Up to ${meta.unfetched_pull_requests ?? 'some'} older pull requests were not fetched, so counts may be low.`}
+          </p>
+        )}
+
         <div
           className={`transition-opacity duration-300 ${loading ? 'opacity-50' : 'opacity-100'}`}
         >
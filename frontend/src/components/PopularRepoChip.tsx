@@ -11,13 +11,15 @@ export const PopularRepoChip = ({ repo, isActive, onClick }: PopularRepoChipProp
   return (
     <button
       onClick={() => onClick(repo)}
+      title={repo.category}
       className={`px-4 py-2 border-2 border-black font-heading transition-all active:translate-x-0 active:translate-y-0 active:shadow-none ${
         isActive
           ? 'bg-main translate-x-[-2px] translate-y-[-2px] shadow-base'
           : 'bg-white hover:bg-main hover:translate-x-[-2px] hover:translate-y-[-2px] hover:shadow-base'
       }`}
     >
-      {repo.owner}/{repo.repo}
+      {repo.display_name ?? `${repo.owner}/${repo.repo}`}
+      {repo.summary && <span className="ml-2 text-sm opacity-70">{repo.summary.merge_rate}%</span>}
     </button>
   )
 }
@@ -38,10 +38,44 @@ export interface RepoMetricsResponse {
   time_series: FlowMetrics[]
 }
 
+/**
+ * Freshness metadata returned alongside repository metrics.
+ */
+export interface MetricsMeta {
+  fetched_at: string
+  cache_age_seconds: number
+  window_days: number
+  data_complete: boolean
+  warnings: string[]
+  unfetched_pull_requests?: number
+}
+
+/**
+ * The envelope wrapping repository metrics from the backend.
+ */
+export interface RepoMetricsEnvelope {
+  data: RepoMetricsResponse
+  meta: MetricsMeta
+}
+
 /**
  * Represents a popular repository returned by the backend.
  */
 export interface PopularRepo {
   owner: string
   repo: string
+  display_name?: string
+  category?: string
+  /** Cached summary, null when the repository hasn't been fetched yet. */
+  summary?: SummaryMetrics | null
+}
+
+/**
+ * The JSON body of an error response from the backend.
+ */
+export interface ApiErrorBody {
+  code: string
+  message: string
+  retry_after: number | null
+  request_id: string | null
 }
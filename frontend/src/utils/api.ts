@@ -1,26 +1,59 @@
-import type { RepoMetricsResponse, PopularRepo } from '../types'
+import type { ApiErrorBody, RepoMetricsEnvelope, PopularRepo } from '../types'
+
+/**
+ * An error response from the backend, carrying its machine-readable code.
+ */
+export class ApiError extends Error {
+  readonly code: string
+  readonly status: number
+
+  constructor(status: number, body: ApiErrorBody) {
+    super(body.message)
+    this.name = 'ApiError'
+    this.code = body.code
+    this.status = status
+  }
+}
+
+/**
+ * Builds an error from a failed response, falling back to `fallback` when the body isn't a JSON
+ * error (e.g. from a proxy in front of the backend).
+ */
+const responseError = async (response: Response, fallback: string): Promise<Error> => {
+  try {
+    const body = (await response.json()) as ApiErrorBody
+    if (typeof body.code === 'string' && typeof body.message === 'string') {
+      return new ApiError(response.status, body)
+    }
+  } catch {
+    // Not JSON; use the fallback below.
+  }
+  return new Error(`${fallback}: ${response.statusText}`)
+}
+
+// API URLs are relative so they resolve under the backend's BASE_PATH via the page's <base>.
 
 /**
  * Fetches repository metrics from the Rust backend API.
  *
  * @param owner - The GitHub username or organization.
  * @param repo - The repository name.
- * @returns A promise that resolves to a RepoMetricsResponse object.
+ * @returns A promise that resolves to a RepoMetricsEnvelope object.
  */
 export const fetchRepoMetrics = async (
   owner: string,
   repo: string,
-): Promise<RepoMetricsResponse> => {
+): Promise<RepoMetricsEnvelope> => {
   const response = await fetch(
-    `/api/repos/${encodeURIComponent(owner)}/${encodeURIComponent(repo)}/metrics`,
+    `api/v1/repos/${encodeURIComponent(owner)}/${encodeURIComponent(repo)}/metrics`,
   )
 
   if (!response.ok) {
-    if (response.status === 404) {
+    const error = await responseError(response, 'Failed to fetch metrics')
+    if (error instanceof ApiError && error.code === 'repo_not_found') {
       throw new Error('Repository not found or no metrics available.')
     }
-    const errorText = await response.text()
-    throw new Error(errorText || `Failed to fetch metrics: ${response.statusText}`)
+    throw error
   }
 
   return response.json()
@@ -31,10 +64,10 @@ export const fetchRepoMetrics = async (
  * @returns A promise that resolves to an array of PopularRepo objects.
  */
 export const fetchPopularRepos = async (): Promise<PopularRepo[]> => {
-  const response = await fetch('/api/repos/popular')
+  const response = await fetch('api/v1/repos/popular?include=summary')
 
   if (!response.ok) {
-    throw new Error(`Failed to fetch popular repos: ${response.statusText}`)
+    throw await responseError(response, 'Failed to fetch popular repos')
   }
 
   return response.json()
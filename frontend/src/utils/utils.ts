@@ -19,3 +19,14 @@ export function isSameRepo(repo1: PopularRepo | null, repo2: PopularRepo | null)
     repo1.repo.toLowerCase() === repo2.repo.toLowerCase()
   )
 }
+
+/**
+ * Describes how long ago data was fetched, e.g. "just now" or "3 hours ago".
+ */
+export function formatAge(seconds: number): string {
+  if (seconds < 60) return 'just now'
+  const minutes = Math.floor(seconds / 60)
+  if (minutes < 60) return `${minutes} minute${minutes === 1 ? '' : 's'} ago`
+  const hours = Math.floor(minutes / 60)
+  return `${hours} hour${hours === 1 ? '' : 's'} ago`
+}
@@ -4,6 +4,9 @@ import react from '@vitejs/plugin-react'
 // https://vite.dev/config/
 export default defineConfig({
   plugins: [react()],
+  // Relative asset URLs resolve against the <base> the backend injects, so the build works under
+  // any BASE_PATH.
+  base: './',
   test: {
     environment: 'jsdom',
     globals: true,